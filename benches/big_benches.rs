@@ -122,12 +122,40 @@ fn remove_from_tree_big_benchmark(c: &mut Criterion) {
     remove_dir_all(&path).unwrap();
 }
 
+/** Benchmarks finding the 10 keys changed between two roots of a tree with 1000000 keys */
+fn changed_keys_since_big_benchmark(c: &mut Criterion) {
+    let path = PathBuf::from("db");
+    let seed = [0xBBu8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (mut keys, values) = prepare_inserts(1_000_000, &mut rng);
+
+    let mut bmt = Tree::open(&path, 160).unwrap();
+    let base_root = bmt.insert(None, &mut keys, &values).unwrap();
+    let mut changed_keys = keys.iter().step_by(100_000).cloned().collect::<Vec<_>>();
+    let changed_values = vec![vec![0xFFu8; KEY_LEN]; changed_keys.len()];
+    let new_root = bmt
+        .insert(Some(&base_root), &mut changed_keys, &changed_values)
+        .unwrap();
+
+    c.bench_function("Big Tree Changed Keys Since/1000000", move |b| {
+        b.iter(|| {
+            let changed = bmt
+                .changed_keys_since(&base_root, &new_root)
+                .count();
+            criterion::black_box(changed);
+        })
+    });
+    #[cfg(any(feature = "use_rocksdb"))]
+    remove_dir_all(&path).unwrap();
+}
+
 criterion_group!(
     big_benches,
     hash_tree_empty_tree_insert_big_benchmark,
     hash_tree_existing_tree_insert_big_benchmark,
     get_from_hash_tree_big_benchmark,
-    remove_from_tree_big_benchmark
+    remove_from_tree_big_benchmark,
+    changed_keys_since_big_benchmark
 );
 criterion_main!(big_benches);
 
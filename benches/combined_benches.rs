@@ -0,0 +1,73 @@
+#[macro_use]
+extern crate criterion;
+
+use std::path::PathBuf;
+
+use criterion::Criterion;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use starling::constants::KEY_LEN;
+use starling::hash_tree::HashTree;
+
+type Tree = HashTree<[u8; KEY_LEN], Vec<u8>>;
+
+/// The number of keys retrieved by each benchmark.
+const NUM_KEYS: usize = 10_000;
+
+/** Benchmarks getting from a tree of combined leaves against one of leaves and data nodes */
+fn combined_benchmark(c: &mut Criterion) {
+    let path = PathBuf::from("db");
+    let seed = [0xB4u8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (mut keys, values) = prepare_inserts(NUM_KEYS, &mut rng);
+
+    let mut split = Tree::open(&path, 160).unwrap();
+    let split_root = split.insert(None, &mut keys, &values).unwrap();
+    let mut combined = Tree::open(&path, 160).unwrap();
+    combined.set_combined_leaves(true);
+    let combined_root = combined.insert(None, &mut keys, &values).unwrap();
+
+    println!(
+        "Nodes for {} keys: split {}, combined {}",
+        NUM_KEYS,
+        split.shared_node_count(&split_root, &split_root).unwrap(),
+        combined
+            .shared_node_count(&combined_root, &combined_root)
+            .unwrap()
+    );
+
+    c.bench_function("Tree Get Split Leaves", |b| {
+        b.iter(|| {
+            let items = split.get(&split_root, &mut keys).unwrap();
+            criterion::black_box(items);
+        });
+    });
+    c.bench_function("Tree Get Combined Leaves", |b| {
+        b.iter(|| {
+            let items = combined.get(&combined_root, &mut keys).unwrap();
+            criterion::black_box(items);
+        });
+    });
+}
+
+criterion_group!(combined_benches, combined_benchmark);
+criterion_main!(combined_benches);
+
+fn prepare_inserts(num_entries: usize, rng: &mut StdRng) -> (Vec<[u8; KEY_LEN]>, Vec<Vec<u8>>) {
+    let mut keys = Vec::with_capacity(num_entries);
+    let mut data = Vec::with_capacity(num_entries);
+    for _ in 0..num_entries {
+        let mut key_value = [0u8; KEY_LEN];
+        rng.fill(&mut key_value);
+        keys.push(key_value);
+
+        let mut data_value = [0u8; 32];
+        rng.fill(data_value.as_mut());
+        data.push(data_value.to_vec());
+    }
+
+    keys.sort();
+
+    (keys, data)
+}
@@ -0,0 +1,62 @@
+#[macro_use]
+extern crate criterion;
+
+use std::path::PathBuf;
+
+use criterion::{Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use starling::constants::KEY_LEN;
+use starling::traits::{Data, Database, NodeVariant};
+use starling::tree::tree_data::TreeData;
+use starling::tree::tree_node::TreeNode;
+use starling::tree_db::HashTreeDB;
+
+/// The number of data nodes in the database.
+const NUM_NODES: usize = 10_000;
+
+/// The size of the value held by each data node.
+const VALUE_LEN: usize = 1024;
+
+/** Benchmarks checking for stored nodes with `contains` against reading them with `get_node` */
+fn contains_benchmark(c: &mut Criterion) {
+    let path = PathBuf::from("db");
+    let seed = [0xBDu8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let mut db = HashTreeDB::open(&path).unwrap();
+    let mut keys = Vec::with_capacity(NUM_NODES);
+    for _ in 0..NUM_NODES {
+        let mut key = [0u8; KEY_LEN];
+        rng.fill(&mut key);
+        let mut value = vec![0u8; VALUE_LEN];
+        rng.fill(value.as_mut_slice());
+
+        let mut data = TreeData::new();
+        data.set_value(&value);
+        db.insert(key, TreeNode::new(NodeVariant::Data(data)))
+            .unwrap();
+        keys.push(key);
+    }
+
+    let mut group = c.benchmark_group("Existence Check");
+    group.throughput(Throughput::Elements(NUM_NODES as u64));
+    group.bench_function("get_node", |b| {
+        b.iter(|| {
+            for key in &keys {
+                criterion::black_box(db.get_node(*key).unwrap().is_some());
+            }
+        });
+    });
+    group.bench_function("contains", |b| {
+        b.iter(|| {
+            for key in &keys {
+                criterion::black_box(db.contains(key).unwrap());
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(contains_benches, contains_benchmark);
+criterion_main!(contains_benches);
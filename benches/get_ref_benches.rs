@@ -0,0 +1,112 @@
+#[macro_use]
+extern crate criterion;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::Criterion;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use starling::constants::KEY_LEN;
+use starling::hash_tree::HashTree;
+
+type Tree = HashTree<[u8; KEY_LEN], Vec<u8>>;
+
+/// The number of keys inserted and retrieved by each benchmark.
+const NUM_KEYS: usize = 10_000;
+
+/// An allocator counting the allocations made, to compare `get` and `get_ref`, and to track what
+/// sharing values costs `insert`.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/** Benchmarks getting owned values against getting shared values */
+fn get_ref_benchmark(c: &mut Criterion) {
+    let path = PathBuf::from("db");
+    let seed = [0xBBu8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (mut keys, values) = prepare_inserts(NUM_KEYS, &mut rng);
+    let mut bmt = Tree::open(&path, 160).unwrap();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let root = bmt.insert(None, &mut keys, &values).unwrap();
+    let inserted = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!("Allocations for inserting {} keys: {}", NUM_KEYS, inserted);
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    criterion::black_box(bmt.get(&root, &mut keys).unwrap());
+    let owned = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    criterion::black_box(bmt.get_ref(&root, &mut keys).unwrap());
+    let shared = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "Allocations for {} keys: get {}, get_ref {}",
+        NUM_KEYS, owned, shared
+    );
+
+    c.bench_function("Tree Get Owned", |b| {
+        b.iter(|| {
+            let items = bmt.get(&root, &mut keys).unwrap();
+            criterion::black_box(items);
+        });
+    });
+    c.bench_function("Tree Get Shared", |b| {
+        b.iter(|| {
+            let items = bmt.get_ref(&root, &mut keys).unwrap();
+            criterion::black_box(items);
+        });
+    });
+}
+
+/** Benchmarks inserting into the database shared values are read from */
+fn insert_benchmark(c: &mut Criterion) {
+    let path = PathBuf::from("db");
+    let seed = [0xBBu8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (mut keys, values) = prepare_inserts(NUM_KEYS, &mut rng);
+
+    c.bench_function("Tree Insert", |b| {
+        b.iter(|| {
+            let mut bmt = Tree::open(&path, 160).unwrap();
+            let root = bmt.insert(None, &mut keys, &values).unwrap();
+            criterion::black_box(root);
+        });
+    });
+}
+
+criterion_group!(get_ref_benches, get_ref_benchmark, insert_benchmark);
+criterion_main!(get_ref_benches);
+
+fn prepare_inserts(num_entries: usize, rng: &mut StdRng) -> (Vec<[u8; KEY_LEN]>, Vec<Vec<u8>>) {
+    let mut keys = Vec::with_capacity(num_entries);
+    let mut data = Vec::with_capacity(num_entries);
+    for _ in 0..num_entries {
+        let mut key_value = [0u8; KEY_LEN];
+        rng.fill(&mut key_value);
+        keys.push(key_value);
+
+        let mut data_value = [0u8; KEY_LEN];
+        rng.fill(data_value.as_mut());
+        data.push(data_value.to_vec());
+    }
+
+    keys.sort();
+
+    (keys, data)
+}
@@ -0,0 +1,71 @@
+#[macro_use]
+extern crate criterion;
+
+use std::path::PathBuf;
+
+use criterion::{Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use starling::constants::KEY_LEN;
+use starling::hash_tree::HashTree;
+
+type Tree = HashTree<[u8; KEY_LEN], Vec<u8>>;
+
+/// The number of keys retrieved by each benchmark.
+const NUM_KEYS: usize = 10_000;
+
+/// The size of each value, small enough to be inlined.
+const VALUE_LEN: usize = 8;
+
+/** Benchmarks getting small values inlined into their leaves against values in data nodes */
+fn inline_get_benchmark(c: &mut Criterion) {
+    let path = PathBuf::from("db");
+    let seed = [0xBBu8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (mut keys, values) = prepare_inserts(NUM_KEYS, &mut rng);
+
+    let mut external = Tree::open(&path, 160).unwrap();
+    let external_root = external.insert(None, &mut keys, &values).unwrap();
+
+    let mut inlined = Tree::open(&path, 160).unwrap();
+    inlined.set_inline_value_bytes(VALUE_LEN + 1);
+    let inlined_root = inlined.insert(None, &mut keys, &values).unwrap();
+
+    let mut group = c.benchmark_group("Small Value Get");
+    group.throughput(Throughput::Elements(NUM_KEYS as u64));
+    group.bench_function("external", |b| {
+        b.iter(|| {
+            let items = external.get(&external_root, &mut keys).unwrap();
+            criterion::black_box(items);
+        });
+    });
+    group.bench_function("inlined", |b| {
+        b.iter(|| {
+            let items = inlined.get(&inlined_root, &mut keys).unwrap();
+            criterion::black_box(items);
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(inline_benches, inline_get_benchmark);
+criterion_main!(inline_benches);
+
+fn prepare_inserts(num_entries: usize, rng: &mut StdRng) -> (Vec<[u8; KEY_LEN]>, Vec<Vec<u8>>) {
+    let mut keys = Vec::with_capacity(num_entries);
+    let mut data = Vec::with_capacity(num_entries);
+    for _ in 0..num_entries {
+        let mut key_value = [0u8; KEY_LEN];
+        rng.fill(&mut key_value);
+        keys.push(key_value);
+
+        let mut data_value = [0u8; VALUE_LEN];
+        rng.fill(data_value.as_mut());
+        data.push(data_value.to_vec());
+    }
+
+    keys.sort();
+
+    (keys, data)
+}
@@ -0,0 +1,78 @@
+#[macro_use]
+extern crate criterion;
+
+use std::path::PathBuf;
+
+use criterion::Criterion;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use starling::constants::KEY_LEN;
+use starling::hash_tree::HashTree;
+
+type Tree = HashTree<[u8; KEY_LEN], Vec<u8>>;
+
+/// The number of keys in the tree the single keys are inserted into.
+const NUM_KEYS: usize = 10_000;
+/// The number of distinct single keys inserted.
+const NUM_UPDATES: usize = 1_000;
+
+/** Benchmarks inserting one new key into an existing tree with `insert_one` against `insert` */
+fn insert_one_benchmark(c: &mut Criterion) {
+    let path = PathBuf::from("db");
+    let seed = [0xB3u8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (mut keys, values) = prepare_inserts(NUM_KEYS, &mut rng);
+    let (new_keys, new_values) = prepare_inserts(NUM_UPDATES, &mut rng);
+
+    let mut tree = Tree::open(&path, 160).unwrap();
+    let root = tree.insert(None, &mut keys, &values).unwrap();
+
+    let mut group = c.benchmark_group("Single Key Insert");
+    let mut i = 0;
+    group.bench_function("insert_one", |b| {
+        b.iter(|| {
+            let new_root = tree
+                .insert_one(Some(&root), &new_keys[i], &new_values[i])
+                .unwrap();
+            i = (i + 1) % NUM_UPDATES;
+            criterion::black_box(new_root);
+        })
+    });
+    let mut i = 0;
+    group.bench_function("insert", |b| {
+        b.iter(|| {
+            let new_root = tree
+                .insert(
+                    Some(&root),
+                    &mut [new_keys[i]],
+                    std::slice::from_ref(&new_values[i]),
+                )
+                .unwrap();
+            i = (i + 1) % NUM_UPDATES;
+            criterion::black_box(new_root);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(insert_one_benches, insert_one_benchmark);
+criterion_main!(insert_one_benches);
+
+fn prepare_inserts(num_entries: usize, rng: &mut StdRng) -> (Vec<[u8; KEY_LEN]>, Vec<Vec<u8>>) {
+    let mut keys = Vec::with_capacity(num_entries);
+    let mut data = Vec::with_capacity(num_entries);
+    for _ in 0..num_entries {
+        let mut key_value = [0u8; KEY_LEN];
+        rng.fill(&mut key_value);
+        keys.push(key_value);
+
+        let mut data_value = [0u8; 32];
+        rng.fill(data_value.as_mut());
+        data.push(data_value.to_vec());
+    }
+
+    keys.sort();
+
+    (keys, data)
+}
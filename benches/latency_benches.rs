@@ -0,0 +1,168 @@
+#[macro_use]
+extern crate criterion;
+
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::Duration;
+
+use criterion::Criterion;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use starling::constants::KEY_LEN;
+use starling::merkle_bit::MerkleBIT;
+use starling::traits::{Database, Exception};
+use starling::tree::tree_branch::TreeBranch;
+use starling::tree::tree_data::TreeData;
+use starling::tree::tree_leaf::TreeLeaf;
+use starling::tree::tree_node::TreeNode;
+use starling::tree_db::HashTreeDB;
+use starling::tree_hasher::TreeHasher;
+
+/// The simulated cost of a single round trip to the backend.
+const ROUND_TRIP: Duration = Duration::from_micros(50);
+
+/// The simulated cost of a single round trip to a slow backend.
+const SLOW_ROUND_TRIP: Duration = Duration::from_millis(1);
+
+/// A database that simulates a network round trip on every read.
+struct LatencyDB {
+    inner: HashTreeDB<[u8; KEY_LEN]>,
+    batched: bool,
+    round_trip: Duration,
+}
+
+impl LatencyDB {
+    fn new(batched: bool) -> Self {
+        Self::with_round_trip(batched, ROUND_TRIP)
+    }
+
+    fn with_round_trip(batched: bool, round_trip: Duration) -> Self {
+        let inner = HashTreeDB::open(&PathBuf::new()).unwrap();
+        Self {
+            inner,
+            batched,
+            round_trip,
+        }
+    }
+}
+
+impl Database<[u8; KEY_LEN]> for LatencyDB {
+    type NodeType = TreeNode<[u8; KEY_LEN]>;
+    type EntryType = ();
+
+    fn open(_path: &PathBuf) -> Result<Self, Exception> {
+        Ok(Self::new(true))
+    }
+
+    fn get_node(&self, key: [u8; KEY_LEN]) -> Result<Option<Self::NodeType>, Exception> {
+        sleep(self.round_trip);
+        self.inner.get_node(key)
+    }
+
+    fn get_nodes(&self, keys: &[[u8; KEY_LEN]]) -> Result<Vec<Option<Self::NodeType>>, Exception> {
+        if !self.batched {
+            let mut nodes = Vec::with_capacity(keys.len());
+            for &key in keys {
+                nodes.push(self.get_node(key)?);
+            }
+            return Ok(nodes);
+        }
+        sleep(self.round_trip);
+        let mut nodes = Vec::with_capacity(keys.len());
+        for &key in keys {
+            nodes.push(self.inner.get_node(key)?);
+        }
+        Ok(nodes)
+    }
+
+    fn insert(&mut self, key: [u8; KEY_LEN], node: Self::NodeType) -> Result<(), Exception> {
+        self.inner.insert(key, node)
+    }
+
+    fn remove(&mut self, key: &[u8; KEY_LEN]) -> Result<(), Exception> {
+        self.inner.remove(key)
+    }
+
+    fn batch_write(&mut self) -> Result<(), Exception> {
+        self.inner.batch_write()
+    }
+}
+
+type Tree = MerkleBIT<
+    LatencyDB,
+    TreeBranch<[u8; KEY_LEN]>,
+    TreeLeaf<[u8; KEY_LEN]>,
+    TreeData,
+    TreeNode<[u8; KEY_LEN]>,
+    TreeHasher,
+    Vec<u8>,
+    [u8; KEY_LEN],
+>;
+
+/** Benchmarks retrieving 256 keys from a high latency backend with and without batched reads */
+fn get_from_high_latency_backend_benchmark(c: &mut Criterion) {
+    let seed = [0xBBu8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (mut keys, values) = prepare_inserts(256, &mut rng);
+    for &batched in &[false, true] {
+        let mut bmt = Tree::from_db(LatencyDB::new(batched), 160).unwrap();
+        let root_hash = bmt.insert(None, &mut keys, &values).unwrap();
+        let mut get_keys = keys.clone();
+        let name = if batched {
+            "High Latency Get/batched/256"
+        } else {
+            "High Latency Get/unbatched/256"
+        };
+        c.bench_function(name, move |b| {
+            b.iter(|| {
+                let items = bmt.get(&root_hash, &mut get_keys).unwrap();
+                criterion::black_box(items);
+            })
+        });
+    }
+}
+
+/** Benchmarks updating a single key of a 256 key tree on a backend taking 1ms per read */
+fn insert_into_high_latency_backend_benchmark(c: &mut Criterion) {
+    let seed = [0xC1u8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (mut keys, values) = prepare_inserts(256, &mut rng);
+    let mut bmt = Tree::from_db(LatencyDB::with_round_trip(true, SLOW_ROUND_TRIP), 160).unwrap();
+    let root_hash = bmt.insert(None, &mut keys, &values).unwrap();
+    let key = keys[128];
+    let mut counter = 0_u64;
+    c.bench_function("High Latency Insert/1ms/1", move |b| {
+        b.iter(|| {
+            // A fresh value every time, so the insert is never a no-op
+            counter += 1;
+            let value = counter.to_be_bytes().to_vec();
+            let root = bmt.insert(Some(&root_hash), &mut [key], &[value]).unwrap();
+            criterion::black_box(root);
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = get_from_high_latency_backend_benchmark, insert_into_high_latency_backend_benchmark
+}
+criterion_main!(benches);
+
+fn prepare_inserts(num_entries: usize, rng: &mut StdRng) -> (Vec<[u8; KEY_LEN]>, Vec<Vec<u8>>) {
+    let mut keys = Vec::with_capacity(num_entries);
+    let mut data = Vec::with_capacity(num_entries);
+    for _ in 0..num_entries {
+        let mut key_value = [0u8; KEY_LEN];
+        rng.fill(&mut key_value);
+        keys.push(key_value);
+
+        let data_value = (0..KEY_LEN).map(|_| rng.gen()).collect();
+        data.push(data_value);
+    }
+
+    keys.sort();
+
+    (keys, data)
+}
@@ -0,0 +1,133 @@
+#[macro_use]
+extern crate criterion;
+
+use std::path::PathBuf;
+
+use criterion::{BatchSize, Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use starling::constants::KEY_LEN;
+use starling::hash_tree::HashTree;
+
+type Tree = HashTree<[u8; KEY_LEN], Vec<u8>>;
+
+/// The number of keys inserted and retrieved by each benchmark.
+const NUM_KEYS: usize = 100_000;
+
+/// The number of keys inserted by the bulk import benchmark.
+const IMPORT_KEYS: usize = 1_000_000;
+
+/// The map implementation being measured, selected by the `use_hashbrown` feature.
+/// Compare the two with `cargo bench --bench map_benches` and
+/// `cargo bench --bench map_benches --features use_hashbrown`.
+const MAP_IMPL: &str = if cfg!(feature = "use_hashbrown") {
+    "hashbrown"
+} else {
+    "std"
+};
+
+/** Benchmarks inserting 100k keys into a new tree */
+fn map_insert_benchmark(c: &mut Criterion) {
+    let path = PathBuf::from("db");
+    let seed = [0xBBu8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (keys, values) = prepare_inserts(NUM_KEYS, &mut rng);
+
+    let mut group = c.benchmark_group(format!("Map Insert ({})", MAP_IMPL));
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(NUM_KEYS as u64));
+    group.bench_function("100k keys", |b| {
+        b.iter_batched(
+            || (Tree::open(&path, 160).unwrap(), keys.clone()),
+            |(mut bmt, mut keys)| {
+                let root = bmt.insert(None, &mut keys, &values).unwrap();
+                criterion::black_box(root);
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.finish();
+}
+
+/** Benchmarks getting 100k keys from an existing tree */
+fn map_get_benchmark(c: &mut Criterion) {
+    let path = PathBuf::from("db");
+    let seed = [0xBBu8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (mut keys, values) = prepare_inserts(NUM_KEYS, &mut rng);
+    let mut bmt = Tree::open(&path, 160).unwrap();
+    let root = bmt.insert(None, &mut keys, &values).unwrap();
+
+    let mut group = c.benchmark_group(format!("Map Get ({})", MAP_IMPL));
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(NUM_KEYS as u64));
+    group.bench_function("100k keys", |b| {
+        b.iter(|| {
+            let items = bmt.get(&root, &mut keys).unwrap();
+            criterion::black_box(items);
+        });
+    });
+    group.finish();
+}
+
+/** Benchmarks importing 1M keys into a new tree, with and without a capacity hint */
+fn map_import_capacity_benchmark(c: &mut Criterion) {
+    let seed = [0xBBu8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (keys, values) = prepare_inserts(IMPORT_KEYS, &mut rng);
+
+    // Each key adds a data node and a leaf, and all but one add a branch
+    let capacity = IMPORT_KEYS * 3;
+
+    let mut group = c.benchmark_group(format!("Map Import ({})", MAP_IMPL));
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(IMPORT_KEYS as u64));
+    group.bench_function("1M keys", |b| {
+        b.iter_batched(
+            || (Tree::new(160).unwrap(), keys.clone()),
+            |(mut bmt, mut keys)| {
+                let root = bmt.insert(None, &mut keys, &values).unwrap();
+                criterion::black_box(root);
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.bench_function("1M keys with capacity", |b| {
+        b.iter_batched(
+            || (Tree::with_capacity(160, capacity).unwrap(), keys.clone()),
+            |(mut bmt, mut keys)| {
+                let root = bmt.insert(None, &mut keys, &values).unwrap();
+                criterion::black_box(root);
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.finish();
+}
+
+criterion_group!(
+    map_benches,
+    map_insert_benchmark,
+    map_get_benchmark,
+    map_import_capacity_benchmark
+);
+criterion_main!(map_benches);
+
+fn prepare_inserts(num_entries: usize, rng: &mut StdRng) -> (Vec<[u8; KEY_LEN]>, Vec<Vec<u8>>) {
+    let mut keys = Vec::with_capacity(num_entries);
+    let mut data = Vec::with_capacity(num_entries);
+    for _ in 0..num_entries {
+        let mut key_value = [0u8; KEY_LEN];
+        rng.fill(&mut key_value);
+        keys.push(key_value);
+
+        let mut data_value = [0u8; KEY_LEN];
+        rng.fill(data_value.as_mut());
+        data.push(data_value.to_vec());
+    }
+
+    keys.sort();
+
+    (keys, data)
+}
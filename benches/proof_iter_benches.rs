@@ -0,0 +1,66 @@
+#[macro_use]
+extern crate criterion;
+
+use std::path::PathBuf;
+
+use criterion::{Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use starling::constants::KEY_LEN;
+use starling::hash_tree::HashTree;
+
+type Tree = HashTree<[u8; KEY_LEN], Vec<u8>>;
+
+/// The number of keys in the tree.
+const NUM_KEYS: usize = 100_000;
+
+/** Benchmarks exporting every proof with `iter_with_proofs` against generating each one by key */
+fn proof_export_benchmark(c: &mut Criterion) {
+    let path = PathBuf::from("db");
+    let seed = [0xC0u8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (mut keys, values) = prepare_inserts(NUM_KEYS, &mut rng);
+    let mut tree = Tree::open(&path, 160).unwrap();
+    let root = tree.insert(None, &mut keys, &values).unwrap();
+
+    let mut group = c.benchmark_group("Proof Export");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(NUM_KEYS as u64));
+    group.bench_function("generate_proof", |b| {
+        b.iter(|| {
+            for key in &keys {
+                criterion::black_box(tree.generate_proof(&root, *key).unwrap());
+            }
+        });
+    });
+    group.bench_function("iter_with_proofs", |b| {
+        b.iter(|| {
+            for entry in tree.iter_with_proofs(&root) {
+                criterion::black_box(entry.unwrap());
+            }
+        });
+    });
+    group.finish();
+}
+
+fn prepare_inserts(num_entries: usize, rng: &mut StdRng) -> (Vec<[u8; KEY_LEN]>, Vec<Vec<u8>>) {
+    let mut keys = Vec::with_capacity(num_entries);
+    let mut data = Vec::with_capacity(num_entries);
+    for _ in 0..num_entries {
+        let mut key_value = [0u8; KEY_LEN];
+        rng.fill(&mut key_value);
+        keys.push(key_value);
+
+        let mut data_value = [0u8; 32];
+        rng.fill(data_value.as_mut());
+        data.push(data_value.to_vec());
+    }
+
+    keys.sort();
+
+    (keys, data)
+}
+
+criterion_group!(proof_iter_benches, proof_export_benchmark);
+criterion_main!(proof_iter_benches);
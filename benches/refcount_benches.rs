@@ -0,0 +1,70 @@
+#[macro_use]
+extern crate criterion;
+
+use std::path::PathBuf;
+
+use criterion::{Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use starling::constants::KEY_LEN;
+use starling::hash_tree::HashTree;
+
+type Tree = HashTree<[u8; KEY_LEN], Vec<u8>>;
+
+/// The number of keys in the build.
+const NUM_KEYS: usize = 100_000;
+
+/** Benchmarks a single large build with reference counting enabled against disabled */
+fn refcount_build_benchmark(c: &mut Criterion) {
+    let path = PathBuf::from("db");
+    let seed = [0xBFu8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let (keys, values) = prepare_inserts(NUM_KEYS, &mut rng);
+
+    let mut group = c.benchmark_group("Single Build");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(NUM_KEYS as u64));
+    for &refcounting in &[true, false] {
+        let name = if refcounting {
+            "refcounting"
+        } else {
+            "no refcounting"
+        };
+        group.bench_function(name, |b| {
+            b.iter_with_setup(
+                || {
+                    let mut tree = Tree::open(&path, 160).unwrap();
+                    tree.set_refcounting(refcounting);
+                    (tree, keys.clone())
+                },
+                |(mut tree, mut keys)| {
+                    let root = tree.insert(None, &mut keys, &values).unwrap();
+                    criterion::black_box(root);
+                },
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(refcount_benches, refcount_build_benchmark);
+criterion_main!(refcount_benches);
+
+fn prepare_inserts(num_entries: usize, rng: &mut StdRng) -> (Vec<[u8; KEY_LEN]>, Vec<Vec<u8>>) {
+    let mut keys = Vec::with_capacity(num_entries);
+    let mut data = Vec::with_capacity(num_entries);
+    for _ in 0..num_entries {
+        let mut key_value = [0u8; KEY_LEN];
+        rng.fill(&mut key_value);
+        keys.push(key_value);
+
+        let mut data_value = [0u8; 32];
+        rng.fill(data_value.as_mut());
+        data.push(data_value.to_vec());
+    }
+
+    keys.sort();
+
+    (keys, data)
+}
@@ -0,0 +1,81 @@
+#[macro_use]
+extern crate criterion;
+
+use std::path::PathBuf;
+
+use criterion::{Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use starling::constants::KEY_LEN;
+use starling::hash_tree::HashTree;
+use starling::utils::tree_build_scratch::TreeBuildScratch;
+
+type Tree = HashTree<[u8; KEY_LEN], Vec<u8>>;
+
+/// The number of inserts in each loop.
+const NUM_INSERTS: usize = 1000;
+
+/// The number of keys in each insert.
+const BATCH_LEN: usize = 1000;
+
+/** Benchmarks a loop of inserts reusing one build scratch against allocating one per insert */
+fn scratch_insert_loop_benchmark(c: &mut Criterion) {
+    let path = PathBuf::from("db");
+    let seed = [0xBCu8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let batches = (0..NUM_INSERTS)
+        .map(|_| prepare_inserts(BATCH_LEN, &mut rng))
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("Insert Loop");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((NUM_INSERTS * BATCH_LEN) as u64));
+    group.bench_function("reallocating scratch", |b| {
+        b.iter_with_setup(
+            || (Tree::open(&path, 160).unwrap(), batches.clone()),
+            |(mut tree, mut batches)| {
+                for (keys, values) in &mut batches {
+                    let root = tree.insert(None, keys, values).unwrap();
+                    criterion::black_box(root);
+                }
+            },
+        );
+    });
+    group.bench_function("reusing scratch", |b| {
+        b.iter_with_setup(
+            || (Tree::open(&path, 160).unwrap(), batches.clone()),
+            |(mut tree, mut batches)| {
+                let mut scratch = TreeBuildScratch::new();
+                for (keys, values) in &mut batches {
+                    let root = tree
+                        .insert_with_scratch(None, keys, values, &mut scratch)
+                        .unwrap();
+                    criterion::black_box(root);
+                }
+            },
+        );
+    });
+    group.finish();
+}
+
+criterion_group!(scratch_benches, scratch_insert_loop_benchmark);
+criterion_main!(scratch_benches);
+
+fn prepare_inserts(num_entries: usize, rng: &mut StdRng) -> (Vec<[u8; KEY_LEN]>, Vec<Vec<u8>>) {
+    let mut keys = Vec::with_capacity(num_entries);
+    let mut data = Vec::with_capacity(num_entries);
+    for _ in 0..num_entries {
+        let mut key_value = [0u8; KEY_LEN];
+        rng.fill(&mut key_value);
+        keys.push(key_value);
+
+        let mut data_value = [0u8; 32];
+        rng.fill(data_value.as_mut());
+        data.push(data_value.to_vec());
+    }
+
+    keys.sort();
+
+    (keys, data)
+}
@@ -0,0 +1,46 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use starling::constants::KEY_LEN;
+use starling::utils::tree_utils::radix_sort_keys;
+
+/** Benchmarks sorting large batches of keys with the comparison sort against the radix sort */
+fn sort_benchmark(c: &mut Criterion) {
+    let seed = [0xBEu8; KEY_LEN];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+
+    let mut group = c.benchmark_group("Key Sort");
+    group.sample_size(10);
+    for &num_keys in &[100_000_usize, 1_000_000] {
+        let mut keys = Vec::with_capacity(num_keys);
+        for _ in 0..num_keys {
+            let mut key = [0u8; KEY_LEN];
+            rng.fill(&mut key);
+            keys.push(key);
+        }
+
+        group.throughput(Throughput::Elements(num_keys as u64));
+        group.bench_with_input(BenchmarkId::new("sort", num_keys), &keys, |b, keys| {
+            b.iter_batched_ref(|| keys.clone(), |k| k.sort(), BatchSize::LargeInput);
+        });
+        group.bench_with_input(
+            BenchmarkId::new("radix_sort_keys", num_keys),
+            &keys,
+            |b, keys| {
+                b.iter_batched_ref(
+                    || keys.clone(),
+                    |k| radix_sort_keys(k),
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(sort_benches, sort_benchmark);
+criterion_main!(sort_benches);
@@ -0,0 +1,16 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate starling;
+
+use starling::traits::{Decode, Encode};
+use starling::tree::tree_node::TreeNode;
+
+type Node = TreeNode<[u8; 32]>;
+
+fuzz_target!(|data: &[u8]| {
+    // Arbitrary bytes must decode into a node or a clean error, never a panic
+    if let Ok(node) = Node::decode(data) {
+        let encoded = node.encode().unwrap();
+        assert_eq!(Node::decode(&encoded).unwrap(), node);
+    }
+});
@@ -2,5 +2,7 @@
 pub const KEY_LEN: usize = 32;
 /// The number of bits in the key.
 pub const KEY_LEN_BITS: usize = KEY_LEN * 8 - 1;
+/// The number of bytes in an Ethereum style address, for use as a key with `MerkleBIT20`.
+pub const ADDRESS_LEN: usize = 20;
 /// These constants are used to quickly calculate the values of log2.
 pub const MULTIPLY_DE_BRUIJN_BIT_POSITION: [u8; 8] = [0, 5, 1, 6, 4, 3, 2, 7];
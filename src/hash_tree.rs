@@ -1,18 +1,31 @@
 #[cfg(not(any(feature = "use_hashbrown")))]
 use std::collections::HashMap;
+use std::io::{Read, Write};
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
 #[cfg(feature = "use_hashbrown")]
 use hashbrown::HashMap;
 
-use crate::merkle_bit::{BinaryMerkleTreeResult, MerkleBIT};
-use crate::traits::{Array, Decode, Encode};
+#[cfg(feature = "debug_tools")]
+use crate::merkle_bit::RefcountHook;
+use crate::merkle_bit::{BinaryMerkleTreeResult, DedupStats, EntryPage, MerkleBIT, ProvenEntry};
+use crate::merkle_bit_reader::MerkleBITReader;
+use crate::traits::{Array, BlobStore, Database, Decode, Encode, Hasher};
 use crate::tree::tree_branch::TreeBranch;
 use crate::tree::tree_data::TreeData;
 use crate::tree::tree_leaf::TreeLeaf;
-use crate::tree::tree_node::TreeNode;
+use crate::tree::tree_node::{TreeNode, TreeNodeVariant};
 use crate::tree_db::HashTreeDB;
 use crate::tree_hasher::TreeHasher;
+use crate::utils::hash_scheme::DomainTags;
+use crate::utils::ics23::{ExistenceProof, HashOp, ProofSpec};
+use crate::utils::proof::{Proof, SubtreeProof};
+use crate::utils::shared_value::SharedValue;
+use crate::utils::snapshot::Snapshot;
+use crate::utils::tree_build_scratch::TreeBuildScratch;
+use crate::utils::tree_options::TreeOptions;
 
 /// Internal type alias for the underlying tree.
 type Tree<ArrayType, ValueType> = MerkleBIT<
@@ -26,6 +39,18 @@ type Tree<ArrayType, ValueType> = MerkleBIT<
     ArrayType,
 >;
 
+/// A read only view of a `HashTree`, shareable between threads.
+pub type HashTreeReader<ArrayType = [u8; 32], ValueType = Vec<u8>> = MerkleBITReader<
+    HashTreeDB<ArrayType>,
+    TreeBranch<ArrayType>,
+    TreeLeaf<ArrayType>,
+    TreeData,
+    TreeNode<ArrayType>,
+    TreeHasher,
+    ValueType,
+    ArrayType,
+>;
+
 /// A `MerkleBIT` implemented with a `HashMap`.  Can be used for quickly storing items in memory, though
 /// larger sets of items should be stored on disk or over the network in a real database.
 pub struct HashTree<ArrayType = [u8; 32], ValueType = Vec<u8>>
@@ -62,6 +87,53 @@ where
         Ok(Self { tree })
     }
 
+    /// Creates a new `HashTree` with room for `capacity` nodes before its map reallocates, which
+    /// avoids repeatedly growing the map during a large import.
+    /// # Errors
+    /// None.
+    #[inline]
+    pub fn with_capacity(depth: usize, capacity: usize) -> BinaryMerkleTreeResult<Self> {
+        let path = PathBuf::new();
+        let tree = MerkleBIT::new_with_capacity(&path, depth, capacity)?;
+        Ok(Self { tree })
+    }
+
+    /// Creates a new `HashTree` whose node locations are separated from those of other trees by
+    /// the prefix `domain`.  See `MerkleBIT::new_with_domain`.
+    /// # Errors
+    /// `Exception` generated if `domain` is longer than `u32::MAX` bytes.
+    #[inline]
+    pub fn with_domain(depth: usize, domain: &[u8]) -> BinaryMerkleTreeResult<Self> {
+        let path = PathBuf::new();
+        let tree = MerkleBIT::new_with_domain(&path, depth, domain)?;
+        Ok(Self { tree })
+    }
+
+    /// Creates a new `HashTree` configured with `options`.  See `MerkleBIT::new_with_options`.
+    /// # Errors
+    /// None.
+    #[inline]
+    pub fn with_options(depth: usize, options: TreeOptions) -> BinaryMerkleTreeResult<Self> {
+        let path = PathBuf::new();
+        let tree = MerkleBIT::new_with_options(&path, depth, options)?;
+        Ok(Self { tree })
+    }
+
+    /// Gets the options of the tree.
+    #[inline]
+    #[must_use]
+    pub fn options(&self) -> &TreeOptions {
+        self.tree.options()
+    }
+
+    /// Gets the root of a tree holding no keys, which is returned by empty inserts when they are
+    /// not strict.  Every method taking a root reads it as a tree without keys.
+    #[inline]
+    #[must_use]
+    pub fn empty_root() -> ArrayType {
+        Tree::<ArrayType, ValueType>::empty_root()
+    }
+
     /// Gets the values associated with `keys` from the tree.
     /// # Errors
     /// `Exception` generated if the `get` encounters an invalid state during tree traversal.
@@ -74,6 +146,44 @@ where
         self.tree.get(root_hash, keys)
     }
 
+    /// Gets the values associated with `keys` from the tree, along with the depth each key's leaf
+    /// was found at.
+    /// # Errors
+    /// `Exception` generated if the `get_with_depth` encounters an invalid state during tree traversal.
+    #[inline]
+    pub fn get_with_depth(
+        &self,
+        root_hash: &ArrayType,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<HashMap<ArrayType, Option<(ValueType, usize)>>> {
+        self.tree.get_with_depth(root_hash, keys)
+    }
+
+    /// Gets the values associated with `keys` from the tree, failing only the keys whose nodes are
+    /// corrupt rather than the whole batch.
+    /// # Errors
+    /// `Exception` generated if there are more `keys` than the maximum batch size.
+    #[inline]
+    pub fn get_with_errors(
+        &self,
+        root_hash: &ArrayType,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<HashMap<ArrayType, BinaryMerkleTreeResult<Option<ValueType>>>> {
+        self.tree.get_with_errors(root_hash, keys)
+    }
+
+    /// Gets the values associated with `keys` from the tree, in the order of `keys`.
+    /// # Errors
+    /// `Exception` generated if the `get_ordered` encounters an invalid state during tree traversal.
+    #[inline]
+    pub fn get_ordered(
+        &self,
+        root_hash: &ArrayType,
+        keys: &[ArrayType],
+    ) -> BinaryMerkleTreeResult<Vec<Option<ValueType>>> {
+        self.tree.get_ordered(root_hash, keys)
+    }
+
     /// Inserts elements into the tree.  Using `previous_root` specifies that the insert depends on
     /// the state from the previous root, and will update references accordingly.
     /// # Errors
@@ -88,6 +198,61 @@ where
         self.tree.insert(previous_root, keys, values)
     }
 
+    /// Inserts items into the tree like `insert`, building the new tree in `scratch` so its memory
+    /// can be reused across inserts.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn insert_with_scratch(
+        &mut self,
+        previous_root: Option<&ArrayType>,
+        keys: &mut [ArrayType],
+        values: &[ValueType],
+        scratch: &mut TreeBuildScratch<ArrayType>,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree
+            .insert_with_scratch(previous_root, keys, values, scratch)
+    }
+
+    /// Inserts items into the tree like `insert`, taking the values already encoded.  The caller is
+    /// responsible for the bytes being what `ValueType::decode` expects.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn insert_encoded(
+        &mut self,
+        previous_root: Option<&ArrayType>,
+        keys: &mut [ArrayType],
+        encoded_values: &[&[u8]],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree
+            .insert_encoded(previous_root, keys, encoded_values)
+    }
+
+    /// Builds a global tree committing to the subtree root given for each key.
+    /// # Errors
+    /// `Exception` generated with the same kinds as `insert_encoded`.
+    #[inline]
+    pub fn commit_subtrees(
+        &mut self,
+        entries: &[(ArrayType, ArrayType)],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.commit_subtrees(entries)
+    }
+
+    /// Computes the root `insert` would return for `keys` and `values` inserted into an empty
+    /// tree, without touching the database.
+    /// # Errors
+    /// `Exception` generated if a value cannot be encoded or `keys` holds a duplicate.
+    #[inline]
+    pub fn root_for(
+        &self,
+        keys: &[ArrayType],
+        values: &[ValueType],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.root_for(keys, values)
+    }
+
     /// Removes a root from the tree.  This will remove all elements with less than two references
     /// under the given root.
     /// # Errors
@@ -97,6 +262,27 @@ where
         self.tree.remove(root_hash)
     }
 
+    /// Gets the current root recorded by `compare_and_set_root`, if any.
+    /// # Errors
+    /// `Exception` generated if the database has no root pointer.
+    #[inline]
+    pub fn current_root(&self) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        self.tree.current_root()
+    }
+
+    /// Records `new` as the current root only if the current root is still `expected`, returning
+    /// whether the root was updated.
+    /// # Errors
+    /// `Exception` generated if `new` is not a root in the tree.
+    #[inline]
+    pub fn compare_and_set_root(
+        &mut self,
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> BinaryMerkleTreeResult<bool> {
+        self.tree.compare_and_set_root(expected, new)
+    }
+
     /// Generates an inclusion proof for the given key at the specified root.
     /// # Errors
     /// `Exception` generated if an invalid state is encountered during tree traversal
@@ -146,4 +332,815 @@ where
     ) -> BinaryMerkleTreeResult<ArrayType> {
         self.tree.insert_one(previous_root, key, value)
     }
+
+    /// Creates a `Snapshot` pinning the given root until the snapshot is dropped.
+    /// # Errors
+    /// `Exception` generated if the root does not exist in the tree.
+    #[inline]
+    pub fn snapshot(
+        &mut self,
+        root_hash: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Snapshot<ArrayType>> {
+        self.tree.snapshot(root_hash)
+    }
+
+    /// Creates a weak `Snapshot` of the given root, which does not stop the root being reclaimed.
+    /// # Errors
+    /// `Exception` generated if the root does not exist in the tree.
+    #[inline]
+    pub fn weak_snapshot(
+        &self,
+        root_hash: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Snapshot<ArrayType>> {
+        self.tree.weak_snapshot(root_hash)
+    }
+
+    /// Gets the values associated with `keys` from the root pinned by `snapshot`.
+    /// # Errors
+    /// `Exception` generated if the `get` encounters an invalid state during tree traversal.
+    #[inline]
+    pub fn get_snapshot(
+        &self,
+        snapshot: &Snapshot<ArrayType>,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<HashMap<ArrayType, Option<ValueType>>> {
+        self.tree.get_snapshot(snapshot, keys)
+    }
+
+    /// Sets whether data nodes are addressed by their value only, letting identical values under
+    /// different keys share one data node.  See `MerkleBIT::set_value_addressed_data` for the
+    /// implications on proofs.
+    #[inline]
+    pub fn set_value_addressed_data(&mut self, value_addressed: bool) {
+        self.tree.set_value_addressed_data(value_addressed)
+    }
+
+    /// Sets whether each key is stored as a single leaf holding its value.  See
+    /// `MerkleBIT::set_combined_leaves` for the implications on proofs.
+    #[inline]
+    pub fn set_combined_leaves(&mut self, combined: bool) {
+        self.tree.set_combined_leaves(combined)
+    }
+
+    /// Sets whether the children of each branch are hashed smaller location first.  See
+    /// `MerkleBIT::set_sorted_children` for the implications on proofs.
+    #[inline]
+    pub fn set_sorted_children(&mut self, sorted: bool) {
+        self.tree.set_sorted_children(sorted)
+    }
+
+    /// Sets the tags hashed before the contents of data, leaf and branch nodes.  See
+    /// `MerkleBIT::set_domain_tags` for the effect on roots and proofs.
+    #[inline]
+    pub fn set_domain_tags(&mut self, tags: DomainTags) {
+        self.tree.set_domain_tags(tags)
+    }
+
+    /// Sets the maximum size in bytes of an encoded value accepted on insert, or zero for no limit.
+    #[inline]
+    pub fn set_max_value_bytes(&mut self, max_value_bytes: usize) {
+        self.tree.set_max_value_bytes(max_value_bytes)
+    }
+
+    /// Sets the maximum number of keys accepted by a single `get` or `insert`, or zero for no limit.
+    #[inline]
+    pub fn set_max_batch_keys(&mut self, max_batch_keys: usize) {
+        self.tree.set_max_batch_keys(max_batch_keys)
+    }
+
+    /// Sets the number of nodes an insert buffers before an intermediate `batch_write`.  See
+    /// `MerkleBIT::set_flush_every` for what a failed insert leaves behind.
+    #[inline]
+    pub fn set_flush_every(&mut self, flush_every: usize) {
+        self.tree.set_flush_every(flush_every)
+    }
+
+    /// Sets whether a panic while encoding values on insert is returned as an `Exception` of kind
+    /// `ErrorKind::WorkerPanic`.
+    #[inline]
+    pub fn set_catch_panics(&mut self, catch_panics: bool) {
+        self.tree.set_catch_panics(catch_panics)
+    }
+
+    /// Sets whether data nodes store only a hash of their value, so values cannot be read back.
+    #[inline]
+    pub fn set_commit_only(&mut self, commit_only: bool) {
+        self.tree.set_commit_only(commit_only)
+    }
+
+    /// Gets the commitment to the value of `key` under `root_hash`.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn get_commitment(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        self.tree.get_commitment(root_hash, key)
+    }
+
+    /// Sets the namespace of the root pointer used by `current_root` and `compare_and_set_root`.
+    #[inline]
+    pub fn set_root_namespace(&mut self, namespace: &[u8]) {
+        self.tree.set_root_namespace(namespace)
+    }
+
+    /// Moves encoded values of at least `min_blob_bytes` bytes into `store`, leaving only a
+    /// reference to each blob in the tree.
+    #[inline]
+    pub fn set_blob_store(
+        &mut self,
+        store: Arc<dyn BlobStore<ArrayType> + Send + Sync>,
+        min_blob_bytes: usize,
+    ) {
+        self.tree.set_blob_store(store, min_blob_bytes)
+    }
+
+    /// Calls `hook` with the location and old and new reference counts of every node whose
+    /// references change.
+    #[cfg(feature = "debug_tools")]
+    #[inline]
+    pub fn set_on_refcount_change(&mut self, hook: RefcountHook<ArrayType>) {
+        self.tree.set_on_refcount_change(hook)
+    }
+
+    /// Sets the size in bytes below which an encoded value is inlined into its leaf, or zero to
+    /// never inline values.
+    #[inline]
+    pub fn set_inline_value_bytes(&mut self, inline_value_bytes: usize) {
+        self.tree.set_inline_value_bytes(inline_value_bytes)
+    }
+
+    /// Sets whether inserting on top of a previous root missing from the tree fails, or instead
+    /// builds a fresh tree from the inserted keys.  Strict by default.
+    #[inline]
+    pub fn set_strict_previous_root(&mut self, strict: bool) {
+        self.tree.set_strict_previous_root(strict)
+    }
+
+    /// Sets whether inserting no keys fails, or instead returns the previous root or the empty
+    /// root.  Strict by default.
+    #[inline]
+    pub fn set_strict_empty_inserts(&mut self, strict: bool) {
+        self.tree.set_strict_empty_inserts(strict)
+    }
+
+    /// Sets whether nodes are reference counted, which is the default.  Trees built without
+    /// reference counting cannot be pruned per root, so `remove` fails on them.
+    #[inline]
+    pub fn set_refcounting(&mut self, enabled: bool) {
+        self.tree.set_refcounting(enabled)
+    }
+
+    /// Sets whether inserts fail with `ErrorKind::HashCollision` when they find a different node at a location.
+    #[inline]
+    pub fn set_collision_check(&mut self, enabled: bool) {
+        self.tree.set_collision_check(enabled)
+    }
+
+    /// Sets whether an in memory index from values to their keys is kept for `keys_for_value`,
+    /// building it from the stored leaves when enabled.
+    /// # Errors
+    /// `Exception` generated if the stored leaves cannot be read.
+    #[inline]
+    pub fn set_value_index(&mut self, enabled: bool) -> BinaryMerkleTreeResult<()> {
+        self.tree.set_value_index(enabled)
+    }
+
+    /// Sets whether the first root written with each leaf is kept in memory for `value_version`.
+    #[inline]
+    pub fn set_value_versioning(&mut self, enabled: bool) {
+        self.tree.set_value_versioning(enabled)
+    }
+
+    /// Gets the first root at which `key` was given the value it holds under `root`, if value
+    /// versioning is enabled.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn value_version(
+        &self,
+        root: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        self.tree.value_version(root, key)
+    }
+
+    /// Gets the statistics on data nodes shared between inserts.
+    #[inline]
+    #[must_use]
+    pub fn dedup_stats(&self) -> &DedupStats {
+        self.tree.dedup_stats()
+    }
+
+    /// Verifies an inclusion proof using the hashing configuration of this tree.
+    /// # Errors
+    /// `Exception` generated if the given proof is invalid.
+    #[inline]
+    pub fn verify_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+        value: &ValueType,
+        proof: &[(ArrayType, bool)],
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.verify_proof(root, key, value, proof)
+    }
+
+    /// Verifies an inclusion proof against the hash of the expected value, the location of the
+    /// key's data node, rather than the value itself.
+    /// # Errors
+    /// `Exception` generated if the given proof is invalid.
+    #[inline]
+    pub fn verify_inclusion_by_value_hash(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+        value_hash: &ArrayType,
+        proof: &[(ArrayType, bool)],
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree
+            .verify_inclusion_by_value_hash(root, key, value_hash, proof)
+    }
+
+    /// Rebuilds the tree under `root_hash` with `NewHasherType`, returning the new root.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn migrate_hasher<NewHasherType>(
+        &mut self,
+        root_hash: &ArrayType,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        NewHasherType: Hasher<ArrayType, HashType = NewHasherType>,
+    {
+        self.tree.migrate_hasher::<NewHasherType>(root_hash)
+    }
+
+    /// Combines two roots over disjoint keys into a single root.
+    /// # Errors
+    /// `Exception` generated if the key spaces of the roots overlap, or if an invalid state is
+    /// encountered during tree traversal.
+    #[inline]
+    pub fn merge_roots(
+        &mut self,
+        root_a: &ArrayType,
+        root_b: &ArrayType,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.merge_roots(root_a, root_b)
+    }
+
+    /// Generates an inclusion proof for `key` as a `Proof`, which has a stable binary encoding.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn generate_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+    ) -> BinaryMerkleTreeResult<Proof<ArrayType>> {
+        self.tree.generate_proof(root, key)
+    }
+
+    /// Generates an inclusion proof for `key` in the ICS23 `ExistenceProof` format.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn generate_ics23_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+        hash: HashOp,
+    ) -> BinaryMerkleTreeResult<ExistenceProof> {
+        self.tree.generate_ics23_proof(root, key, hash)
+    }
+
+    /// Gets the ICS23 `ProofSpec` the proofs from `generate_ics23_proof` are checked against.
+    /// # Errors
+    /// `Exception` generated if the hash scheme cannot be described by a `ProofSpec`.
+    #[inline]
+    pub fn ics23_proof_spec(&self, hash: HashOp) -> BinaryMerkleTreeResult<ProofSpec> {
+        self.tree.ics23_proof_spec(hash)
+    }
+
+    /// Verifies a `Proof` using the hashing configuration of this tree.
+    /// # Errors
+    /// `Exception` generated when the given proof is invalid.
+    #[inline]
+    pub fn verify_compact_proof(
+        &self,
+        root: &ArrayType,
+        proof: &Proof<ArrayType>,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.verify_compact_proof(root, proof)
+    }
+
+    /// Generates a proof of `key` in the subtree committed under `subtree_key` in the global tree.
+    /// # Errors
+    /// `Exception` generated if either key is not present.
+    #[inline]
+    pub fn generate_subtree_proof(
+        &self,
+        global_root: &ArrayType,
+        subtree_key: ArrayType,
+        key: ArrayType,
+    ) -> BinaryMerkleTreeResult<SubtreeProof<ArrayType>> {
+        self.tree
+            .generate_subtree_proof(global_root, subtree_key, key)
+    }
+
+    /// Verifies a `SubtreeProof` against `global_root`.
+    /// # Errors
+    /// `Exception` generated when either level of the proof is invalid.
+    #[inline]
+    pub fn verify_subtree_proof(
+        &self,
+        global_root: &ArrayType,
+        proof: &SubtreeProof<ArrayType>,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.verify_subtree_proof(global_root, proof)
+    }
+
+    /// Removes all items with less than 1 reference under the given root, unless `cancel` is set
+    /// before the removal completes.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal, or when
+    /// the removal is cancelled.
+    #[inline]
+    pub fn remove_cancellable(
+        &mut self,
+        root_hash: &ArrayType,
+        cancel: &AtomicBool,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.remove_cancellable(root_hash, cancel)
+    }
+
+    /// Replaces the value of a key already present under `previous_root`, reusing the rest of the
+    /// tree, and returns the new root.
+    /// # Errors
+    /// `Exception` generated if `key` is not present under `previous_root`, or if an invalid state
+    /// is encountered during tree traversal.
+    #[inline]
+    pub fn update_value(
+        &mut self,
+        previous_root: &ArrayType,
+        key: &ArrayType,
+        new_value: &ValueType,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.update_value(previous_root, key, new_value)
+    }
+
+    /// Creates a new root containing only the keys under `root_hash` satisfying `predicate`.
+    /// # Errors
+    /// `Exception` generated if no keys match `predicate`, or if an invalid state is encountered
+    /// during tree traversal.
+    #[inline]
+    pub fn filter<PredicateType>(
+        &mut self,
+        root_hash: &ArrayType,
+        predicate: PredicateType,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        PredicateType: Fn(&ArrayType, &ValueType) -> bool,
+    {
+        self.tree.filter(root_hash, predicate)
+    }
+
+    /// Splits the tree under `root_hash` into a root of the keys less than `boundary` and a root
+    /// of the keys greater than or equal to it.
+    /// # Errors
+    /// `Exception` generated if either side of `boundary` holds no keys, or an invalid state is
+    /// encountered during tree traversal.
+    #[inline]
+    pub fn split(
+        &mut self,
+        root_hash: &ArrayType,
+        boundary: &ArrayType,
+    ) -> BinaryMerkleTreeResult<(ArrayType, ArrayType)> {
+        self.tree.split(root_hash, boundary)
+    }
+
+    /// Rebuilds the tree under `root_hash` with every key replaced by `rekey_fn` of the key.
+    /// # Errors
+    /// `Exception` generated if `rekey_fn` maps two keys to the same key, or if an invalid state is
+    /// encountered during tree traversal.
+    #[inline]
+    pub fn rekey<RekeyFnType>(
+        &mut self,
+        root_hash: &ArrayType,
+        rekey_fn: RekeyFnType,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        RekeyFnType: Fn(&ArrayType) -> ArrayType,
+    {
+        self.tree.rekey(root_hash, rekey_fn)
+    }
+
+    /// Builds a new tree by merging `shards` of key and value pairs, each sorted in key order.
+    /// # Errors
+    /// `Exception` generated if the merged pairs hold a duplicate key or a shard is not sorted.
+    #[inline]
+    pub fn merge_sorted_shards<ShardType>(
+        &mut self,
+        shards: Vec<ShardType>,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        ShardType: Iterator<Item = (ArrayType, ValueType)>,
+    {
+        self.tree.merge_sorted_shards(shards)
+    }
+
+    /// Gets the values of `keys` under `root_hash` as shared handles to their encoded bytes, without
+    /// copying or decoding them.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn get_ref(
+        &self,
+        root_hash: &ArrayType,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<HashMap<ArrayType, Option<SharedValue<ArrayType>>>> {
+        self.tree.get_ref(root_hash, keys)
+    }
+
+    /// Recomputes the root hash under `root_hash` from its keys, values and shape, ignoring stored
+    /// locations and reference counts.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn structural_root(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.structural_root(root_hash)
+    }
+
+    /// Checks that every node under `root_hash` is present and that they hash to `root_hash`.
+    /// # Errors
+    /// `Exception` generated if the tree does not match `root_hash`, or if an invalid state is
+    /// encountered during tree traversal.
+    #[inline]
+    pub fn verify_tree(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<()> {
+        self.tree.verify_tree(root_hash)
+    }
+
+    /// Checks that the tree under `root_hash` is intact, unless `cancel` is set before the check
+    /// completes.
+    /// # Errors
+    /// `Exception` generated if the tree does not match `root_hash`, if an invalid state is
+    /// encountered during tree traversal, or when the check is cancelled.
+    #[inline]
+    pub fn verify_tree_cancellable(
+        &self,
+        root_hash: &ArrayType,
+        cancel: &AtomicBool,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.verify_tree_cancellable(root_hash, cancel)
+    }
+
+    /// Finds the node covering exactly the keys whose first `prefix_bits` bits match `prefix`.
+    /// # Errors
+    /// `Exception` generated if the prefix is invalid, or if an invalid state is encountered
+    /// during tree traversal.
+    #[inline]
+    pub fn subtree_root(
+        &self,
+        root_hash: &ArrayType,
+        prefix: &[u8],
+        prefix_bits: u32,
+    ) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        self.tree.subtree_root(root_hash, prefix, prefix_bits)
+    }
+
+    /// Lazily finds the keys under `new_root` that are new or changed since `base_root`, in
+    /// ascending order, skipping subtrees the two roots share.
+    #[inline]
+    pub fn changed_keys_since<'a>(
+        &'a self,
+        base_root: &ArrayType,
+        new_root: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<ArrayType>> + 'a {
+        self.tree.changed_keys_since(base_root, new_root)
+    }
+
+    /// Lazily gets the keys under `root_a` absent from `root_b`, in ascending order, skipping the
+    /// subtrees the two roots share.
+    #[inline]
+    pub fn keys_only_in<'a>(
+        &'a self,
+        root_a: &ArrayType,
+        root_b: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<ArrayType>> + 'a {
+        self.tree.keys_only_in(root_a, root_b)
+    }
+
+    /// Checks whether `key` is present under `root_hash`.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn contains_key(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<bool> {
+        self.tree.contains_key(root_hash, key)
+    }
+
+    /// Checks whether `root_hash` is a root known to the database.
+    /// # Errors
+    /// `Exception` generated if the node cannot be read.
+    #[inline]
+    pub fn has_root(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<bool> {
+        self.tree.has_root(root_hash)
+    }
+
+    /// Gets every key and value under `root_hash` lazily, in ascending key order.  After an error
+    /// the iterator ends.
+    #[inline]
+    pub fn iter<'a>(
+        &'a self,
+        root_hash: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<(ArrayType, ValueType)>> + 'a {
+        self.tree.iter(root_hash)
+    }
+
+    /// Gets every key and value under `root_hash` lazily, in ascending key order, ending with an
+    /// `Exception` of kind `ErrorKind::Cancelled` once `cancel` is set.
+    #[inline]
+    pub fn iter_cancellable<'a>(
+        &'a self,
+        root_hash: &ArrayType,
+        cancel: &'a AtomicBool,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<(ArrayType, ValueType)>> + 'a {
+        self.tree.iter_cancellable(root_hash, cancel)
+    }
+
+    /// Gets every key and value under `root_hash` along with its inclusion proof, in ascending key
+    /// order, reading each node once.
+    #[inline]
+    pub fn iter_with_proofs<'a>(
+        &'a self,
+        root_hash: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<ProvenEntry<ArrayType, ValueType>>> + 'a {
+        self.tree.iter_with_proofs(root_hash)
+    }
+
+    /// Turns this tree into a `HashTreeReader` over the same nodes, which can be shared between
+    /// threads.
+    /// # Errors
+    /// None.
+    #[inline]
+    pub fn into_reader(self) -> BinaryMerkleTreeResult<HashTreeReader<ArrayType, ValueType>> {
+        self.tree.into_reader()
+    }
+
+    /// Recomputes the reference counts of the nodes reachable from `live_roots`, with one entry
+    /// per outstanding reference to a root.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn recount_references(&mut self, live_roots: &[ArrayType]) -> BinaryMerkleTreeResult<()> {
+        self.tree.recount_references(live_roots)
+    }
+
+    /// Gets the encoded bytes of the value of `key` under `root_hash`, without decoding them.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn get_raw(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<Vec<u8>>> {
+        self.tree.get_raw(root_hash, key)
+    }
+
+    /// Gets a reader over the encoded bytes of the value of `key` under `root_hash`, for
+    /// streaming the value without decoding it.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn get_value_reader(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<impl Read>> {
+        self.tree.get_value_reader(root_hash, key)
+    }
+
+    /// Gets the locations of the nodes `depth` levels below `root_hash`, including leaves found
+    /// above that depth.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn nodes_at_depth(
+        &self,
+        root_hash: &ArrayType,
+        depth: usize,
+    ) -> BinaryMerkleTreeResult<Vec<ArrayType>> {
+        self.tree.nodes_at_depth(root_hash, depth)
+    }
+
+    /// Gets the number of leaves under each of the nodes `depth` levels below `root_hash`.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn subtree_size_histogram(
+        &self,
+        root_hash: &ArrayType,
+        depth: usize,
+    ) -> BinaryMerkleTreeResult<Vec<(ArrayType, u64)>> {
+        self.tree.subtree_size_histogram(root_hash, depth)
+    }
+
+    /// Gets the decoded contents of the node stored at `location`, for tools walking the tree.
+    /// # Errors
+    /// `Exception` generated if the node cannot be read.
+    #[inline]
+    pub fn get_node_at(
+        &self,
+        location: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<TreeNodeVariant<ArrayType>>> {
+        self.tree.get_node_at(location)
+    }
+
+    /// Gets the keys under `root_hash` that currently hold `value`, using the value index.
+    /// # Errors
+    /// `Exception` generated if the value index is not enabled, or if an invalid state is
+    /// encountered during tree traversal.
+    #[inline]
+    pub fn keys_for_value(
+        &self,
+        root_hash: &ArrayType,
+        value: &ValueType,
+    ) -> BinaryMerkleTreeResult<Vec<ArrayType>> {
+        self.tree.keys_for_value(root_hash, value)
+    }
+
+    /// Verifies a `Proof` against `root_hash` and stores the nodes it proves, so the proven key
+    /// can be read locally.
+    /// # Errors
+    /// `Exception` generated when the given proof is invalid.
+    #[inline]
+    pub fn import_proof(
+        &mut self,
+        root_hash: &ArrayType,
+        proof: &Proof<ArrayType>,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.import_proof(root_hash, proof)
+    }
+
+    /// Rebuilds the branches of a tree from its surviving leaves, returning the recovered root.
+    /// # Errors
+    /// `Exception` generated if a location does not hold a leaf, or two leaves share a key.
+    #[inline]
+    pub fn rebuild_from_leaves(
+        &mut self,
+        leaf_locations: &[ArrayType],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.rebuild_from_leaves(leaf_locations)
+    }
+
+    /// Counts the nodes reachable from both `root_a` and `root_b`.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn shared_node_count(
+        &self,
+        root_a: &ArrayType,
+        root_b: &ArrayType,
+    ) -> BinaryMerkleTreeResult<usize> {
+        self.tree.shared_node_count(root_a, root_b)
+    }
+
+    /// Counts the distinct data node locations referenced by the leaves under `root_hash`: the
+    /// distinct (key, value) pairs by default, or the distinct values under value addressing.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn distinct_value_count(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<usize> {
+        self.tree.distinct_value_count(root_hash)
+    }
+
+    /// Gets the maximum depth of the tree, which may be larger than the depth it was opened with
+    /// if the database recorded a larger one.
+    #[inline]
+    #[must_use]
+    pub const fn depth(&self) -> usize {
+        self.tree.depth()
+    }
+
+    /// Gets the depth of the tree under `root_hash`, the number of branches on its longest path
+    /// from the root to a leaf.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn depth_of(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<usize> {
+        self.tree.depth_of(root_hash)
+    }
+
+    /// Gets the locations of every stored node not reachable from any of `live_roots`, without removing anything.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn find_orphans(&self, live_roots: &[ArrayType]) -> BinaryMerkleTreeResult<Vec<ArrayType>> {
+        self.tree.find_orphans(live_roots)
+    }
+
+    /// Copies every stored node into `target`, returning the number copied.
+    /// # Errors
+    /// `Exception` generated if a node cannot be read or written to `target`.
+    #[inline]
+    pub fn backup_to<TargetType>(&self, target: &mut TargetType) -> BinaryMerkleTreeResult<usize>
+    where
+        TargetType: Database<ArrayType, NodeType = TreeNode<ArrayType>>,
+    {
+        self.tree.backup_to(target)
+    }
+
+    /// Gets up to `limit` entries sorting after `after`, with the cursor for the next page.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn iter_from(
+        &self,
+        root_hash: &ArrayType,
+        after: Option<ArrayType>,
+        limit: usize,
+    ) -> BinaryMerkleTreeResult<EntryPage<ArrayType, ValueType>> {
+        self.tree.iter_from(root_hash, after, limit)
+    }
+
+    /// Writes a human-readable rendering of the tree under `root_hash` to `writer`, for troubleshooting.
+    /// # Errors
+    /// `Exception` generated if writing fails, or when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn debug_dump(
+        &self,
+        root_hash: &ArrayType,
+        writer: impl Write,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.debug_dump(root_hash, writer)
+    }
+
+    /// Writes the canonical JSON export of the tree under `root_hash` to `writer`, mapping hex keys to base64 values.
+    /// # Errors
+    /// `Exception` generated if writing fails, or when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn write_json(
+        &self,
+        root_hash: &ArrayType,
+        writer: impl Write,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.write_json(root_hash, writer)
+    }
+
+    /// Gets the canonical JSON export of the tree under `root_hash` as a `String`.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn to_json(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<String> {
+        self.tree.to_json(root_hash)
+    }
+
+    /// Inserts every entry of a JSON export into a new root, checking it against the recorded root.
+    /// # Errors
+    /// `Exception` generated if `json` is not a valid export, or its root does not match its entries.
+    #[inline]
+    pub fn import_json(&mut self, json: &str) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.import_json(json)
+    }
+}
+
+impl<ValueType, ArrayType> HashTree<ArrayType, ValueType>
+where
+    ValueType: Encode + Decode,
+    ArrayType: Array,
+    TreeNode<ArrayType>: Encode,
+{
+    /// Gets the number of bytes occupied by the encoded nodes reachable from `root_hash`, counting
+    /// shared nodes once.
+    /// # Errors
+    /// `Exception` generated if a node is missing, or when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn storage_bytes(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<u64> {
+        self.tree.storage_bytes(root_hash)
+    }
+}
+
+impl<ValueType, ArrayType> HashTree<ArrayType, ValueType>
+where
+    ValueType: Encode + Decode + Ord,
+    ArrayType: Array,
+{
+    /// Gets every key and value under `root_hash` ordered by value, ascending or `descending`.
+    /// Only available when `ValueType` is `Ord`.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn entries_by_value(
+        &self,
+        root_hash: &ArrayType,
+        descending: bool,
+    ) -> BinaryMerkleTreeResult<Vec<(ArrayType, ValueType)>> {
+        self.tree.entries_by_value(root_hash, descending)
+    }
 }
@@ -13,7 +13,8 @@
 #![allow(clippy::else_if_without_else)]
 #![allow(clippy::module_name_repetitions)]
 
-#![forbid(unsafe_code)]
+#![cfg_attr(not(feature = "use_mmap"), forbid(unsafe_code))]
+#![cfg_attr(feature = "use_mmap", deny(unsafe_code))]
 
 //! # Merkle Binary Indexed Tree
 //! ## Introduction
@@ -45,6 +46,13 @@
 //! a copy of the `key` used during creation to determine if a branch should be inserted before it, and
 //! a `count` of the nodes under that branch.
 //!
+//! Keys are read as big-endian bit strings: bit `0` is the most significant bit of the first byte
+//! of the key.  Every key on the `zero` side of a branch therefore sorts before every key on its
+//! `one` side, and walking the tree from `zero` to `one` visits keys in ascending byte order, the
+//! same order given by `Ord` on byte arrays.  Integer keys only keep their numeric order when
+//! encoded with `to_be_bytes`.  The byte order a `Hasher` writes its output in only affects node
+//! locations, which are never ordered.
+//!
 //! A `Leaf` node contains an associated `key` for comparison, and a pointer to a `Data` node for retrieving
 //! information regarding access to the data.  This is separate from the `Data` node for the purpose of only
 //! accessing data information if data should be retrieved.
@@ -81,6 +89,10 @@ pub mod constants;
 pub mod hash_tree;
 /// Contains the actual operations of inserting, getting, and removing items from a tree.
 pub mod merkle_bit;
+/// Contains a read only view of a tree that can be shared between threads.
+pub mod merkle_bit_reader;
+/// Contains a sparse view of a tree holding only the nodes of imported proofs.
+pub mod partial_tree;
 /// Contains the traits necessary for tree operations
 pub mod traits;
 /// Contains a collection of structs for representing locations within the tree.
@@ -1,26 +1,61 @@
+use std::collections::{BTreeSet, VecDeque};
 #[cfg(not(any(feature = "use_hashbrown")))]
-use std::collections::HashMap;
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::io::{Cursor, Read, Write};
 use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
 
 #[cfg(feature = "use_hashbrown")]
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 
+use crate::constants::ADDRESS_LEN;
+use crate::merkle_bit_reader::MerkleBITReader;
 use crate::traits::{
-    Array, Branch, Data, Database, Decode, Encode, Exception, Hasher, Leaf, Node, NodeVariant,
+    Array, BlobStore, Branch, CachingDatabase, Data, Database, Decode, Encode, ErrorKind,
+    Exception, Hasher, KeyOrder, Leaf, Node, NodeVariant, SharedDatabase,
 };
+use crate::tree::tree_branch::TreeBranch;
+use crate::tree::tree_data::TreeData;
+use crate::tree::tree_leaf::TreeLeaf;
+use crate::tree::tree_node::TreeNode;
+use crate::utils::hash_scheme::{DomainTags, HashScheme};
+use crate::utils::ics23::{ExistenceProof, HashOp, ProofSpec};
+use crate::utils::json_export::{parse_json_export, to_base64};
+use crate::utils::key_order::BigEndianOrder;
+use crate::utils::proof::{committed_root, Proof, ProofSibling, SubtreeProof};
+use crate::utils::snapshot::Snapshot;
+use crate::utils::tree_build_scratch::TreeBuildScratch;
 use crate::utils::tree_cell::TreeCell;
+use crate::utils::tree_options::TreeOptions;
 use crate::utils::tree_ref::TreeRef;
 use crate::utils::tree_utils::{
-    calc_min_split_index, check_descendants, choose_zero, generate_leaf_map,
-    generate_tree_ref_queue, split_pairs,
+    calc_min_split_index_ordered, check_descendants_ordered, choose_zero_ordered,
+    common_prefix_bits, compare_prefix, generate_leaf_map, generate_tree_ref_queue_ordered,
+    prefix_matches, split_pairs_ordered, to_hex, tree_depth_ordered,
 };
 
 /// A generic `Result` from an operation involving a `MerkleBIT`
 pub type BinaryMerkleTreeResult<T> = Result<T, Exception>;
 
+/// A `MerkleBIT` keyed by 20 byte Ethereum style addresses, using the standard tree types.  Node
+/// locations are 20 bytes as well, so `HasherType` should produce a full 20 byte hash: with the
+/// `use_keccak` feature, `Keccak256Hasher` keeps the last 20 bytes of the Keccak-256 digest as
+/// in address derivation.
+pub type MerkleBIT20<DatabaseType, HasherType, ValueType = Vec<u8>> = MerkleBIT<
+    DatabaseType,
+    TreeBranch<[u8; ADDRESS_LEN]>,
+    TreeLeaf<[u8; ADDRESS_LEN]>,
+    TreeData,
+    TreeNode<[u8; ADDRESS_LEN]>,
+    HasherType,
+    ValueType,
+    [u8; ADDRESS_LEN],
+>;
+
 /// The `MerkleBIT` structure relies on many specified types:
 /// # Required Type Annotations
 /// * **`DatabaseType`**: The type to use for database-like operations.  `DatabaseType` must implement the `Database` trait.
@@ -31,6 +66,7 @@ pub type BinaryMerkleTreeResult<T> = Result<T, Exception>;
 /// * **`HasherType`**: The type of hasher to use for hashing locations on the tree.  `HasherType` must implement the `Hasher` trait.
 /// * **`ValueType`**: The type to return from a get.  `ValueType` must implement the `Encode` and `Decode` traits.
 /// * **`ArrayType`**: The type that stores the keys and hash results.  `ArrayType` must implement the `Array` trait.
+/// * **`KeyOrderType`**: The order the tree arranges keys in, `BigEndianOrder` unless specified.  `KeyOrderType` must implement the `KeyOrder` trait.
 /// # Properties
 /// * **db**: The database to store and retrieve values.
 /// * **depth**: The maximum permitted depth of the tree.
@@ -43,6 +79,7 @@ pub struct MerkleBIT<
     HasherType,
     ValueType,
     ArrayType,
+    KeyOrderType = BigEndianOrder,
 > where
     DatabaseType: Database<ArrayType, NodeType = NodeType>,
     BranchType: Branch<ArrayType>,
@@ -52,28 +89,73 @@ pub struct MerkleBIT<
     HasherType: Hasher<ArrayType>,
     ArrayType: Array,
     ValueType: Decode + Encode,
+    KeyOrderType: KeyOrder<ArrayType>,
 {
     /// The database to store tree nodes.
     db: DatabaseType,
     /// The maximum depth of the tree.
     depth: usize,
+    /// Whether `depth` is the depth recorded in the database.
+    depth_recorded: bool,
+    /// The roots pinned by outstanding `Snapshot`s.
+    snapshots: Vec<(ArrayType, Weak<()>)>,
+    /// Incremented by every removal that deletes nodes, so weak `Snapshot`s can tell whether their
+    /// root may have been reclaimed since they were taken.
+    removal_epoch: u64,
+    /// The scheme used for computing node locations and the other settings of the tree.
+    options: TreeOptions,
+    /// Statistics on data nodes shared between inserts.
+    dedup_stats: DedupStats,
+    /// The nodes the current insert has buffered since it last wrote them.
+    buffered_nodes: usize,
+    /// The references of the data nodes written since the last `batch_write`, by location.  A
+    /// database deferring its writes to `batch_write` cannot read these nodes back yet, so data
+    /// nodes shared by several keys of one batch count their references here.
+    pending_data: HashMap<ArrayType, u64>,
+    /// The keys inserted with each value, by the value addressed location of the value, when the
+    /// value index is enabled.
+    value_index: Option<HashMap<ArrayType, BTreeSet<ArrayType>>>,
+    /// The first root written with each leaf, by the location of the leaf, when value versioning
+    /// is enabled.
+    value_versions: Option<HashMap<ArrayType, ArrayType>>,
+    /// The store holding values too large to keep in data nodes, if any.
+    blob_store: Option<Arc<dyn BlobStore<ArrayType> + Send + Sync>>,
+    /// Encoded values of at least this many bytes are moved to the blob store.
+    min_blob_bytes: usize,
+    /// The hook observing reference count changes, if any.
+    #[cfg(feature = "debug_tools")]
+    on_refcount_change: Option<RefcountHook<ArrayType>>,
+    // The markers name types the tree never holds, so they use `fn() -> T` to leave whether the
+    // tree is `Send` or `Sync` to the fields it does hold
     /// Marker for dealing with `BranchType`.
-    branch: PhantomData<BranchType>,
+    branch: PhantomData<fn() -> BranchType>,
     /// Marker for dealing with `LeafType`.
-    leaf: PhantomData<LeafType>,
+    leaf: PhantomData<fn() -> LeafType>,
     /// Marker for dealing with `DataType`.
-    data: PhantomData<DataType>,
+    data: PhantomData<fn() -> DataType>,
     /// Marker for dealing with `NodeType`.
-    node: PhantomData<NodeType>,
+    node: PhantomData<fn() -> NodeType>,
     /// Marker for dealing with `HasherType`.
-    hasher: PhantomData<HasherType>,
+    hasher: PhantomData<fn() -> HasherType>,
     /// Marker for dealing with `ValueType`.
-    value: PhantomData<ValueType>,
+    value: PhantomData<fn() -> ValueType>,
     /// Marker for dealing with `ArrayType`.
-    array: PhantomData<ArrayType>,
+    array: PhantomData<fn() -> ArrayType>,
+    /// Marker for dealing with `KeyOrderType`.
+    key_order: PhantomData<fn() -> KeyOrderType>,
 }
 
-impl<DatabaseType, BranchType, LeafType, DataType, NodeType, HasherType, ValueType, ArrayType>
+impl<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >
     MerkleBIT<
         DatabaseType,
         BranchType,
@@ -83,6 +165,7 @@ impl<DatabaseType, BranchType, LeafType, DataType, NodeType, HasherType, ValueTy
         HasherType,
         ValueType,
         ArrayType,
+        KeyOrderType,
     >
 where
     DatabaseType: Database<ArrayType, NodeType = NodeType>,
@@ -93,6 +176,7 @@ where
     HasherType: Hasher<ArrayType, HashType = HasherType>,
     ValueType: Decode + Encode,
     ArrayType: Array,
+    KeyOrderType: KeyOrder<ArrayType>,
 {
     /// Create a new `MerkleBIT` from a saved database
     /// # Errors
@@ -100,27 +184,70 @@ where
     #[inline]
     pub fn new(path: &PathBuf, depth: usize) -> BinaryMerkleTreeResult<Self> {
         let db = DatabaseType::open(path)?;
-        Ok(Self {
-            db,
-            depth,
-            branch: PhantomData,
-            leaf: PhantomData,
-            data: PhantomData,
-            node: PhantomData,
-            hasher: PhantomData,
-            value: PhantomData,
-            array: PhantomData,
-        })
+        Self::from_db(db, depth)
     }
 
-    /// Create a new `MerkleBIT` from an already opened database
+    /// Create a new `MerkleBIT` from a saved database, opened with room for about `capacity`
+    /// nodes.  See `Database::open_with_capacity`.
     /// # Errors
-    /// None.
+    /// `Exception` generated if the `open` fails.
+    #[inline]
+    pub fn new_with_capacity(
+        path: &PathBuf,
+        depth: usize,
+        capacity: usize,
+    ) -> BinaryMerkleTreeResult<Self> {
+        let db = DatabaseType::open_with_capacity(path, capacity)?;
+        Self::from_db(db, depth)
+    }
+
+    /// Create a new `MerkleBIT` from a saved database, separating its node locations from those
+    /// of other trees in the same database with the prefix `domain`.  An empty `domain` gives the
+    /// same locations as `new`.  Proofs from a tree with a domain must be checked with
+    /// `verify_proof` on a tree with the same domain, not with `verify_inclusion_proof`.
+    ///
+    /// Giving each tenant its own domain is how to keep their nodes physically apart: trees with
+    /// different domains never share a node, while roots within one domain share every subtree
+    /// they have in common.  There is no option to copy shared subtrees for a new root instead,
+    /// since node locations are content addressed and a copy would land on the same locations.
+    /// # Errors
+    /// `Exception` generated if the `open` fails, or if `domain` is longer than `u32::MAX` bytes.
+    #[inline]
+    pub fn new_with_domain(
+        path: &PathBuf,
+        depth: usize,
+        domain: &[u8],
+    ) -> BinaryMerkleTreeResult<Self> {
+        let db = DatabaseType::open(path)?;
+        Self::from_db_with_domain(db, depth, domain)
+    }
+
+    /// Create a new `MerkleBIT` from an already opened database.  If the database records a
+    /// larger depth than `depth` for the trees it holds, the recorded depth is used instead, so a
+    /// tree built with a larger depth can be read without exceeding the depth limit.  The depth
+    /// of the tree is recorded on its first insert.
+    /// # Errors
+    /// `Exception` generated if the recorded depth cannot be read.
     #[inline]
     pub fn from_db(db: DatabaseType, depth: usize) -> BinaryMerkleTreeResult<Self> {
+        let stored_depth = db.get_depth()?;
+        let depth = stored_depth.map_or(depth, |stored| stored.max(depth));
         Ok(Self {
             db,
             depth,
+            depth_recorded: stored_depth == Some(depth),
+            snapshots: Vec::new(),
+            removal_epoch: 0,
+            options: TreeOptions::new(),
+            dedup_stats: DedupStats::default(),
+            buffered_nodes: 0,
+            pending_data: HashMap::new(),
+            value_index: None,
+            value_versions: None,
+            blob_store: None,
+            min_blob_bytes: 0,
+            #[cfg(feature = "debug_tools")]
+            on_refcount_change: None,
             branch: PhantomData,
             leaf: PhantomData,
             data: PhantomData,
@@ -128,30 +255,270 @@ where
             hasher: PhantomData,
             value: PhantomData,
             array: PhantomData,
+            key_order: PhantomData,
         })
     }
 
+    /// Create a new `MerkleBIT` from an already opened database, separating its node locations
+    /// from those of other trees in the same database with the prefix `domain`.
+    /// # Errors
+    /// `Exception` generated if `domain` is longer than `u32::MAX` bytes.
+    #[inline]
+    pub fn from_db_with_domain(
+        db: DatabaseType,
+        depth: usize,
+        domain: &[u8],
+    ) -> BinaryMerkleTreeResult<Self> {
+        let mut tree = Self::from_db(db, depth)?;
+        tree.options.scheme_mut().set_domain(domain)?;
+        Ok(tree)
+    }
+
+    /// Create a new `MerkleBIT` from a saved database, configured with `options` instead of the
+    /// defaults.  Since no option is recorded in the database, this is how to reopen a tree with
+    /// the options it was written with.
+    /// # Errors
+    /// `Exception` generated if the `open` fails.
+    #[inline]
+    pub fn new_with_options(
+        path: &PathBuf,
+        depth: usize,
+        options: TreeOptions,
+    ) -> BinaryMerkleTreeResult<Self> {
+        let db = DatabaseType::open(path)?;
+        Self::from_db_with_options(db, depth, options)
+    }
+
+    /// Create a new `MerkleBIT` from an already opened database, configured with `options`
+    /// instead of the defaults.
+    /// # Errors
+    /// `Exception` generated if the recorded depth cannot be read.
+    #[inline]
+    pub fn from_db_with_options(
+        db: DatabaseType,
+        depth: usize,
+        options: TreeOptions,
+    ) -> BinaryMerkleTreeResult<Self> {
+        let mut tree = Self::from_db(db, depth)?;
+        tree.options = options;
+        Ok(tree)
+    }
+
+    /// Gets the options of the tree, as set by its constructor and setters.
+    #[inline]
+    #[must_use]
+    pub const fn options(&self) -> &TreeOptions {
+        &self.options
+    }
+
+    /// Gets the root of a tree holding no keys, the all zero location, at which no node is ever
+    /// stored.  Every method taking a root treats it as a tree without keys: gets find nothing,
+    /// iterations and traversals visit nothing, it verifies and snapshots without reading the
+    /// database, and merging it with a root gives that root.  Inserting on top of it builds a
+    /// fresh tree, and removing it does nothing.
+    #[inline]
+    #[must_use]
+    pub fn empty_root() -> ArrayType {
+        ArrayType::default()
+    }
+
+    /// Gets the maximum depth of the tree, which may be larger than the depth it was opened with
+    /// if the database recorded a larger one.
+    #[inline]
+    #[must_use]
+    pub const fn depth(&self) -> usize {
+        self.depth
+    }
+
     /// Get items from the `MerkleBIT`.  Returns a map of `Option`s which may include the corresponding values.
     /// # Errors
     /// `Exception` generated when an invalid state is encountered during tree traversal.
+    /// An `Exception` of kind `ErrorKind::BatchTooLarge` is generated if there are more `keys` than
+    /// the maximum batch size.
     #[inline]
     pub fn get(
         &self,
         root_hash: &ArrayType,
         keys: &mut [ArrayType],
     ) -> BinaryMerkleTreeResult<HashMap<ArrayType, Option<ValueType>>> {
-        if keys.is_empty() {
-            return Ok(HashMap::new());
+        self.check_batch_size(keys.len())?;
+        let mut leaf_map = generate_leaf_map(keys);
+        for leaf in self.get_leaves(root_hash, keys)? {
+            let value = ValueType::decode(&self.get_leaf_value(&leaf)?)?;
+            leaf_map.insert(*leaf.get_key(), Some(value));
         }
+        Ok(leaf_map)
+    }
 
+    /// Get items from the `MerkleBIT` along with the depth of the leaf each was found at, where the
+    /// root is at depth zero.  Keys sharing long prefixes with other keys sit deeper in the tree.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    /// An `Exception` of kind `ErrorKind::BatchTooLarge` is generated if there are more `keys` than
+    /// the maximum batch size.
+    #[inline]
+    pub fn get_with_depth(
+        &self,
+        root_hash: &ArrayType,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<HashMap<ArrayType, Option<(ValueType, usize)>>> {
+        self.check_batch_size(keys.len())?;
         let mut leaf_map = generate_leaf_map(keys);
+        for (leaf, depth) in self.get_leaves_with_depth(root_hash, keys)? {
+            let value = ValueType::decode(&self.get_leaf_value(&leaf)?)?;
+            leaf_map.insert(*leaf.get_key(), Some((value, depth)));
+        }
+        Ok(leaf_map)
+    }
 
-        keys.sort();
+    /// Get items from the `MerkleBIT` like `get`, but without failing the whole batch on a corrupt
+    /// node.  A key whose path or value reaches a corrupt or unreadable node has that `Exception`
+    /// as its result, while the other keys still resolve to their values or `None`.
+    /// # Errors
+    /// An `Exception` of kind `ErrorKind::BatchTooLarge` is generated if there are more `keys` than
+    /// the maximum batch size.
+    #[inline]
+    pub fn get_with_errors(
+        &self,
+        root_hash: &ArrayType,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<HashMap<ArrayType, BinaryMerkleTreeResult<Option<ValueType>>>> {
+        self.check_batch_size(keys.len())?;
+        let mut failures = HashMap::new();
+        let (leaves, _) = self.find_leaves(root_hash, keys, false, Some(&mut failures))?;
+        let mut results = keys
+            .iter()
+            .map(|key| (*key, Ok(None)))
+            .collect::<HashMap<_, _>>();
+        for (leaf, _) in leaves {
+            let value = self
+                .get_leaf_value(&leaf)
+                .and_then(|encoded| ValueType::decode(&encoded));
+            results.insert(*leaf.get_key(), value.map(Some));
+        }
+        for (key, error) in failures {
+            results.insert(key, Err(error));
+        }
+        Ok(results)
+    }
 
-        let root_node = if let Some(n) = self.db.get_node(*root_hash)? {
-            n
-        } else {
-            return Ok(leaf_map);
+    /// Get items from the `MerkleBIT` in the order of `keys`.  Returns a value for each key, so a key
+    /// given more than once has its value repeated at each position.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    /// An `Exception` of kind `ErrorKind::BatchTooLarge` is generated if there are more `keys` than
+    /// the maximum batch size.
+    #[inline]
+    pub fn get_ordered(
+        &self,
+        root_hash: &ArrayType,
+        keys: &[ArrayType],
+    ) -> BinaryMerkleTreeResult<Vec<Option<ValueType>>> {
+        self.check_batch_size(keys.len())?;
+        let mut unique_keys = keys.to_vec();
+        unique_keys.sort();
+        unique_keys.dedup();
+        let mut encoded_values = HashMap::with_capacity(unique_keys.len());
+        for leaf in self.get_leaves(root_hash, &mut unique_keys)? {
+            encoded_values.insert(*leaf.get_key(), self.get_leaf_value(&leaf)?);
+        }
+        keys.iter()
+            .map(|key| {
+                encoded_values
+                    .get(key)
+                    .map(|encoded| ValueType::decode(encoded))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    /// Checks that a batch of `keys` keys is within the maximum batch size, before anything
+    /// proportional to the batch is allocated.
+    /// # Errors
+    /// `Exception` of kind `ErrorKind::BatchTooLarge` generated if the batch is too large.
+    fn check_batch_size(&self, keys: usize) -> BinaryMerkleTreeResult<()> {
+        if self.options.max_batch_keys() != 0 && keys > self.options.max_batch_keys() {
+            return Err(Exception::with_kind(
+                ErrorKind::BatchTooLarge {
+                    keys,
+                    limit: self.options.max_batch_keys(),
+                },
+                "Batch has more keys than the maximum batch size",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Encodes `value` for an insert, catching a panic in `ValueType::encode` if the tree is set to
+    /// catch panics.
+    /// # Errors
+    /// `Exception` generated if the value cannot be encoded, of kind `ErrorKind::WorkerPanic` if
+    /// encoding it panicked.
+    fn encode_value(&self, value: &ValueType) -> BinaryMerkleTreeResult<Vec<u8>> {
+        if !self.options.catch_panics() {
+            return value.encode();
+        }
+        panic::catch_unwind(AssertUnwindSafe(|| value.encode()))
+            .unwrap_or_else(|payload| Err(Exception::worker_panic(payload.as_ref())))
+    }
+
+    /// Finds the leaves of the given keys under `root_hash`.  Sorts `keys`, and skips keys that are
+    /// not present in the tree.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    fn get_leaves(
+        &self,
+        root_hash: &ArrayType,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<Vec<LeafType>> {
+        Ok(self
+            .get_leaves_with_depth(root_hash, keys)?
+            .into_iter()
+            .map(|(leaf, _)| leaf)
+            .collect())
+    }
+
+    /// Finds the leaves of the given keys under `root_hash`, along with the depth of each.  Sorts
+    /// `keys`, and skips keys that are not present in the tree.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    fn get_leaves_with_depth(
+        &self,
+        root_hash: &ArrayType,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<Vec<(LeafType, usize)>> {
+        Ok(self.find_leaves(root_hash, keys, false, None)?.0)
+    }
+
+    /// Finds the leaves of the given keys under `root_hash` like `get_leaves_with_depth`.  When
+    /// `keep_root` is set and the root is a branch, a copy of the root node is returned as well,
+    /// so an insert can build on it without reading it from the database a second time.  When
+    /// `failures` is given, a corrupt or unreadable node fails only the keys passing through it,
+    /// which are recorded in `failures` while the traversal carries on with the other keys.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    fn find_leaves(
+        &self,
+        root_hash: &ArrayType,
+        keys: &mut [ArrayType],
+        keep_root: bool,
+        mut failures: Option<&mut HashMap<ArrayType, Exception>>,
+    ) -> BinaryMerkleTreeResult<FoundLeaves<LeafType, NodeType>> {
+        let mut leaves = Vec::with_capacity(keys.len());
+        let mut kept_root = None;
+        if keys.is_empty() {
+            return Ok((leaves, kept_root));
+        }
+
+        KeyOrderType::sort_keys(keys);
+
+        let root_node = match self.db.get_node(*root_hash) {
+            Ok(Some(n)) => n,
+            Ok(None) => return Ok((leaves, kept_root)),
+            Err(e) => {
+                Self::fail_keys(&mut failures, keys, e)?;
+                return Ok((leaves, kept_root));
+            }
         };
 
         let mut cell_queue = VecDeque::with_capacity(keys.len());
@@ -163,145 +530,532 @@ where
 
         while let Some(tree_cell) = cell_queue.pop_front() {
             if tree_cell.depth > self.depth {
-                return Err(Exception::new("Depth of merkle tree exceeded"));
+                Self::fail_keys(
+                    &mut failures,
+                    tree_cell.keys,
+                    Exception::with_kind(ErrorKind::DepthExceeded, "Depth of merkle tree exceeded"),
+                )?;
+                continue;
             }
 
             let node = tree_cell.node;
+            let references = node.get_references();
 
             match node.get_variant() {
                 NodeVariant::Branch(branch) => {
-                    let (_, zero, one, branch_split_index, branch_key) = branch.decompose();
-                    let min_split_index = calc_min_split_index(tree_cell.keys, &branch_key)?;
-                    let descendants = check_descendants(
+                    if keep_root && tree_cell.depth == 0 {
+                        let mut root =
+                            NodeType::new(NodeVariant::Branch(Self::copy_branch(&branch)));
+                        root.set_references(references);
+                        kept_root = Some(root);
+                    }
+                    self.queue_children(
+                        branch,
                         tree_cell.keys,
-                        branch_split_index,
-                        &branch_key,
-                        min_split_index,
+                        tree_cell.depth,
+                        &mut cell_queue,
+                        &mut failures,
                     )?;
-                    if descendants.is_empty() {
-                        continue;
-                    }
-
-                    let (zeros, ones) = split_pairs(descendants, branch_split_index)?;
-
-                    if let Some(one_node) = self.db.get_node(one)? {
-                        if !ones.is_empty() {
-                            let new_cell = TreeCell::new::<BranchType, LeafType, DataType>(
-                                one,
-                                ones,
-                                one_node,
-                                tree_cell.depth + 1,
-                            );
-                            cell_queue.push_front(new_cell);
-                        }
-                    }
-
-                    if let Some(zero_node) = self.db.get_node(zero)? {
-                        if !zeros.is_empty() {
-                            let new_cell = TreeCell::new::<BranchType, LeafType, DataType>(
-                                zero,
-                                zeros,
-                                zero_node,
-                                tree_cell.depth + 1,
-                            );
-                            cell_queue.push_front(new_cell);
-                        }
-                    }
                 }
                 NodeVariant::Leaf(n) => {
-                    if let Some(d) = self.db.get_node(*n.get_data())? {
-                        if let NodeVariant::Data(data) = d.get_variant() {
-                            let value = ValueType::decode(data.get_value())?;
-                            if let Ok(index) = keys.binary_search(n.get_key()) {
-                                leaf_map.insert(keys[index], Some(value));
-                            }
-                        } else {
-                            return Err(Exception::new(
-                                "Corrupt merkle tree: Found non data node after leaf",
-                            ));
-                        }
-                    } else {
-                        return Err(Exception::new(
-                            "Corrupt merkle tree: Failed to get leaf node from DB",
-                        ));
+                    if keys
+                        .binary_search_by(|k| KeyOrderType::compare(k, n.get_key()))
+                        .is_ok()
+                    {
+                        leaves.push((n, tree_cell.depth));
                     }
                 }
                 NodeVariant::Data(_) => {
-                    return Err(Exception::new(
-                        "Corrupt merkle tree: Found data node while traversing tree",
-                    ));
+                    Self::fail_keys(
+                        &mut failures,
+                        tree_cell.keys,
+                        Exception::new(
+                            "Corrupt merkle tree: Found data node while traversing tree",
+                        ),
+                    )?;
                 }
                 NodeVariant::Phantom(_) => {
-                    return Err(Exception::new(
-                        "Corrupt merkle tree: Found phantom node while traversing tree",
-                    ));
+                    Self::fail_keys(
+                        &mut failures,
+                        tree_cell.keys,
+                        Exception::new(
+                            "Corrupt merkle tree: Found phantom node while traversing tree",
+                        ),
+                    )?;
                 }
             }
         }
 
-        Ok(leaf_map)
+        Ok((leaves, kept_root))
+    }
+
+    /// Copies `branch`, for keeping a root the traversal of `find_leaves` consumes.
+    fn copy_branch(branch: &BranchType) -> BranchType {
+        let mut copy = BranchType::new();
+        copy.set_count(branch.get_count());
+        copy.set_zero(*branch.get_zero());
+        copy.set_one(*branch.get_one());
+        copy.set_split_index(branch.get_split_index());
+        copy.set_key(*branch.get_key());
+        copy
+    }
+
+    /// Queues the children of `branch` that `keys` descend into for `find_leaves`, fetching both
+    /// children in a single round trip to the database unless `failures` is given.
+    /// # Errors
+    /// `Exception` generated when a child cannot be read and there is nowhere to record it.
+    fn queue_children<'keys>(
+        &self,
+        branch: BranchType,
+        keys: &'keys [ArrayType],
+        depth: usize,
+        cell_queue: &mut VecDeque<TreeCell<'keys, NodeType, ArrayType>>,
+        failures: &mut Option<&mut HashMap<ArrayType, Exception>>,
+    ) -> BinaryMerkleTreeResult<()> {
+        let (_, zero, one, branch_split_index, branch_key) = branch.decompose();
+        let min_split_index = calc_min_split_index_ordered::<KeyOrderType, _>(keys, &branch_key)?;
+        let descendants = check_descendants_ordered::<KeyOrderType, _>(
+            keys,
+            branch_split_index,
+            &branch_key,
+            min_split_index,
+        )?;
+        if descendants.is_empty() {
+            return Ok(());
+        }
+
+        let (zeros, ones) =
+            split_pairs_ordered::<KeyOrderType, _>(descendants, branch_split_index)?;
+
+        let mut children = Vec::with_capacity(2);
+        if !ones.is_empty() {
+            children.push((one, ones));
+        }
+        if !zeros.is_empty() {
+            children.push((zero, zeros));
+        }
+        let child_nodes: Vec<BinaryMerkleTreeResult<Option<NodeType>>> = if failures.is_some() {
+            // Fetched one at a time, so an unreadable child only fails its own keys
+            children.iter().map(|c| self.db.get_node(c.0)).collect()
+        } else {
+            let locations = children.iter().map(|c| c.0).collect::<Vec<_>>();
+            self.db.get_nodes(&locations)?.into_iter().map(Ok).collect()
+        };
+
+        for ((location, child_keys), child_node) in children.into_iter().zip(child_nodes) {
+            match child_node {
+                Ok(Some(n)) => {
+                    let new_cell = TreeCell::new::<BranchType, LeafType, DataType>(
+                        location,
+                        child_keys,
+                        n,
+                        depth + 1,
+                    );
+                    cell_queue.push_front(new_cell);
+                }
+                Ok(None) => {}
+                Err(e) => Self::fail_keys(failures, child_keys, e)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Records `error` against each of `keys` in `failures`, or returns it if there is nowhere to
+    /// record it.
+    /// # Errors
+    /// `error` is returned when `failures` is `None`.
+    fn fail_keys(
+        failures: &mut Option<&mut HashMap<ArrayType, Exception>>,
+        keys: &[ArrayType],
+        error: Exception,
+    ) -> BinaryMerkleTreeResult<()> {
+        if let Some(failures) = failures {
+            for key in keys {
+                failures.insert(*key, error.clone());
+            }
+            Ok(())
+        } else {
+            Err(error)
+        }
     }
 
     /// Insert items into the `MerkleBIT`.  Keys must be sorted.  Returns a new root hash for the `MerkleBIT`.
+    ///
+    /// Keys overwritten with the value they already hold under `previous_root` are not written
+    /// again.  If no key changes, `previous_root` itself is returned and no node is created.
+    ///
+    /// An insert never changes the nodes under existing roots, apart from their reference counts,
+    /// and never deletes a node, so readers querying an earlier root through another handle to the
+    /// database (see `ConcurrentDatabase`) keep resolving the same values while the insert runs.
+    ///
+    /// Subtrees the insert leaves untouched are shared with `previous_root` by bumping their
+    /// reference counts, never copied.  See `new_with_domain` for keeping the nodes of separate
+    /// trees apart.
     /// # Errors
     /// `Exception` generated if an invalid state is encountered during tree traversal.
+    /// An `Exception` of kind `ErrorKind::DepthInsufficient` is generated, before anything is
+    /// written, if the tree built from `keys` alone would be deeper than the configured `depth`.
+    /// An `Exception` of kind `ErrorKind::DepthExceeded` is generated if traversing `previous_root`
+    /// goes deeper than the configured `depth`.  The tree is not grown automatically since the
+    /// depth limit also guards against traversing corrupt trees; reopen the tree with a larger
+    /// `depth` to insert such keys.
+    /// An `Exception` of kind `ErrorKind::BatchTooLarge` is generated, before anything is read or
+    /// written, if there are more `keys` than the maximum batch size.
     #[inline]
     pub fn insert(
         &mut self,
         previous_root: Option<&ArrayType>,
         keys: &mut [ArrayType],
         values: &[ValueType],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.check_batch_size(keys.len())?;
+        let mut scratch = TreeBuildScratch::with_capacity(keys.len());
+        self.insert_values_with_scratch(previous_root, keys, values, &mut scratch)
+    }
+
+    /// Inserts items into the `MerkleBIT` like `insert`, building the new tree in `scratch`.
+    /// Reusing one `TreeBuildScratch` across a loop of inserts keeps the memory used to build each
+    /// tree instead of allocating it again for every batch.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn insert_with_scratch(
+        &mut self,
+        previous_root: Option<&ArrayType>,
+        keys: &mut [ArrayType],
+        values: &[ValueType],
+        scratch: &mut TreeBuildScratch<ArrayType>,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.check_batch_size(keys.len())?;
+        self.insert_values_with_scratch(previous_root, keys, values, scratch)
+    }
+
+    /// Encodes `values` and inserts them at `keys`, building the new tree in `scratch`, for
+    /// `insert` and `insert_with_scratch`.  The batch size is checked by the callers.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    fn insert_values_with_scratch(
+        &mut self,
+        previous_root: Option<&ArrayType>,
+        keys: &mut [ArrayType],
+        values: &[ValueType],
+        scratch: &mut TreeBuildScratch<ArrayType>,
     ) -> BinaryMerkleTreeResult<ArrayType> {
         if keys.len() != values.len() {
             return Err(Exception::new("Keys and values have different lengths"));
         }
-
-        if keys.is_empty() || values.is_empty() {
-            return Err(Exception::new("Keys or values are empty"));
+        let mut encoded = Vec::with_capacity(values.len());
+        for value in values {
+            encoded.push(self.encode_value(value)?);
         }
+        let encoded = encoded.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        self.insert_encoded_with_scratch(previous_root, keys, &encoded, scratch)
+    }
 
-        let mut value_map = HashMap::new();
-        for (&key, value) in keys.iter().zip(values.iter()) {
-            value_map.insert(key, value);
-        }
+    /// Inserts items into the `MerkleBIT` like `insert`, taking the values already encoded.  The
+    /// bytes are stored as given, without passing through `ValueType::encode`, which avoids
+    /// encoding again when migrating data that is already encoded.  The caller is responsible for
+    /// the bytes being what `ValueType::decode` expects; values that do not decode are only
+    /// detected when they are read.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal, with the
+    /// same kinds as `insert`.
+    #[inline]
+    pub fn insert_encoded(
+        &mut self,
+        previous_root: Option<&ArrayType>,
+        keys: &mut [ArrayType],
+        encoded_values: &[&[u8]],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.check_batch_size(keys.len())?;
+        let mut scratch = TreeBuildScratch::with_capacity(keys.len());
+        self.insert_encoded_with_scratch(previous_root, keys, encoded_values, &mut scratch)
+    }
 
-        keys.sort();
+    /// Builds a global tree committing to other trees, with each entry giving the key of a subtree
+    /// and the root of that subtree, such as the state root of each account committed into a
+    /// single root.  Each subtree root is stored as the encoded value of its key, so
+    /// `generate_subtree_proof` can chain a proof in the global tree with a proof in the subtree.
+    /// The subtrees themselves are neither read nor referenced, so removing a subtree does not
+    /// change the global root.
+    /// # Errors
+    /// `Exception` generated with the same kinds as `insert_encoded`, including for duplicate
+    /// keys.
+    #[inline]
+    pub fn commit_subtrees(
+        &mut self,
+        entries: &[(ArrayType, ArrayType)],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        let mut keys = entries.iter().map(|entry| entry.0).collect::<Vec<_>>();
+        let roots = entries
+            .iter()
+            .map(|entry| entry.1.as_ref())
+            .collect::<Vec<_>>();
+        self.insert_encoded(None, &mut keys, &roots)
+    }
 
-        let nodes = self.insert_leaves(keys, &value_map)?;
+    /// Computes the root `insert` would return for `keys` and `values` inserted into an empty
+    /// tree, without reading or writing the database.  The root depends only on the key and
+    /// value pairs and the hashing scheme, described in the README under "Hashing Scheme", so it
+    /// can be compared against the root another implementation builds for the same dataset.
+    /// # Errors
+    /// `Exception` generated if a value cannot be encoded or `keys` holds a duplicate, with the
+    /// same kinds as `insert` for oversized batches and insufficient depth.
+    #[inline]
+    pub fn root_for(
+        &self,
+        keys: &[ArrayType],
+        values: &[ValueType],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        if keys.len() != values.len() {
+            return Err(Exception::new("Keys and values have different lengths"));
+        }
+        self.check_batch_size(keys.len())?;
+        if keys.is_empty() {
+            return Ok(Self::empty_root());
+        }
 
-        let mut tree_refs = Vec::with_capacity(keys.len());
-        let mut key_map = HashMap::new();
-        for (loc, &key) in nodes.into_iter().zip(keys.iter()) {
-            key_map.insert(key, loc);
-            let tree_ref = TreeRef::new(key, loc, 1, 1);
-            tree_refs.push(tree_ref);
+        let mut leaves = Vec::with_capacity(keys.len());
+        for (key, value) in keys.iter().zip(values.iter()) {
+            let location = self
+                .options
+                .scheme()
+                .leaf_location_of_value::<HasherType, _>(key, &self.encode_value(value)?);
+            leaves.push((*key, location));
+        }
+        leaves.sort_by(|a, b| KeyOrderType::compare(&a.0, &b.0));
+        if leaves.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(Exception::new(
+                "Attempted to insert item with duplicate keys",
+            ));
         }
 
-        if let Some(root) = previous_root {
-            let mut proof_nodes = self.generate_treerefs(root, keys, &key_map)?;
-            tree_refs.append(&mut proof_nodes);
+        let sorted_keys = leaves.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        let needed = tree_depth_ordered::<KeyOrderType, _>(&sorted_keys);
+        if needed > self.depth {
+            return Err(Exception::with_kind(
+                ErrorKind::DepthInsufficient {
+                    needed,
+                    configured: self.depth,
+                },
+                "The keys need a deeper tree than the configured depth",
+            ));
         }
 
-        let new_root = self.create_tree(tree_refs)?;
-        Ok(new_root)
+        self.root_of_sorted_leaves(&leaves)
     }
 
-    /// Traverses the tree and searches for nodes to include in the merkle proof.
+    /// Computes the root of the subtree holding `leaves`, the keys and leaf locations of a
+    /// subtree sorted in the order of `KeyOrderType`, for `root_for`.  A single leaf is its own
+    /// root, and otherwise the leaves are split at the first bit where the smallest and largest
+    /// keys differ.
     /// # Errors
-    /// `Exception` generated when an invalid state is encountered during tree traversal.
-    fn generate_treerefs(
-        &mut self,
-        root: &ArrayType,
-        keys: &mut [ArrayType],
-        key_map: &HashMap<ArrayType, ArrayType>,
-    ) -> BinaryMerkleTreeResult<Vec<TreeRef<ArrayType>>> {
+    /// `Exception` generated from a failure to convert a `u8` to a `usize`.
+    fn root_of_sorted_leaves(
+        &self,
+        leaves: &[(ArrayType, ArrayType)],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        if leaves.len() == 1 {
+            return Ok(leaves[0].1);
+        }
+        let first = KeyOrderType::order_key(&leaves[0].0);
+        let last = KeyOrderType::order_key(&leaves[leaves.len() - 1].0);
+        let split_index = common_prefix_bits(first.as_ref(), last.as_ref());
+
+        let mut ones = leaves.len();
+        for (i, (key, _)) in leaves.iter().enumerate() {
+            if !choose_zero_ordered::<KeyOrderType, _>(*key, split_index)? {
+                ones = i;
+                break;
+            }
+        }
+        let zero = self.root_of_sorted_leaves(&leaves[..ones])?;
+        let one = self.root_of_sorted_leaves(&leaves[ones..])?;
+        Ok(self
+            .options
+            .scheme()
+            .branch_location::<HasherType, _>(&zero, &one))
+    }
+
+    /// Inserts `keys` with their already encoded `values`, building the new tree in `scratch`, for
+    /// `insert_values_with_scratch` and `insert_encoded`.  The batch size is checked by the
+    /// callers.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    fn insert_encoded_with_scratch(
+        &mut self,
+        previous_root: Option<&ArrayType>,
+        keys: &mut [ArrayType],
+        values: &[&[u8]],
+        scratch: &mut TreeBuildScratch<ArrayType>,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        if keys.len() != values.len() {
+            return Err(Exception::new("Keys and values have different lengths"));
+        }
+
+        if keys.is_empty() || values.is_empty() {
+            if self.options.strict_empty_inserts() {
+                return Err(Exception::new("Keys or values are empty"));
+            }
+            if let Some(root) = previous_root.filter(|root| **root != Self::empty_root()) {
+                if self.options.strict_previous_root() || self.db.contains(root)? {
+                    return self.reference_root(root);
+                }
+            }
+            return Ok(Self::empty_root());
+        }
+        let previous_root = previous_root.filter(|root| **root != Self::empty_root());
+
+        let mut value_map = HashMap::new();
+        for (&key, &value) in keys.iter().zip(values.iter()) {
+            value_map.insert(key, value);
+        }
+
+        KeyOrderType::sort_keys(keys);
+
+        let needed = tree_depth_ordered::<KeyOrderType, _>(keys);
+        if needed > self.depth {
+            return Err(Exception::with_kind(
+                ErrorKind::DepthInsufficient {
+                    needed,
+                    configured: self.depth,
+                },
+                "The keys need a deeper tree than the configured depth",
+            ));
+        }
+
+        self.record_depth()?;
+        self.release_dropped_snapshots()?;
+        self.buffered_nodes = 0;
+
+        // Keys overwritten with the value they already hold keep sharing their existing leaves
+        let mut changed_keys;
+        let mut root_node = None;
+        let keys: &mut [ArrayType] = if let Some(root) = previous_root {
+            let (changed, node) = self.changed_keys(root, keys, &value_map)?;
+            if changed.is_empty() {
+                return self.reference_root(root);
+            }
+            changed_keys = changed;
+            root_node = node;
+            &mut changed_keys
+        } else {
+            keys
+        };
+
+        let nodes = self.insert_leaves(keys, &value_map)?;
+
+        scratch.clear();
+        let mut key_map = HashMap::new();
+        for (loc, &key) in nodes.into_iter().zip(keys.iter()) {
+            key_map.insert(key, loc);
+            let tree_ref = TreeRef::new(key, loc, 1, 1);
+            scratch.tree_refs.push(tree_ref);
+        }
+
+        if let Some(root) = previous_root {
+            let mut proof_nodes = self.generate_treerefs(root, root_node, keys, &key_map)?;
+            scratch.tree_refs.append(&mut proof_nodes);
+        }
+
+        let new_root = self.create_tree_with_scratch::<HasherType>(scratch)?;
+        let leaf_locations = key_map.values().copied().collect::<Vec<_>>();
+        self.record_value_versions(&leaf_locations, new_root);
+        Ok(new_root)
+    }
+
+    /// Gets the sorted `keys` whose encoded value in `values` differs from the value they hold
+    /// under `root`, including keys not present under `root`, along with the root node when it is
+    /// a branch, for `generate_treerefs` to start from.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    fn changed_keys(
+        &self,
+        root: &ArrayType,
+        keys: &[ArrayType],
+        values: &HashMap<ArrayType, &[u8]>,
+    ) -> BinaryMerkleTreeResult<(Vec<ArrayType>, Option<NodeType>)> {
+        let (leaves, root_node) = self.find_leaves(root, &mut keys.to_vec(), true, None)?;
+        let mut existing = HashMap::new();
+        for (leaf, _) in leaves {
+            existing.insert(*leaf.get_key(), *leaf.get_data());
+        }
+
+        let mut changed = Vec::with_capacity(keys.len());
+        for key in keys {
+            if let Some(data) = existing.get(key) {
+                if *data
+                    == self
+                        .options
+                        .scheme()
+                        .data_location::<HasherType, _>(key, values[key])
+                {
+                    continue;
+                }
+            }
+            changed.push(*key);
+        }
+        Ok((changed, root_node))
+    }
+
+    /// Records `root` as the version of every leaf in `leaf_locations`, which were written by the
+    /// insert that created `root`, if value versioning is enabled.  A leaf written before keeps
+    /// the first root it was written with.
+    fn record_value_versions(&mut self, leaf_locations: &[ArrayType], root: ArrayType) {
+        if let Some(versions) = &mut self.value_versions {
+            for location in leaf_locations {
+                versions.entry(*location).or_insert(root);
+            }
+        }
+    }
+
+    /// Adds a reference to the existing `root` on behalf of an insert which changed nothing, so
+    /// that it returns `root` without writing any new node, and removing either root later
+    /// leaves the other intact.
+    /// # Errors
+    /// `Exception` generated if `root` is missing from the database.
+    fn reference_root(&mut self, root: &ArrayType) -> BinaryMerkleTreeResult<ArrayType> {
+        if !self.options.refcounting() {
+            if self.db.contains(root)? {
+                return Ok(*root);
+            }
+            return Err(Exception::new("Could not find root"));
+        }
+        if let Some(mut node) = self.db.get_node(*root)? {
+            let references = node.get_references() + 1;
+            node.set_references(references);
+            self.refcount_changed(root, references - 1, references);
+            self.db.insert(*root, node)?;
+            self.write_batch()?;
+            Ok(*root)
+        } else {
+            Err(Exception::new("Could not find root"))
+        }
+    }
+
+    /// Traverses the tree and searches for nodes to include in the merkle proof.  `root_node` is the
+    /// node at `root` if the caller already read it, and is otherwise read from the database.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    fn generate_treerefs(
+        &mut self,
+        root: &ArrayType,
+        root_node: Option<NodeType>,
+        keys: &mut [ArrayType],
+        key_map: &HashMap<ArrayType, ArrayType>,
+    ) -> BinaryMerkleTreeResult<Vec<TreeRef<ArrayType>>> {
         // Nodes that form the merkle proof for the new tree
         let mut proof_nodes = Vec::with_capacity(keys.len());
 
-        let root_node = if let Some(m) = self.db.get_node(*root)? {
+        let root_node = if let Some(m) = root_node {
             m
-        } else {
+        } else if let Some(m) = self.db.get_node(*root)? {
+            m
+        } else if self.options.strict_previous_root() {
             return Err(Exception::new("Could not find root"));
+        } else {
+            // Build on an empty base instead
+            return Ok(proof_nodes);
         };
 
         let mut cell_queue = VecDeque::with_capacity(keys.len());
@@ -311,45 +1065,23 @@ where
 
         while let Some(tree_cell) = cell_queue.pop_front() {
             if tree_cell.depth > self.depth {
-                return Err(Exception::new("Depth of merkle tree exceeded"));
+                return Err(Exception::with_kind(
+                    ErrorKind::DepthExceeded,
+                    "Depth of merkle tree exceeded",
+                ));
             }
 
             let node = tree_cell.node;
             let depth = tree_cell.depth;
 
             let branch;
-            let mut refs = node.get_references();
+            let refs = node.get_references();
             match node.get_variant() {
                 NodeVariant::Branch(n) => branch = n,
                 NodeVariant::Leaf(n) => {
-                    let key = n.get_key();
-
-                    let mut update = false;
-
-                    // Check if we are updating an existing value
-                    if let Some(loc) = key_map.get(key) {
-                        update = loc == &tree_cell.location;
-                        if !update {
-                            continue;
-                        }
-                    }
-
-                    if let Some(mut l) = self.db.get_node(tree_cell.location)? {
-                        let leaf_refs = l.get_references() + 1;
-                        l.set_references(leaf_refs);
-                        self.db.insert(tree_cell.location, l)?;
-                    } else {
-                        return Err(Exception::new(
-                            "Corrupt merkle tree: Failed to update leaf references",
-                        ));
+                    if let Some(tree_ref) = self.keep_leaf(&tree_cell.location, &n, key_map)? {
+                        proof_nodes.push(tree_ref);
                     }
-
-                    if update {
-                        continue;
-                    }
-
-                    let tree_ref = TreeRef::new(*key, tree_cell.location, 1, 1);
-                    proof_nodes.push(tree_ref);
                     continue;
                 }
                 NodeVariant::Data(_) => {
@@ -364,15 +1096,16 @@ where
                 }
             }
 
-            let (branch_count, branch_zero, branch_one, branch_split_index, branch_key) =
-                branch.decompose();
+            let branch_split_index = branch.get_split_index();
+            let branch_key = *branch.get_key();
 
-            let min_split_index = calc_min_split_index(tree_cell.keys, &branch_key)?;
+            let min_split_index =
+                calc_min_split_index_ordered::<KeyOrderType, _>(tree_cell.keys, &branch_key)?;
 
             let mut descendants = tree_cell.keys;
 
             if min_split_index < branch_split_index {
-                descendants = check_descendants(
+                descendants = check_descendants_ordered::<KeyOrderType, _>(
                     tree_cell.keys,
                     branch_split_index,
                     &branch_key,
@@ -380,33 +1113,26 @@ where
                 )?;
 
                 if descendants.is_empty() {
-                    let mut new_branch = BranchType::new();
-                    new_branch.set_count(branch_count);
-                    new_branch.set_zero(branch_zero);
-                    new_branch.set_one(branch_one);
-                    new_branch.set_split_index(branch_split_index);
-                    new_branch.set_key(branch_key);
-
-                    let tree_ref = TreeRef::new(branch_key, tree_cell.location, branch_count, 1);
-                    refs += 1;
-                    let mut new_node = NodeType::new(NodeVariant::Branch(new_branch));
-                    new_node.set_references(refs);
-                    self.db.insert(tree_ref.location, new_node)?;
-                    proof_nodes.push(tree_ref);
+                    proof_nodes.push(self.keep_branch(tree_cell.location, branch, refs)?);
                     continue;
                 }
             }
 
-            let (zeros, ones) = split_pairs(descendants, branch_split_index)?;
+            let (_, branch_zero, branch_one, _, _) = branch.decompose();
+            let (zeros, ones) =
+                split_pairs_ordered::<KeyOrderType, _>(descendants, branch_split_index)?;
+            let mut children = self.db.get_nodes(&[branch_one, branch_zero])?;
+            let zero_node = children.pop().and_then(|n| n);
+            let one_node = children.pop().and_then(|n| n);
             {
-                match self.split_nodes(depth, branch_one, ones)? {
+                match self.split_nodes(depth, branch_one, one_node, ones)? {
                     SplitNodeType::Ref(tree_ref) => proof_nodes.push(tree_ref),
                     SplitNodeType::Cell(cell) => cell_queue.push_front(cell),
                     _ => (),
                 }
             }
             {
-                match self.split_nodes(depth, branch_zero, zeros)? {
+                match self.split_nodes(depth, branch_zero, zero_node, zeros)? {
                     SplitNodeType::Ref(tree_ref) => proof_nodes.push(tree_ref),
                     SplitNodeType::Cell(cell) => cell_queue.push_front(cell),
                     _ => (),
@@ -416,18 +1142,77 @@ where
         Ok(proof_nodes)
     }
 
+    /// Keeps the existing leaf `leaf` at `location` in the tree an insert is building, adding a
+    /// reference to it unless an inserted key replaces it with a different leaf.  Returns the
+    /// `TreeRef` of the leaf if no inserted key touches it, and `None` otherwise.
+    /// # Errors
+    /// `Exception` generated if the leaf cannot be read back to update its references.
+    fn keep_leaf(
+        &mut self,
+        location: &ArrayType,
+        leaf: &LeafType,
+        key_map: &HashMap<ArrayType, ArrayType>,
+    ) -> BinaryMerkleTreeResult<Option<TreeRef<ArrayType>>> {
+        let key = leaf.get_key();
+
+        // Check if we are updating an existing value
+        let update = key_map.get(key).map(|loc| loc == location);
+        if update == Some(false) {
+            return Ok(None);
+        }
+
+        if self.options.refcounting() {
+            if let Some(mut l) = self.db.get_node(*location)? {
+                let leaf_refs = l.get_references() + 1;
+                l.set_references(leaf_refs);
+                self.refcount_changed(location, leaf_refs - 1, leaf_refs);
+                self.buffer_node(*location, l)?;
+            } else {
+                return Err(Exception::new(
+                    "Corrupt merkle tree: Failed to update leaf references",
+                ));
+            }
+        }
+
+        if update.is_some() {
+            return Ok(None);
+        }
+        Ok(Some(TreeRef::new(*key, *location, 1, 1)))
+    }
+
+    /// Keeps the existing `branch` at `location`, holding `refs` references, in the tree an insert
+    /// is building when no inserted key descends into it, adding a reference to it.
+    /// # Errors
+    /// `Exception` generated if the branch cannot be buffered for writing.
+    fn keep_branch(
+        &mut self,
+        location: ArrayType,
+        branch: BranchType,
+        refs: u64,
+    ) -> BinaryMerkleTreeResult<TreeRef<ArrayType>> {
+        let tree_ref = TreeRef::new(*branch.get_key(), location, branch.get_count(), 1);
+        if self.options.refcounting() {
+            let mut new_node = NodeType::new(NodeVariant::Branch(branch));
+            new_node.set_references(refs + 1);
+            self.refcount_changed(&location, refs, refs + 1);
+            self.buffer_node(location, new_node)?;
+        }
+        Ok(tree_ref)
+    }
+
     /// Splits nodes during tree traversal into either zeros or ones, depending on the selected bit
-    /// from the index
+    /// from the index.  `branch_node` is the node previously fetched from `branch`.
     /// # Errors
     /// `Exception` generated when an invalid state is encountered during tree traversal.
     fn split_nodes<'a>(
         &mut self,
         depth: usize,
         branch: ArrayType,
+        branch_node: Option<NodeType>,
         node_list: &'a [ArrayType],
     ) -> Result<SplitNodeType<'a, BranchType, LeafType, DataType, NodeType, ArrayType>, Exception>
     {
-        if let Some(node) = self.db.get_node(branch)? {
+        if let Some(node) = branch_node {
             return if node_list.is_empty() {
                 let other_key;
                 let count;
@@ -455,8 +1240,11 @@ where
                         ));
                     }
                 }
-                new_node.set_references(refs);
-                self.db.insert(branch, new_node)?;
+                if self.options.refcounting() {
+                    new_node.set_references(refs);
+                    self.refcount_changed(&branch, refs - 1, refs);
+                    self.buffer_node(branch, new_node)?;
+                }
                 let tree_ref = TreeRef::new(other_key, branch, count, 1);
                 Ok(SplitNodeType::Ref(tree_ref))
             } else {
@@ -472,59 +1260,221 @@ where
         Err(Exception::new("Failed to find node in database."))
     }
 
-    /// Inserts all the new leaves into the database.
+    /// Inserts all the new leaves, holding the encoded `values`, into the database.
     /// Updates reference count if a leaf already exists.
     fn insert_leaves(
         &mut self,
         keys: &[ArrayType],
-        values: &HashMap<ArrayType, &ValueType>,
+        values: &HashMap<ArrayType, &[u8]>,
     ) -> BinaryMerkleTreeResult<Vec<ArrayType>> {
+        // Check every value first so an oversized value is rejected before anything is written
+        for k in keys.iter() {
+            let value = values[k];
+            if self.options.max_value_bytes() != 0 && value.len() > self.options.max_value_bytes() {
+                return Err(Exception::with_kind(
+                    ErrorKind::ValueTooLarge {
+                        key: k.as_ref().to_vec(),
+                        size: value.len(),
+                    },
+                    "Encoded value exceeds the maximum value size",
+                ));
+            }
+        }
+
         let mut nodes = Vec::with_capacity(keys.len());
         for k in keys.iter() {
-            let key = k.as_ref();
-            // Create data node
-            let mut data = DataType::new();
-            data.set_value(&(values[k].encode()?));
-
-            let mut data_hasher = HasherType::new(key.len());
-            data_hasher.update(b"d");
-            data_hasher.update(key);
-            data_hasher.update(data.get_value());
-            let data_node_location = data_hasher.finalize();
-
-            let mut data_node = NodeType::new(NodeVariant::Data(data));
-            data_node.set_references(1);
+            nodes.push(self.insert_leaf::<HasherType>(k, values[k])?);
+        }
+        Ok(nodes)
+    }
 
-            // Create leaf node
-            let mut leaf = LeafType::new();
-            leaf.set_data(data_node_location);
-            leaf.set_key(*k);
+    /// Records that `key` was written with the encoded `value`, if the value index is enabled.
+    fn index_value(&mut self, key: &ArrayType, value: &[u8]) {
+        if let Some(index) = &mut self.value_index {
+            let value_location = self.options.scheme().value_location::<HasherType, _>(value);
+            index.entry(value_location).or_default().insert(*key);
+        }
+    }
 
-            let mut leaf_hasher = HasherType::new(key.len());
-            leaf_hasher.update(b"l");
-            leaf_hasher.update(key.as_ref());
-            leaf_hasher.update(leaf.get_data().as_ref());
-            let leaf_node_location = leaf_hasher.finalize();
+    /// Computes the location of the leaf `leaf` is stored at with `LeafHasherType`.
+    fn leaf_location_of<LeafHasherType>(&self, leaf: &LeafType) -> ArrayType
+    where
+        LeafHasherType: Hasher<ArrayType, HashType = LeafHasherType>,
+    {
+        match leaf.get_inline_value() {
+            Some(value) if self.options.scheme().combined_leaves() => {
+                self.options
+                    .scheme()
+                    .combined_leaf_location::<LeafHasherType, _>(leaf.get_key(), value)
+            }
+            _ => self
+                .options
+                .scheme()
+                .leaf_location::<LeafHasherType, _>(leaf.get_key(), leaf.get_data()),
+        }
+    }
 
-            let mut leaf_node = NodeType::new(NodeVariant::Leaf(leaf));
-            leaf_node.set_references(1);
+    /// Inserts a single leaf and its data node holding the already encoded `value`, returning the
+    /// location of the leaf.  Locations are computed with `LeafHasherType`.  Values smaller than
+    /// the inline threshold are inlined into the leaf instead of being written to a data node.
+    /// Updates reference count if the leaf already exists.
+    fn insert_leaf<LeafHasherType>(
+        &mut self,
+        key: &ArrayType,
+        value: &[u8],
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        LeafHasherType: Hasher<ArrayType, HashType = LeafHasherType>,
+    {
+        let data_node_location = self
+            .options
+            .scheme()
+            .data_location::<LeafHasherType, _>(key, value);
+        self.index_value(key, value);
+
+        // Create leaf node
+        let mut leaf = LeafType::new();
+        leaf.set_data(data_node_location);
+        leaf.set_key(*key);
+
+        let combined = self.options.scheme().combined_leaves();
+        let leaf_node_location = if combined {
+            self.options
+                .scheme()
+                .combined_leaf_location::<LeafHasherType, _>(key, value)
+        } else {
+            self.options
+                .scheme()
+                .leaf_location::<LeafHasherType, _>(key, leaf.get_data())
+        };
 
-            if let Some(n) = self.db.get_node(data_node_location)? {
-                let references = n.get_references() + 1;
-                data_node.set_references(references);
+        // An existing leaf keeps the form it was stored in, so its data node stays consistent
+        let mut inline = combined
+            || (!self.options.commit_only()
+                && self.options.inline_value_bytes() != 0
+                && value.len() < self.options.inline_value_bytes());
+        let mut leaf_references = 1;
+        let mut leaf_exists = false;
+        if self.options.refcounting() || self.options.collision_check() {
+            if let Some(n) = self.db.get_node(leaf_node_location)? {
+                if self.options.refcounting() {
+                    leaf_references = n.get_references() + 1;
+                    leaf_exists = true;
+                }
+                if let NodeVariant::Leaf(l) = n.get_variant() {
+                    if self.options.collision_check()
+                        && (l.get_key() != key || l.get_data() != &data_node_location)
+                    {
+                        return Err(Exception::hash_collision(leaf_node_location.as_ref()));
+                    }
+                    inline = l.get_inline_value().is_some();
+                } else if self.options.collision_check() {
+                    return Err(Exception::hash_collision(leaf_node_location.as_ref()));
+                }
             }
+        }
+        if inline {
+            leaf.set_inline_value(value);
+            inline = leaf.get_inline_value().is_some();
+            if combined && !inline {
+                return Err(Exception::new(
+                    "Combined leaves need a leaf type able to hold inline values",
+                ));
+            }
+        }
 
-            if let Some(n) = self.db.get_node(leaf_node_location)? {
-                let references = n.get_references() + 1;
-                leaf_node.set_references(references);
+        let mut leaf_node = NodeType::new(NodeVariant::Leaf(leaf));
+        leaf_node.set_references(leaf_references);
+
+        // An existing leaf already holds the reference to its data node
+        if !inline && !leaf_exists {
+            let mut data_node = NodeType::new(NodeVariant::Data(
+                self.create_data(&data_node_location, value)?,
+            ));
+            data_node.set_references(1);
+            // A data node written earlier in the batch was already checked for collisions
+            let mut existing_references = self.pending_data.get(&data_node_location).copied();
+            if existing_references.is_none()
+                && (self.options.refcounting() || self.options.collision_check())
+            {
+                if let Some(n) = self.db.get_node(data_node_location)? {
+                    existing_references = Some(n.get_references());
+                    if self.options.collision_check() {
+                        self.check_data_collision(&data_node_location, n, value)?;
+                    }
+                }
+            }
+            if let Some(references) = existing_references.filter(|_| self.options.refcounting()) {
+                data_node.set_references(references + 1);
+                self.refcount_changed(&data_node_location, references, references + 1);
+                self.dedup_stats.shared_data_nodes += 1;
+                self.dedup_stats.bytes_saved += u64::try_from(value.len())?;
+            } else {
+                self.refcount_changed(&data_node_location, 0, 1);
+                self.dedup_stats.written_data_nodes += 1;
             }
+            self.pending_data
+                .insert(data_node_location, data_node.get_references());
+            self.buffer_node(data_node_location, data_node)?;
+        }
+        self.refcount_changed(&leaf_node_location, leaf_references - 1, leaf_references);
+        self.buffer_node(leaf_node_location, leaf_node)?;
+
+        Ok(leaf_node_location)
+    }
 
-            self.db.insert(data_node_location, data_node)?;
-            self.db.insert(leaf_node_location, leaf_node)?;
+    /// Checks that the data node `existing`, already stored at `location`, holds `value`.
+    /// # Errors
+    /// `Exception` generated if `existing` holds a different value, or is not a data node.
+    fn check_data_collision(
+        &self,
+        location: &ArrayType,
+        existing: NodeType,
+        value: &[u8],
+    ) -> BinaryMerkleTreeResult<()> {
+        if let NodeVariant::Data(d) = existing.get_variant() {
+            if self.options.commit_only() {
+                let commitment = self
+                    .options
+                    .scheme()
+                    .value_location::<HasherType, ArrayType>(value);
+                if d.get_value() == commitment.as_ref() {
+                    return Ok(());
+                }
+            } else if self.resolve_data(&d)? == value {
+                return Ok(());
+            }
+        }
+        Err(Exception::hash_collision(location.as_ref()))
+    }
 
-            nodes.push(leaf_node_location);
+    /// Creates the data node to be stored at `location` holding `value`, or a reference to it when
+    /// it is moved to the blob store, or only the value location of `value` in commit-only mode.
+    /// A blob is stored under `location`, so it lives exactly as long as its data node.
+    /// # Errors
+    /// `Exception` generated if the value cannot be written to the blob store.
+    fn create_data(&self, location: &ArrayType, value: &[u8]) -> BinaryMerkleTreeResult<DataType> {
+        let mut data = DataType::new();
+        if self.options.commit_only() {
+            let commitment = self
+                .options
+                .scheme()
+                .value_location::<HasherType, ArrayType>(value);
+            data.set_value(commitment.as_ref());
+            return Ok(data);
         }
-        Ok(nodes)
+        if let Some(store) = &self.blob_store {
+            if value.len() >= self.min_blob_bytes {
+                data.set_value(location.as_ref());
+                data.set_blob_ref(true);
+                if data.is_blob_ref() {
+                    store.put_blob(*location, value)?;
+                    return Ok(data);
+                }
+            }
+        }
+        data.set_value(value);
+        Ok(data)
     }
 
     /// This function generates the queue of `TreeRef`s and merges the queue together to create a
@@ -534,30 +1484,61 @@ where
     /// tree traversal
     fn create_tree(
         &mut self,
-        mut tree_refs: Vec<TreeRef<ArrayType>>,
+        tree_refs: Vec<TreeRef<ArrayType>>,
     ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.create_tree_with_hasher::<HasherType>(tree_refs)
+    }
+
+    /// Creates a new tree root from the given `TreeRef`s, computing branch locations with
+    /// `BranchHasherType`.
+    /// # Errors
+    /// `Exception` generated when `tree_refs` is empty or an invalid state is encountered during
+    /// tree traversal
+    fn create_tree_with_hasher<BranchHasherType>(
+        &mut self,
+        tree_refs: Vec<TreeRef<ArrayType>>,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        BranchHasherType: Hasher<ArrayType, HashType = BranchHasherType>,
+    {
+        self.create_tree_with_scratch::<BranchHasherType>(&mut TreeBuildScratch::from(tree_refs))
+    }
+
+    /// Creates a new tree root from the `TreeRef`s held in `scratch`, computing branch locations
+    /// with `BranchHasherType`.  The merge levels of `scratch` must be empty.
+    /// # Errors
+    /// `Exception` generated when `scratch` holds no `TreeRef`s or an invalid state is
+    /// encountered during tree traversal
+    fn create_tree_with_scratch<BranchHasherType>(
+        &mut self,
+        scratch: &mut TreeBuildScratch<ArrayType>,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        BranchHasherType: Hasher<ArrayType, HashType = BranchHasherType>,
+    {
+        let tree_refs = &mut scratch.tree_refs;
         if tree_refs.is_empty() {
             return Err(Exception::new("tree_refs should not be empty!"))
         }
 
         if tree_refs.len() == 1 {
-            self.db.batch_write()?;
-            let node = tree_refs.remove(0);
-            return Ok(node.location);
+            self.write_batch()?;
+            return Ok(tree_refs[0].location);
         }
 
-        tree_refs.sort();
+        tree_refs.sort_by(|a, b| KeyOrderType::compare(&a.key, &b.key));
 
-        let mut tree_ref_queue = HashMap::new();
+        let tree_ref_queue = &mut scratch.levels;
 
-        let unique_split_bits = generate_tree_ref_queue(&mut tree_refs, &mut tree_ref_queue)?;
+        let unique_split_bits =
+            generate_tree_ref_queue_ordered::<KeyOrderType, _>(tree_refs, tree_ref_queue)?;
         let mut indices = unique_split_bits.into_iter().collect::<Vec<_>>();
         indices.sort();
 
         let mut root = None;
         for i in indices.into_iter().rev() {
-            if let Some(level) = tree_ref_queue.remove(&i){
-                root = self.merge_nodes(&mut tree_refs, level)?;
+            if let Some(level) = tree_ref_queue.get(&i) {
+                root = self.merge_nodes::<BranchHasherType>(tree_refs, level)?;
             } else {
                 return Err(Exception::new("Level should not be empty."))
             }
@@ -570,15 +1551,17 @@ where
     }
 
     /// Performs the merging of `TreeRef`s until a single new root is left.
-    fn merge_nodes(
+    fn merge_nodes<BranchHasherType>(
         &mut self,
-        tree_refs: &mut Vec<TreeRef<ArrayType>>,
-        level: Vec<(usize, usize, usize)>,
-    ) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        tree_refs: &mut [TreeRef<ArrayType>],
+        level: &[(usize, usize, usize)],
+    ) -> BinaryMerkleTreeResult<Option<ArrayType>>
+    where
+        BranchHasherType: Hasher<ArrayType, HashType = BranchHasherType>,
+    {
         let mut root = ArrayType::default();
-        for (split_index, tree_ref_pointer, next_tree_ref_pointer) in level {
+        for &(split_index, tree_ref_pointer, next_tree_ref_pointer) in level {
             let mut branch = BranchType::new();
-
             let tree_ref_key = tree_refs[tree_ref_pointer].key;
             let tree_ref_location = tree_refs[tree_ref_pointer].location;
             let tree_ref_count = tree_refs[tree_ref_pointer].node_count;
@@ -606,25 +1589,12 @@ where
 
             let next_tree_ref_location = tree_refs[lookahead_tree_ref_pointer].location;
             let count = tree_ref_count + tree_refs[lookahead_tree_ref_pointer].node_count;
-            let branch_node_location;
-            {
-                let mut branch_hasher = HasherType::new(root.as_ref().len());
-                branch_hasher.update(b"b");
-                branch_hasher.update(tree_ref_location.as_ref());
-                branch_hasher.update(next_tree_ref_location.as_ref());
-                branch_node_location = branch_hasher.finalize();
-
-                branch.set_zero(tree_ref_location);
-                branch.set_one(next_tree_ref_location);
-                branch.set_count(count);
-                branch.set_split_index(split_index);
-                branch.set_key(tree_ref_key);
-            }
-
-            let mut branch_node = NodeType::new(NodeVariant::Branch(branch));
-            branch_node.set_references(1);
-
-            self.db.insert(branch_node_location, branch_node)?;
+            branch.set_zero(tree_ref_location);
+            branch.set_one(next_tree_ref_location);
+            branch.set_count(count);
+            branch.set_split_index(split_index);
+            branch.set_key(tree_ref_key);
+            let branch_node_location = self.write_branch::<BranchHasherType>(branch)?;
 
             {
                 tree_refs[lookahead_tree_ref_pointer].key = tree_ref_key;
@@ -637,157 +1607,3424 @@ where
 
             root = branch_node_location;
         }
-        self.db.batch_write()?;
+        self.write_batch()?;
         Ok(Some(root))
     }
 
-    /// Remove all items with less than 1 reference under the given root.
+    /// Writes `branch` at the location of its children computed with `BranchHasherType`, returning
+    /// the location.  The children must already hold the references the branch takes.  If an
+    /// identical branch is already stored, as when two trees share a subtree, that branch gains a
+    /// reference instead, and the references the children gained for `branch` are released, so a
+    /// node's references count the stored nodes and roots pointing at it.
     /// # Errors
-    /// `Exception` generated when an invalid state is encountered during tree traversal.
-    #[inline]
-    pub fn remove(&mut self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<()> {
-        let mut nodes = VecDeque::with_capacity(128);
-        nodes.push_front(*root_hash);
-
-        while !nodes.is_empty() {
-            let node_location;
-            if let Some(location) = nodes.pop_front() {
-                node_location = location;
-            } else {
-                return Err(Exception::new("Nodes should not be empty."));
-            }
-
-            let node = if let Some(n) = self.db.get_node(node_location)? {
-                n
-            } else {
-                continue;
-            };
-
-            let mut refs = node.get_references();
-            if refs > 0 {
-                refs -= 1;
-            }
-
-            let mut new_node;
-            match node.get_variant() {
-                NodeVariant::Branch(b) => {
-                    if refs == 0 {
-                        let zero = *b.get_zero();
-                        let one = *b.get_one();
-                        nodes.push_back(zero);
-                        nodes.push_back(one);
-                        self.db.remove(&node_location)?;
-                        continue;
-                    }
-                    new_node = NodeType::new(NodeVariant::Branch(b))
-                }
-                NodeVariant::Leaf(l) => {
-                    if refs == 0 {
-                        let data = *l.get_data();
-                        nodes.push_back(data);
-                        self.db.remove(&node_location)?;
-                        continue;
-                    }
-                    new_node = NodeType::new(NodeVariant::Leaf(l));
-                }
-                NodeVariant::Data(d) => {
-                    if refs == 0 {
-                        self.db.remove(&node_location)?;
-                        continue;
+    /// `Exception` generated if the branch cannot be written, or collides with a different node.
+    fn write_branch<BranchHasherType>(
+        &mut self,
+        branch: BranchType,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        BranchHasherType: Hasher<ArrayType, HashType = BranchHasherType>,
+    {
+        let zero = *branch.get_zero();
+        let one = *branch.get_one();
+        let location = self
+            .options
+            .scheme()
+            .branch_location::<BranchHasherType, _>(&zero, &one);
+
+        let mut branch_node = NodeType::new(NodeVariant::Branch(branch));
+        branch_node.set_references(1);
+
+        // An identical branch may already be part of another tree.  Its children already hold a
+        // reference from it, so the references they gained for this tree are released.
+        if self.options.refcounting() || self.options.collision_check() {
+            if let Some(n) = self.db.get_node(location)? {
+                let references = n.get_references() + 1;
+                if self.options.collision_check() {
+                    let matches = match n.get_variant() {
+                        NodeVariant::Branch(b) => b.get_zero() == &zero && b.get_one() == &one,
+                        _ => false,
+                    };
+                    if !matches {
+                        return Err(Exception::hash_collision(location.as_ref()));
                     }
-                    new_node = NodeType::new(NodeVariant::Data(d))
                 }
-                NodeVariant::Phantom(_) => {
-                    return Err(Exception::new(
-                        "Corrupt merkle tree: Found phantom node while traversing tree",
-                    ));
+                if self.options.refcounting() {
+                    branch_node.set_references(references);
+                    self.release_reference(&zero)?;
+                    self.release_reference(&one)?;
                 }
             }
-
-            new_node.set_references(refs);
-            self.db.insert(node_location, new_node)?;
         }
-        self.db.batch_write()?;
 
-        Ok(())
+        let references = branch_node.get_references();
+        self.refcount_changed(&location, references - 1, references);
+        self.buffer_node(location, branch_node)?;
+        Ok(location)
     }
 
-    /// Generates an inclusion proof.  The proof consists of a list of hashes beginning with the key/value
-    /// pair and traveling up the tree until the level below the root is reached.
+    /// Writes the nodes buffered in the database with `batch_write`, after which the database
+    /// reads them back and the pending data references are no longer needed.
+    /// # Errors
+    /// `Exception` generated if the batch cannot be written.
+    fn write_batch(&mut self) -> BinaryMerkleTreeResult<()> {
+        self.db.batch_write()?;
+        self.pending_data.clear();
+        Ok(())
+    }
+
+    /// Buffers `node` at `location` in the database on behalf of an insert, writing the buffered
+    /// nodes once there are as many as the flush interval.
+    /// # Errors
+    /// `Exception` generated if the node cannot be buffered or written.
+    fn buffer_node(&mut self, location: ArrayType, node: NodeType) -> BinaryMerkleTreeResult<()> {
+        self.db.insert(location, node)?;
+        if self.options.flush_every() != 0 {
+            self.buffered_nodes += 1;
+            if self.buffered_nodes >= self.options.flush_every() {
+                self.write_batch()?;
+                self.buffered_nodes = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a reference to the node at `location`, which an insert shares with the new root.
+    /// # Errors
+    /// `Exception` generated if the node is missing from the database.
+    fn add_reference(&mut self, location: &ArrayType) -> BinaryMerkleTreeResult<()> {
+        if let Some(mut node) = self.db.get_node(*location)? {
+            let references = node.get_references() + 1;
+            node.set_references(references);
+            self.refcount_changed(location, references - 1, references);
+            self.buffer_node(*location, node)?;
+            Ok(())
+        } else {
+            Err(Exception::new(
+                "Corrupt merkle tree: Failed to update node references",
+            ))
+        }
+    }
+
+    /// Decrements the references of the node at `location` without removing it.
+    /// # Errors
+    /// `Exception` generated if the node is missing from the database.
+    fn release_reference(&mut self, location: &ArrayType) -> BinaryMerkleTreeResult<()> {
+        if let Some(mut node) = self.db.get_node(*location)? {
+            let previous = node.get_references();
+            let references = previous.saturating_sub(1);
+            node.set_references(references);
+            self.refcount_changed(location, previous, references);
+            self.buffer_node(*location, node)?;
+            Ok(())
+        } else {
+            Err(Exception::new(
+                "Corrupt merkle tree: Failed to update node references",
+            ))
+        }
+    }
+
+    /// Remove all items with less than 1 reference under the given root.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn remove(&mut self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<()> {
+        self.check_refcounting()?;
+        self.release_dropped_snapshots()?;
+        self.remove_root(root_hash)
+    }
+
+    /// Remove all items with less than 1 reference under the given root, checking `cancel` at
+    /// each node.  If `cancel` is set before all the nodes have been visited, nothing is removed
+    /// and an `Exception` of kind `Cancelled` is returned.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal, or when
+    /// the removal is cancelled.
+    #[inline]
+    pub fn remove_cancellable(
+        &mut self,
+        root_hash: &ArrayType,
+        cancel: &AtomicBool,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.check_refcounting()?;
+        self.release_dropped_snapshots()?;
+        self.remove_root_with_cancel(root_hash, Some(cancel))
+    }
+
+    /// Checks that nodes are reference counted, so roots can be removed.
+    /// # Errors
+    /// `Exception` generated if reference counting is disabled.
+    fn check_refcounting(&self) -> BinaryMerkleTreeResult<()> {
+        if self.options.refcounting() {
+            return Ok(());
+        }
+        Err(Exception::new(
+            "Cannot remove roots while reference counting is disabled",
+        ))
+    }
+
+    /// Gets the current root recorded in the database's root pointer, or `None` if no root has
+    /// been recorded yet.
+    /// # Errors
+    /// `Exception` generated if the database has no root pointer.
+    #[inline]
+    pub fn current_root(&self) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        self.db.get_root_in(self.options.root_namespace())
+    }
+
+    /// Records `new` as the current root only if the current root is still `expected`, returning
+    /// `false` without changing the root pointer otherwise.  A writer which loses the race should
+    /// `remove` the root it built, so its nodes are freed, then read the `current_root` again, redo
+    /// its insert on top of it, and retry.  The writers must share this tree, since two trees
+    /// writing to the same database overwrite each other's reference counts.
+    /// # Errors
+    /// `Exception` generated if `new` is not a root in the tree, or the database has no root
+    /// pointer.
+    #[inline]
+    pub fn compare_and_set_root(
+        &mut self,
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> BinaryMerkleTreeResult<bool> {
+        if !self.db.contains(&new)? {
+            return Err(Exception::new("Could not find root"));
+        }
+        self.db
+            .compare_and_set_root_in(self.options.root_namespace(), expected, new)
+    }
+
+    /// Decrements the references of all nodes under the given root, removing those which are no
+    /// longer referenced.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    fn remove_root(&mut self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<()> {
+        self.remove_root_with_cancel(root_hash, None)
+    }
+
+    /// Decrements the references of all nodes under the given root, removing those which are no
+    /// longer referenced.  The changes are only applied once the traversal has finished, so a
+    /// cancelled removal leaves the tree untouched.  A node reached more than once, such as a data
+    /// node shared by several leaves, is decremented from its pending count on each visit.  The
+    /// blob of a removed data node is deleted from the blob store once the nodes are written.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal, or when
+    /// `cancel` is set.
+    fn remove_root_with_cancel(
+        &mut self,
+        root_hash: &ArrayType,
+        cancel: Option<&AtomicBool>,
+    ) -> BinaryMerkleTreeResult<()> {
+        let mut nodes = VecDeque::with_capacity(128);
+        nodes.push_front(*root_hash);
+
+        // Nodes to update by location, with their references before the removal, paired with
+        // `None` when the node should be removed
+        let mut changes: HashMap<ArrayType, (u64, Option<NodeType>)> = HashMap::with_capacity(128);
+        // The order nodes were first visited in, which the changes are applied in
+        let mut visited = Vec::with_capacity(128);
+        // The blobs of the removed data nodes
+        let mut blobs = Vec::new();
+
+        while !nodes.is_empty() {
+            check_cancelled(cancel)?;
+
+            let node_location;
+            if let Some(location) = nodes.pop_front() {
+                node_location = location;
+            } else {
+                return Err(Exception::new("Nodes should not be empty."));
+            }
+
+            // A node visited before is read back from the pending changes, not the database
+            let (original, node) = match changes.remove(&node_location) {
+                Some((original, Some(n))) => (original, n),
+                Some((original, None)) => {
+                    changes.insert(node_location, (original, None));
+                    continue;
+                }
+                None => {
+                    if let Some(n) = self.db.get_node(node_location)? {
+                        visited.push(node_location);
+                        (n.get_references(), n)
+                    } else {
+                        continue;
+                    }
+                }
+            };
+
+            let mut refs = node.get_references();
+            if refs > 0 {
+                refs -= 1;
+            }
+
+            let mut new_node;
+            match node.get_variant() {
+                NodeVariant::Branch(b) => {
+                    if refs == 0 {
+                        let zero = *b.get_zero();
+                        let one = *b.get_one();
+                        nodes.push_back(zero);
+                        nodes.push_back(one);
+                        changes.insert(node_location, (original, None));
+                        continue;
+                    }
+                    new_node = NodeType::new(NodeVariant::Branch(b))
+                }
+                NodeVariant::Leaf(l) => {
+                    if refs == 0 {
+                        let data = *l.get_data();
+                        nodes.push_back(data);
+                        changes.insert(node_location, (original, None));
+                        continue;
+                    }
+                    new_node = NodeType::new(NodeVariant::Leaf(l));
+                }
+                NodeVariant::Data(d) => {
+                    if refs == 0 {
+                        // A blob stored under another key, as written by earlier versions, may
+                        // be shared with other data nodes, so it is left in the store
+                        if d.is_blob_ref() && d.get_value() == node_location.as_ref() {
+                            blobs.push(node_location);
+                        }
+                        changes.insert(node_location, (original, None));
+                        continue;
+                    }
+                    new_node = NodeType::new(NodeVariant::Data(d))
+                }
+                NodeVariant::Phantom(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found phantom node while traversing tree",
+                    ));
+                }
+            }
+
+            new_node.set_references(refs);
+            changes.insert(node_location, (original, Some(new_node)));
+        }
+
+        if changes.values().any(|(_, change)| change.is_none()) {
+            self.removal_epoch = self.removal_epoch.wrapping_add(1);
+        }
+        for location in visited {
+            let (previous, change) = changes
+                .remove(&location)
+                .ok_or_else(|| Exception::new("Corrupt merkle tree: Lost a removal change"))?;
+            if let Some(node) = change {
+                self.refcount_changed(&location, previous, node.get_references());
+                self.db.insert(location, node)?;
+            } else {
+                self.refcount_changed(&location, previous, 0);
+                self.db.remove(&location)?;
+            }
+        }
+        self.write_batch()?;
+        if let Some(store) = &self.blob_store {
+            for blob in blobs {
+                store.delete(&blob)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Recomputes the reference count of every node reachable from `live_roots` and writes back
+    /// any count that has drifted.  Each entry of `live_roots` accounts for one reference to that
+    /// root, so a root that should survive two calls to `remove` is listed twice.  Roots pinned by
+    /// outstanding `Snapshot`s are counted automatically.  After recounting, removing every live
+    /// root frees all of its nodes.
     /// # Errors
     /// `Exception` generated when an invalid state is encountered during tree traversal.
     #[inline]
-    pub fn generate_inclusion_proof(
+    pub fn recount_references(&mut self, live_roots: &[ArrayType]) -> BinaryMerkleTreeResult<()> {
+        self.release_dropped_snapshots()?;
+
+        let mut counts: HashMap<ArrayType, u64> = HashMap::new();
+        let mut nodes = Vec::with_capacity(live_roots.len());
+        let pinned = self.snapshots.iter().map(|(root, _)| root);
+        for root in live_roots.iter().chain(pinned) {
+            let count = counts.entry(*root).or_insert(0);
+            if *count == 0 {
+                nodes.push((*root, 0));
+            }
+            *count += 1;
+        }
+
+        // Each node is only expanded the first time it is reached, so a child gains one reference
+        // per distinct parent
+        let mut found = Vec::with_capacity(counts.len());
+        while let Some((location, depth)) = nodes.pop() {
+            if depth > self.depth {
+                return Err(Exception::with_kind(
+                    ErrorKind::DepthExceeded,
+                    "Depth of merkle tree exceeded",
+                ));
+            }
+
+            let node = self.get_tree_node(location)?;
+            let references = node.get_references();
+            let variant = node.get_variant();
+            let children = match &variant {
+                NodeVariant::Branch(b) => vec![*b.get_zero(), *b.get_one()],
+                NodeVariant::Leaf(l) if l.get_inline_value().is_some() => Vec::new(),
+                NodeVariant::Leaf(l) => vec![*l.get_data()],
+                NodeVariant::Data(_) => Vec::new(),
+                NodeVariant::Phantom(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found phantom node while traversing tree",
+                    ));
+                }
+            };
+            for child in children {
+                let count = counts.entry(child).or_insert(0);
+                if *count == 0 {
+                    nodes.push((child, depth + 1));
+                }
+                *count += 1;
+            }
+            found.push((location, references, variant));
+        }
+
+        for (location, references, variant) in found {
+            let count = counts[&location];
+            if references != count {
+                let mut node = NodeType::new(variant);
+                node.set_references(count);
+                self.refcount_changed(&location, references, count);
+                self.db.insert(location, node)?;
+            }
+        }
+        self.write_batch()?;
+        Ok(())
+    }
+
+    /// Combines two roots built over disjoint sets of keys into a single root containing the keys
+    /// of both.  This allows subtrees over separate key ranges to be built independently (for
+    /// instance, in parallel on different trees sharing a database) and merged afterwards.
+    /// The roots are joined at the branch level: only the branches on the paths where their key
+    /// spaces interleave are rebuilt, and every other subtree is shared with the new root, so
+    /// merging roots over disjoint key ranges reads and writes a single path.  Both original
+    /// roots remain valid.
+    /// # Errors
+    /// `Exception` generated if a key is present under both roots, or when an invalid state is
+    /// encountered during tree traversal.  Nothing is written when a key is present under both.
+    #[inline]
+    pub fn merge_roots(
+        &mut self,
+        root_a: &ArrayType,
+        root_b: &ArrayType,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.release_dropped_snapshots()?;
+        // Merging with an empty tree gives the other tree
+        if *root_a == Self::empty_root() {
+            if *root_b == Self::empty_root() {
+                return Ok(Self::empty_root());
+            }
+            return self.reference_root(root_b);
+        }
+        if *root_b == Self::empty_root() {
+            return self.reference_root(root_a);
+        }
+
+        // Plan the whole merge first, so overlapping key spaces are found before writing anything
+        let merged = self.plan_merge(*root_a, *root_b)?;
+        let root = self.write_merged(&merged)?;
+        self.write_batch()?;
+        Ok(root)
+    }
+
+    /// Describes the existing node at `location` for `plan_merge`, as its key, the number of
+    /// leading key bits shared by every leaf under it, its leaf count, and the children of a
+    /// branch.
+    /// # Errors
+    /// `Exception` generated if the node is missing, or is neither a branch nor a leaf.
+    fn merge_summary(
+        &self,
+        location: ArrayType,
+    ) -> BinaryMerkleTreeResult<MergeSummary<ArrayType>> {
+        match self.get_tree_node(location)?.get_variant() {
+            NodeVariant::Branch(b) => Ok((
+                *b.get_key(),
+                b.get_split_index(),
+                b.get_count(),
+                Some((*b.get_zero(), *b.get_one())),
+            )),
+            NodeVariant::Leaf(l) => Ok((*l.get_key(), l.get_key().as_ref().len() * 8, 1, None)),
+            NodeVariant::Data(_) | NodeVariant::Phantom(_) => Err(Exception::new(
+                "Corrupt merkle tree: Found a node which is neither a branch nor a leaf",
+            )),
+        }
+    }
+
+    /// Gets the existing node at `location` as a `MergedNode` shared by the merged tree.
+    /// # Errors
+    /// `Exception` generated if the node is missing, or is neither a branch nor a leaf.
+    fn merge_existing(&self, location: ArrayType) -> BinaryMerkleTreeResult<MergedNode<ArrayType>> {
+        let (key, _, count, _) = self.merge_summary(location)?;
+        Ok(MergedNode::Existing {
+            location,
+            key,
+            count,
+        })
+    }
+
+    /// Plans the tree joining the subtrees at `a` and `b` without writing anything.  Where the
+    /// keys of the subtrees first differ above both of their splits, a new branch joins them
+    /// whole.  Otherwise one subtree falls on a single side of the other's split, or both split at
+    /// the same bit and are joined side by side.
+    /// # Errors
+    /// `Exception` generated if a key is present under both subtrees, or a node is missing.
+    fn plan_merge(
+        &self,
+        a: ArrayType,
+        b: ArrayType,
+    ) -> BinaryMerkleTreeResult<MergedNode<ArrayType>> {
+        let (a_key, a_prefix, a_count, a_children) = self.merge_summary(a)?;
+        let (b_key, b_prefix, b_count, b_children) = self.merge_summary(b)?;
+        let difference = common_prefix_bits(
+            KeyOrderType::order_key(&a_key).as_ref(),
+            KeyOrderType::order_key(&b_key).as_ref(),
+        );
+
+        if difference < a_prefix.min(b_prefix) {
+            let a_node = MergedNode::Existing {
+                location: a,
+                key: a_key,
+                count: a_count,
+            };
+            let b_node = MergedNode::Existing {
+                location: b,
+                key: b_key,
+                count: b_count,
+            };
+            return if choose_zero_ordered::<KeyOrderType, _>(a_key, difference)? {
+                Ok(MergedNode::branch(difference, a_node, b_node))
+            } else {
+                Ok(MergedNode::branch(difference, b_node, a_node))
+            };
+        }
+
+        match (a_children, b_children) {
+            (Some((a_zero, a_one)), Some((b_zero, b_one))) if a_prefix == b_prefix => {
+                Ok(MergedNode::branch(
+                    a_prefix,
+                    self.plan_merge(a_zero, b_zero)?,
+                    self.plan_merge(a_one, b_one)?,
+                ))
+            }
+            (Some((zero, one)), _) if a_prefix < b_prefix => {
+                self.plan_merge_into(a_prefix, zero, one, b, b_key)
+            }
+            (_, Some((zero, one))) if b_prefix < a_prefix => {
+                self.plan_merge_into(b_prefix, zero, one, a, a_key)
+            }
+            _ => Err(Exception::new(
+                "Cannot merge roots: the key spaces of the roots overlap",
+            )),
+        }
+    }
+
+    /// Plans merging the subtree at `other`, whose keys all fall on one side of the branch
+    /// splitting at `split_index` into `zero` and `one`, into that side.
+    /// # Errors
+    /// `Exception` generated if a key is present under both subtrees, or a node is missing.
+    fn plan_merge_into(
+        &self,
+        split_index: usize,
+        zero: ArrayType,
+        one: ArrayType,
+        other: ArrayType,
+        other_key: ArrayType,
+    ) -> BinaryMerkleTreeResult<MergedNode<ArrayType>> {
+        if choose_zero_ordered::<KeyOrderType, _>(other_key, split_index)? {
+            Ok(MergedNode::branch(
+                split_index,
+                self.plan_merge(zero, other)?,
+                self.merge_existing(one)?,
+            ))
+        } else {
+            Ok(MergedNode::branch(
+                split_index,
+                self.merge_existing(zero)?,
+                self.plan_merge(one, other)?,
+            ))
+        }
+    }
+
+    /// Writes the new branches of `node` planned by `plan_merge`, returning its location.  Each
+    /// existing node gains a reference from the new branch above it.
+    /// # Errors
+    /// `Exception` generated if a node cannot be read or written.
+    fn write_merged(&mut self, node: &MergedNode<ArrayType>) -> BinaryMerkleTreeResult<ArrayType> {
+        match node {
+            MergedNode::Existing { location, .. } => {
+                if self.options.refcounting() {
+                    self.add_reference(location)?;
+                }
+                Ok(*location)
+            }
+            MergedNode::Branch {
+                split_index,
+                key,
+                count,
+                zero,
+                one,
+            } => {
+                let zero_location = self.write_merged(zero)?;
+                let one_location = self.write_merged(one)?;
+                let mut branch = BranchType::new();
+                branch.set_zero(zero_location);
+                branch.set_one(one_location);
+                branch.set_count(*count);
+                branch.set_split_index(*split_index);
+                branch.set_key(*key);
+                self.write_branch::<HasherType>(branch)
+            }
+        }
+    }
+
+    /// Rebuilds the branches of a tree from its surviving leaves, returning the recovered root.
+    /// This recovers a tree whose branch nodes were lost while its leaf and data nodes survived,
+    /// and the root matches the original when every leaf of the tree is given.  The leaves keep
+    /// the references held by their lost branches, so if any branches survived the references
+    /// should be corrected with `recount_references` afterwards.
+    /// # Errors
+    /// `Exception` generated if `leaf_locations` is empty, a location does not hold a leaf, or two
+    /// leaves share a key.
+    #[inline]
+    pub fn rebuild_from_leaves(
+        &mut self,
+        leaf_locations: &[ArrayType],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        let mut tree_refs = Vec::with_capacity(leaf_locations.len());
+        for location in leaf_locations {
+            if let NodeVariant::Leaf(leaf) = self.get_tree_node(*location)?.get_variant() {
+                tree_refs.push(TreeRef::new(*leaf.get_key(), *location, 1, 1));
+            } else {
+                return Err(Exception::new(
+                    "Cannot rebuild tree: location does not hold a leaf",
+                ));
+            }
+        }
+        tree_refs.sort_by(|a, b| KeyOrderType::compare(&a.key, &b.key));
+
+        if tree_refs.windows(2).any(|pair| pair[0].key == pair[1].key) {
+            return Err(Exception::new(
+                "Cannot rebuild tree: two leaves share a key",
+            ));
+        }
+
+        self.create_tree(tree_refs)
+    }
+
+    /// Creates a new root containing only the keys under `root_hash` whose key and value satisfy
+    /// `predicate`.  The leaves of the matching keys are shared with the original tree, which is
+    /// left intact.
+    /// # Errors
+    /// `Exception` generated if no keys match `predicate`, or when an invalid state is encountered
+    /// during tree traversal.
+    #[inline]
+    pub fn filter<PredicateType>(
+        &mut self,
+        root_hash: &ArrayType,
+        predicate: PredicateType,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        PredicateType: Fn(&ArrayType, &ValueType) -> bool,
+    {
+        self.release_dropped_snapshots()?;
+
+        let mut tree_refs = Vec::new();
+        for (location, leaf) in self.collect_leaves(root_hash)? {
+            let value = ValueType::decode(&self.get_leaf_value(&leaf)?)?;
+            if predicate(leaf.get_key(), &value) {
+                tree_refs.push(TreeRef::new(*leaf.get_key(), location, 1, 1));
+            }
+        }
+
+        if tree_refs.is_empty() {
+            return Err(Exception::new("No keys match the predicate"));
+        }
+
+        self.share_leaves(&tree_refs)?;
+        self.create_tree(tree_refs)
+    }
+
+    /// Splits the tree under `root_hash` into two new roots at `boundary`: the first holding the
+    /// keys less than `boundary` and the second the keys greater than or equal to it.  Together the
+    /// two roots hold every key of the original tree, whose leaves they share, and the original
+    /// tree is left intact.
+    /// # Errors
+    /// `Exception` generated if either side of `boundary` holds no keys, or when an invalid state is
+    /// encountered during tree traversal.
+    #[inline]
+    pub fn split(
+        &mut self,
+        root_hash: &ArrayType,
+        boundary: &ArrayType,
+    ) -> BinaryMerkleTreeResult<(ArrayType, ArrayType)> {
+        self.release_dropped_snapshots()?;
+
+        let mut tree_refs = Vec::new();
+        for (location, leaf) in self.collect_leaves(root_hash)? {
+            tree_refs.push(TreeRef::new(*leaf.get_key(), location, 1, 1));
+        }
+        let (lower, upper): (Vec<_>, Vec<_>) = tree_refs.into_iter().partition(|tree_ref| {
+            KeyOrderType::compare(&tree_ref.key, boundary) == std::cmp::Ordering::Less
+        });
+
+        if lower.is_empty() || upper.is_empty() {
+            return Err(Exception::new(
+                "Cannot split tree: no keys on one side of the boundary",
+            ));
+        }
+
+        self.share_leaves(&lower)?;
+        let lower_root = self.create_tree(lower)?;
+        self.share_leaves(&upper)?;
+        let upper_root = self.create_tree(upper)?;
+        Ok((lower_root, upper_root))
+    }
+
+    /// Rebuilds the tree under `root_hash` with every key replaced by `rekey_fn` of the key, for
+    /// migrating a tree to a new key derivation, such as from raw keys to hashed keys.  Each value
+    /// is stored again under its new key without being decoded, and the new root is returned.  The
+    /// original tree is left intact, and an empty tree rekeys to the empty root.
+    /// # Errors
+    /// `Exception` generated if `rekey_fn` maps two keys to the same key, before anything is
+    /// written, or when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn rekey<RekeyFnType>(
+        &mut self,
+        root_hash: &ArrayType,
+        rekey_fn: RekeyFnType,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        RekeyFnType: Fn(&ArrayType) -> ArrayType,
+    {
+        if *root_hash == Self::empty_root() {
+            return Ok(Self::empty_root());
+        }
+
+        let mut entries = Vec::new();
+        for (_, leaf) in self.collect_leaves(root_hash)? {
+            let key = leaf.get_key();
+            entries.push((rekey_fn(key), *key, self.get_leaf_value(&leaf)?));
+        }
+        entries.sort_by(|a, b| KeyOrderType::compare(&a.0, &b.0));
+        if let Some(pair) = entries.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(Exception::new(&format!(
+                "Cannot rekey tree: keys {} and {} both map to {}",
+                to_hex(pair[0].1.as_ref()),
+                to_hex(pair[1].1.as_ref()),
+                to_hex(pair[0].0.as_ref())
+            )));
+        }
+
+        let mut keys = entries.iter().map(|entry| entry.0).collect::<Vec<_>>();
+        let values = entries
+            .iter()
+            .map(|entry| entry.2.as_slice())
+            .collect::<Vec<_>>();
+        self.insert_encoded(None, &mut keys, &values)
+    }
+
+    /// Builds a new tree from `shards`, each yielding key and value pairs sorted in key order, by
+    /// merging them into a single sorted stream.  Each value is encoded and written as its leaf as
+    /// soon as it is merged, so only the keys and leaf locations of the merged stream are held in
+    /// memory, never the values.  Shards yielding nothing are ignored, and merging no pairs at all
+    /// returns the empty root.
+    /// # Errors
+    /// `Exception` generated if the merged stream holds a duplicate key, which includes the same
+    /// key appearing in two shards, or a shard is not sorted.  Otherwise generated with the same
+    /// kinds as `insert`, except that the depth is only checked once every leaf has been written.
+    /// With reference counting enabled, the leaves already written when the error is found are
+    /// released again, so nothing is left unreferenced.
+    #[inline]
+    pub fn merge_sorted_shards<ShardType>(
+        &mut self,
+        shards: Vec<ShardType>,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        ShardType: Iterator<Item = (ArrayType, ValueType)>,
+    {
+        self.release_dropped_snapshots()?;
+
+        let mut tree_refs: Vec<TreeRef<ArrayType>> = Vec::new();
+        let merged = self
+            .write_shard_leaves(shards, &mut tree_refs)
+            .and_then(|()| self.check_shard_depth(&tree_refs));
+        if let Err(e) = merged {
+            if self.options.refcounting() {
+                for tree_ref in &tree_refs {
+                    self.remove_root(&tree_ref.location)?;
+                }
+            }
+            return Err(e);
+        }
+
+        if tree_refs.is_empty() {
+            return Ok(Self::empty_root());
+        }
+        self.record_depth()?;
+        self.create_tree(tree_refs)
+    }
+
+    /// Merges `shards` into a single sorted stream for `merge_sorted_shards`, writing the leaf of
+    /// each pair and pushing its `TreeRef` onto `tree_refs` as it goes.
+    /// # Errors
+    /// `Exception` generated if the merged stream holds a duplicate key or is not sorted, or a
+    /// leaf cannot be written.  The leaves written before the error are left in `tree_refs`.
+    fn write_shard_leaves<ShardType>(
+        &mut self,
+        mut shards: Vec<ShardType>,
+        tree_refs: &mut Vec<TreeRef<ArrayType>>,
+    ) -> BinaryMerkleTreeResult<()>
+    where
+        ShardType: Iterator<Item = (ArrayType, ValueType)>,
+    {
+        let mut heads = shards.iter_mut().map(Iterator::next).collect::<Vec<_>>();
+        loop {
+            let mut smallest: Option<usize> = None;
+            for (i, head) in heads.iter().enumerate() {
+                if let Some((key, _)) = head {
+                    let is_smaller = smallest.map_or(true, |j| {
+                        heads[j].as_ref().is_some_and(|(smallest_key, _)| {
+                            KeyOrderType::compare(key, smallest_key) == std::cmp::Ordering::Less
+                        })
+                    });
+                    if is_smaller {
+                        smallest = Some(i);
+                    }
+                }
+            }
+            let shard = match smallest {
+                Some(shard) => shard,
+                None => break,
+            };
+            let (key, value) = match heads[shard].take() {
+                Some(pair) => pair,
+                None => break,
+            };
+            heads[shard] = shards[shard].next();
+
+            if let Some(previous) = tree_refs.last() {
+                match KeyOrderType::compare(&previous.key, &key) {
+                    std::cmp::Ordering::Less => (),
+                    std::cmp::Ordering::Equal => {
+                        return Err(Exception::new(
+                            "Attempted to insert item with duplicate keys",
+                        ));
+                    }
+                    std::cmp::Ordering::Greater => {
+                        return Err(Exception::new(&format!(
+                            "Shard {} is not sorted: key {} follows a larger key",
+                            shard,
+                            to_hex(key.as_ref())
+                        )));
+                    }
+                }
+            }
+            self.check_batch_size(tree_refs.len() + 1)?;
+
+            let encoded = self.encode_value(&value)?;
+            let mut value_map = HashMap::with_capacity(1);
+            value_map.insert(key, encoded.as_slice());
+            let location = self.insert_leaves(&[key], &value_map)?[0];
+            tree_refs.push(TreeRef::new(key, location, 1, 1));
+        }
+        Ok(())
+    }
+
+    /// Checks that the configured depth can hold the tree over the keys of `tree_refs`, which are
+    /// sorted in key order.
+    /// # Errors
+    /// `Exception` of kind `DepthInsufficient` generated if the keys need a deeper tree.
+    fn check_shard_depth(&self, tree_refs: &[TreeRef<ArrayType>]) -> BinaryMerkleTreeResult<()> {
+        let keys = tree_refs
+            .iter()
+            .map(|tree_ref| tree_ref.key)
+            .collect::<Vec<_>>();
+        let needed = tree_depth_ordered::<KeyOrderType, _>(&keys);
+        if needed > self.depth {
+            return Err(Exception::with_kind(
+                ErrorKind::DepthInsufficient {
+                    needed,
+                    configured: self.depth,
+                },
+                "The keys need a deeper tree than the configured depth",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Bumps the references of the existing leaves in `tree_refs`, which are about to be shared by
+    /// a new tree.
+    /// # Errors
+    /// `Exception` generated if a leaf is missing from the database.
+    fn share_leaves(&mut self, tree_refs: &[TreeRef<ArrayType>]) -> BinaryMerkleTreeResult<()> {
+        if !self.options.refcounting() {
+            return Ok(());
+        }
+        for tree_ref in tree_refs {
+            if let Some(mut l) = self.db.get_node(tree_ref.location)? {
+                let leaf_refs = l.get_references() + 1;
+                l.set_references(leaf_refs);
+                self.refcount_changed(&tree_ref.location, leaf_refs - 1, leaf_refs);
+                self.buffer_node(tree_ref.location, l)?;
+            } else {
+                return Err(Exception::new(
+                    "Corrupt merkle tree: Failed to update leaf references",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the tree under `root_hash` using `NewHasherType` to compute node locations, returning
+    /// the new root.  The keys and values are unchanged and the original tree is left intact.
+    /// Since `get` only follows stored locations, the new root can be read from this tree, but
+    /// generating and verifying proofs for it requires a tree using `NewHasherType`.  An empty
+    /// tree migrates to the empty root.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn migrate_hasher<NewHasherType>(
+        &mut self,
+        root_hash: &ArrayType,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        NewHasherType: Hasher<ArrayType, HashType = NewHasherType>,
+    {
+        if *root_hash == Self::empty_root() {
+            return Ok(Self::empty_root());
+        }
+
+        let leaves = self.collect_leaves(root_hash)?;
+        let mut tree_refs = Vec::with_capacity(leaves.len());
+        for (_, leaf) in leaves {
+            let key = *leaf.get_key();
+            let value = self.get_leaf_value(&leaf)?;
+            let location = self.insert_leaf::<NewHasherType>(&key, &value)?;
+            tree_refs.push(TreeRef::new(key, location, 1, 1));
+        }
+        self.create_tree_with_hasher::<NewHasherType>(tree_refs)
+    }
+
+    /// Recomputes the root hash of the tree under `root_hash` from the keys, values and shape of
+    /// the tree alone, ignoring stored locations and reference counts.  Two trees holding the same
+    /// keys and values have the same structural root however they were built, and for an intact
+    /// tree it matches `root_hash`.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn structural_root(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<ArrayType> {
+        self.structural_root_with_cancel(root_hash, None)
+    }
+
+    /// Checks that the tree under `root_hash` is intact: every node it references is present, and
+    /// the root recomputed from its keys and values by `structural_root` is `root_hash` itself.
+    /// Reading every value, this fails on a commit-only tree.
+    /// # Errors
+    /// `Exception` generated if the tree does not match `root_hash`, or when an invalid state is
+    /// encountered during tree traversal.
+    #[inline]
+    pub fn verify_tree(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<()> {
+        self.verify_tree_with_cancel(root_hash, None)
+    }
+
+    /// Checks that the tree under `root_hash` is intact like `verify_tree`, checking `cancel` at
+    /// each node.
+    /// # Errors
+    /// `Exception` generated if the tree does not match `root_hash`, when an invalid state is
+    /// encountered during tree traversal, or when the verification is cancelled.
+    #[inline]
+    pub fn verify_tree_cancellable(
+        &self,
+        root_hash: &ArrayType,
+        cancel: &AtomicBool,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.verify_tree_with_cancel(root_hash, Some(cancel))
+    }
+
+    /// Checks that the tree under `root_hash` is intact, checking the optional `cancel` at each
+    /// node.
+    /// # Errors
+    /// `Exception` generated if the tree does not match `root_hash`, when an invalid state is
+    /// encountered during tree traversal, or when `cancel` is set.
+    fn verify_tree_with_cancel(
+        &self,
+        root_hash: &ArrayType,
+        cancel: Option<&AtomicBool>,
+    ) -> BinaryMerkleTreeResult<()> {
+        if self.structural_root_with_cancel(root_hash, cancel)? == *root_hash {
+            return Ok(());
+        }
+        Err(Exception::new(
+            "Corrupt merkle tree: Nodes do not hash to the root",
+        ))
+    }
+
+    /// Recomputes the root hash of the tree under `root_hash` like `structural_root`, checking the
+    /// optional `cancel` at each node.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal, or when
+    /// `cancel` is set.
+    fn structural_root_with_cancel(
+        &self,
+        root_hash: &ArrayType,
+        cancel: Option<&AtomicBool>,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        if *root_hash == Self::empty_root() {
+            return Ok(Self::empty_root());
+        }
+        // Each entry is a node location, its depth, and whether its children have been hashed
+        let mut nodes = vec![(*root_hash, 0, false)];
+        let mut hashes = Vec::with_capacity(self.depth);
+        while let Some((location, depth, children_hashed)) = nodes.pop() {
+            check_cancelled(cancel)?;
+            if depth > self.depth {
+                return Err(Exception::with_kind(
+                    ErrorKind::DepthExceeded,
+                    "Depth of merkle tree exceeded",
+                ));
+            }
+
+            if children_hashed {
+                let one = hashes.pop();
+                let zero = hashes.pop();
+                if let (Some(z), Some(o)) = (zero, one) {
+                    hashes.push(
+                        self.options
+                            .scheme()
+                            .branch_location::<HasherType, _>(&z, &o),
+                    );
+                    continue;
+                }
+                return Err(Exception::new("Branch is missing its children"));
+            }
+
+            let node = if let Some(n) = self.db.get_node(location)? {
+                n
+            } else {
+                return Err(Exception::new("Failed to find node in database."));
+            };
+
+            match node.get_variant() {
+                NodeVariant::Branch(b) => {
+                    nodes.push((location, depth, true));
+                    nodes.push((*b.get_one(), depth + 1, false));
+                    nodes.push((*b.get_zero(), depth + 1, false));
+                }
+                NodeVariant::Leaf(l) => {
+                    let value = self.get_leaf_value(&l)?;
+                    hashes.push(
+                        self.options
+                            .scheme()
+                            .leaf_location_of_value::<HasherType, _>(l.get_key(), &value),
+                    );
+                }
+                NodeVariant::Data(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found data node while traversing tree",
+                    ));
+                }
+                NodeVariant::Phantom(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found phantom node while traversing tree",
+                    ));
+                }
+            }
+        }
+
+        if let Some(root) = hashes.pop() {
+            Ok(root)
+        } else {
+            Err(Exception::new("Failed to get root."))
+        }
+    }
+
+    /// Finds the node covering exactly the keys under `root_hash` whose first `prefix_bits` bits
+    /// match `prefix`, returning its location.  This is the branch (or, for a single key, the leaf)
+    /// whose subtree holds all of those keys and no others, so an inclusion proof of that node
+    /// commits to the whole range.  Returns `None` if no keys have the prefix.
+    /// # Errors
+    /// `Exception` generated if `prefix` is shorter than `prefix_bits` or `prefix_bits` exceeds
+    /// the key length, or when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn subtree_root(
+        &self,
+        root_hash: &ArrayType,
+        prefix: &[u8],
+        prefix_bits: u32,
+    ) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        let prefix_bits = usize::try_from(prefix_bits)?;
+        let key_bits = ArrayType::default().as_ref().len() * 8;
+        if prefix_bits > prefix.len() * 8 || prefix_bits > key_bits {
+            return Err(Exception::new(
+                "Prefix is longer than the given bytes or the key",
+            ));
+        }
+
+        let mut location = *root_hash;
+        for _ in 0..=self.depth {
+            let node = if let Some(n) = self.db.get_node(location)? {
+                n
+            } else {
+                return Ok(None);
+            };
+
+            match node.get_variant() {
+                NodeVariant::Branch(b) => {
+                    let split_index = b.get_split_index();
+                    if prefix_bits <= split_index {
+                        // Every key below this branch shares its first `split_index` bits
+                        if prefix_matches(
+                            KeyOrderType::order_key(b.get_key()).as_ref(),
+                            prefix,
+                            prefix_bits,
+                        ) {
+                            return Ok(Some(location));
+                        }
+                        return Ok(None);
+                    }
+                    if !prefix_matches(
+                        KeyOrderType::order_key(b.get_key()).as_ref(),
+                        prefix,
+                        split_index,
+                    ) {
+                        return Ok(None);
+                    }
+                    let bit = (prefix[split_index >> 3] >> (7 - split_index % 8)) & 1;
+                    location = if bit == 0 {
+                        *b.get_zero()
+                    } else {
+                        *b.get_one()
+                    };
+                }
+                NodeVariant::Leaf(l) => {
+                    if prefix_matches(
+                        KeyOrderType::order_key(l.get_key()).as_ref(),
+                        prefix,
+                        prefix_bits,
+                    ) {
+                        return Ok(Some(location));
+                    }
+                    return Ok(None);
+                }
+                NodeVariant::Data(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found data node while traversing tree",
+                    ));
+                }
+                NodeVariant::Phantom(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found phantom node while traversing tree",
+                    ));
+                }
+            }
+        }
+        Err(Exception::with_kind(
+            ErrorKind::DepthExceeded,
+            "Depth of merkle tree exceeded",
+        ))
+    }
+
+    /// Finds the keys under `new_root` whose leaves are new or changed since `base_root`, in
+    /// ascending order.  Both trees are descended together and subtrees stored at the same
+    /// location in both are skipped, so the work done is proportional to the size of the change
+    /// rather than the size of the trees.  Only branches and leaves are read, never values.  Keys
+    /// removed since `base_root` are not included.  Like `keys_only_in`, keys are produced lazily
+    /// and the iterator ends after an error.
+    #[inline]
+    pub fn changed_keys_since<'a>(
+        &'a self,
+        base_root: &ArrayType,
+        new_root: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<ArrayType>> + 'a {
+        self.key_differences(*new_root, *base_root, true)
+    }
+
+    /// Gets the keys under `root_a` which are absent from `root_b`, in ascending order, regardless
+    /// of their values.  Like `changed_keys_since`, both trees are descended together and subtrees
+    /// stored at the same location in both are skipped, and only branches and leaves are read.
+    /// Keys are produced lazily, so large differences are never held in memory at once; after an
+    /// error the iterator ends.  Swapping the roots gives the keys only in `root_b`.
+    #[inline]
+    pub fn keys_only_in<'a>(
+        &'a self,
+        root_a: &ArrayType,
+        root_b: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<ArrayType>> + 'a {
+        self.key_differences(*root_a, *root_b, false)
+    }
+
+    /// Lazily gets the keys under `new_root` missing from `base_root`, and those whose leaves
+    /// differ between them if `include_changed` is set, for `changed_keys_since` and
+    /// `keys_only_in`.
+    fn key_differences(
+        &self,
+        new_root: ArrayType,
+        base_root: ArrayType,
+        include_changed: bool,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<ArrayType>> + '_ {
+        let mut nodes = Vec::with_capacity(self.depth);
+        if new_root != Self::empty_root() {
+            let base = Some(base_root).filter(|root| *root != Self::empty_root());
+            nodes.push((base, new_root, 0));
+        }
+        std::iter::from_fn(move || {
+            while let Some((base_subtree, location, depth)) = nodes.pop() {
+                match self.visit_for_key_difference(
+                    base_subtree,
+                    location,
+                    depth,
+                    include_changed,
+                    &mut nodes,
+                ) {
+                    Ok(Some(key)) => return Some(Ok(key)),
+                    Ok(None) => (),
+                    Err(e) => {
+                        nodes.clear();
+                        return Some(Err(e));
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    /// Visits the node at `new_location`, `depth` levels below the root of the new tree, for
+    /// `changed_keys_since` and `keys_only_in`.  `base_subtree` is the subtree of the base tree
+    /// that could hold the same keys, or `None` if the base tree holds none of them.  The pairs
+    /// left to compare are pushed onto `nodes`, and a key missing from the base tree is returned.
+    /// A key whose leaf differs between the trees is returned as well if `include_changed` is set.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    fn visit_for_key_difference(
+        &self,
+        base_subtree: Option<ArrayType>,
+        new_location: ArrayType,
+        depth: usize,
+        include_changed: bool,
+        nodes: &mut Vec<(Option<ArrayType>, ArrayType, usize)>,
+    ) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        if depth > self.depth {
+            return Err(Exception::with_kind(
+                ErrorKind::DepthExceeded,
+                "Depth of merkle tree exceeded",
+            ));
+        }
+        if base_subtree == Some(new_location) {
+            return Ok(None);
+        }
+
+        let (new_split, new_key, new_children) = self.split_of(new_location)?;
+        let base_location = if let Some(b) = base_subtree {
+            b
+        } else {
+            // Every key of the subtree is missing from the base tree
+            if let Some((new_zero, new_one)) = new_children {
+                nodes.push((None, new_one, depth + 1));
+                nodes.push((None, new_zero, depth + 1));
+                return Ok(None);
+            }
+            return Ok(Some(new_key));
+        };
+        let (base_split, base_key, base_children) = self.split_of(base_location)?;
+
+        if !prefix_matches(
+            KeyOrderType::order_key(&base_key).as_ref(),
+            KeyOrderType::order_key(&new_key).as_ref(),
+            new_split.min(base_split),
+        ) {
+            // The subtrees hold disjoint keys
+            nodes.push((None, new_location, depth));
+            return Ok(None);
+        }
+
+        match (base_children, new_children) {
+            (Some((base_zero, base_one)), Some((new_zero, new_one))) if base_split == new_split => {
+                nodes.push((Some(base_one), new_one, depth + 1));
+                nodes.push((Some(base_zero), new_zero, depth + 1));
+            }
+            (Some((base_zero, base_one)), _) if base_split < new_split => {
+                // The new subtree lies entirely on one side of the base branch
+                let base_child = if choose_zero_ordered::<KeyOrderType, _>(new_key, base_split)? {
+                    base_zero
+                } else {
+                    base_one
+                };
+                nodes.push((Some(base_child), new_location, depth));
+            }
+            (_, Some((new_zero, new_one))) => {
+                // The base subtree lies entirely on one side of the new branch
+                if choose_zero_ordered::<KeyOrderType, _>(base_key, new_split)? {
+                    nodes.push((None, new_one, depth + 1));
+                    nodes.push((Some(base_location), new_zero, depth + 1));
+                } else {
+                    nodes.push((Some(base_location), new_one, depth + 1));
+                    nodes.push((None, new_zero, depth + 1));
+                }
+            }
+            _ => {
+                // Two leaves at different locations holding the same key, whose value changed
+                if include_changed {
+                    return Ok(Some(new_key));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Gets the split index, key and children of the branch or leaf at `location`.  A leaf
+    /// behaves as a branch splitting past the end of its key, with no children.
+    /// # Errors
+    /// `Exception` generated if the node is missing or is not a branch or leaf.
+    fn split_of(&self, location: ArrayType) -> BinaryMerkleTreeResult<SplitSummary<ArrayType>> {
+        match self.get_tree_node(location)?.get_variant() {
+            NodeVariant::Branch(b) => Ok((
+                b.get_split_index(),
+                *b.get_key(),
+                Some((*b.get_zero(), *b.get_one())),
+            )),
+            NodeVariant::Leaf(l) => {
+                Ok((ArrayType::default().as_ref().len() * 8, *l.get_key(), None))
+            }
+            NodeVariant::Data(_) | NodeVariant::Phantom(_) => Err(Exception::new(
+                "Corrupt merkle tree: Found non branch or leaf node while traversing tree",
+            )),
+        }
+    }
+
+    /// Gets the decoded contents of the node stored at `location`, or `None` if there is no node
+    /// there, so external tools can walk the structure of a tree without access to its database.
+    /// Locations come from roots, the siblings of proofs, `nodes_at_depth`, or the children and
+    /// data locations of nodes already read.
+    /// # Errors
+    /// `Exception` generated if the node cannot be read from the database.
+    #[inline]
+    pub fn get_node_at(
+        &self,
+        location: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<NodeVariant<BranchType, LeafType, DataType, ArrayType>>>
+    {
+        Ok(self.db.get_node(*location)?.map(Node::get_variant))
+    }
+
+    /// Gets the node at `location`, failing if it is missing.
+    /// # Errors
+    /// `Exception` generated if the node is not in the database.
+    fn get_tree_node(&self, location: ArrayType) -> BinaryMerkleTreeResult<NodeType> {
+        if let Some(n) = self.db.get_node(location)? {
+            Ok(n)
+        } else {
+            Err(Exception::new("Failed to find node in database."))
+        }
+    }
+
+    /// Gets the locations of the nodes `depth` levels below `root_hash`, from the zero side to the
+    /// one side.  A leaf found above `depth` ends its path and is returned as well, so together the
+    /// locations cover every key under `root_hash` exactly once, and each can be used as the root
+    /// of an independent subtree.  A `depth` of zero returns `root_hash` itself.
+    /// # Errors
+    /// `Exception` generated if `depth` exceeds the depth of the tree, or when an invalid state is
+    /// encountered during tree traversal.
+    #[inline]
+    pub fn nodes_at_depth(
+        &self,
+        root_hash: &ArrayType,
+        depth: usize,
+    ) -> BinaryMerkleTreeResult<Vec<ArrayType>> {
+        if depth > self.depth {
+            return Err(Exception::with_kind(
+                ErrorKind::DepthExceeded,
+                "Depth of merkle tree exceeded",
+            ));
+        }
+
+        if *root_hash == Self::empty_root() {
+            return Ok(Vec::new());
+        }
+        let mut level = vec![*root_hash];
+        for _ in 0..depth {
+            let mut next_level = Vec::with_capacity(level.len() * 2);
+            for location in level {
+                match self.get_tree_node(location)?.get_variant() {
+                    NodeVariant::Branch(b) => {
+                        next_level.push(*b.get_zero());
+                        next_level.push(*b.get_one());
+                    }
+                    NodeVariant::Leaf(_) => next_level.push(location),
+                    NodeVariant::Data(_) => {
+                        return Err(Exception::new(
+                            "Corrupt merkle tree: Found data node while traversing tree",
+                        ));
+                    }
+                    NodeVariant::Phantom(_) => {
+                        return Err(Exception::new(
+                            "Corrupt merkle tree: Found phantom node while traversing tree",
+                        ));
+                    }
+                }
+            }
+            level = next_level;
+        }
+        Ok(level)
+    }
+
+    /// Gets the number of leaves under each of the nodes `depth` levels below `root_hash`, in the
+    /// order of `nodes_at_depth`.  A branch reports the count it records and a leaf counts once, so
+    /// the counts add up to the number of keys under `root_hash`, for choosing balanced boundaries
+    /// when splitting the tree into shards.
+    /// # Errors
+    /// `Exception` generated if `depth` exceeds the depth of the tree, or when an invalid state is
+    /// encountered during tree traversal.
+    #[inline]
+    pub fn subtree_size_histogram(
+        &self,
+        root_hash: &ArrayType,
+        depth: usize,
+    ) -> BinaryMerkleTreeResult<Vec<(ArrayType, u64)>> {
+        let locations = self.nodes_at_depth(root_hash, depth)?;
+        let mut histogram = Vec::with_capacity(locations.len());
+        for location in locations {
+            let count = match self.get_tree_node(location)?.get_variant() {
+                NodeVariant::Branch(b) => b.get_count(),
+                NodeVariant::Leaf(_) => 1,
+                NodeVariant::Data(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found data node while traversing tree",
+                    ));
+                }
+                NodeVariant::Phantom(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found phantom node while traversing tree",
+                    ));
+                }
+            };
+            histogram.push((location, count));
+        }
+        Ok(histogram)
+    }
+
+    /// Counts the nodes reachable from both `root_a` and `root_b`, including data nodes.  Trees
+    /// built on top of one another share every node outside the paths that changed, so this
+    /// checks that an insert only rewrote the nodes it had to.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn shared_node_count(
+        &self,
+        root_a: &ArrayType,
+        root_b: &ArrayType,
+    ) -> BinaryMerkleTreeResult<usize> {
+        let reachable_a = self.reachable_locations(root_a)?;
+        let reachable_b = self.reachable_locations(root_b)?;
+        Ok(reachable_a.intersection(&reachable_b).count())
+    }
+
+    /// Counts the distinct data node locations referenced by the leaves under `root_hash`.  Data
+    /// nodes are content addressed by their key and value, so by default this is the number of
+    /// distinct (key, value) pairs, which is every leaf.  With `set_value_addressed_data` the
+    /// location depends on the value alone, and this becomes the number of distinct values.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn distinct_value_count(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<usize> {
+        let locations = self
+            .collect_leaves(root_hash)?
+            .iter()
+            .map(|(_, leaf)| *leaf.get_data())
+            .collect::<HashSet<_>>();
+        Ok(locations.len())
+    }
+
+    /// Gets the depth of the tree under `root_hash`, the number of branches on its longest path
+    /// from the root to a leaf.  The traversal is not limited by the maximum depth of this tree,
+    /// so this also measures trees too deep to be read with it.
+    /// # Errors
+    /// `Exception` generated if a node is missing, or when an invalid state is encountered during
+    /// tree traversal.
+    #[inline]
+    pub fn depth_of(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<usize> {
+        let key_bits = ArrayType::default().as_ref().len() * 8;
+        let mut max_depth = 0;
+        if *root_hash == Self::empty_root() {
+            return Ok(max_depth);
+        }
+        let mut nodes = vec![(*root_hash, 0)];
+        while let Some((location, depth)) = nodes.pop() {
+            if depth > key_bits {
+                return Err(Exception::new(
+                    "Corrupt merkle tree: Found more branches than key bits",
+                ));
+            }
+
+            let node = if let Some(n) = self.db.get_node(location)? {
+                n
+            } else {
+                return Err(Exception::new("Failed to find node in database."));
+            };
+
+            match node.get_variant() {
+                NodeVariant::Branch(b) => {
+                    nodes.push((*b.get_one(), depth + 1));
+                    nodes.push((*b.get_zero(), depth + 1));
+                }
+                NodeVariant::Leaf(_) => max_depth = max_depth.max(depth),
+                NodeVariant::Data(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found data node while traversing tree",
+                    ));
+                }
+                NodeVariant::Phantom(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found phantom node while traversing tree",
+                    ));
+                }
+            }
+        }
+        Ok(max_depth)
+    }
+
+    /// Gets the locations of every stored node not reachable from any of `live_roots`, in
+    /// ascending order, without removing anything.  These are the nodes a collection keeping only
+    /// `live_roots` would reclaim.  Requires a database able to enumerate its nodes.
+    /// # Errors
+    /// `Exception` generated if the database cannot enumerate its nodes, or when an invalid state
+    /// is encountered during tree traversal.
+    #[inline]
+    pub fn find_orphans(&self, live_roots: &[ArrayType]) -> BinaryMerkleTreeResult<Vec<ArrayType>> {
+        let mut reachable = HashSet::new();
+        for root in live_roots {
+            reachable.extend(self.reachable_locations(root)?);
+        }
+
+        let mut orphans = self
+            .db
+            .node_locations()?
+            .into_iter()
+            .filter(|location| !reachable.contains(location))
+            .collect::<Vec<_>>();
+        orphans.sort();
+        Ok(orphans)
+    }
+
+    /// Copies every node stored in the database into `target`, whichever roots they belong to, for
+    /// a backup of the whole database rather than of one tree.  Nodes are streamed through
+    /// `Database::scan`, so they are never all held in memory; with a flush interval set (see
+    /// `set_flush_every`) `target` is also written every that many nodes.  The root pointer and
+    /// recorded depth are not copied.  Returns the number of nodes copied.
+    /// # Errors
+    /// `Exception` generated if the database cannot enumerate its nodes, or a node cannot be read or
+    /// written to `target`.
+    #[inline]
+    pub fn backup_to<TargetType>(&self, target: &mut TargetType) -> BinaryMerkleTreeResult<usize>
+    where
+        TargetType: Database<ArrayType, NodeType = NodeType>,
+    {
+        let mut copied = 0;
+        for entry in self.db.scan() {
+            let (location, node) = entry?;
+            target.insert(location, node)?;
+            copied += 1;
+            if self.options.flush_every() != 0 && copied % self.options.flush_every() == 0 {
+                target.batch_write()?;
+            }
+        }
+        target.batch_write()?;
+        Ok(copied)
+    }
+
+    /// Collects the location of every node reachable from `root_hash`, including data nodes.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    fn reachable_locations(
+        &self,
+        root_hash: &ArrayType,
+    ) -> BinaryMerkleTreeResult<HashSet<ArrayType>> {
+        let mut reachable = HashSet::new();
+        if *root_hash == Self::empty_root() {
+            return Ok(reachable);
+        }
+        let mut nodes = vec![(*root_hash, 0)];
+        while let Some((location, depth)) = nodes.pop() {
+            if depth > self.depth {
+                return Err(Exception::with_kind(
+                    ErrorKind::DepthExceeded,
+                    "Depth of merkle tree exceeded",
+                ));
+            }
+            if !reachable.insert(location) {
+                continue;
+            }
+
+            match self.get_tree_node(location)?.get_variant() {
+                NodeVariant::Branch(b) => {
+                    nodes.push((*b.get_zero(), depth + 1));
+                    nodes.push((*b.get_one(), depth + 1));
+                }
+                NodeVariant::Leaf(l) => {
+                    if l.get_inline_value().is_none() {
+                        reachable.insert(*l.get_data());
+                    }
+                }
+                NodeVariant::Data(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found data node while traversing tree",
+                    ));
+                }
+                NodeVariant::Phantom(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found phantom node while traversing tree",
+                    ));
+                }
+            }
+        }
+        Ok(reachable)
+    }
+
+    /// Collects the location and contents of every leaf under `root_hash`, in ascending key order.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    fn collect_leaves(
+        &self,
+        root_hash: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Vec<(ArrayType, LeafType)>> {
+        self.collect_leaves_with_cancel(root_hash, None)
+    }
+
+    /// Collects the leaves under `root_hash` like `collect_leaves`, checking the optional `cancel`
+    /// at each node.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal, or when
+    /// `cancel` is set.
+    fn collect_leaves_with_cancel(
+        &self,
+        root_hash: &ArrayType,
+        cancel: Option<&AtomicBool>,
+    ) -> BinaryMerkleTreeResult<Vec<(ArrayType, LeafType)>> {
+        let mut leaves = Vec::new();
+        if *root_hash == Self::empty_root() {
+            return Ok(leaves);
+        }
+        let mut nodes = vec![(*root_hash, 0)];
+        while let Some((location, depth)) = nodes.pop() {
+            check_cancelled(cancel)?;
+            if depth > self.depth {
+                return Err(Exception::with_kind(
+                    ErrorKind::DepthExceeded,
+                    "Depth of merkle tree exceeded",
+                ));
+            }
+
+            let node = if let Some(n) = self.db.get_node(location)? {
+                n
+            } else {
+                return Err(Exception::new("Failed to find node in database."));
+            };
+
+            match node.get_variant() {
+                NodeVariant::Branch(b) => {
+                    // Push the one side first so the zero side is visited first
+                    nodes.push((*b.get_one(), depth + 1));
+                    nodes.push((*b.get_zero(), depth + 1));
+                }
+                NodeVariant::Leaf(l) => leaves.push((location, l)),
+                NodeVariant::Data(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found data node while traversing tree",
+                    ));
+                }
+                NodeVariant::Phantom(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found phantom node while traversing tree",
+                    ));
+                }
+            }
+        }
+        Ok(leaves)
+    }
+
+    /// Gets the encoded value stored in the data node at `location`.
+    /// # Errors
+    /// `Exception` generated if the node is missing or is not a data node.
+    fn get_data_value(&self, location: &ArrayType) -> BinaryMerkleTreeResult<Vec<u8>> {
+        if let Some(d) = self.db.get_node(*location)? {
+            if let NodeVariant::Data(data) = d.get_variant() {
+                return self.resolve_data(&data);
+            }
+            return Err(Exception::new(
+                "Corrupt merkle tree: Found non data node after leaf",
+            ));
+        }
+        Err(Exception::new(
+            "Corrupt merkle tree: Failed to get leaf node from DB",
+        ))
+    }
+
+    /// Gets the encoded value held by `data`, reading it from the blob store if `data` only holds a
+    /// reference to it.
+    /// # Errors
+    /// `Exception` generated if the blob is missing, or the tree has no blob store.
+    fn resolve_data(&self, data: &DataType) -> BinaryMerkleTreeResult<Vec<u8>> {
+        if self.options.commit_only() {
+            return Err(Exception::new(
+                "Values cannot be read from a commit-only tree",
+            ));
+        }
+        if !data.is_blob_ref() {
+            return Ok(data.get_value().to_vec());
+        }
+        let store = self.blob_store.as_ref().ok_or_else(|| {
+            Exception::new("Data node references a blob, but the tree has no blob store")
+        })?;
+        let mut blob_key = ArrayType::default();
+        if blob_key.as_ref().len() != data.get_value().len() {
+            return Err(Exception::new(
+                "Corrupt merkle tree: Invalid blob reference",
+            ));
+        }
+        blob_key.as_mut().copy_from_slice(data.get_value());
+        store
+            .get_blob(&blob_key)?
+            .ok_or_else(|| Exception::new("Blob referenced by data node is missing"))
+    }
+
+    /// Gets the encoded value of `leaf`, from the leaf itself if it was inlined or otherwise from
+    /// its data node.
+    /// # Errors
+    /// `Exception` generated if the data node is missing or is not a data node.
+    fn get_leaf_value(&self, leaf: &LeafType) -> BinaryMerkleTreeResult<Vec<u8>> {
+        if let Some(value) = leaf.get_inline_value() {
+            return Ok(value.to_vec());
+        }
+        self.get_data_value(leaf.get_data())
+    }
+
+    /// Sets whether data nodes store only a hash of their value, the value location computed by
+    /// `HashScheme::value_location`, instead of the value itself, for commitment-only uses where
+    /// values never need to be read back.  Every data node then has the same small size, while
+    /// data node locations, roots and proofs stay the same as with the values stored.  Values
+    /// cannot be recovered from the tree in this mode: reading one fails, and `get_commitment`
+    /// gives the hash of the value instead.  Values are never inlined into leaves, apart from the
+    /// values of combined leaves.  The mode is not recorded in the database, so it should be
+    /// chosen before any data is inserted and set again whenever the tree is opened.
+    #[inline]
+    pub fn set_commit_only(&mut self, commit_only: bool) {
+        self.options.set_commit_only(commit_only);
+    }
+
+    /// Gets the commitment to the value of `key` under `root_hash`, the value location computed
+    /// by `HashScheme::value_location` from its encoded value, or `None` if `key` is not present.
+    /// Works in any mode, and is the only way of reading a key of a commit-only tree.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn get_commitment(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        let leaf = match self.get_leaves(root_hash, &mut [*key])?.pop() {
+            Some(leaf) => leaf,
+            None => return Ok(None),
+        };
+        if let Some(value) = leaf.get_inline_value() {
+            return Ok(Some(
+                self.options.scheme().value_location::<HasherType, _>(value),
+            ));
+        }
+        if !self.options.commit_only() {
+            let value = self.get_data_value(leaf.get_data())?;
+            return Ok(Some(
+                self.options
+                    .scheme()
+                    .value_location::<HasherType, _>(&value),
+            ));
+        }
+
+        if let Some(node) = self.db.get_node(*leaf.get_data())? {
+            if let NodeVariant::Data(data) = node.get_variant() {
+                let mut commitment = ArrayType::default();
+                if commitment.as_ref().len() != data.get_value().len() {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Invalid value commitment",
+                    ));
+                }
+                commitment.as_mut().copy_from_slice(data.get_value());
+                return Ok(Some(commitment));
+            }
+            return Err(Exception::new(
+                "Corrupt merkle tree: Found non data node after leaf",
+            ));
+        }
+        Err(Exception::new(
+            "Corrupt merkle tree: Failed to get leaf node from DB",
+        ))
+    }
+
+    /// Sets whether data nodes are addressed by their value only (`hash(b"d" || value)`) instead
+    /// of by their key and value (`hash(b"d" || key || value)`).  Value addressing lets identical
+    /// values under different keys share one data node, saving storage for datasets with many
+    /// repeated values.  Since the data location is part of every leaf hash, this changes all
+    /// roots and proofs produced by the tree: proofs must be checked with `verify_proof` on a tree
+    /// configured the same way, not with `verify_inclusion_proof`.  This setting should be chosen
+    /// before any data is inserted, for instance by opening the tree with `new_with_options` and
+    /// options whose scheme has value addressed data.
+    #[inline]
+    pub fn set_value_addressed_data(&mut self, value_addressed: bool) {
+        self.options
+            .scheme_mut()
+            .set_value_addressed_data(value_addressed);
+    }
+
+    /// Sets whether each key is stored as a single combined leaf holding its value and addressed
+    /// by `hash(b"kv" || key || value)`, instead of a leaf pointing at a separate data node.  This
+    /// halves the nodes of a tree and the reads of a `get`, at the cost of copying the value into
+    /// every leaf; the leaf type must support inline values.  Like `set_value_addressed_data`, this
+    /// changes all roots and proofs, so proofs must be checked with `verify_proof`, and
+    /// `verify_inclusion_by_value_hash` is unavailable since a combined leaf commits to the value
+    /// itself.  This setting should be chosen before any data is inserted.
+    #[inline]
+    pub fn set_combined_leaves(&mut self, combined: bool) {
+        self.options.scheme_mut().set_combined_leaves(combined);
+    }
+
+    /// Sets whether the children of each branch are hashed smaller location first, as
+    /// specifications sorting the children of a node expect, instead of zero child first.  The
+    /// children are still stored as zero and one, so traversal is unchanged, but like
+    /// `set_value_addressed_data` this changes all roots and proofs, and proofs must be checked
+    /// with `verify_proof`.  This setting should be chosen before any data is inserted.
+    #[inline]
+    pub fn set_sorted_children(&mut self, sorted: bool) {
+        self.options.scheme_mut().set_sorted_children(sorted);
+    }
+
+    /// Sets the tags hashed before the contents of data, leaf and branch nodes, which default to
+    /// `b"d"`, `b"l"` and `b"b"`.  Matching the tags of another implementation lets it verify this
+    /// tree's roots and proofs.  Like `set_value_addressed_data`, this changes all roots and proofs
+    /// produced by the tree, so proofs must be checked with `verify_proof`, and the tags should be
+    /// chosen before any data is inserted.
+    #[inline]
+    pub fn set_domain_tags(&mut self, tags: DomainTags) {
+        self.options.scheme_mut().set_tags(tags);
+    }
+
+    /// Sets the maximum size in bytes of an encoded value accepted on insert.  Inserting a larger
+    /// value fails with `ErrorKind::ValueTooLarge` before any node is written.  Zero, the default,
+    /// means values of any size are accepted.
+    #[inline]
+    pub fn set_max_value_bytes(&mut self, max_value_bytes: usize) {
+        self.options.set_max_value_bytes(max_value_bytes);
+    }
+
+    /// Sets the maximum number of keys accepted by a single `get`, `get_with_depth` or `insert`.  A
+    /// larger batch fails with `ErrorKind::BatchTooLarge` before anything proportional to it is
+    /// allocated, which lets a server bound the memory of each request.  Zero, the default, means
+    /// batches of any size are accepted.
+    #[inline]
+    pub fn set_max_batch_keys(&mut self, max_batch_keys: usize) {
+        self.options.set_max_batch_keys(max_batch_keys);
+    }
+
+    /// Sets the number of nodes an insert buffers in the database before writing them with an
+    /// intermediate `batch_write`, bounding the memory a database with write batches uses for a
+    /// large insert at the cost of more frequent commits.  The last nodes are still written when
+    /// the insert completes, so the root is the same for any interval, but an insert that fails
+    /// after an intermediate write leaves the nodes written so far in the database, unreachable
+    /// from any root.  Zero, the default, writes every node at the end of the insert.
+    #[inline]
+    pub fn set_flush_every(&mut self, flush_every: usize) {
+        self.options.set_flush_every(flush_every);
+    }
+
+    /// Sets whether a panic in `ValueType::encode` during `insert`, `insert_one` or `root_for` is
+    /// caught and returned as an `Exception` of kind `ErrorKind::WorkerPanic`, so a buggy `Encode`
+    /// fails the call instead of unwinding through the caller.  Values are encoded before anything
+    /// is written, so the tree is left as it was.  The panic hook still runs, and nothing can be
+    /// caught in a build with `panic = "abort"`.  Off by default.
+    #[inline]
+    pub fn set_catch_panics(&mut self, catch_panics: bool) {
+        self.options.set_catch_panics(catch_panics);
+    }
+
+    /// Sets the namespace of the root pointer read by `current_root` and swapped by
+    /// `compare_and_set_root`, so several logical trees sharing one database each keep their own
+    /// current root instead of overwriting each other's.  The empty namespace, the default, is the
+    /// database's single root pointer, as read by `Database::get_root`.
+    #[inline]
+    pub fn set_root_namespace(&mut self, namespace: &[u8]) {
+        self.options.set_root_namespace(namespace);
+    }
+
+    /// Moves encoded values of at least `min_blob_bytes` bytes into `store`, leaving only a
+    /// reference to the blob in their data nodes.  The data node locations still cover the whole
+    /// value, so roots and proofs are unchanged, and `get` reads each blob back as it is needed.
+    /// Each blob is stored under the location of its data node and deleted when `remove` frees
+    /// that node.
+    /// Values already in the tree stay where they are.  Small values inlined into their leaves
+    /// are never moved to the blob store.
+    #[inline]
+    pub fn set_blob_store(
+        &mut self,
+        store: Arc<dyn BlobStore<ArrayType> + Send + Sync>,
+        min_blob_bytes: usize,
+    ) {
+        self.blob_store = Some(store);
+        self.min_blob_bytes = min_blob_bytes;
+    }
+
+    /// Calls `hook` with the location of a node and its old and new reference counts whenever an
+    /// insert, a removal, or another operation changes the references of a node, for diagnosing
+    /// reference counts that drift.  A new node goes from zero references and a removed node goes
+    /// to zero.  Changes are only reported while reference counting is enabled.
+    #[cfg(feature = "debug_tools")]
+    #[inline]
+    pub fn set_on_refcount_change(&mut self, hook: RefcountHook<ArrayType>) {
+        self.on_refcount_change = Some(hook);
+    }
+
+    /// Reports a change of the references of the node at `location` to the refcount hook.
+    #[cfg(feature = "debug_tools")]
+    fn refcount_changed(&self, location: &ArrayType, old: u64, new: u64) {
+        if let Some(hook) = &self.on_refcount_change {
+            if self.options.refcounting() && old != new {
+                hook(location, old, new);
+            }
+        }
+    }
+
+    /// Reference count changes are only observed with the `debug_tools` feature.
+    #[cfg(not(feature = "debug_tools"))]
+    #[allow(clippy::unused_self)]
+    const fn refcount_changed(&self, _location: &ArrayType, _old: u64, _new: u64) {}
+
+    /// Sets the size in bytes below which an encoded value is inlined into its leaf rather than
+    /// stored in a separate data node, saving a database read on `get`.  The leaf location still
+    /// covers the value, so roots and proofs are the same either way.  Zero, the default, never
+    /// inlines values.  Leaves already in the database keep the form they were stored with.
+    #[inline]
+    pub fn set_inline_value_bytes(&mut self, inline_value_bytes: usize) {
+        self.options.set_inline_value_bytes(inline_value_bytes);
+    }
+
+    /// Sets whether inserting on top of a previous root that is missing from the database fails,
+    /// which is the default.  When disabled, such an insert builds a fresh tree holding only the
+    /// inserted keys, as if no previous root was given.  This allows recovering a usable tree after
+    /// old nodes were lost, for instance by a partial restore; callers can detect the fallback
+    /// beforehand with `get_one` or `subtree_root` on the previous root.
+    #[inline]
+    pub fn set_strict_previous_root(&mut self, strict: bool) {
+        self.options.set_strict_previous_root(strict);
+    }
+
+    /// Sets whether inserting no keys fails, which is the default.  When disabled, an empty insert
+    /// returns its previous root (adding a reference to it, like an insert which changes no key) or
+    /// `empty_root` when there is none, so pipelines need not special case empty batches.
+    #[inline]
+    pub fn set_strict_empty_inserts(&mut self, strict: bool) {
+        self.options.set_strict_empty_inserts(strict);
+    }
+
+    /// Sets whether nodes are reference counted, which is the default.  When disabled, nodes are
+    /// written with a single reference and inserts skip the reads and writes that share existing
+    /// nodes between roots, which speeds up trees that are built, queried and discarded.  Since
+    /// the references no longer reflect how many roots share a node, such trees cannot be pruned
+    /// per root: `remove` and `remove_cancellable` fail, `snapshot` pins nothing, and
+    /// `dedup_stats` counts every data node as written.  Enabling
+    /// reference counting again and calling `recount_references` with the live roots makes a tree
+    /// prunable again.
+    #[inline]
+    pub fn set_refcounting(&mut self, enabled: bool) {
+        self.options.set_refcounting(enabled);
+    }
+
+    /// Sets whether inserts check for hash collisions, which is disabled by default.  When
+    /// enabled, every node an insert writes over an existing node is compared with it, and the
+    /// insert fails with `ErrorKind::HashCollision` if they differ, rather than silently sharing
+    /// the existing node.  This costs a read per node written even when reference counting is
+    /// disabled, and is meant for testing custom hashers and schemes.
+    #[inline]
+    pub fn set_collision_check(&mut self, enabled: bool) {
+        self.options.set_collision_check(enabled);
+    }
+
+    /// Sets whether an index from each value to the keys it was inserted under is kept, for
+    /// answering `keys_for_value`.  Enabling the index builds it from every leaf stored in the
+    /// database, so it also covers leaves written before it was enabled, and every leaf written
+    /// afterwards is added to it.  The index is held in memory and not persisted, so a reopened
+    /// tree must enable it again to rebuild it.  Disabling it discards it.  Disabled by default,
+    /// since enabling it reads every stored node and every insert then also updates the index.
+    /// # Errors
+    /// `Exception` generated if the database cannot enumerate its nodes, or the value of a stored
+    /// leaf cannot be read, in which case the index is left disabled.
+    #[inline]
+    pub fn set_value_index(&mut self, enabled: bool) -> BinaryMerkleTreeResult<()> {
+        if !enabled {
+            self.value_index = None;
+            return Ok(());
+        }
+        if self.value_index.is_some() {
+            return Ok(());
+        }
+
+        let mut index: HashMap<ArrayType, BTreeSet<ArrayType>> = HashMap::new();
+        for entry in self.db.scan() {
+            let (_, node) = entry?;
+            if let NodeVariant::Leaf(leaf) = node.get_variant() {
+                let value = self.get_leaf_value(&leaf)?;
+                let value_location = self
+                    .options
+                    .scheme()
+                    .value_location::<HasherType, _>(&value);
+                index
+                    .entry(value_location)
+                    .or_default()
+                    .insert(*leaf.get_key());
+            }
+        }
+        self.value_index = Some(index);
+        Ok(())
+    }
+
+    /// Sets whether the first root written with each leaf is kept, for answering `value_version`.
+    /// The versions are held in memory and only cover inserts made while versioning is enabled,
+    /// so they are lost when the tree is reopened; disabling it discards them.  Disabled by
+    /// default, since every insert then also records a version for each leaf it writes.
+    #[inline]
+    pub fn set_value_versioning(&mut self, enabled: bool) {
+        if !enabled {
+            self.value_versions = None;
+        } else if self.value_versions.is_none() {
+            self.value_versions = Some(HashMap::new());
+        }
+    }
+
+    /// Gets the first root at which `key` was given the value it holds under `root`, or `None`
+    /// if value versioning is disabled, `key` is not under `root`, or its leaf was written before
+    /// versioning was enabled.  The version is found from the leaf of `key` under `root`, so an
+    /// older root answers with the version of the value it holds, and inserts writing a key's
+    /// value again leave its version as it was.  A downstream cache of the value is valid for as
+    /// long as the version is unchanged.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn value_version(
+        &self,
+        root: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        let versions = if let Some(v) = &self.value_versions {
+            v
+        } else {
+            return Ok(None);
+        };
+        for leaf in self.get_leaves(root, &mut [*key])? {
+            if leaf.get_key() == key {
+                let location = self.leaf_location_of::<HasherType>(&leaf);
+                return Ok(versions.get(&location).copied());
+            }
+        }
+        Ok(None)
+    }
+
+    /// Gets the statistics on data nodes shared between inserts since the tree was opened.
+    #[inline]
+    pub const fn dedup_stats(&self) -> &DedupStats {
+        &self.dedup_stats
+    }
+
+    /// Creates a `Snapshot` of the given root.  The snapshot holds an extra reference to the root,
+    /// so the nodes reachable from it will not be removed until the snapshot is dropped, even if
+    /// `remove` is called on the root.  The pin is released on the next call to `insert` or `remove`
+    /// after every clone of the snapshot has been dropped.
+    ///
+    /// The extra reference is stored in the database, but which snapshots are outstanding is only
+    /// known to this tree.  If the tree is dropped, or its process crashes, while a snapshot is
+    /// still pinning a root, the reference is never released and a persistent database keeps the
+    /// root's nodes after every `remove`.  Call `recount_references` with the live roots after
+    /// reopening such a database to correct the counts.
+    /// # Errors
+    /// `Exception` generated if the root does not exist in the database.
+    #[inline]
+    pub fn snapshot(
+        &mut self,
+        root_hash: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Snapshot<ArrayType>> {
+        if !self.options.refcounting() || *root_hash == Self::empty_root() {
+            // Nothing is ever removed, or there is no node to remove, so the root needs no pin
+            return self.weak_snapshot(root_hash);
+        }
+        let mut root_node = if let Some(n) = self.db.get_node(*root_hash)? {
+            n
+        } else {
+            return Err(Exception::new("Could not find root"));
+        };
+        let refs = root_node.get_references() + 1;
+        root_node.set_references(refs);
+        self.refcount_changed(root_hash, refs - 1, refs);
+        self.db.insert(*root_hash, root_node)?;
+        self.write_batch()?;
+
+        let snapshot = Snapshot::new(*root_hash);
+        self.snapshots.push((*root_hash, snapshot.pin()));
+        Ok(snapshot)
+    }
+
+    /// Creates a weak `Snapshot` of the given root.  Unlike `snapshot`, it does not hold a
+    /// reference to the root, so forgotten weak snapshots never keep nodes alive.  Reading it once
+    /// its root has been reclaimed fails with `ErrorKind::SnapshotExpired`.
+    /// # Errors
+    /// `Exception` generated if the root does not exist in the database.
+    #[inline]
+    pub fn weak_snapshot(
+        &self,
+        root_hash: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Snapshot<ArrayType>> {
+        if *root_hash != Self::empty_root() && !self.db.contains(root_hash)? {
+            return Err(Exception::new("Could not find root"));
+        }
+        Ok(Snapshot::new_weak(*root_hash, self.removal_epoch))
+    }
+
+    /// Gets items from the root pinned by the given `Snapshot`.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal, or with
+    /// kind `SnapshotExpired` if the root of a weak snapshot has been reclaimed.
+    #[inline]
+    pub fn get_snapshot(
+        &self,
+        snapshot: &Snapshot<ArrayType>,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<HashMap<ArrayType, Option<ValueType>>> {
+        self.check_snapshot(snapshot)?;
+        self.get(snapshot.root(), keys)
+    }
+
+    /// Checks that the root of `snapshot` can still be read.  Nothing can have been reclaimed
+    /// while the removal epoch is unchanged, otherwise the root must still be in the database.
+    /// # Errors
+    /// `Exception` of kind `SnapshotExpired` if the root of a weak snapshot has been reclaimed.
+    fn check_snapshot(&self, snapshot: &Snapshot<ArrayType>) -> BinaryMerkleTreeResult<()> {
+        match snapshot.epoch() {
+            Some(epoch) if epoch != self.removal_epoch => {
+                if *snapshot.root() != Self::empty_root() && !self.db.contains(snapshot.root())? {
+                    return Err(Exception::with_kind(
+                        ErrorKind::SnapshotExpired,
+                        "The root of the snapshot has been reclaimed",
+                    ));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Records the depth of the tree in the database, if it has not been recorded yet.
+    /// # Errors
+    /// `Exception` generated if the depth cannot be recorded.
+    fn record_depth(&mut self) -> BinaryMerkleTreeResult<()> {
+        if !self.depth_recorded {
+            self.db.set_depth(self.depth)?;
+            self.depth_recorded = true;
+        }
+        Ok(())
+    }
+
+    /// Releases the pins held for snapshots that have since been dropped.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    fn release_dropped_snapshots(&mut self) -> BinaryMerkleTreeResult<()> {
+        let mut dropped = Vec::new();
+        self.snapshots.retain(|(root, pin)| {
+            if pin.upgrade().is_some() {
+                true
+            } else {
+                dropped.push(*root);
+                false
+            }
+        });
+        for root in dropped {
+            self.remove_root(&root)?;
+        }
+        Ok(())
+    }
+
+    /// Generates an inclusion proof.  The proof consists of a list of hashes beginning with the key/value
+    /// pair and traveling up the tree until the level below the root is reached.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn generate_inclusion_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+    ) -> BinaryMerkleTreeResult<Vec<(ArrayType, bool)>> {
+        Ok(self.trace_inclusion_proof(root, key)?.0)
+    }
+
+    /// Generates an inclusion proof as a `Proof`, which has a stable binary encoding.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal, or when
+    /// the tree is commit-only: a `Proof` carries the value of its key, which a commit-only tree
+    /// cannot read back, so such trees only give proofs through `generate_inclusion_proof`.
+    #[inline]
+    pub fn generate_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+    ) -> BinaryMerkleTreeResult<Proof<ArrayType>> {
+        if self.options.commit_only() {
+            return Err(Exception::new(
+                "Proofs carrying values cannot be generated from a commit-only tree",
+            ));
+        }
+        let (path, split_indices, value) = self.trace_inclusion_proof(root, key)?;
+        let siblings = path
+            .iter()
+            .skip(2)
+            .zip(split_indices.iter().rev())
+            .map(|(&(location, direction), &split_index)| ProofSibling {
+                location,
+                direction,
+                split_index,
+            })
+            .collect();
+        Ok(Proof::new(key, value, siblings))
+    }
+
+    /// Generates an inclusion proof in the ICS23 `ExistenceProof` format, for verifiers which
+    /// only understand ICS23 (see `ICS23_SPEC_VERSION`).  `hash` names the operation `HasherType`
+    /// computes.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn generate_ics23_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+        hash: HashOp,
+    ) -> BinaryMerkleTreeResult<ExistenceProof> {
+        let proof = self.generate_proof(root, key)?;
+        Ok(ExistenceProof::from_proof::<HasherType, _>(
+            &proof,
+            self.options.scheme(),
+            hash,
+        ))
+    }
+
+    /// Gets the ICS23 `ProofSpec` the proofs from `generate_ics23_proof` are checked against.
+    /// # Errors
+    /// `Exception` generated if the hash scheme cannot be described by a `ProofSpec`.
+    #[inline]
+    pub fn ics23_proof_spec(&self, hash: HashOp) -> BinaryMerkleTreeResult<ProofSpec> {
+        ProofSpec::for_scheme::<ArrayType>(self.options.scheme(), hash)
+    }
+
+    /// Walks the tree to `key`, returning the inclusion proof along with the split indices of the
+    /// branches passed (from the root down) and the encoded value of the key, which is left empty
+    /// in a commit-only tree unless the value was inlined.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    fn trace_inclusion_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+    ) -> BinaryMerkleTreeResult<InclusionTrace<ArrayType>> {
+        let mut nodes = VecDeque::with_capacity(self.depth);
+        nodes.push_front(*root);
+
+        let mut proof = Vec::with_capacity(self.depth);
+        let mut split_indices = Vec::with_capacity(self.depth);
+        let mut value = Vec::new();
+
+        let mut found_leaf = false;
+        let mut depth = 0;
+        while let Some(location) = nodes.pop_front() {
+            if depth > self.depth {
+                return Err(Exception::with_kind(
+                    ErrorKind::DepthExceeded,
+                    "Depth limit exceeded",
+                ));
+            }
+            depth += 1;
+
+            if let Some(node) = self.db.get_node(location)? {
+                match node.get_variant() {
+                    NodeVariant::Branch(b) => {
+                        if found_leaf {
+                            return Err(Exception::new("Corrupt Merkle Tree"));
+                        }
+                        let index = b.get_split_index();
+                        let b_key = b.get_key();
+                        let min_split_index =
+                            calc_min_split_index_ordered::<KeyOrderType, _>(&[key], b_key)?;
+                        let keys = &[key];
+                        let descendants = check_descendants_ordered::<KeyOrderType, _>(
+                            keys,
+                            index,
+                            b_key,
+                            min_split_index,
+                        )?;
+                        if descendants.is_empty() {
+                            return Err(Exception::new("Key not found in tree"));
+                        }
+
+                        split_indices.push(index);
+                        if choose_zero_ordered::<KeyOrderType, _>(key, index)? {
+                            proof.push((*b.get_one(), true));
+                            nodes.push_back(*b.get_zero());
+                        } else {
+                            proof.push((*b.get_zero(), false));
+                            nodes.push_back(*b.get_one());
+                        }
+                    }
+                    NodeVariant::Leaf(l) => {
+                        if found_leaf {
+                            return Err(Exception::new("Corrupt Merkle Tree"));
+                        }
+                        if *l.get_key() != key {
+                            return Err(Exception::new("Key not found in tree"));
+                        }
+
+                        let leaf_node_location = self.leaf_location_of::<HasherType>(&l);
+
+                        proof.push((leaf_node_location, false));
+                        if let Some(inline_value) = l.get_inline_value() {
+                            let data_node_location = self
+                                .options
+                                .scheme()
+                                .data_location::<HasherType, _>(&key, inline_value);
+                            proof.push((data_node_location, false));
+                            value = inline_value.to_vec();
+                        } else {
+                            nodes.push_back(*l.get_data());
+                        }
+                        found_leaf = true;
+                    }
+                    NodeVariant::Data(d) => {
+                        if !found_leaf {
+                            return Err(Exception::new("Corrupt Merkle Tree"));
+                        }
+
+                        // The leaf commits to the location of its data node, so the proof holds
+                        // even when the value cannot be read back, as in a commit-only tree
+                        if !self.options.commit_only() {
+                            value = self.resolve_data(&d)?;
+                        }
+                        proof.push((location, false));
+                    }
+                    NodeVariant::Phantom(_) => {
+                        return Err(Exception::new(
+                            "Corrupt merkle tree: Found phantom node while traversing tree",
+                        ));
+                    }
+                }
+            } else {
+                return Err(Exception::new("Failed to find node"));
+            }
+        }
+
+        proof.reverse();
+
+        Ok((proof, split_indices, value))
+    }
+
+    /// Verifies an inclusion proof against the default `HashScheme`.  Trees configured with a
+    /// different scheme (such as value addressed data nodes) must use `verify_proof` instead.
+    /// # Errors
+    /// `Exception` generated when the given proof is invalid.
+    #[inline]
+    pub fn verify_inclusion_proof(
+        root: &ArrayType,
+        key: ArrayType,
+        value: &ValueType,
+        proof: &[(ArrayType, bool)],
+    ) -> BinaryMerkleTreeResult<()> {
+        Self::verify_proof_with_scheme(&HashScheme::new(), root, key, &value.encode()?, proof)
+    }
+
+    /// Verifies an inclusion proof using the `HashScheme` this tree was configured with.
+    /// # Errors
+    /// `Exception` generated when the given proof is invalid.
+    #[inline]
+    pub fn verify_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+        value: &ValueType,
+        proof: &[(ArrayType, bool)],
+    ) -> BinaryMerkleTreeResult<()> {
+        Self::verify_proof_with_scheme(self.options.scheme(), root, key, &value.encode()?, proof)
+    }
+
+    /// Verifies an inclusion proof against the hash of the expected value rather than the value
+    /// itself, so the value can be withheld from the verifier.  `value_hash` is the location of
+    /// the key's data node under the `HashScheme` this tree was configured with, as computed by
+    /// `HashScheme::data_location` from the key and encoded value.
+    /// # Errors
+    /// `Exception` generated when the given proof is invalid.
+    #[inline]
+    pub fn verify_inclusion_by_value_hash(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+        value_hash: &ArrayType,
+        proof: &[(ArrayType, bool)],
+    ) -> BinaryMerkleTreeResult<()> {
+        Self::verify_proof_with_data_hash(self.options.scheme(), root, key, value_hash, proof)
+    }
+
+    /// Generates a two-level proof of `key` in the subtree committed under `subtree_key` in the
+    /// global tree at `global_root`, as built by `commit_subtrees`.  The subtree must be stored in
+    /// this tree's database.
+    /// # Errors
+    /// `Exception` generated if `subtree_key` or `key` is not present, or when an invalid state is
+    /// encountered during tree traversal.
+    #[inline]
+    pub fn generate_subtree_proof(
+        &self,
+        global_root: &ArrayType,
+        subtree_key: ArrayType,
+        key: ArrayType,
+    ) -> BinaryMerkleTreeResult<SubtreeProof<ArrayType>> {
+        let global = self.generate_proof(global_root, subtree_key)?;
+        let subtree = self.generate_proof(&committed_root(global.value())?, key)?;
+        Ok(SubtreeProof::new(global, subtree))
+    }
+
+    /// Verifies a `SubtreeProof`, checking that the global proof leads to `global_root` and the
+    /// subtree proof leads to the subtree root the global proof commits to, using the `HashScheme`
+    /// this tree was configured with for both levels.
+    /// # Errors
+    /// `Exception` generated when either level of the proof is invalid.
+    #[inline]
+    pub fn verify_subtree_proof(
+        &self,
+        global_root: &ArrayType,
+        proof: &SubtreeProof<ArrayType>,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.verify_compact_proof(global_root, proof.global())?;
+        self.verify_compact_proof(&proof.subtree_root()?, proof.subtree())
+    }
+
+    /// Verifies a `Proof` using the `HashScheme` this tree was configured with.
+    /// # Errors
+    /// `Exception` generated when the given proof is invalid.
+    #[inline]
+    pub fn verify_compact_proof(
+        &self,
+        root: &ArrayType,
+        proof: &Proof<ArrayType>,
+    ) -> BinaryMerkleTreeResult<()> {
+        let key = *proof.key();
+        let data_hash = self
+            .options
+            .scheme()
+            .data_location::<HasherType, _>(&key, proof.value());
+        let leaf_hash = self
+            .options
+            .scheme()
+            .leaf_location_of_value::<HasherType, _>(&key, proof.value());
+
+        let mut path = Vec::with_capacity(proof.siblings().len() + 2);
+        path.push((data_hash, false));
+        path.push((leaf_hash, false));
+        for sibling in proof.siblings() {
+            path.push((sibling.location, sibling.direction));
+        }
+
+        Self::verify_proof_with_scheme(self.options.scheme(), root, key, proof.value(), &path)
+    }
+
+    /// Verifies `proof` against `root_hash` and stores the data, leaf and branch nodes it proves,
+    /// so the proven key can be read from `root_hash` without the rest of the tree.  Nothing is
+    /// written if the proof does not verify.  Nodes already in the database are left untouched.
+    /// Imported branches record a leaf count of zero, since a proof does not carry their counts,
+    /// and their sibling subtrees are not imported, so only proven keys can be read.
+    /// # Errors
+    /// `Exception` generated when the given proof is invalid, or the nodes cannot be written.
+    #[inline]
+    pub fn import_proof(
+        &mut self,
+        root_hash: &ArrayType,
+        proof: &Proof<ArrayType>,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.verify_compact_proof(root_hash, proof)?;
+
+        let key = *proof.key();
+        let value = proof.value();
+        let data_location = self
+            .options
+            .scheme()
+            .data_location::<HasherType, _>(&key, value);
+        let leaf_location = self
+            .options
+            .scheme()
+            .leaf_location_of_value::<HasherType, _>(&key, value);
+
+        if !self.db.contains(&leaf_location)? {
+            self.index_value(&key, value);
+            let mut leaf = LeafType::new();
+            leaf.set_key(key);
+            leaf.set_data(data_location);
+            if self.options.scheme().combined_leaves()
+                || (self.options.inline_value_bytes() != 0
+                    && value.len() < self.options.inline_value_bytes())
+            {
+                leaf.set_inline_value(value);
+            }
+
+            if leaf.get_inline_value().is_none() && !self.db.contains(&data_location)? {
+                let mut data_node =
+                    NodeType::new(NodeVariant::Data(self.create_data(&data_location, value)?));
+                data_node.set_references(1);
+                self.refcount_changed(&data_location, 0, 1);
+                self.db.insert(data_location, data_node)?;
+            }
+
+            let mut leaf_node = NodeType::new(NodeVariant::Leaf(leaf));
+            leaf_node.set_references(1);
+            self.refcount_changed(&leaf_location, 0, 1);
+            self.db.insert(leaf_location, leaf_node)?;
+        }
+
+        let mut current = leaf_location;
+        for sibling in proof.siblings() {
+            let (zero, one) = if sibling.direction {
+                (current, sibling.location)
+            } else {
+                (sibling.location, current)
+            };
+            let branch_location = self
+                .options
+                .scheme()
+                .branch_location::<HasherType, _>(&zero, &one);
+
+            if !self.db.contains(&branch_location)? {
+                let mut branch = BranchType::new();
+                branch.set_zero(zero);
+                branch.set_one(one);
+                branch.set_count(0);
+                branch.set_split_index(sibling.split_index);
+                branch.set_key(key);
+
+                let mut branch_node = NodeType::new(NodeVariant::Branch(branch));
+                branch_node.set_references(1);
+                self.refcount_changed(&branch_location, 0, 1);
+                self.db.insert(branch_location, branch_node)?;
+            }
+            current = branch_location;
+        }
+
+        self.write_batch()
+    }
+
+    /// Verifies an inclusion proof, computing locations with the given `HashScheme`.
+    /// # Errors
+    /// `Exception` generated when the given proof is invalid.
+    fn verify_proof_with_scheme(
+        scheme: &HashScheme,
+        root: &ArrayType,
+        key: ArrayType,
+        value: &[u8],
+        proof: &[(ArrayType, bool)],
+    ) -> BinaryMerkleTreeResult<()> {
+        let data_hash = scheme.data_location::<HasherType, _>(&key, value);
+        let leaf_hash = scheme.leaf_location_of_value::<HasherType, _>(&key, value);
+        Self::verify_proof_path(scheme, root, &data_hash, &leaf_hash, proof)
+    }
+
+    /// Verifies an inclusion proof for the data node at `data_hash`, computing locations with the
+    /// given `HashScheme`.  A combined leaf commits to the value itself rather than to its data
+    /// location, so its proofs cannot be checked against `data_hash` alone.
+    /// # Errors
+    /// `Exception` generated when the given proof is invalid, or the scheme combines leaves.
+    fn verify_proof_with_data_hash(
+        scheme: &HashScheme,
+        root: &ArrayType,
+        key: ArrayType,
+        data_hash: &ArrayType,
+        proof: &[(ArrayType, bool)],
+    ) -> BinaryMerkleTreeResult<()> {
+        if scheme.combined_leaves() {
+            return Err(Exception::new(
+                "Proofs of combined leaves must be verified against the value",
+            ));
+        }
+        let leaf_hash = scheme.leaf_location::<HasherType, _>(&key, data_hash);
+        Self::verify_proof_path(scheme, root, data_hash, &leaf_hash, proof)
+    }
+
+    /// Verifies that `proof` starts at the data node at `data_hash` and the leaf at `leaf_hash`,
+    /// and that its siblings hash up to `root`.
+    /// # Errors
+    /// `Exception` generated when the given proof is invalid.
+    fn verify_proof_path(
+        scheme: &HashScheme,
+        root: &ArrayType,
+        data_hash: &ArrayType,
+        leaf_hash: &ArrayType,
+        proof: &[(ArrayType, bool)],
+    ) -> BinaryMerkleTreeResult<()> {
+        if proof.len() < 2 {
+            return Err(Exception::new("Proof is too short to be valid"));
+        }
+
+        if *data_hash != proof[0].0 || *leaf_hash != proof[1].0 {
+            return Err(Exception::new("Proof is invalid"));
+        }
+
+        let mut current_hash = *leaf_hash;
+
+        for item in proof.iter().skip(2) {
+            let branch_hash = if item.1 {
+                scheme.branch_location::<HasherType, _>(&current_hash, &item.0)
+            } else {
+                scheme.branch_location::<HasherType, _>(&item.0, &current_hash)
+            };
+            current_hash = branch_hash;
+        }
+
+        if *root != current_hash {
+            return Err(Exception::new("Proof is invalid"));
+        }
+
+        Ok(())
+    }
+
+    /// Gets a single key from the tree.
+    /// # Errors
+    /// `Exception` generated from encountering an invalid state during tree traversal.
+    #[inline]
+    pub fn get_one(
+        &self,
+        root: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<ValueType>> {
+        let mut nodes = VecDeque::with_capacity(3);
+        nodes.push_front(*root);
+
+        let mut found_leaf = false;
+        let mut depth = 0;
+
+        while let Some(location) = nodes.pop_front() {
+            if depth > self.depth {
+                return Err(Exception::with_kind(
+                    ErrorKind::DepthExceeded,
+                    "Depth limit exceeded",
+                ));
+            }
+            depth += 1;
+
+            if let Some(node) = self.db.get_node(location)? {
+                match node.get_variant() {
+                    NodeVariant::Branch(b) => {
+                        if found_leaf {
+                            return Err(Exception::new("Corrupt Merkle Tree"));
+                        }
+
+                        let index = b.get_split_index();
+                        let b_key = b.get_key();
+                        let min_split_index =
+                            calc_min_split_index_ordered::<KeyOrderType, _>(&[*key], b_key)?;
+                        let keys = &[*key];
+                        let descendants = check_descendants_ordered::<KeyOrderType, _>(
+                            keys,
+                            index,
+                            b_key,
+                            min_split_index,
+                        )?;
+                        if descendants.is_empty() {
+                            return Ok(None);
+                        }
+
+                        if choose_zero_ordered::<KeyOrderType, _>(*key, index)? {
+                            nodes.push_back(*b.get_zero());
+                        } else {
+                            nodes.push_back(*b.get_one());
+                        }
+                    }
+                    NodeVariant::Leaf(l) => {
+                        if found_leaf {
+                            return Err(Exception::new("Corrupt Merkle Tree"));
+                        }
+
+                        if l.get_key() != key {
+                            return Ok(None);
+                        }
+
+                        if let Some(inline_value) = l.get_inline_value() {
+                            return Ok(Some(ValueType::decode(inline_value)?));
+                        }
+
+                        found_leaf = true;
+                        nodes.push_back(*l.get_data());
+                    }
+                    NodeVariant::Data(d) => {
+                        if !found_leaf {
+                            return Err(Exception::new("Corrupt Merkle Tree"));
+                        }
+
+                        let value = ValueType::decode(&self.resolve_data(&d)?)?;
+                        return Ok(Some(value));
+                    }
+                    NodeVariant::Phantom(_) => {
+                        return Err(Exception::new(
+                            "Corrupt merkle tree: Found phantom node while traversing tree",
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Inserts a single value into a tree.  On top of an existing root, the path to `key` is walked
+    /// once and the new leaf is spliced in where `key` leaves the path, rebuilding only the
+    /// branches above it, which produces the same root and nodes as `insert` with a single key
+    /// without sorting and merging the siblings of the path.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn insert_one(
+        &mut self,
+        previous_root: Option<&ArrayType>,
+        key: &ArrayType,
+        value: &ValueType,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.record_depth()?;
+        self.release_dropped_snapshots()?;
+        self.buffered_nodes = 0;
+
+        let previous_root = previous_root.filter(|root| **root != Self::empty_root());
+        let encoded = self.encode_value(value)?;
+        let mut value_map = HashMap::new();
+        value_map.insert(*key, encoded.as_slice());
+
+        if let Some(root) = previous_root {
+            if let Some(root_node) = self.db.get_node(*root)? {
+                return self.splice_one(root, root_node, key, &value_map);
+            }
+        }
+
+        // Without a previous root to splice into, build the tree like `insert` does
+        let mut root_node = None;
+        if let Some(root) = previous_root {
+            let (changed, node) = self.changed_keys(root, &[*key], &value_map)?;
+            if changed.is_empty() {
+                return self.reference_root(root);
+            }
+            root_node = node;
+        }
+
+        let leaf_location = self.insert_leaves(&[*key], &value_map)?[0];
+
+        let mut tree_refs = Vec::with_capacity(1);
+        let mut key_map = HashMap::new();
+        key_map.insert(*key, leaf_location);
+
+        let tree_ref = TreeRef::new(*key, leaf_location, 1, 1);
+        tree_refs.push(tree_ref);
+
+        if let Some(root) = previous_root {
+            let mut proof_nodes = self.generate_treerefs(root, root_node, &mut [*key], &key_map)?;
+            tree_refs.append(&mut proof_nodes);
+        }
+
+        let new_root = self.create_tree(tree_refs)?;
+        self.record_value_versions(&[leaf_location], new_root);
+        Ok(new_root)
+    }
+
+    /// Writes the branch joining the new leaf of `key` at `leaf_location` with the subtree `key`
+    /// left the path at, given as its location, key and count, for `splice_one`.  Returns the
+    /// location and key of the branch.
+    /// # Errors
+    /// `Exception` generated if the branch cannot be written.
+    fn join_subtree(
+        &mut self,
+        key: &ArrayType,
+        leaf_location: ArrayType,
+        subtree: (ArrayType, ArrayType, u64),
+    ) -> BinaryMerkleTreeResult<(ArrayType, ArrayType)> {
+        let (d, d_key, d_count) = subtree;
+        let split_index = common_prefix_bits(
+            KeyOrderType::order_key(key).as_ref(),
+            KeyOrderType::order_key(&d_key).as_ref(),
+        );
+        let mut branch = BranchType::new();
+        let branch_key = if choose_zero_ordered::<KeyOrderType, _>(*key, split_index)? {
+            branch.set_zero(leaf_location);
+            branch.set_one(d);
+            *key
+        } else {
+            branch.set_zero(d);
+            branch.set_one(leaf_location);
+            d_key
+        };
+        branch.set_count(d_count + 1);
+        branch.set_split_index(split_index);
+        branch.set_key(branch_key);
+        Ok((self.write_branch::<HasherType>(branch)?, branch_key))
+    }
+
+    /// Inserts the single entry of `values` on top of `root`, whose node is `root_node`, for
+    /// `insert_one`.  Nodes are written and shared exactly as `insert` would for the same key.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    fn splice_one(
+        &mut self,
+        root: &ArrayType,
+        root_node: NodeType,
+        key: &ArrayType,
+        values: &HashMap<ArrayType, &[u8]>,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        let ordered_key = KeyOrderType::order_key(key);
+        let data_location = self
+            .options
+            .scheme()
+            .data_location::<HasherType, _>(key, values[key]);
+
+        // The branches the path passes through, as their split index, key, count, whether the path
+        // takes the zero side, and the location of the sibling on the other side
+        let mut path = Vec::with_capacity(self.depth);
+        let mut location = *root;
+        let mut node = root_node;
+        let mut depth = 0;
+        // The subtree `key` leaves the path at, as its location, key and count, unless the path
+        // ends at the leaf of `key` itself
+        let diverging = loop {
+            if depth > self.depth {
+                return Err(Exception::with_kind(
+                    ErrorKind::DepthExceeded,
+                    "Depth of merkle tree exceeded",
+                ));
+            }
+            match node.get_variant() {
+                NodeVariant::Branch(b) => {
+                    let (count, zero, one, split_index, branch_key) = b.decompose();
+                    if !prefix_matches(
+                        KeyOrderType::order_key(&branch_key).as_ref(),
+                        ordered_key.as_ref(),
+                        split_index,
+                    ) {
+                        break Some((location, branch_key, count));
+                    }
+                    let takes_zero = choose_zero_ordered::<KeyOrderType, _>(*key, split_index)?;
+                    let (child, sibling) = if takes_zero { (zero, one) } else { (one, zero) };
+                    path.push((split_index, branch_key, count, takes_zero, sibling));
+                    location = child;
+                    node = self.get_tree_node(child)?;
+                    depth += 1;
+                }
+                NodeVariant::Leaf(l) => {
+                    if l.get_key() != key {
+                        break Some((location, *l.get_key(), 1));
+                    }
+                    if *l.get_data() == data_location {
+                        return self.reference_root(root);
+                    }
+                    // The leaf of `key` is replaced, so it gains no reference
+                    break None;
+                }
+                NodeVariant::Data(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found data node while traversing tree",
+                    ));
+                }
+                NodeVariant::Phantom(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found phantom node while traversing tree",
+                    ));
+                }
+            }
+        };
+
+        let leaf_location = self.insert_leaves(&[*key], values)?[0];
+
+        // Every subtree kept from `root` is shared with the new root
+        if self.options.refcounting() {
+            for &(_, _, _, _, sibling) in &path {
+                self.add_reference(&sibling)?;
+            }
+            if let Some((diverging_location, _, _)) = diverging {
+                self.add_reference(&diverging_location)?;
+            }
+        }
+
+        // The subtree replacing the end of the path, as its location, key and count
+        let (mut location, mut subtree_key, added) = if let Some(subtree) = diverging {
+            let (joined, joined_key) = self.join_subtree(key, leaf_location, subtree)?;
+            (joined, joined_key, 1)
+        } else {
+            (leaf_location, *key, 0)
+        };
+
+        // A branch takes the key of its zero side
+        for (split_index, branch_key, count, takes_zero, sibling) in path.into_iter().rev() {
+            let mut branch = BranchType::new();
+            if takes_zero {
+                branch.set_zero(location);
+                branch.set_one(sibling);
+            } else {
+                branch.set_zero(sibling);
+                branch.set_one(location);
+                subtree_key = branch_key;
+            }
+            branch.set_count(count + added);
+            branch.set_split_index(split_index);
+            branch.set_key(subtree_key);
+            location = self.write_branch::<HasherType>(branch)?;
+        }
+
+        self.write_batch()?;
+        self.record_value_versions(&[leaf_location], location);
+        Ok(location)
+    }
+
+    /// Replaces the value of a key already present under `previous_root`, returning the new root.
+    /// Only a new data node, a new leaf and the branches on the path from that leaf to the root are
+    /// created; every other node of the new root is shared with `previous_root`.
+    /// # Errors
+    /// `Exception` generated if `key` is not present under `previous_root`, or if an invalid state
+    /// is encountered during tree traversal.
+    #[inline]
+    pub fn update_value(
+        &mut self,
+        previous_root: &ArrayType,
+        key: &ArrayType,
+        new_value: &ValueType,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        if self.get_one(previous_root, key)?.is_none() {
+            return Err(Exception::new("Key not found in tree"));
+        }
+        self.insert_one(Some(previous_root), key, new_value)
+    }
+
+    /// Checks whether `key` is present under `root_hash`, without reading its value.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn contains_key(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<bool> {
+        Ok(!self.get_leaves(root_hash, &mut [*key])?.is_empty())
+    }
+
+    /// Checks whether `root_hash` is a root known to the database, that is the location of a
+    /// stored branch or leaf, or the empty root.  `get` finds no keys both under an unknown root
+    /// and under a root lacking the keys, so this tells the two apart.
+    /// # Errors
+    /// `Exception` generated if the node cannot be read.
+    #[inline]
+    pub fn has_root(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<bool> {
+        if *root_hash == Self::empty_root() {
+            return Ok(true);
+        }
+        Ok(self.db.get_node(*root_hash)?.is_some_and(|node| {
+            matches!(
+                node.get_variant(),
+                NodeVariant::Branch(_) | NodeVariant::Leaf(_)
+            )
+        }))
+    }
+
+    /// Gets the stored bytes of the value of `key` under `root_hash` without decoding them.  The
+    /// bytes are the output of `ValueType::encode` when the value was inserted, so they can be
+    /// forwarded or hashed without knowing `ValueType`.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn get_raw(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<Vec<u8>>> {
+        if let Some(leaf) = self.get_leaves(root_hash, &mut [*key])?.pop() {
+            return Ok(Some(self.get_leaf_value(&leaf)?));
+        }
+        Ok(None)
+    }
+
+    /// Gets a reader over the stored bytes of the value of `key` under `root_hash`, as returned by
+    /// `get_raw`, so large values can be streamed out (for instance into a response body) without
+    /// decoding them.  Values are never split across several data nodes, so the bytes are read
+    /// from the data node, inline value or blob store in one piece when the reader is created.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn get_value_reader(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<impl Read>> {
+        Ok(self.get_raw(root_hash, key)?.map(Cursor::new))
+    }
+
+    /// Gets the keys under `root_hash` that currently hold `value`, in ascending order.  Relies on
+    /// the value index, which covers every leaf stored since it was last enabled.
+    /// # Errors
+    /// `Exception` generated if the value index is not enabled, or if an invalid state is
+    /// encountered during tree traversal.
+    #[inline]
+    pub fn keys_for_value(
+        &self,
+        root_hash: &ArrayType,
+        value: &ValueType,
+    ) -> BinaryMerkleTreeResult<Vec<ArrayType>> {
+        let index = if let Some(i) = &self.value_index {
+            i
+        } else {
+            return Err(Exception::new("Value index is not enabled"));
+        };
+
+        let encoded = value.encode()?;
+        let value_location = self
+            .options
+            .scheme()
+            .value_location::<HasherType, _>(&encoded);
+        let mut candidates = if let Some(keys) = index.get(&value_location) {
+            keys.iter().copied().collect::<Vec<_>>()
+        } else {
+            return Ok(Vec::new());
+        };
+
+        // The index also holds keys whose value has since changed, or that are not under this root
+        let mut keys = Vec::with_capacity(candidates.len());
+        for leaf in self.get_leaves(root_hash, &mut candidates)? {
+            let key = leaf.get_key();
+            if *leaf.get_data()
+                == self
+                    .options
+                    .scheme()
+                    .data_location::<HasherType, _>(key, &encoded)
+            {
+                keys.push(*key);
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Gets every key and value under `root_hash`, in ascending key order as defined by
+    /// `KeyOrderType`.  With the default `BigEndianOrder`, keys are compared byte by byte from the
+    /// first byte, as big-endian numbers.  Entries are produced lazily, reading each node and
+    /// value as it is reached; after an error the iterator ends.
+    #[inline]
+    pub fn iter<'a>(
+        &'a self,
+        root_hash: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<(ArrayType, ValueType)>> + 'a {
+        self.iter_with_cancel(root_hash, None)
+    }
+
+    /// Gets every key and value under `root_hash` lazily in ascending key order like `iter`,
+    /// checking `cancel` at each node.  Once `cancel` is set, the next entry is an `Exception` of
+    /// kind `ErrorKind::Cancelled` and the iterator ends.
+    #[inline]
+    pub fn iter_cancellable<'a>(
+        &'a self,
+        root_hash: &ArrayType,
+        cancel: &'a AtomicBool,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<(ArrayType, ValueType)>> + 'a {
+        self.iter_with_cancel(root_hash, Some(cancel))
+    }
+
+    /// Gets every key and value under `root_hash` lazily, checking the optional `cancel` at each
+    /// node.
+    fn iter_with_cancel<'a>(
+        &'a self,
+        root_hash: &ArrayType,
+        cancel: Option<&'a AtomicBool>,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<(ArrayType, ValueType)>> + 'a {
+        let mut nodes = Vec::with_capacity(self.depth);
+        if *root_hash != Self::empty_root() {
+            nodes.push((*root_hash, 0));
+        }
+        std::iter::from_fn(move || {
+            while let Some((location, depth)) = nodes.pop() {
+                match self.visit_for_iter(location, depth, cancel, &mut nodes) {
+                    Ok(Some(entry)) => return Some(Ok(entry)),
+                    Ok(None) => (),
+                    Err(e) => {
+                        nodes.clear();
+                        return Some(Err(e));
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    /// Visits the node at `location`, `depth` levels below the root, for `iter`.  The children of
+    /// a branch are pushed onto `nodes`, and the key and value of a leaf are returned.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal, or when
+    /// `cancel` is set.
+    fn visit_for_iter(
+        &self,
+        location: ArrayType,
+        depth: usize,
+        cancel: Option<&AtomicBool>,
+        nodes: &mut Vec<(ArrayType, usize)>,
+    ) -> BinaryMerkleTreeResult<Option<(ArrayType, ValueType)>> {
+        check_cancelled(cancel)?;
+        if depth > self.depth {
+            return Err(Exception::with_kind(
+                ErrorKind::DepthExceeded,
+                "Depth of merkle tree exceeded",
+            ));
+        }
+
+        match self.get_tree_node(location)?.get_variant() {
+            NodeVariant::Branch(b) => {
+                // Push the one side first so the zero side is visited first
+                nodes.push((*b.get_one(), depth + 1));
+                nodes.push((*b.get_zero(), depth + 1));
+                Ok(None)
+            }
+            NodeVariant::Leaf(l) => {
+                let value = ValueType::decode(&self.get_leaf_value(&l)?)?;
+                Ok(Some((*l.get_key(), value)))
+            }
+            NodeVariant::Data(_) => Err(Exception::new(
+                "Corrupt merkle tree: Found data node while traversing tree",
+            )),
+            NodeVariant::Phantom(_) => Err(Exception::new(
+                "Corrupt merkle tree: Found phantom node while traversing tree",
+            )),
+        }
+    }
+
+    /// Gets every key and value under `root_hash` along with its inclusion proof, in ascending key
+    /// order.  The tree is walked depth first once, keeping the siblings of the current path on a
+    /// stack, so each node is read a single time rather than once per proof passing through it.
+    /// Entries are produced lazily; after an error the iterator ends.
+    #[inline]
+    pub fn iter_with_proofs<'a>(
+        &'a self,
+        root_hash: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<ProvenEntry<ArrayType, ValueType>>> + 'a {
+        let mut nodes = Vec::with_capacity(self.depth);
+        if *root_hash != Self::empty_root() {
+            nodes.push((*root_hash, 0, None));
+        }
+        let mut path = Vec::with_capacity(self.depth);
+        std::iter::from_fn(move || {
+            while let Some((location, depth, sibling)) = nodes.pop() {
+                match self.visit_for_proofs(location, depth, sibling, &mut nodes, &mut path) {
+                    Ok(Some(entry)) => return Some(Ok(entry)),
+                    Ok(None) => (),
+                    Err(e) => {
+                        nodes.clear();
+                        return Some(Err(e));
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    /// Visits the node at `location`, `depth` levels below the root, for `iter_with_proofs`.
+    /// `sibling` is the sibling of the node on its path, `path` holds the siblings from the root
+    /// to its parent, and the children of a branch are pushed onto `nodes`.  Returns the entry
+    /// and its proof when the node is a leaf.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    fn visit_for_proofs(
+        &self,
+        location: ArrayType,
+        depth: usize,
+        sibling: Option<ProofSibling<ArrayType>>,
+        nodes: &mut Vec<(ArrayType, usize, Option<ProofSibling<ArrayType>>)>,
+        path: &mut Vec<ProofSibling<ArrayType>>,
+    ) -> BinaryMerkleTreeResult<Option<ProvenEntry<ArrayType, ValueType>>> {
+        if depth > self.depth {
+            return Err(Exception::with_kind(
+                ErrorKind::DepthExceeded,
+                "Depth of merkle tree exceeded",
+            ));
+        }
+        path.truncate(depth.saturating_sub(1));
+        if let Some(s) = sibling {
+            path.push(s);
+        }
+
+        match self.get_tree_node(location)?.get_variant() {
+            NodeVariant::Branch(b) => {
+                let split_index = b.get_split_index();
+                let (zero, one) = (*b.get_zero(), *b.get_one());
+                nodes.push((
+                    one,
+                    depth + 1,
+                    Some(ProofSibling {
+                        location: zero,
+                        direction: false,
+                        split_index,
+                    }),
+                ));
+                nodes.push((
+                    zero,
+                    depth + 1,
+                    Some(ProofSibling {
+                        location: one,
+                        direction: true,
+                        split_index,
+                    }),
+                ));
+                Ok(None)
+            }
+            NodeVariant::Leaf(l) => {
+                let encoded = self.get_leaf_value(&l)?;
+                let value = ValueType::decode(&encoded)?;
+                let siblings = path.iter().rev().copied().collect();
+                let key = *l.get_key();
+                Ok(Some((key, value, Proof::new(key, encoded, siblings))))
+            }
+            NodeVariant::Data(_) => Err(Exception::new(
+                "Corrupt merkle tree: Found data node while traversing tree",
+            )),
+            NodeVariant::Phantom(_) => Err(Exception::new(
+                "Corrupt merkle tree: Found phantom node while traversing tree",
+            )),
+        }
+    }
+
+    /// Gets up to `limit` keys and values under `root_hash` which sort strictly after `after`, in
+    /// ascending key order, along with the cursor to pass as `after` for the next page.  The
+    /// cursor is `None` once the last page has been returned.  The traversal seeks to `after`
+    /// along its bit path, skipping the subtrees holding only smaller keys rather than reading
+    /// them.  A `limit` of zero returns no entries and `after` as the cursor.
+    /// # Errors
+    /// `Exception` generated if an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn iter_from(
         &self,
-        root: &ArrayType,
-        key: ArrayType,
-    ) -> BinaryMerkleTreeResult<Vec<(ArrayType, bool)>> {
-        let mut nodes = VecDeque::with_capacity(self.depth);
-        nodes.push_front(*root);
-
-        let mut proof = Vec::with_capacity(self.depth);
+        root_hash: &ArrayType,
+        after: Option<ArrayType>,
+        limit: usize,
+    ) -> BinaryMerkleTreeResult<EntryPage<ArrayType, ValueType>> {
+        if limit == 0 {
+            return Ok((Vec::new(), after));
+        }
+        if *root_hash == Self::empty_root() {
+            return Ok((Vec::new(), None));
+        }
 
-        let mut found_leaf = false;
-        let mut depth = 0;
-        while let Some(location) = nodes.pop_front() {
+        let mut entries = Vec::with_capacity(limit);
+        // Nodes still to visit, and whether their subtree may hold keys up to `after`
+        let mut nodes = vec![(*root_hash, 0, after.is_some())];
+        while let Some((location, depth, bounded)) = nodes.pop() {
             if depth > self.depth {
-                return Err(Exception::new("Depth limit exceeded"));
+                return Err(Exception::with_kind(
+                    ErrorKind::DepthExceeded,
+                    "Depth of merkle tree exceeded",
+                ));
             }
-            depth += 1;
-
-            if let Some(node) = self.db.get_node(location)? {
-                match node.get_variant() {
-                    NodeVariant::Branch(b) => {
-                        if found_leaf {
-                            return Err(Exception::new("Corrupt Merkle Tree"));
-                        }
-                        let index = b.get_split_index();
-                        let b_key = b.get_key();
-                        let min_split_index = calc_min_split_index(&[key], b_key)?;
-                        let keys = &[key];
-                        let descendants = check_descendants(keys, index, b_key, min_split_index)?;
-                        if descendants.is_empty() {
-                            return Err(Exception::new("Key not found in tree"));
-                        }
 
-                        if choose_zero(key, index)? {
-                            proof.push((*b.get_one(), true));
-                            nodes.push_back(*b.get_zero());
-                        } else {
-                            proof.push((*b.get_zero(), false));
-                            nodes.push_back(*b.get_one());
+            match self.get_tree_node(location)?.get_variant() {
+                NodeVariant::Branch(b) => {
+                    let (zero, one) = (*b.get_zero(), *b.get_one());
+                    let mut bound = None;
+                    if bounded {
+                        if let Some(after_key) = &after {
+                            let split_index = b.get_split_index();
+                            match compare_prefix(
+                                KeyOrderType::order_key(b.get_key()).as_ref(),
+                                KeyOrderType::order_key(after_key).as_ref(),
+                                split_index,
+                            ) {
+                                // Every key under this branch sorts before `after`
+                                std::cmp::Ordering::Less => continue,
+                                std::cmp::Ordering::Equal => {
+                                    bound = Some(choose_zero_ordered::<KeyOrderType, _>(
+                                        *after_key,
+                                        split_index,
+                                    )?)
+                                }
+                                std::cmp::Ordering::Greater => (),
+                            }
                         }
                     }
-                    NodeVariant::Leaf(l) => {
-                        if found_leaf {
-                            return Err(Exception::new("Corrupt Merkle Tree"));
+                    match bound {
+                        Some(true) => {
+                            nodes.push((one, depth + 1, false));
+                            nodes.push((zero, depth + 1, true));
                         }
-                        if *l.get_key() != key {
-                            return Err(Exception::new("Key not found in tree"));
+                        Some(false) => nodes.push((one, depth + 1, true)),
+                        None => {
+                            nodes.push((one, depth + 1, false));
+                            nodes.push((zero, depth + 1, false));
                         }
+                    }
+                }
+                NodeVariant::Leaf(l) => {
+                    if bounded
+                        && after.is_some_and(|after_key| {
+                            KeyOrderType::compare(l.get_key(), &after_key)
+                                != std::cmp::Ordering::Greater
+                        })
+                    {
+                        continue;
+                    }
+                    let value = ValueType::decode(&self.get_leaf_value(&l)?)?;
+                    entries.push((*l.get_key(), value));
+                    if entries.len() == limit {
+                        break;
+                    }
+                }
+                NodeVariant::Data(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found data node while traversing tree",
+                    ));
+                }
+                NodeVariant::Phantom(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found phantom node while traversing tree",
+                    ));
+                }
+            }
+        }
 
-                        let mut leaf_hasher = HasherType::new(location.as_ref().len());
-                        leaf_hasher.update(b"l");
-                        leaf_hasher.update(l.get_key().as_ref());
-                        leaf_hasher.update(l.get_data().as_ref());
-                        let leaf_node_location = leaf_hasher.finalize();
+        // Every subtree left to visit holds at least one more key
+        let cursor = if nodes.is_empty() {
+            None
+        } else {
+            entries.last().map(|(key, _)| *key)
+        };
+        Ok((entries, cursor))
+    }
 
-                        proof.push((leaf_node_location, false));
-                        nodes.push_back(*l.get_data());
-                        found_leaf = true;
+    /// Writes a human-readable rendering of the tree under `root_hash` to `writer`, one node per
+    /// line and indented by depth.  Branches show their split index and key count, leaves their
+    /// key, and the value of each leaf its length and whether it is inlined or in a data node.
+    /// Every node is read, so this is meant for troubleshooting rather than regular use.
+    /// # Errors
+    /// `Exception` generated if writing fails, or when an invalid state is encountered during tree
+    /// traversal.
+    #[inline]
+    pub fn debug_dump(
+        &self,
+        root_hash: &ArrayType,
+        mut writer: impl Write,
+    ) -> BinaryMerkleTreeResult<()> {
+        if *root_hash == Self::empty_root() {
+            return Ok(());
+        }
+        let mut nodes = vec![(*root_hash, 0)];
+        while let Some((location, depth)) = nodes.pop() {
+            if depth > self.depth {
+                return Err(Exception::with_kind(
+                    ErrorKind::DepthExceeded,
+                    "Depth of merkle tree exceeded",
+                ));
+            }
+
+            let indent = "  ".repeat(depth);
+            match self.get_tree_node(location)?.get_variant() {
+                NodeVariant::Branch(b) => {
+                    writeln!(
+                        writer,
+                        "{}branch {} split={} count={}",
+                        indent,
+                        to_hex(location.as_ref()),
+                        b.get_split_index(),
+                        b.get_count()
+                    )?;
+                    // Push the one side first so the zero side is written first
+                    nodes.push((*b.get_one(), depth + 1));
+                    nodes.push((*b.get_zero(), depth + 1));
+                }
+                NodeVariant::Leaf(l) => {
+                    writeln!(
+                        writer,
+                        "{}leaf {} key={}",
+                        indent,
+                        to_hex(location.as_ref()),
+                        to_hex(l.get_key().as_ref())
+                    )?;
+                    if let Some(value) = l.get_inline_value() {
+                        writeln!(writer, "{}  inline len={}", indent, value.len())?;
+                    } else {
+                        writeln!(
+                            writer,
+                            "{}  data {} len={}",
+                            indent,
+                            to_hex(l.get_data().as_ref()),
+                            self.get_data_value(l.get_data())?.len()
+                        )?;
                     }
-                    NodeVariant::Data(d) => {
-                        if !found_leaf {
-                            return Err(Exception::new("Corrupt Merkle Tree"));
-                        }
+                }
+                NodeVariant::Data(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found data node while traversing tree",
+                    ));
+                }
+                NodeVariant::Phantom(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found phantom node while traversing tree",
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
 
-                        let mut data_hasher = HasherType::new(location.as_ref().len());
-                        data_hasher.update(b"d");
-                        data_hasher.update(key.as_ref());
-                        data_hasher.update(d.get_value());
-                        let data_node_location = data_hasher.finalize();
+    /// Writes the canonical JSON export of the tree under `root_hash` to `writer`: an object whose
+    /// `root` is the hex root hash and whose `entries` map each hex key to its base64 encoded
+    /// value, in the order of the tree and without whitespace.  The same tree always produces the
+    /// same bytes.  Entries are written as the tree is walked, so the export is never held in
+    /// memory.
+    /// # Errors
+    /// `Exception` generated if writing fails, or when an invalid state is encountered during tree
+    /// traversal.
+    #[inline]
+    pub fn write_json(
+        &self,
+        root_hash: &ArrayType,
+        mut writer: impl Write,
+    ) -> BinaryMerkleTreeResult<()> {
+        write!(
+            writer,
+            "{{\"root\":\"{}\",\"entries\":{{",
+            to_hex(root_hash.as_ref())
+        )?;
+        if *root_hash != Self::empty_root() {
+            let mut first = true;
+            let mut nodes = vec![(*root_hash, 0)];
+            while let Some((location, depth)) = nodes.pop() {
+                if depth > self.depth {
+                    return Err(Exception::with_kind(
+                        ErrorKind::DepthExceeded,
+                        "Depth of merkle tree exceeded",
+                    ));
+                }
 
-                        proof.push((data_node_location, false));
+                match self.get_tree_node(location)?.get_variant() {
+                    NodeVariant::Branch(b) => {
+                        nodes.push((*b.get_one(), depth + 1));
+                        nodes.push((*b.get_zero(), depth + 1));
+                    }
+                    NodeVariant::Leaf(l) => {
+                        if !first {
+                            write!(writer, ",")?;
+                        }
+                        first = false;
+                        write!(
+                            writer,
+                            "\"{}\":\"{}\"",
+                            to_hex(l.get_key().as_ref()),
+                            to_base64(&self.get_leaf_value(&l)?)
+                        )?;
+                    }
+                    NodeVariant::Data(_) => {
+                        return Err(Exception::new(
+                            "Corrupt merkle tree: Found data node while traversing tree",
+                        ));
                     }
                     NodeVariant::Phantom(_) => {
                         return Err(Exception::new(
@@ -795,179 +5032,576 @@ where
                         ));
                     }
                 }
-            } else {
-                return Err(Exception::new("Failed to find node"));
             }
         }
+        write!(writer, "}}}}")?;
+        Ok(())
+    }
 
-        proof.reverse();
-
-        Ok(proof)
+    /// Gets the canonical JSON export of the tree under `root_hash` as a `String`, as written by
+    /// `write_json`.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn to_json(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<String> {
+        let mut json = Vec::new();
+        self.write_json(root_hash, &mut json)?;
+        String::from_utf8(json)
+            .map_err(|e| Exception::new(&format!("JSON export is not UTF-8: {e}")))
     }
 
-    /// Verifies an inclusion proof.
+    /// Inserts every entry of a JSON export, as produced by `write_json`, into a new root, which
+    /// is returned.  The root is checked against the one recorded in the export, so a tree using
+    /// a different hasher or hash scheme than the exporting tree is rejected.
     /// # Errors
-    /// `Exception` generated when the given proof is invalid.
+    /// `Exception` generated if `json` is not a valid export, a key has the wrong length, a value
+    /// cannot be decoded, or the new root differs from the recorded root.
     #[inline]
-    pub fn verify_inclusion_proof(
-        root: &ArrayType,
-        key: ArrayType,
-        value: &ValueType,
-        proof: &[(ArrayType, bool)],
-    ) -> BinaryMerkleTreeResult<()> {
-        if proof.len() < 2 {
-            return Err(Exception::new("Proof is too short to be valid"));
+    pub fn import_json(&mut self, json: &str) -> BinaryMerkleTreeResult<ArrayType> {
+        let (recorded_root, entries) = parse_json_export(json)?;
+        let mut keys = Vec::with_capacity(entries.len());
+        let mut values = Vec::with_capacity(entries.len());
+        for (key_bytes, value) in entries {
+            let mut key = ArrayType::default();
+            if key_bytes.len() != key.as_ref().len() {
+                return Err(Exception::new(
+                    "JSON export holds a key of the wrong length",
+                ));
+            }
+            key.as_mut().copy_from_slice(&key_bytes);
+            keys.push(key);
+            values.push(ValueType::decode(&value)?);
         }
 
-        let key_len = root.as_ref().len();
-
-        let mut data_hasher = HasherType::new(key_len);
-        data_hasher.update(b"d");
-        data_hasher.update(key.as_ref());
-        data_hasher.update(&value.encode()?);
-        let data_hash = data_hasher.finalize();
-
-        if data_hash != proof[0].0 {
-            return Err(Exception::new("Proof is invalid"));
+        let root = if keys.is_empty() {
+            Self::empty_root()
+        } else {
+            self.insert(None, &mut keys, &values)?
+        };
+        if root.as_ref() != recorded_root.as_slice() {
+            return Err(Exception::new(
+                "JSON export root does not match the root of its entries",
+            ));
         }
+        Ok(root)
+    }
 
-        let mut leaf_hasher = HasherType::new(key_len);
-        leaf_hasher.update(b"l");
-        leaf_hasher.update(key.as_ref());
-        leaf_hasher.update(data_hash.as_ref());
-        let leaf_hash = leaf_hasher.finalize();
-
-        if leaf_hash != proof[1].0 {
-            return Err(Exception::new("Proof is invalid"));
+    /// Turns this tree into a `MerkleBITReader` over the same database, for sharing the finished
+    /// tree between readers.  The reader keeps the options and blob store of the tree, so it
+    /// computes proofs and reads values exactly as the tree would.
+    /// # Errors
+    /// None.
+    #[inline]
+    pub fn into_reader(
+        self,
+    ) -> ReaderResult<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    > {
+        let blob_store = self.blob_store;
+        let mut reader =
+            MerkleBITReader::new_with_options(Arc::new(self.db), self.depth, self.options)?;
+        if let Some(store) = blob_store {
+            reader.set_blob_store(store);
         }
+        Ok(reader)
+    }
+}
 
-        let mut current_hash = leaf_hash;
-
-        for item in proof.iter().skip(2) {
-            let mut branch_hasher = HasherType::new(key_len);
-            branch_hasher.update(b"b");
-            if item.1 {
-                branch_hasher.update(current_hash.as_ref());
-                branch_hasher.update(item.0.as_ref());
+impl<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >
+    MerkleBIT<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >
+where
+    DatabaseType: SharedDatabase<ArrayType, NodeType = NodeType>,
+    BranchType: Branch<ArrayType>,
+    LeafType: Leaf<ArrayType>,
+    DataType: Data,
+    NodeType: Node<BranchType, LeafType, DataType, ArrayType>,
+    HasherType: Hasher<ArrayType, HashType = HasherType>,
+    ValueType: Decode + Encode,
+    ArrayType: Array,
+    KeyOrderType: KeyOrder<ArrayType>,
+{
+    /// Get items from the `MerkleBIT` as shared handles to their encoded values, avoiding the copy
+    /// and decode performed by `get`.  Only available for databases implementing `SharedDatabase`.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn get_ref(
+        &self,
+        root_hash: &ArrayType,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<HashMap<ArrayType, Option<DatabaseType::ValueRef>>> {
+        let mut leaf_map = generate_leaf_map(keys);
+        for leaf in self.get_leaves(root_hash, keys)? {
+            // An inlined value is shared from the leaf node itself
+            let location = if let Some(value) = leaf.get_inline_value() {
+                if self.options.scheme().combined_leaves() {
+                    self.options
+                        .scheme()
+                        .combined_leaf_location::<HasherType, _>(leaf.get_key(), value)
+                } else {
+                    self.options
+                        .scheme()
+                        .leaf_location::<HasherType, _>(leaf.get_key(), leaf.get_data())
+                }
             } else {
-                branch_hasher.update(item.0.as_ref());
-                branch_hasher.update(current_hash.as_ref());
+                *leaf.get_data()
+            };
+            if let Some(value) = self.db.get_value_ref(location)? {
+                leaf_map.insert(*leaf.get_key(), Some(value));
+            } else {
+                return Err(Exception::new(
+                    "Corrupt merkle tree: Failed to get leaf node from DB",
+                ));
             }
-            let branch_hash = branch_hasher.finalize();
-            current_hash = branch_hash;
         }
+        Ok(leaf_map)
+    }
+}
 
-        if *root != current_hash {
-            return Err(Exception::new("Proof is invalid"));
+impl<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >
+    MerkleBIT<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >
+where
+    DatabaseType: CachingDatabase<ArrayType, NodeType = NodeType>,
+    BranchType: Branch<ArrayType>,
+    LeafType: Leaf<ArrayType>,
+    DataType: Data,
+    NodeType: Node<BranchType, LeafType, DataType, ArrayType>,
+    HasherType: Hasher<ArrayType, HashType = HasherType>,
+    ValueType: Decode + Encode,
+    ArrayType: Array,
+    KeyOrderType: KeyOrder<ArrayType>,
+{
+    /// Loads the nodes under `root_hash` into the database cache, level by level, down to
+    /// `depth_limit` levels below the root (the data nodes of leaves count as one level below
+    /// them).  The budget is checked before each node is read, and warming stops at the first node
+    /// the cache has no room for, so no more than one node is read past a full cache.  Returns the
+    /// number of nodes held by the cache.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn warm_cache(
+        &self,
+        root_hash: &ArrayType,
+        depth_limit: usize,
+    ) -> BinaryMerkleTreeResult<usize> {
+        let mut warmed = 0;
+        let mut level = vec![*root_hash];
+        for _ in 0..=depth_limit {
+            let mut next_level = Vec::with_capacity(level.len() * 2);
+            for location in level {
+                if self.db.cache_is_full()? {
+                    return Ok(warmed);
+                }
+                let node = match self.db.warm(location)? {
+                    Some((node, true)) => node,
+                    Some((_, false)) => return Ok(warmed),
+                    None => continue,
+                };
+                warmed += 1;
+                match node.get_variant() {
+                    NodeVariant::Branch(b) => {
+                        next_level.push(*b.get_zero());
+                        next_level.push(*b.get_one());
+                    }
+                    NodeVariant::Leaf(l) => {
+                        if l.get_inline_value().is_none() {
+                            next_level.push(*l.get_data());
+                        }
+                    }
+                    NodeVariant::Data(_) => (),
+                    NodeVariant::Phantom(_) => {
+                        return Err(Exception::new(
+                            "Corrupt merkle tree: Found phantom node while traversing tree",
+                        ));
+                    }
+                }
+            }
+            if next_level.is_empty() {
+                break;
+            }
+            level = next_level;
         }
-
-        Ok(())
+        Ok(warmed)
     }
 
-    /// Gets a single key from the tree.
+    /// Pins every node on the path from `root_hash` to `key` in the database cache, so that gets of
+    /// `key` never miss the cache until `unpin_path` is called.  The path ends at the data node of
+    /// the leaf for `key`, or where `key` leaves the tree if it is absent.  Returns the number of
+    /// nodes pinned.
     /// # Errors
-    /// `Exception` generated from encountering an invalid state during tree traversal.
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
     #[inline]
-    pub fn get_one(
+    pub fn pin_path(
         &self,
-        root: &ArrayType,
+        root_hash: &ArrayType,
         key: &ArrayType,
-    ) -> BinaryMerkleTreeResult<Option<ValueType>> {
-        let mut nodes = VecDeque::with_capacity(3);
-        nodes.push_front(*root);
-
-        let mut found_leaf = false;
-        let mut depth = 0;
+    ) -> BinaryMerkleTreeResult<usize> {
+        self.visit_path(root_hash, key, |location| self.db.pin(location))
+    }
 
-        while let Some(location) = nodes.pop_front() {
-            if depth > self.depth {
-                return Err(Exception::new("Depth limit exceeded"));
+    /// Unpins the nodes on the path from `root_hash` to `key` pinned by `pin_path`, making them
+    /// evictable again.  Returns the number of nodes on the path.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn unpin_path(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<usize> {
+        self.visit_path(root_hash, key, |location| {
+            match self.db.unpin(&location)? {
+                Some(node) => Ok(Some(node)),
+                None => self.db.get_node(location),
             }
-            depth += 1;
-
-            if let Some(node) = self.db.get_node(location)? {
-                match node.get_variant() {
-                    NodeVariant::Branch(b) => {
-                        if found_leaf {
-                            return Err(Exception::new("Corrupt Merkle Tree"));
-                        }
-
-                        let index = b.get_split_index();
-                        let b_key = b.get_key();
-                        let min_split_index = calc_min_split_index(&[*key], b_key)?;
-                        let keys = &[*key];
-                        let descendants = check_descendants(keys, index, b_key, min_split_index)?;
-                        if descendants.is_empty() {
-                            return Ok(None);
-                        }
+        })
+    }
 
-                        if choose_zero(*key, index)? {
-                            nodes.push_back(*b.get_zero());
-                        } else {
-                            nodes.push_back(*b.get_one());
-                        }
-                    }
-                    NodeVariant::Leaf(l) => {
-                        if found_leaf {
-                            return Err(Exception::new("Corrupt Merkle Tree"));
-                        }
+    /// Evicts every node of the database cache that is not pinned, returning the number evicted.
+    /// # Errors
+    /// `Exception` generated if the cache cannot be evicted.
+    #[inline]
+    pub fn evict_cache(&self) -> BinaryMerkleTreeResult<usize> {
+        self.db.evict_unpinned()
+    }
 
-                        if l.get_key() != key {
-                            return Ok(None);
-                        }
+    /// Walks the path from `root_hash` to `key`, getting each node with `visit`, and returns the
+    /// number of nodes visited.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    fn visit_path<VisitFnType>(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+        mut visit: VisitFnType,
+    ) -> BinaryMerkleTreeResult<usize>
+    where
+        VisitFnType: FnMut(ArrayType) -> BinaryMerkleTreeResult<Option<NodeType>>,
+    {
+        let mut visited = 0;
+        let mut next = Some(*root_hash);
+        while let Some(location) = next.take() {
+            if visited > self.depth + 1 {
+                return Err(Exception::with_kind(
+                    ErrorKind::DepthExceeded,
+                    "Depth limit exceeded",
+                ));
+            }
 
-                        found_leaf = true;
-                        nodes.push_back(*l.get_data());
+            let node = match visit(location)? {
+                Some(node) => node,
+                None => break,
+            };
+            visited += 1;
+            match node.get_variant() {
+                NodeVariant::Branch(b) => {
+                    let index = b.get_split_index();
+                    let b_key = b.get_key();
+                    let keys = &[*key];
+                    let min_split_index =
+                        calc_min_split_index_ordered::<KeyOrderType, _>(keys, b_key)?;
+                    let descendants = check_descendants_ordered::<KeyOrderType, _>(
+                        keys,
+                        index,
+                        b_key,
+                        min_split_index,
+                    )?;
+                    if descendants.is_empty() {
+                        break;
                     }
-                    NodeVariant::Data(d) => {
-                        if !found_leaf {
-                            return Err(Exception::new("Corrupt Merkle Tree"));
-                        }
 
-                        let buffer = d.get_value();
-                        let value = ValueType::decode(buffer)?;
-                        return Ok(Some(value));
-                    }
-                    NodeVariant::Phantom(_) => {
-                        return Err(Exception::new(
-                            "Corrupt merkle tree: Found phantom node while traversing tree",
-                        ));
+                    next = if choose_zero_ordered::<KeyOrderType, _>(*key, index)? {
+                        Some(*b.get_zero())
+                    } else {
+                        Some(*b.get_one())
+                    };
+                }
+                NodeVariant::Leaf(l) => {
+                    if l.get_key() == key && l.get_inline_value().is_none() {
+                        next = Some(*l.get_data());
                     }
                 }
+                NodeVariant::Data(_) => (),
+                NodeVariant::Phantom(_) => {
+                    return Err(Exception::new(
+                        "Corrupt merkle tree: Found phantom node while traversing tree",
+                    ));
+                }
             }
         }
-        Ok(None)
+        Ok(visited)
+    }
+}
+
+impl<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >
+    MerkleBIT<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >
+where
+    DatabaseType: Database<ArrayType, NodeType = NodeType>,
+    BranchType: Branch<ArrayType>,
+    LeafType: Leaf<ArrayType>,
+    DataType: Data,
+    NodeType: Node<BranchType, LeafType, DataType, ArrayType> + Encode,
+    HasherType: Hasher<ArrayType, HashType = HasherType>,
+    ValueType: Decode + Encode,
+    ArrayType: Array,
+    KeyOrderType: KeyOrder<ArrayType>,
+{
+    /// Gets the number of bytes occupied by the encoded nodes reachable from `root_hash`,
+    /// including data nodes.  Nodes shared within the tree, or with other trees, are counted once,
+    /// so this is the storage held by the root rather than the space freed by removing it.
+    /// Values moved to a `BlobStore` are not counted.  Only available for encodable nodes.
+    /// # Errors
+    /// `Exception` generated if a node is missing, or when an invalid state is encountered during
+    /// tree traversal.
+    #[inline]
+    pub fn storage_bytes(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<u64> {
+        let mut total = 0;
+        for location in self.reachable_locations(root_hash)? {
+            if let Some(size) = self.db.node_size(location)? {
+                total += size;
+            } else {
+                return Err(Exception::new("Failed to find node in database."));
+            }
+        }
+        Ok(total)
     }
+}
 
-    /// Inserts a single value into a tree.
+impl<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >
+    MerkleBIT<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >
+where
+    DatabaseType: Database<ArrayType, NodeType = NodeType>,
+    BranchType: Branch<ArrayType>,
+    LeafType: Leaf<ArrayType>,
+    DataType: Data,
+    NodeType: Node<BranchType, LeafType, DataType, ArrayType>,
+    HasherType: Hasher<ArrayType, HashType = HasherType>,
+    ValueType: Decode + Encode + Ord,
+    ArrayType: Array,
+    KeyOrderType: KeyOrder<ArrayType>,
+{
+    /// Gets every key and value under `root_hash` ordered by value, ascending or `descending`.
+    /// Keys holding equal values stay in key order.  This collects the entries of `iter` and sorts
+    /// them, so it is only available when `ValueType` is `Ord`, and compares decoded values rather
+    /// than their encodings.
     /// # Errors
     /// `Exception` generated if an invalid state is encountered during tree traversal.
     #[inline]
-    pub fn insert_one(
-        &mut self,
-        previous_root: Option<&ArrayType>,
-        key: &ArrayType,
-        value: &ValueType,
-    ) -> BinaryMerkleTreeResult<ArrayType> {
-        let mut value_map = HashMap::new();
-        value_map.insert(*key, value);
+    pub fn entries_by_value(
+        &self,
+        root_hash: &ArrayType,
+        descending: bool,
+    ) -> BinaryMerkleTreeResult<Vec<(ArrayType, ValueType)>> {
+        let mut entries = self
+            .iter(root_hash)
+            .collect::<BinaryMerkleTreeResult<Vec<_>>>()?;
+        if descending {
+            entries.sort_by(|a, b| b.1.cmp(&a.1));
+        } else {
+            entries.sort_by(|a, b| a.1.cmp(&b.1));
+        }
+        Ok(entries)
+    }
+}
 
-        let leaf_location = self.insert_leaves(&[*key], &value_map)?[0];
+/// A hook given the location of a node and its old and new reference counts, as set by
+/// `set_on_refcount_change`.
+#[cfg(feature = "debug_tools")]
+pub type RefcountHook<ArrayType> = Arc<dyn Fn(&ArrayType, u64, u64) + Send + Sync>;
 
-        let mut tree_refs = Vec::with_capacity(1);
-        let mut key_map = HashMap::new();
-        key_map.insert(*key, leaf_location);
+/// A key and value along with its inclusion proof, as produced by `iter_with_proofs`.
+pub type ProvenEntry<ArrayType, ValueType> = (ArrayType, ValueType, Proof<ArrayType>);
 
-        let tree_ref = TreeRef::new(*key, leaf_location, 1, 1);
-        tree_refs.push(tree_ref);
+/// A page of entries and the key to continue after, as returned by `iter_from`.
+pub type EntryPage<ArrayType, ValueType> = (Vec<(ArrayType, ValueType)>, Option<ArrayType>);
 
-        if let Some(root) = previous_root {
-            let mut proof_nodes = self.generate_treerefs(root, &mut [*key], &key_map)?;
-            tree_refs.append(&mut proof_nodes);
+/// The `MerkleBITReader` over the database of a `MerkleBIT`, or the error creating it, as returned
+/// by `into_reader`.
+type ReaderResult<
+    DatabaseType,
+    BranchType,
+    LeafType,
+    DataType,
+    NodeType,
+    HasherType,
+    ValueType,
+    ArrayType,
+    KeyOrderType,
+> = BinaryMerkleTreeResult<
+    MerkleBITReader<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >,
+>;
+
+/// The leaves found for a set of keys along with the index of the key each one holds, and the
+/// root node when it was kept, as returned by `find_leaves`.
+type FoundLeaves<LeafType, NodeType> = (Vec<(LeafType, usize)>, Option<NodeType>);
+
+/// The inclusion proof for a key, the split indices of the branches on its path, and its encoded
+/// value.
+type InclusionTrace<ArrayType> = (Vec<(ArrayType, bool)>, Vec<usize>, Vec<u8>);
+
+/// The split index, key and children of a branch or leaf, as read by `split_of`.
+type SplitSummary<ArrayType> = (usize, ArrayType, Option<(ArrayType, ArrayType)>);
+
+/// The key of a node, the number of leading key bits shared by every leaf under it, its leaf
+/// count, and the children of a branch, as read by `merge_roots`.
+type MergeSummary<ArrayType> = (ArrayType, usize, u64, Option<(ArrayType, ArrayType)>);
+
+/// Statistics on how many data nodes were shared rather than written during inserts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DedupStats {
+    /// The number of new data nodes written.
+    pub written_data_nodes: u64,
+    /// The number of data nodes which already existed and were shared.
+    pub shared_data_nodes: u64,
+    /// The number of value bytes not written because an existing data node was shared.
+    pub bytes_saved: u64,
+}
+
+/// A node of the tree planned by `merge_roots`, before any of it is written.
+enum MergedNode<ArrayType> {
+    /// An existing node shared by the merged tree, with the key it is indexed by and the number
+    /// of leaves under it.
+    Existing {
+        /// The location of the node.
+        location: ArrayType,
+        /// The key the node is indexed by.
+        key: ArrayType,
+        /// The number of leaves under the node.
+        count: u64,
+    },
+    /// A new branch joining two planned subtrees.
+    Branch {
+        /// The bit the branch splits its keys at.
+        split_index: usize,
+        /// The key of its zero side.
+        key: ArrayType,
+        /// The number of leaves under both sides.
+        count: u64,
+        /// The side of the keys with a zero at `split_index`.
+        zero: Box<Self>,
+        /// The side of the keys with a one at `split_index`.
+        one: Box<Self>,
+    },
+}
+
+impl<ArrayType: Copy> MergedNode<ArrayType> {
+    /// Creates a new branch splitting at `split_index` into `zero` and `one`.  The branch takes
+    /// its key from the zero side, as the branches of an insert do.
+    fn branch(split_index: usize, zero: Self, one: Self) -> Self {
+        let (key, zero_count) = zero.summary();
+        let (_, one_count) = one.summary();
+        Self::Branch {
+            split_index,
+            key,
+            count: zero_count + one_count,
+            zero: Box::new(zero),
+            one: Box::new(one),
         }
+    }
 
-        let new_root = self.create_tree(tree_refs)?;
-        Ok(new_root)
+    /// Gets the key the node is indexed by and the number of leaves under it.
+    fn summary(&self) -> (ArrayType, u64) {
+        match self {
+            Self::Existing { key, count, .. } | Self::Branch { key, count, .. } => (*key, *count),
+        }
     }
 }
 
@@ -992,9 +5626,28 @@ where
     _UnusedData(PhantomData<DataType>),
 }
 
+/// Returns a `Cancelled` `Exception` if the optional `cancel` flag has been set.
+/// # Errors
+/// `Exception` generated when `cancel` is set.
+fn check_cancelled(cancel: Option<&AtomicBool>) -> BinaryMerkleTreeResult<()> {
+    if let Some(c) = cancel {
+        if c.load(Ordering::Relaxed) {
+            return Err(Exception::with_kind(
+                ErrorKind::Cancelled,
+                "The operation was cancelled",
+            ));
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 pub mod tests {
-    use crate::utils::tree_utils::choose_zero;
+    use crate::utils::tree_ref::TreeRef;
+    use crate::utils::tree_utils::{
+        calc_min_split_index, check_descendants, choose_zero, common_prefix_bits, fast_log_2,
+        generate_tree_ref_queue, radix_sort_keys, split_pairs, tree_depth,
+    };
 
     use super::*;
 
@@ -1069,6 +5722,20 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "not partitioned")]
+    fn it_rejects_pairs_not_partitioned_at_the_split_bit() {
+        // A one key between zero keys can only come from a corrupt branch
+        let zero_key = [0x00u8; KEY_LEN];
+        let one_key = [0xFFu8; KEY_LEN];
+        let keys = vec![zero_key, zero_key, one_key, zero_key, one_key, one_key];
+        // Bisection alone would send a key down the wrong side of the split
+        if let Ok((zeros, ones)) = split_pairs(&keys, 0) {
+            assert!(zeros.contains(&one_key) || ones.contains(&zero_key));
+        }
+    }
+
     #[test]
     fn it_splits_an_all_ones_sorted_list_of_pairs() -> Result<(), Exception> {
         let one_key = [0xFFu8; KEY_LEN];
@@ -1147,4 +5814,176 @@ pub mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn it_calculates_the_depth_of_a_tree_from_its_keys() {
+        let mut clustered_a = [0x40u8; KEY_LEN];
+        clustered_a[KEY_LEN - 1] = 0x00;
+        let mut clustered_b = [0x40u8; KEY_LEN];
+        clustered_b[KEY_LEN - 1] = 0x01;
+        let keys = vec![
+            [0x00u8; KEY_LEN],
+            clustered_a,
+            clustered_b,
+            [0x80u8; KEY_LEN],
+        ];
+
+        assert_eq!(
+            common_prefix_bits(&clustered_a, &clustered_b),
+            KEY_LEN * 8 - 1
+        );
+        assert_eq!(common_prefix_bits(&keys[0], &keys[3]), 0);
+        assert_eq!(common_prefix_bits(&keys[0], &keys[0]), KEY_LEN * 8);
+
+        assert_eq!(tree_depth(&keys), 3);
+        assert_eq!(tree_depth(&keys[..1]), 0);
+        assert_eq!(tree_depth(&[keys[0], keys[0], keys[3]]), 1);
+        assert_eq!(tree_depth::<[u8; KEY_LEN]>(&[]), 0);
+    }
+
+    #[test]
+    fn it_radix_sorts_keys_in_byte_order() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng: StdRng = SeedableRng::from_seed([0x41u8; KEY_LEN]);
+        let mut keys = Vec::with_capacity(4096);
+        for i in 0..4096 {
+            let mut key = [0u8; KEY_LEN];
+            rng.fill(&mut key);
+            // Share long prefixes and repeat keys, so buckets are split many bytes deep
+            if i % 4 == 0 {
+                key[..KEY_LEN - 1].copy_from_slice(&[0xAAu8; KEY_LEN - 1]);
+            }
+            keys.push(key);
+            if i % 16 == 0 {
+                keys.push(key);
+            }
+        }
+
+        let mut expected = keys.clone();
+        expected.sort();
+        radix_sort_keys(&mut keys);
+        assert_eq!(keys, expected);
+
+        let mut small = vec![[0x02u8; KEY_LEN], [0x01u8; KEY_LEN]];
+        radix_sort_keys(&mut small);
+        assert_eq!(small, vec![[0x01u8; KEY_LEN], [0x02u8; KEY_LEN]]);
+        radix_sort_keys::<[u8; KEY_LEN]>(&mut []);
+    }
+
+    #[test]
+    fn it_finds_the_highest_set_bit_of_each_byte() -> Result<(), Exception> {
+        for position in 0..8_u8 {
+            let highest = 0x80_u8 >> (7 - position);
+            assert_eq!(fast_log_2(highest), position);
+            // Lower bits never change the result
+            assert_eq!(fast_log_2(highest | (highest - 1)), position);
+        }
+        for num in 1..=u8::MAX {
+            assert_eq!(fast_log_2(num), 7 - u8::try_from(num.leading_zeros())?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "fast_log_2 called with zero")]
+    fn it_rejects_a_zero_log_2_in_debug_builds() {
+        assert_eq!(fast_log_2(0), 0);
+    }
+
+    #[test]
+    fn it_never_queues_a_split_between_identical_keys() {
+        let key = [0x3Cu8; KEY_LEN];
+        let mut tree_refs = vec![
+            TreeRef::new(key, [0_u8; KEY_LEN], 1, 1),
+            TreeRef::new(key, [1_u8; KEY_LEN], 1, 1),
+        ];
+        let mut tree_ref_queue = HashMap::new();
+        assert!(generate_tree_ref_queue(&mut tree_refs, &mut tree_ref_queue).is_err());
+        assert!(tree_ref_queue.is_empty());
+    }
+
+    #[test]
+    fn it_splits_keys_differing_only_in_the_last_bit() -> Result<(), Exception> {
+        let low = [0x5Au8; KEY_LEN];
+        let mut high = low;
+        high[KEY_LEN - 1] |= 0x01;
+        let last_bit = KEY_LEN * 8 - 1;
+        let keys = [low, high];
+
+        assert_eq!(common_prefix_bits(&low, &high), last_bit);
+        assert_eq!(calc_min_split_index(&keys, &low)?, last_bit);
+        assert_eq!(tree_depth(&keys), 1);
+        assert!(choose_zero(low, last_bit)?);
+        assert!(!choose_zero(high, last_bit)?);
+
+        for bit in 0..last_bit {
+            let (zeros, ones) = split_pairs(&keys, bit)?;
+            assert_eq!(zeros.len() + ones.len(), 2);
+            assert!(zeros.is_empty() || ones.is_empty());
+        }
+        let (zeros, ones) = split_pairs(&keys, last_bit)?;
+        assert_eq!(zeros, &[low]);
+        assert_eq!(ones, &[high]);
+
+        // Both keys descend from a branch splitting at the last bit, and only one from its children
+        assert_eq!(check_descendants(&keys, last_bit, &low, 0)?, &keys[..]);
+        assert_eq!(check_descendants(&keys, last_bit, &high, 0)?, &keys[..]);
+        assert_eq!(check_descendants(&keys, KEY_LEN * 8, &low, 0)?, &keys[..1]);
+        assert_eq!(check_descendants(&keys, KEY_LEN * 8, &high, 0)?, &keys[1..]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_splits_keys_differing_in_a_single_byte() -> Result<(), Exception> {
+        for byte in 0..KEY_LEN {
+            for bit in 0..8 {
+                let low = [0x00u8; KEY_LEN];
+                let mut high = low;
+                high[byte] = 0x80 >> bit;
+                let split = byte * 8 + bit;
+                let keys = [low, high];
+
+                assert_eq!(common_prefix_bits(&low, &high), split);
+                assert_eq!(calc_min_split_index(&keys, &low)?, split);
+                assert_eq!(calc_min_split_index(&keys, &high)?, split);
+                assert!(!choose_zero(high, split)?);
+
+                let (zeros, ones) = split_pairs(&keys, split)?;
+                assert_eq!(zeros, &[low]);
+                assert_eq!(ones, &[high]);
+                if split > 0 {
+                    assert_eq!(split_pairs(&keys, split - 1)?.0.len(), 2);
+                }
+
+                assert_eq!(check_descendants(&keys, split, &low, 0)?, &keys[..]);
+                assert_eq!(check_descendants(&keys, split + 1, &low, 0)?, &keys[..1]);
+                assert_eq!(check_descendants(&keys, split + 1, &high, 0)?, &keys[1..]);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_checks_every_byte_before_the_split_for_descendants() -> Result<(), Exception> {
+        // The keys first diverge from the branch key in the middle of the first byte, so the bits
+        // compared are not aligned to bytes, and the second key only differs in the second byte
+        let branch_key = [0x00u8; KEY_LEN];
+        let mut second_byte = [0x00u8; KEY_LEN];
+        second_byte[1] = 0x80;
+        let mut first_byte = [0x00u8; KEY_LEN];
+        first_byte[0] = 0x10;
+        let keys = [second_byte, first_byte];
+
+        let min_split_index = calc_min_split_index(&keys, &branch_key)?;
+        assert_eq!(min_split_index, 3);
+        assert!(check_descendants(&keys, 9, &branch_key, min_split_index)?.is_empty());
+        assert_eq!(
+            check_descendants(&keys, 8, &branch_key, min_split_index)?,
+            &keys[..1]
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[cfg(not(any(feature = "use_hashbrown")))]
+use std::collections::HashMap;
+
+#[cfg(feature = "use_hashbrown")]
+use hashbrown::HashMap;
+
+use crate::merkle_bit::{BinaryMerkleTreeResult, MerkleBIT};
+use crate::traits::{
+    Array, BlobStore, Branch, Data, Database, Decode, Encode, Hasher, KeyOrder, Leaf, Node,
+};
+use crate::tree_db::read_only::ReadOnlyDatabase;
+use crate::utils::hash_scheme::HashScheme;
+use crate::utils::key_order::BigEndianOrder;
+use crate::utils::proof::Proof;
+use crate::utils::tree_options::TreeOptions;
+
+/// A read only view of a `MerkleBIT`.
+///
+/// A `MerkleBITReader` offers the queries of a `MerkleBIT` (`get`, `contains_key`, `iter` and
+/// proof generation) but none of its mutations.  It reads from a database shared through an
+/// `Arc`, so several readers, possibly on different threads, can query one database without a
+/// `Mutex`.  Writes still go through a `MerkleBIT` owning its database; a reader over a database
+/// that is also being written to sees every root written before it reads it.  The reader is
+/// `Send` and `Sync` whenever the database is.
+pub struct MerkleBITReader<
+    DatabaseType,
+    BranchType,
+    LeafType,
+    DataType,
+    NodeType,
+    HasherType,
+    ValueType,
+    ArrayType,
+    KeyOrderType = BigEndianOrder,
+> where
+    DatabaseType: Database<ArrayType, NodeType = NodeType>,
+    BranchType: Branch<ArrayType>,
+    LeafType: Leaf<ArrayType>,
+    DataType: Data,
+    NodeType: Node<BranchType, LeafType, DataType, ArrayType>,
+    HasherType: Hasher<ArrayType>,
+    ArrayType: Array,
+    ValueType: Decode + Encode,
+    KeyOrderType: KeyOrder<ArrayType>,
+{
+    /// The underlying tree, which cannot write to its database.
+    tree: MerkleBIT<
+        ReadOnlyDatabase<DatabaseType>,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >,
+}
+
+impl<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >
+    MerkleBITReader<
+        DatabaseType,
+        BranchType,
+        LeafType,
+        DataType,
+        NodeType,
+        HasherType,
+        ValueType,
+        ArrayType,
+        KeyOrderType,
+    >
+where
+    DatabaseType: Database<ArrayType, NodeType = NodeType>,
+    BranchType: Branch<ArrayType>,
+    LeafType: Leaf<ArrayType>,
+    DataType: Data,
+    NodeType: Node<BranchType, LeafType, DataType, ArrayType>,
+    HasherType: Hasher<ArrayType, HashType = HasherType>,
+    ValueType: Decode + Encode,
+    ArrayType: Array,
+    KeyOrderType: KeyOrder<ArrayType>,
+{
+    /// Create a new `MerkleBITReader` over a shared database.
+    /// # Errors
+    /// None.
+    #[inline]
+    pub fn new(db: Arc<DatabaseType>, depth: usize) -> BinaryMerkleTreeResult<Self> {
+        let tree = MerkleBIT::from_db(ReadOnlyDatabase::new(db), depth)?;
+        Ok(Self { tree })
+    }
+
+    /// Create a new `MerkleBITReader` over a shared database written by a tree configured with
+    /// `options`.  Proofs and values are only read correctly with the options of the tree that
+    /// wrote them: its `HashScheme` for computing locations, and whether it is commit-only.
+    /// # Errors
+    /// None.
+    #[inline]
+    pub fn new_with_options(
+        db: Arc<DatabaseType>,
+        depth: usize,
+        options: TreeOptions,
+    ) -> BinaryMerkleTreeResult<Self> {
+        let tree = MerkleBIT::from_db_with_options(ReadOnlyDatabase::new(db), depth, options)?;
+        Ok(Self { tree })
+    }
+
+    /// Create a new `MerkleBITReader` over a shared database written by a tree computing
+    /// locations with `scheme`, and otherwise configured with the defaults.
+    /// # Errors
+    /// None.
+    #[inline]
+    pub fn new_with_scheme(
+        db: Arc<DatabaseType>,
+        depth: usize,
+        scheme: HashScheme,
+    ) -> BinaryMerkleTreeResult<Self> {
+        Self::new_with_options(db, depth, TreeOptions::from(scheme))
+    }
+
+    /// Create a new `MerkleBITReader` from a saved database.
+    /// # Errors
+    /// `Exception` generated if the `open` fails.
+    #[inline]
+    pub fn open(path: &PathBuf, depth: usize) -> BinaryMerkleTreeResult<Self> {
+        let tree = MerkleBIT::new(path, depth)?;
+        Ok(Self { tree })
+    }
+
+    /// Gets the options the reader was configured with.
+    #[inline]
+    #[must_use]
+    pub const fn options(&self) -> &TreeOptions {
+        self.tree.options()
+    }
+
+    /// Sets the store to read values held outside the tree from.  See `MerkleBIT::set_blob_store`.
+    #[inline]
+    pub fn set_blob_store(&mut self, store: Arc<dyn BlobStore<ArrayType> + Send + Sync>) {
+        // The reader never writes, so the size above which values are moved is irrelevant
+        self.tree.set_blob_store(store, usize::MAX);
+    }
+
+    /// Get items from the tree.  Returns a map of `Option`s which may include the corresponding values.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn get(
+        &self,
+        root_hash: &ArrayType,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<HashMap<ArrayType, Option<ValueType>>> {
+        self.tree.get(root_hash, keys)
+    }
+
+    /// Gets a single item out of the tree.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn get_one(
+        &self,
+        root: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<ValueType>> {
+        self.tree.get_one(root, key)
+    }
+
+    /// Checks whether `key` is present under `root_hash`, without reading its value.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn contains_key(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<bool> {
+        self.tree.contains_key(root_hash, key)
+    }
+
+    /// Gets the encoded bytes of the value of `key` under `root_hash`, without decoding them.
+    /// # Errors
+    /// `Exception` generated when an invalid state is encountered during tree traversal.
+    #[inline]
+    pub fn get_raw(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<Vec<u8>>> {
+        self.tree.get_raw(root_hash, key)
+    }
+
+    /// Gets every key and value under `root_hash` lazily, in ascending key order.  After an error
+    /// the iterator ends.
+    #[inline]
+    pub fn iter<'a>(
+        &'a self,
+        root_hash: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<(ArrayType, ValueType)>> + 'a {
+        self.tree.iter(root_hash)
+    }
+
+    /// Generates an inclusion proof for `key` under `root`.
+    /// # Errors
+    /// `Exception` generated when the key does not exist in the tree.
+    #[inline]
+    pub fn generate_inclusion_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+    ) -> BinaryMerkleTreeResult<Vec<(ArrayType, bool)>> {
+        self.tree.generate_inclusion_proof(root, key)
+    }
+
+    /// Generates a self contained `Proof` for `key` under `root`.
+    /// # Errors
+    /// `Exception` generated when the key does not exist in the tree.
+    #[inline]
+    pub fn generate_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+    ) -> BinaryMerkleTreeResult<Proof<ArrayType>> {
+        self.tree.generate_proof(root, key)
+    }
+}
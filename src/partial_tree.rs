@@ -0,0 +1,213 @@
+#[cfg(not(any(feature = "use_hashbrown")))]
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+#[cfg(feature = "use_hashbrown")]
+use hashbrown::HashMap;
+
+use crate::merkle_bit::BinaryMerkleTreeResult;
+use crate::traits::{Array, Decode, ErrorKind, Exception, Hasher, KeyOrder};
+use crate::utils::hash_scheme::HashScheme;
+use crate::utils::key_order::BigEndianOrder;
+use crate::utils::proof::Proof;
+use crate::utils::tree_utils::{choose_zero_ordered, prefix_matches};
+
+/// A node of the tree known to a `PartialTree`.
+enum PartialNode<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// A branch on the path of a proven key.
+    Branch {
+        /// The location of the zero child.
+        zero: ArrayType,
+        /// The location of the one child.
+        one: ArrayType,
+        /// The bit the branch splits its keys at.
+        split_index: usize,
+        /// A key below the branch, sharing every bit before `split_index` with all of them.
+        key: ArrayType,
+    },
+    /// The leaf of a proven key.
+    Leaf {
+        /// The key of the leaf.
+        key: ArrayType,
+        /// The encoded value of the leaf.
+        value: Vec<u8>,
+    },
+}
+
+/// A sparse view of a tree under a single root, holding only the nodes on the paths of keys
+/// whose inclusion proofs were imported.
+///
+/// A `PartialTree` lets a verifier with little memory keep the part of a tree it cares about
+/// without a database.  Each proof is verified against the root before its nodes are kept, and
+/// `get` walks the kept nodes, answering for a key whose path is fully present and failing with
+/// `ErrorKind::MissingNode` where the path leaves them.  The split indices of the branches are
+/// taken from the proofs and are not covered by the root, so a key reported absent is only known to
+/// be absent if the proofs were generated honestly; a value returned is always proven.
+pub struct PartialTree<HasherType, ValueType, ArrayType, KeyOrderType = BigEndianOrder>
+where
+    HasherType: Hasher<ArrayType, HashType = HasherType>,
+    ValueType: Decode,
+    ArrayType: Array,
+    KeyOrderType: KeyOrder<ArrayType>,
+{
+    /// The root every imported proof is verified against.
+    root: ArrayType,
+    /// The scheme used for computing node locations.
+    scheme: HashScheme,
+    /// The known nodes, by location.
+    nodes: HashMap<ArrayType, PartialNode<ArrayType>>,
+    /// Marker for the hasher.
+    hasher: PhantomData<fn() -> HasherType>,
+    /// Marker for the value type.
+    value: PhantomData<fn() -> ValueType>,
+    /// Marker for the key order.
+    key_order: PhantomData<fn() -> KeyOrderType>,
+}
+
+impl<HasherType, ValueType, ArrayType, KeyOrderType>
+    PartialTree<HasherType, ValueType, ArrayType, KeyOrderType>
+where
+    HasherType: Hasher<ArrayType, HashType = HasherType>,
+    ValueType: Decode,
+    ArrayType: Array,
+    KeyOrderType: KeyOrder<ArrayType>,
+{
+    /// Creates a new `PartialTree` for `root`, knowing no nodes, with the default hashing scheme.
+    #[inline]
+    pub fn new(root: ArrayType) -> Self {
+        Self::with_scheme(root, HashScheme::new())
+    }
+
+    /// Creates a new `PartialTree` for `root`, knowing no nodes, computing locations with `scheme`,
+    /// which must match the scheme of the tree the proofs come from.
+    #[inline]
+    pub fn with_scheme(root: ArrayType, scheme: HashScheme) -> Self {
+        Self {
+            root,
+            scheme,
+            nodes: HashMap::new(),
+            hasher: PhantomData,
+            value: PhantomData,
+            key_order: PhantomData,
+        }
+    }
+
+    /// Gets the root of the partial tree.
+    #[inline]
+    pub const fn root(&self) -> &ArrayType {
+        &self.root
+    }
+
+    /// Gets the number of nodes the partial tree knows.
+    #[inline]
+    #[must_use]
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Verifies `proof` against the root and keeps the leaf and branches it proves.  Nothing is
+    /// kept if the proof does not verify.  Nodes already known are left untouched, so proofs
+    /// sharing part of their paths only store the shared branches once.
+    /// # Errors
+    /// `Exception` generated when the proof does not lead to the root.
+    #[inline]
+    pub fn import_proof(&mut self, proof: &Proof<ArrayType>) -> BinaryMerkleTreeResult<()> {
+        let key = *proof.key();
+        let leaf_location = self
+            .scheme
+            .leaf_location_of_value::<HasherType, _>(&key, proof.value());
+
+        let mut branches = Vec::with_capacity(proof.siblings().len());
+        let mut current = leaf_location;
+        for sibling in proof.siblings() {
+            let (zero, one) = if sibling.direction {
+                (current, sibling.location)
+            } else {
+                (sibling.location, current)
+            };
+            current = self.scheme.branch_location::<HasherType, _>(&zero, &one);
+            branches.push((
+                current,
+                PartialNode::Branch {
+                    zero,
+                    one,
+                    split_index: sibling.split_index,
+                    key,
+                },
+            ));
+        }
+        if current != self.root {
+            return Err(Exception::new("Proof is invalid"));
+        }
+
+        self.nodes
+            .entry(leaf_location)
+            .or_insert_with(|| PartialNode::Leaf {
+                key,
+                value: proof.value().to_vec(),
+            });
+        for (location, branch) in branches {
+            self.nodes.entry(location).or_insert(branch);
+        }
+        Ok(())
+    }
+
+    /// Gets the value of `key` from the known nodes.  Returns `None` if the path to `key` ends at
+    /// a different leaf, or at a branch whose keys do not share the prefix of `key`.
+    /// # Errors
+    /// `Exception` of kind `ErrorKind::MissingNode` generated if the path to `key` reaches a node
+    /// no imported proof covers, or generated if the value cannot be decoded.
+    #[inline]
+    pub fn get(&self, key: &ArrayType) -> BinaryMerkleTreeResult<Option<ValueType>> {
+        if self.root == ArrayType::default() {
+            return Ok(None);
+        }
+
+        let ordered_key = KeyOrderType::order_key(key);
+        let mut location = self.root;
+        loop {
+            match self.nodes.get(&location) {
+                Some(PartialNode::Branch {
+                    zero,
+                    one,
+                    split_index,
+                    key: branch_key,
+                }) => {
+                    let ordered_branch_key = KeyOrderType::order_key(branch_key);
+                    if !prefix_matches(
+                        ordered_key.as_ref(),
+                        ordered_branch_key.as_ref(),
+                        *split_index,
+                    ) {
+                        return Ok(None);
+                    }
+                    location = if choose_zero_ordered::<KeyOrderType, _>(*key, *split_index)? {
+                        *zero
+                    } else {
+                        *one
+                    };
+                }
+                Some(PartialNode::Leaf {
+                    key: leaf_key,
+                    value,
+                }) => {
+                    if leaf_key != key {
+                        return Ok(None);
+                    }
+                    return Ok(Some(ValueType::decode(value)?));
+                }
+                None => {
+                    return Err(Exception::with_kind(
+                        ErrorKind::MissingNode {
+                            location: location.as_ref().to_vec(),
+                        },
+                        "Node is not known to the partial tree",
+                    ));
+                }
+            }
+        }
+    }
+}
@@ -1,18 +1,29 @@
 #[cfg(not(any(feature = "use_hashbrown")))]
 use std::collections::HashMap;
+use std::io::{Read, Write};
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
 #[cfg(feature = "use_hashbrown")]
 use hashbrown::HashMap;
 
-use crate::merkle_bit::{BinaryMerkleTreeResult, MerkleBIT};
-use crate::traits::{Array, Database, Decode, Encode};
+#[cfg(feature = "debug_tools")]
+use crate::merkle_bit::RefcountHook;
+use crate::merkle_bit::{BinaryMerkleTreeResult, DedupStats, EntryPage, MerkleBIT, ProvenEntry};
+use crate::traits::{Array, BlobStore, Database, Decode, Encode, Hasher};
 use crate::tree::tree_branch::TreeBranch;
 use crate::tree::tree_data::TreeData;
 use crate::tree::tree_leaf::TreeLeaf;
-use crate::tree::tree_node::TreeNode;
+use crate::tree::tree_node::{TreeNode, TreeNodeVariant};
 use crate::tree_db::rocksdb::RocksDB;
 use crate::tree_hasher::TreeHasher;
+use crate::utils::hash_scheme::DomainTags;
+use crate::utils::ics23::{ExistenceProof, HashOp, ProofSpec};
+use crate::utils::proof::{Proof, SubtreeProof};
+use crate::utils::snapshot::Snapshot;
+use crate::utils::tree_build_scratch::TreeBuildScratch;
+use crate::utils::tree_options::TreeOptions;
 #[cfg(feature = "use_serde")]
 use serde::de::DeserializeOwned;
 #[cfg(feature = "use_serde")]
@@ -50,12 +61,46 @@ where
         Ok(Self { tree })
     }
 
+    #[inline]
+    pub fn open_with_domain(
+        path: &PathBuf,
+        depth: usize,
+        domain: &[u8],
+    ) -> BinaryMerkleTreeResult<Self> {
+        let db = RocksDB::open(path)?;
+        let tree = MerkleBIT::from_db_with_domain(db, depth, domain)?;
+        Ok(Self { tree })
+    }
+
+    #[inline]
+    pub fn open_with_options(
+        path: &PathBuf,
+        depth: usize,
+        options: TreeOptions,
+    ) -> BinaryMerkleTreeResult<Self> {
+        let db = RocksDB::open(path)?;
+        let tree = MerkleBIT::from_db_with_options(db, depth, options)?;
+        Ok(Self { tree })
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn options(&self) -> &TreeOptions {
+        self.tree.options()
+    }
+
     #[inline]
     pub fn from_db(db: RocksDB<ArrayType>, depth: usize) -> BinaryMerkleTreeResult<Self> {
         let tree = MerkleBIT::from_db(db, depth)?;
         Ok(Self { tree })
     }
 
+    #[inline]
+    #[must_use]
+    pub fn empty_root() -> ArrayType {
+        Tree::<ArrayType, ValueType>::empty_root()
+    }
+
     #[inline]
     pub fn get(
         &self,
@@ -65,6 +110,33 @@ where
         self.tree.get(root_hash, keys)
     }
 
+    #[inline]
+    pub fn get_with_depth(
+        &self,
+        root_hash: &ArrayType,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<HashMap<ArrayType, Option<(ValueType, usize)>>> {
+        self.tree.get_with_depth(root_hash, keys)
+    }
+
+    #[inline]
+    pub fn get_with_errors(
+        &self,
+        root_hash: &ArrayType,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<HashMap<ArrayType, BinaryMerkleTreeResult<Option<ValueType>>>> {
+        self.tree.get_with_errors(root_hash, keys)
+    }
+
+    #[inline]
+    pub fn get_ordered(
+        &self,
+        root_hash: &ArrayType,
+        keys: &[ArrayType],
+    ) -> BinaryMerkleTreeResult<Vec<Option<ValueType>>> {
+        self.tree.get_ordered(root_hash, keys)
+    }
+
     #[inline]
     pub fn get_one(
         &self,
@@ -84,6 +156,46 @@ where
         self.tree.insert(previous_root, keys, values)
     }
 
+    #[inline]
+    pub fn insert_with_scratch(
+        &mut self,
+        previous_root: Option<&ArrayType>,
+        keys: &mut [ArrayType],
+        values: &[ValueType],
+        scratch: &mut TreeBuildScratch<ArrayType>,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree
+            .insert_with_scratch(previous_root, keys, values, scratch)
+    }
+
+    #[inline]
+    pub fn insert_encoded(
+        &mut self,
+        previous_root: Option<&ArrayType>,
+        keys: &mut [ArrayType],
+        encoded_values: &[&[u8]],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree
+            .insert_encoded(previous_root, keys, encoded_values)
+    }
+
+    #[inline]
+    pub fn commit_subtrees(
+        &mut self,
+        entries: &[(ArrayType, ArrayType)],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.commit_subtrees(entries)
+    }
+
+    #[inline]
+    pub fn root_for(
+        &self,
+        keys: &[ArrayType],
+        values: &[ValueType],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.root_for(keys, values)
+    }
+
     #[inline]
     pub fn insert_one(
         &mut self,
@@ -99,6 +211,20 @@ where
         self.tree.remove(root_hash)
     }
 
+    #[inline]
+    pub fn current_root(&self) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        self.tree.current_root()
+    }
+
+    #[inline]
+    pub fn compare_and_set_root(
+        &mut self,
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> BinaryMerkleTreeResult<bool> {
+        self.tree.compare_and_set_root(expected, new)
+    }
+
     #[inline]
     pub fn generate_inclusion_proof(
         &self,
@@ -117,4 +243,568 @@ where
     ) -> BinaryMerkleTreeResult<()> {
         Tree::verify_inclusion_proof(root, key, value, proof)
     }
+
+    #[inline]
+    pub fn snapshot(
+        &mut self,
+        root_hash: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Snapshot<ArrayType>> {
+        self.tree.snapshot(root_hash)
+    }
+
+    #[inline]
+    pub fn weak_snapshot(
+        &self,
+        root_hash: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Snapshot<ArrayType>> {
+        self.tree.weak_snapshot(root_hash)
+    }
+
+    #[inline]
+    pub fn get_snapshot(
+        &self,
+        snapshot: &Snapshot<ArrayType>,
+        keys: &mut [ArrayType],
+    ) -> BinaryMerkleTreeResult<HashMap<ArrayType, Option<ValueType>>> {
+        self.tree.get_snapshot(snapshot, keys)
+    }
+
+    #[inline]
+    pub fn set_value_addressed_data(&mut self, value_addressed: bool) {
+        self.tree.set_value_addressed_data(value_addressed)
+    }
+
+    #[inline]
+    pub fn set_combined_leaves(&mut self, combined: bool) {
+        self.tree.set_combined_leaves(combined)
+    }
+
+    #[inline]
+    pub fn set_sorted_children(&mut self, sorted: bool) {
+        self.tree.set_sorted_children(sorted)
+    }
+
+    #[inline]
+    pub fn set_domain_tags(&mut self, tags: DomainTags) {
+        self.tree.set_domain_tags(tags)
+    }
+
+    #[inline]
+    pub fn set_max_value_bytes(&mut self, max_value_bytes: usize) {
+        self.tree.set_max_value_bytes(max_value_bytes)
+    }
+
+    #[inline]
+    pub fn set_max_batch_keys(&mut self, max_batch_keys: usize) {
+        self.tree.set_max_batch_keys(max_batch_keys)
+    }
+
+    #[inline]
+    pub fn set_flush_every(&mut self, flush_every: usize) {
+        self.tree.set_flush_every(flush_every)
+    }
+
+    #[inline]
+    pub fn set_catch_panics(&mut self, catch_panics: bool) {
+        self.tree.set_catch_panics(catch_panics)
+    }
+
+    #[inline]
+    pub fn set_commit_only(&mut self, commit_only: bool) {
+        self.tree.set_commit_only(commit_only)
+    }
+
+    #[inline]
+    pub fn get_commitment(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        self.tree.get_commitment(root_hash, key)
+    }
+
+    #[inline]
+    pub fn set_root_namespace(&mut self, namespace: &[u8]) {
+        self.tree.set_root_namespace(namespace)
+    }
+
+    #[inline]
+    pub fn set_blob_store(
+        &mut self,
+        store: Arc<dyn BlobStore<ArrayType> + Send + Sync>,
+        min_blob_bytes: usize,
+    ) {
+        self.tree.set_blob_store(store, min_blob_bytes)
+    }
+
+    #[cfg(feature = "debug_tools")]
+    #[inline]
+    pub fn set_on_refcount_change(&mut self, hook: RefcountHook<ArrayType>) {
+        self.tree.set_on_refcount_change(hook)
+    }
+
+    #[inline]
+    pub fn set_inline_value_bytes(&mut self, inline_value_bytes: usize) {
+        self.tree.set_inline_value_bytes(inline_value_bytes)
+    }
+
+    #[inline]
+    pub fn set_strict_previous_root(&mut self, strict: bool) {
+        self.tree.set_strict_previous_root(strict)
+    }
+
+    #[inline]
+    pub fn set_strict_empty_inserts(&mut self, strict: bool) {
+        self.tree.set_strict_empty_inserts(strict)
+    }
+
+    #[inline]
+    pub fn set_refcounting(&mut self, enabled: bool) {
+        self.tree.set_refcounting(enabled)
+    }
+
+    #[inline]
+    pub fn set_collision_check(&mut self, enabled: bool) {
+        self.tree.set_collision_check(enabled)
+    }
+
+    #[inline]
+    pub fn set_value_index(&mut self, enabled: bool) -> BinaryMerkleTreeResult<()> {
+        self.tree.set_value_index(enabled)
+    }
+
+    #[inline]
+    pub fn set_value_versioning(&mut self, enabled: bool) {
+        self.tree.set_value_versioning(enabled)
+    }
+
+    #[inline]
+    pub fn value_version(
+        &self,
+        root: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        self.tree.value_version(root, key)
+    }
+
+    #[inline]
+    pub fn dedup_stats(&self) -> &DedupStats {
+        self.tree.dedup_stats()
+    }
+
+    #[inline]
+    pub fn verify_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+        value: &ValueType,
+        proof: &[(ArrayType, bool)],
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.verify_proof(root, key, value, proof)
+    }
+
+    #[inline]
+    pub fn verify_inclusion_by_value_hash(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+        value_hash: &ArrayType,
+        proof: &[(ArrayType, bool)],
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree
+            .verify_inclusion_by_value_hash(root, key, value_hash, proof)
+    }
+
+    #[inline]
+    pub fn migrate_hasher<NewHasherType>(
+        &mut self,
+        root_hash: &ArrayType,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        NewHasherType: Hasher<ArrayType, HashType = NewHasherType>,
+    {
+        self.tree.migrate_hasher::<NewHasherType>(root_hash)
+    }
+
+    #[inline]
+    pub fn merge_roots(
+        &mut self,
+        root_a: &ArrayType,
+        root_b: &ArrayType,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.merge_roots(root_a, root_b)
+    }
+
+    #[inline]
+    pub fn generate_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+    ) -> BinaryMerkleTreeResult<Proof<ArrayType>> {
+        self.tree.generate_proof(root, key)
+    }
+
+    #[inline]
+    pub fn generate_ics23_proof(
+        &self,
+        root: &ArrayType,
+        key: ArrayType,
+        hash: HashOp,
+    ) -> BinaryMerkleTreeResult<ExistenceProof> {
+        self.tree.generate_ics23_proof(root, key, hash)
+    }
+
+    #[inline]
+    pub fn ics23_proof_spec(&self, hash: HashOp) -> BinaryMerkleTreeResult<ProofSpec> {
+        self.tree.ics23_proof_spec(hash)
+    }
+
+    #[inline]
+    pub fn verify_compact_proof(
+        &self,
+        root: &ArrayType,
+        proof: &Proof<ArrayType>,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.verify_compact_proof(root, proof)
+    }
+
+    #[inline]
+    pub fn generate_subtree_proof(
+        &self,
+        global_root: &ArrayType,
+        subtree_key: ArrayType,
+        key: ArrayType,
+    ) -> BinaryMerkleTreeResult<SubtreeProof<ArrayType>> {
+        self.tree
+            .generate_subtree_proof(global_root, subtree_key, key)
+    }
+
+    #[inline]
+    pub fn verify_subtree_proof(
+        &self,
+        global_root: &ArrayType,
+        proof: &SubtreeProof<ArrayType>,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.verify_subtree_proof(global_root, proof)
+    }
+
+    #[inline]
+    pub fn remove_cancellable(
+        &mut self,
+        root_hash: &ArrayType,
+        cancel: &AtomicBool,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.remove_cancellable(root_hash, cancel)
+    }
+
+    #[inline]
+    pub fn update_value(
+        &mut self,
+        previous_root: &ArrayType,
+        key: &ArrayType,
+        new_value: &ValueType,
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.update_value(previous_root, key, new_value)
+    }
+
+    #[inline]
+    pub fn filter<PredicateType>(
+        &mut self,
+        root_hash: &ArrayType,
+        predicate: PredicateType,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        PredicateType: Fn(&ArrayType, &ValueType) -> bool,
+    {
+        self.tree.filter(root_hash, predicate)
+    }
+
+    #[inline]
+    pub fn split(
+        &mut self,
+        root_hash: &ArrayType,
+        boundary: &ArrayType,
+    ) -> BinaryMerkleTreeResult<(ArrayType, ArrayType)> {
+        self.tree.split(root_hash, boundary)
+    }
+
+    #[inline]
+    pub fn rekey<RekeyFnType>(
+        &mut self,
+        root_hash: &ArrayType,
+        rekey_fn: RekeyFnType,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        RekeyFnType: Fn(&ArrayType) -> ArrayType,
+    {
+        self.tree.rekey(root_hash, rekey_fn)
+    }
+
+    #[inline]
+    pub fn merge_sorted_shards<ShardType>(
+        &mut self,
+        shards: Vec<ShardType>,
+    ) -> BinaryMerkleTreeResult<ArrayType>
+    where
+        ShardType: Iterator<Item = (ArrayType, ValueType)>,
+    {
+        self.tree.merge_sorted_shards(shards)
+    }
+
+    #[inline]
+    pub fn structural_root(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.structural_root(root_hash)
+    }
+
+    #[inline]
+    pub fn verify_tree(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<()> {
+        self.tree.verify_tree(root_hash)
+    }
+
+    #[inline]
+    pub fn verify_tree_cancellable(
+        &self,
+        root_hash: &ArrayType,
+        cancel: &AtomicBool,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.verify_tree_cancellable(root_hash, cancel)
+    }
+
+    #[inline]
+    pub fn subtree_root(
+        &self,
+        root_hash: &ArrayType,
+        prefix: &[u8],
+        prefix_bits: u32,
+    ) -> BinaryMerkleTreeResult<Option<ArrayType>> {
+        self.tree.subtree_root(root_hash, prefix, prefix_bits)
+    }
+
+    #[inline]
+    pub fn changed_keys_since<'a>(
+        &'a self,
+        base_root: &ArrayType,
+        new_root: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<ArrayType>> + 'a {
+        self.tree.changed_keys_since(base_root, new_root)
+    }
+
+    #[inline]
+    pub fn keys_only_in<'a>(
+        &'a self,
+        root_a: &ArrayType,
+        root_b: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<ArrayType>> + 'a {
+        self.tree.keys_only_in(root_a, root_b)
+    }
+
+    #[inline]
+    pub fn contains_key(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<bool> {
+        self.tree.contains_key(root_hash, key)
+    }
+
+    #[inline]
+    pub fn has_root(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<bool> {
+        self.tree.has_root(root_hash)
+    }
+
+    #[inline]
+    pub fn iter<'a>(
+        &'a self,
+        root_hash: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<(ArrayType, ValueType)>> + 'a {
+        self.tree.iter(root_hash)
+    }
+
+    #[inline]
+    pub fn iter_cancellable<'a>(
+        &'a self,
+        root_hash: &ArrayType,
+        cancel: &'a AtomicBool,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<(ArrayType, ValueType)>> + 'a {
+        self.tree.iter_cancellable(root_hash, cancel)
+    }
+
+    #[inline]
+    pub fn iter_with_proofs<'a>(
+        &'a self,
+        root_hash: &ArrayType,
+    ) -> impl Iterator<Item = BinaryMerkleTreeResult<ProvenEntry<ArrayType, ValueType>>> + 'a {
+        self.tree.iter_with_proofs(root_hash)
+    }
+
+    #[inline]
+    pub fn recount_references(&mut self, live_roots: &[ArrayType]) -> BinaryMerkleTreeResult<()> {
+        self.tree.recount_references(live_roots)
+    }
+
+    #[inline]
+    pub fn get_raw(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<Vec<u8>>> {
+        self.tree.get_raw(root_hash, key)
+    }
+
+    #[inline]
+    pub fn get_value_reader(
+        &self,
+        root_hash: &ArrayType,
+        key: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<impl Read>> {
+        self.tree.get_value_reader(root_hash, key)
+    }
+
+    #[inline]
+    pub fn nodes_at_depth(
+        &self,
+        root_hash: &ArrayType,
+        depth: usize,
+    ) -> BinaryMerkleTreeResult<Vec<ArrayType>> {
+        self.tree.nodes_at_depth(root_hash, depth)
+    }
+
+    #[inline]
+    pub fn subtree_size_histogram(
+        &self,
+        root_hash: &ArrayType,
+        depth: usize,
+    ) -> BinaryMerkleTreeResult<Vec<(ArrayType, u64)>> {
+        self.tree.subtree_size_histogram(root_hash, depth)
+    }
+
+    #[inline]
+    pub fn get_node_at(
+        &self,
+        location: &ArrayType,
+    ) -> BinaryMerkleTreeResult<Option<TreeNodeVariant<ArrayType>>> {
+        self.tree.get_node_at(location)
+    }
+
+    #[inline]
+    pub fn keys_for_value(
+        &self,
+        root_hash: &ArrayType,
+        value: &ValueType,
+    ) -> BinaryMerkleTreeResult<Vec<ArrayType>> {
+        self.tree.keys_for_value(root_hash, value)
+    }
+
+    #[inline]
+    pub fn import_proof(
+        &mut self,
+        root_hash: &ArrayType,
+        proof: &Proof<ArrayType>,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.import_proof(root_hash, proof)
+    }
+
+    #[inline]
+    pub fn rebuild_from_leaves(
+        &mut self,
+        leaf_locations: &[ArrayType],
+    ) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.rebuild_from_leaves(leaf_locations)
+    }
+
+    #[inline]
+    pub fn shared_node_count(
+        &self,
+        root_a: &ArrayType,
+        root_b: &ArrayType,
+    ) -> BinaryMerkleTreeResult<usize> {
+        self.tree.shared_node_count(root_a, root_b)
+    }
+
+    #[inline]
+    pub fn distinct_value_count(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<usize> {
+        self.tree.distinct_value_count(root_hash)
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn depth(&self) -> usize {
+        self.tree.depth()
+    }
+
+    #[inline]
+    pub fn depth_of(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<usize> {
+        self.tree.depth_of(root_hash)
+    }
+
+    #[inline]
+    pub fn find_orphans(&self, live_roots: &[ArrayType]) -> BinaryMerkleTreeResult<Vec<ArrayType>> {
+        self.tree.find_orphans(live_roots)
+    }
+
+    #[inline]
+    pub fn backup_to<TargetType>(&self, target: &mut TargetType) -> BinaryMerkleTreeResult<usize>
+    where
+        TargetType: Database<ArrayType, NodeType = TreeNode<ArrayType>>,
+    {
+        self.tree.backup_to(target)
+    }
+
+    #[inline]
+    pub fn iter_from(
+        &self,
+        root_hash: &ArrayType,
+        after: Option<ArrayType>,
+        limit: usize,
+    ) -> BinaryMerkleTreeResult<EntryPage<ArrayType, ValueType>> {
+        self.tree.iter_from(root_hash, after, limit)
+    }
+
+    #[inline]
+    pub fn debug_dump(
+        &self,
+        root_hash: &ArrayType,
+        writer: impl Write,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.debug_dump(root_hash, writer)
+    }
+
+    #[inline]
+    pub fn write_json(
+        &self,
+        root_hash: &ArrayType,
+        writer: impl Write,
+    ) -> BinaryMerkleTreeResult<()> {
+        self.tree.write_json(root_hash, writer)
+    }
+
+    #[inline]
+    pub fn to_json(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<String> {
+        self.tree.to_json(root_hash)
+    }
+
+    #[inline]
+    pub fn import_json(&mut self, json: &str) -> BinaryMerkleTreeResult<ArrayType> {
+        self.tree.import_json(json)
+    }
+
+    #[inline]
+    pub fn storage_bytes(&self, root_hash: &ArrayType) -> BinaryMerkleTreeResult<u64> {
+        self.tree.storage_bytes(root_hash)
+    }
+}
+
+impl<ArrayType, ValueType> RocksTree<ArrayType, ValueType>
+where
+    ArrayType: Array + Serialize + DeserializeOwned,
+    ValueType: Encode + Decode + Ord,
+{
+    #[inline]
+    pub fn entries_by_value(
+        &self,
+        root_hash: &ArrayType,
+        descending: bool,
+    ) -> BinaryMerkleTreeResult<Vec<(ArrayType, ValueType)>> {
+        self.tree.entries_by_value(root_hash, descending)
+    }
 }
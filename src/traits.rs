@@ -1,14 +1,18 @@
+use std::any::Any;
+use std::cmp::Ordering;
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::path::PathBuf;
 
+#[cfg(feature = "use_borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
 #[cfg(feature = "use_serde")]
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "use_digest")]
 use digest::Digest;
 
-use std::convert::Infallible;
+use std::convert::{Infallible, TryFrom};
 use std::hash::Hash;
 use std::marker::PhantomData;
 use std::num::TryFromIntError;
@@ -33,6 +37,34 @@ where
     fn finalize(self) -> ArrayType;
 }
 
+/// The required interface for structs defining the order keys are arranged in by the tree.
+///
+/// The tree lays keys out by the bits of `order_key`, from the most significant bit of its first
+/// byte: the zero branch at each split holds the keys sorting first.  Keys therefore sort by the
+/// big-endian order of their `order_key`s, which is the order `iter`, `iter_from` and every other
+/// ordered query follow.  `order_key` must map distinct keys to distinct arrays, so the order is a
+/// consistent total order, and must never change for a tree once written, since it decides where
+/// each key is found.  Prefixes passed to prefix queries are prefixes of the `order_key`.
+pub trait KeyOrder<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// Maps `key` to the array whose bits decide its path through the tree.
+    fn order_key(key: &ArrayType) -> ArrayType;
+
+    /// Compares two keys in this order.
+    #[inline]
+    fn compare(a: &ArrayType, b: &ArrayType) -> Ordering {
+        Self::order_key(a).cmp(&Self::order_key(b))
+    }
+
+    /// Sorts `keys` in this order.
+    #[inline]
+    fn sort_keys(keys: &mut [ArrayType]) {
+        keys.sort_unstable_by_key(Self::order_key);
+    }
+}
+
 #[cfg(feature = "use_digest")]
 impl<T, ArrayType> Hasher<ArrayType> for T
 where
@@ -107,6 +139,16 @@ where
     fn set_key(&mut self, key: ArrayType);
     /// Sets the location of the `Data` node.
     fn set_data(&mut self, data: ArrayType);
+    /// Gets the encoded value inlined into this node, if any.  Leaves holding an inlined value
+    /// have no `Data` node stored in the tree.
+    #[inline]
+    fn get_inline_value(&self) -> Option<&[u8]> {
+        None
+    }
+    /// Inlines the encoded value into this node.  Implementations that cannot hold an inlined
+    /// value ignore it, and the value is stored in a `Data` node instead.
+    #[inline]
+    fn set_inline_value(&mut self, _value: &[u8]) {}
     /// Decomposes the `Leaf` into its constituent parts.
     fn decompose(self) -> (ArrayType, ArrayType);
 }
@@ -119,6 +161,16 @@ pub trait Data {
     fn get_value(&self) -> &[u8];
     /// Sets the value for the `Data` node.
     fn set_value(&mut self, value: &[u8]);
+    /// Checks whether the value of this node is the key of a blob in a `BlobStore` rather than
+    /// the value itself.
+    #[inline]
+    fn is_blob_ref(&self) -> bool {
+        false
+    }
+    /// Marks the value of this node as the key of a blob in a `BlobStore`.  Implementations that
+    /// cannot hold blob references ignore it, and the value is stored in the node instead.
+    #[inline]
+    fn set_blob_ref(&mut self, _blob_ref: bool) {}
 }
 
 /// The required interface for structs representing nodes in the tree.
@@ -145,9 +197,17 @@ where
     fn set_data(&mut self, data: DataType);
 }
 
+/// Estimates the memory used by a node, for caches holding nodes within a byte budget.
+pub trait MemorySize {
+    /// Gets the estimated number of bytes the node uses in memory: its own size, plus the heap
+    /// data it holds, such as the value of a data node or a value inlined into a leaf.
+    fn memory_bytes(&self) -> usize;
+}
+
 /// Contains the distinguishing data from the node
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(any(feature = "use_serde",), derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "use_borsh", derive(BorshSerialize, BorshDeserialize))]
 pub enum NodeVariant<BranchType, LeafType, DataType, ArrayType>
 where
     BranchType: Branch<ArrayType>,
@@ -165,6 +225,10 @@ where
     Phantom(PhantomData<ArrayType>),
 }
 
+/// An iterator over the nodes stored in a `Database`, as returned by `Database::scan`.
+pub type NodeScan<'a, ArrayType, NodeType> =
+    Box<dyn Iterator<Item = Result<(ArrayType, NodeType), Exception>> + 'a>;
+
 /// This trait defines the required interface for connecting a storage mechanism to the `MerkleBIT`.
 pub trait Database<ArrayType>
 where
@@ -180,10 +244,190 @@ where
     fn open(path: &PathBuf) -> Result<Self, Exception>
     where
         Self: Sized;
+    /// Opens an existing `Database`, sized up front for about `capacity` nodes.  Backends which
+    /// cannot make use of the hint open as with `open`.
+    /// # Errors
+    /// `Exception` generated if the `open` does not succeed.
+    #[inline]
+    fn open_with_capacity(path: &PathBuf, _capacity: usize) -> Result<Self, Exception>
+    where
+        Self: Sized,
+    {
+        Self::open(path)
+    }
     /// Gets a value from the database based on the given key.
     /// # Errors
     /// `Exception` generated if the `get_node` does not succeed.
     fn get_node(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception>;
+    /// Gets multiple values from the database in a single call.  The results are returned in the
+    /// same order as the given keys.  Backends with a high round-trip cost should override this to
+    /// fetch all keys at once.
+    /// # Errors
+    /// `Exception` generated if the `get_nodes` does not succeed.
+    #[inline]
+    fn get_nodes(&self, keys: &[ArrayType]) -> Result<Vec<Option<Self::NodeType>>, Exception> {
+        let mut nodes = Vec::with_capacity(keys.len());
+        for &key in keys {
+            nodes.push(self.get_node(key)?);
+        }
+        Ok(nodes)
+    }
+    /// Checks whether a node is stored at the given key.  The default reads the node, so backends
+    /// able to check for a key without reading or decoding its node should override this.
+    /// # Errors
+    /// `Exception` generated if the `contains` does not succeed.
+    #[inline]
+    fn contains(&self, key: &ArrayType) -> Result<bool, Exception> {
+        Ok(self.get_node(*key)?.is_some())
+    }
+    /// Gets the number of bytes the node at the given key occupies when encoded, or `None` if the
+    /// node is missing.  Backends storing encoded nodes should override this to report the stored
+    /// size without decoding and encoding the node again.
+    /// # Errors
+    /// `Exception` generated if the node cannot be read or encoded.
+    #[inline]
+    fn node_size(&self, key: ArrayType) -> Result<Option<u64>, Exception>
+    where
+        Self::NodeType: Encode,
+    {
+        if let Some(node) = self.get_node(key)? {
+            return Ok(Some(u64::try_from(node.encode()?.len())?));
+        }
+        Ok(None)
+    }
+    /// Gets the location of every node stored in the database, in no particular order.  Backends
+    /// unable to enumerate their nodes return an error, which is the default.
+    /// # Errors
+    /// `Exception` generated if the database cannot enumerate its nodes, or if the
+    /// `node_locations` does not succeed.
+    #[inline]
+    fn node_locations(&self) -> Result<Vec<ArrayType>, Exception> {
+        Err(Exception::new(
+            "Database does not support iterating its nodes",
+        ))
+    }
+    /// Iterates over every node stored in the database with its location, in no particular order,
+    /// reading each node only as the iterator reaches it, so a large store can be backed up
+    /// without holding its nodes in memory.  The default reads the nodes listed by
+    /// `node_locations`, so backends unable to enumerate their nodes yield a single error.
+    /// Backends able to walk their storage directly should override this.
+    #[inline]
+    fn scan<'a>(&'a self) -> NodeScan<'a, ArrayType, Self::NodeType>
+    where
+        ArrayType: 'a,
+        Self::NodeType: 'a,
+    {
+        match self.node_locations() {
+            Ok(locations) => Box::new(locations.into_iter().filter_map(move |location| {
+                match self.get_node(location) {
+                    Ok(Some(node)) => Some(Ok((location, node))),
+                    Ok(None) => None,
+                    Err(e) => Some(Err(e)),
+                }
+            })),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        }
+    }
+    /// Gets the current root recorded in the database's root pointer, or `None` if no root has
+    /// been recorded.  Backends without a root pointer return an error, which is the default.
+    /// # Errors
+    /// `Exception` generated if the database has no root pointer, or if the `get_root` does not
+    /// succeed.
+    #[inline]
+    fn get_root(&self) -> Result<Option<ArrayType>, Exception> {
+        Err(Exception::new("Database does not support a root pointer"))
+    }
+    /// Records `root` in the database's root pointer.  Unlike `insert`, the pointer is written
+    /// immediately rather than on the next `batch_write`.
+    /// # Errors
+    /// `Exception` generated if the database has no root pointer, or if the `set_root` does not
+    /// succeed.
+    #[inline]
+    fn set_root(&mut self, _root: ArrayType) -> Result<(), Exception> {
+        Err(Exception::new("Database does not support a root pointer"))
+    }
+    /// Records `new` in the database's root pointer only if the current root is `expected`,
+    /// returning whether the pointer was updated.  Backends shared between several writers should
+    /// override this so the comparison and the update happen atomically.
+    /// # Errors
+    /// `Exception` generated if the database has no root pointer, or if the
+    /// `compare_and_set_root` does not succeed.
+    #[inline]
+    fn compare_and_set_root(
+        &mut self,
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> Result<bool, Exception> {
+        if self.get_root()? != expected {
+            return Ok(false);
+        }
+        self.set_root(new)?;
+        Ok(true)
+    }
+    /// Gets the current root recorded in the root pointer of `namespace`, so several trees sharing
+    /// the database each keep their own current root.  The empty namespace is the root pointer
+    /// of `get_root`.  The default only supports the empty namespace.
+    /// # Errors
+    /// `Exception` generated if the database has no root pointer for `namespace`, or if the
+    /// `get_root_in` does not succeed.
+    #[inline]
+    fn get_root_in(&self, namespace: &[u8]) -> Result<Option<ArrayType>, Exception> {
+        if namespace.is_empty() {
+            return self.get_root();
+        }
+        Err(Exception::new("Database does not support root namespaces"))
+    }
+    /// Records `root` in the root pointer of `namespace`, leaving the pointers of other namespaces
+    /// untouched.  The default only supports the empty namespace.
+    /// # Errors
+    /// `Exception` generated if the database has no root pointer for `namespace`, or if the
+    /// `set_root_in` does not succeed.
+    #[inline]
+    fn set_root_in(&mut self, namespace: &[u8], root: ArrayType) -> Result<(), Exception> {
+        if namespace.is_empty() {
+            return self.set_root(root);
+        }
+        Err(Exception::new("Database does not support root namespaces"))
+    }
+    /// Records `new` in the root pointer of `namespace` only if its current root is `expected`,
+    /// like `compare_and_set_root`.  The default only supports the empty namespace.
+    /// # Errors
+    /// `Exception` generated if the database has no root pointer for `namespace`, or if the
+    /// `compare_and_set_root_in` does not succeed.
+    #[inline]
+    fn compare_and_set_root_in(
+        &mut self,
+        namespace: &[u8],
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> Result<bool, Exception> {
+        if namespace.is_empty() {
+            return self.compare_and_set_root(expected, new);
+        }
+        if self.get_root_in(namespace)? != expected {
+            return Ok(false);
+        }
+        self.set_root_in(namespace, new)?;
+        Ok(true)
+    }
+    /// Gets the maximum depth recorded for the trees stored in the database, or `None` if no depth
+    /// has been recorded.  Backends without space for metadata record nothing, which is the
+    /// default.
+    /// # Errors
+    /// `Exception` generated if the `get_depth` does not succeed.
+    #[inline]
+    fn get_depth(&self) -> Result<Option<usize>, Exception> {
+        Ok(None)
+    }
+    /// Records the maximum depth of the trees stored in the database.  Like `set_root`, the depth
+    /// is written immediately.  Backends without space for metadata ignore it, which is the
+    /// default.
+    /// # Errors
+    /// `Exception` generated if the `set_depth` does not succeed.
+    #[inline]
+    fn set_depth(&mut self, _depth: usize) -> Result<(), Exception> {
+        Ok(())
+    }
     /// Queues a key and its associated value for insertion to the database.
     /// # Errors
     /// `Exception` generated if the `insert` does not succeed.
@@ -198,7 +442,84 @@ where
     fn batch_write(&mut self) -> Result<(), Exception>;
 }
 
+/// A `Database` able to hand out the stored values of data nodes without copying them.  This suits
+/// in-memory backends, which can share the nodes they already hold rather than cloning them.
+pub trait SharedDatabase<ArrayType>: Database<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// A shared handle to the encoded value of a data node.
+    type ValueRef: AsRef<[u8]> + Clone;
+    /// Gets a shared handle to the value of the data node at the given key.
+    /// # Errors
+    /// `Exception` generated if the node is not a data node, or if the `get_value_ref` does not
+    /// succeed.
+    fn get_value_ref(&self, key: ArrayType) -> Result<Option<Self::ValueRef>, Exception>;
+}
+
+/// A `Database` keeping a cache of nodes in front of its backend, which can be warmed ahead of
+/// time.
+pub trait CachingDatabase<ArrayType>: Database<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// Checks whether the cache has run out of room for more nodes.
+    /// # Errors
+    /// `Exception` generated if the `cache_is_full` does not succeed.
+    fn cache_is_full(&self) -> Result<bool, Exception>;
+
+    /// Reads the node at `key` into the cache, reading it from the backend if it is not cached.
+    /// Returns the node along with whether it is now held by the cache, which is false when the
+    /// cache had no room left for it, or `None` if there is no node at `key`.
+    /// # Errors
+    /// `Exception` generated if the `warm` does not succeed.
+    fn warm(&self, key: ArrayType) -> Result<Option<(Self::NodeType, bool)>, Exception>;
+
+    /// Pins the node at `key` in the cache, reading it from the backend if it is not cached, so
+    /// that it stays cached until `unpin` is called for it.  Returns the node, or `None` if there
+    /// is no node at `key`.
+    /// # Errors
+    /// `Exception` generated if the `pin` does not succeed.
+    fn pin(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception>;
+
+    /// Unpins the node at `key`, making it evictable again.  Returns the node if it was pinned.
+    /// # Errors
+    /// `Exception` generated if the `unpin` does not succeed.
+    fn unpin(&self, key: &ArrayType) -> Result<Option<Self::NodeType>, Exception>;
+
+    /// Evicts every cached node that is not pinned, returning the number evicted.
+    /// # Errors
+    /// `Exception` generated if the `evict_unpinned` does not succeed.
+    fn evict_unpinned(&self) -> Result<usize, Exception>;
+}
+
+/// A store for large values kept outside of the tree.  A data node holding a blob reference
+/// stores only the key of its value in the `BlobStore`, which can then live in object storage.
+/// Blobs are keyed by the location of the data node referencing them, so each blob belongs to a
+/// single data node and is deleted by the tree when that node is removed.
+pub trait BlobStore<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// Gets the blob stored under `key`.
+    /// # Errors
+    /// `Exception` generated if the `get_blob` does not succeed.
+    fn get_blob(&self, key: &ArrayType) -> Result<Option<Vec<u8>>, Exception>;
+    /// Stores `blob` under `key`.
+    /// # Errors
+    /// `Exception` generated if the `put_blob` does not succeed.
+    fn put_blob(&self, key: ArrayType, blob: &[u8]) -> Result<(), Exception>;
+    /// Deletes the blob stored under `key`, if any.
+    /// # Errors
+    /// `Exception` generated if the `delete` does not succeed.
+    fn delete(&self, key: &ArrayType) -> Result<(), Exception>;
+}
+
 /// This trait must be implemented to allow a struct to be serialized.
+///
+/// Node locations are hashed from the fields of a node (keys, child locations and encoded values),
+/// never from its encoding, so switching between encodings such as `use_bincode` and `use_borsh`
+/// changes the bytes stored for each node but leaves every root and proof unchanged.
 pub trait Encode {
     /// Encodes a struct into bytes.
     /// # Errors
@@ -214,6 +535,10 @@ impl Encode for Vec<u8> {
 }
 
 /// This trait must be implemented to allow an arbitrary sized buffer to be deserialized.
+///
+/// Buffers may come from corrupt or untrusted storage, so implementations should return an
+/// `Exception` for malformed input rather than panicking, and should not trust length prefixes
+/// for allocations.  The `decode_node` fuzz target exercises this for `TreeNode`.
 /// # Errors
 /// `Exception` generated when the buffer fails to be decoded to the target type.
 pub trait Decode {
@@ -232,12 +557,77 @@ impl Decode for Vec<u8> {
     }
 }
 
+/// The kind of failure an `Exception` represents, allowing callers to match on specific errors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A failure without a more specific kind.
+    Other,
+    /// The traversal went deeper than the maximum depth of the tree.  This happens when keys share
+    /// a longer common prefix than the configured `depth` can distinguish.  Reopening the tree
+    /// with a larger `depth` (up to the number of bits in the key) resolves it.
+    DepthExceeded,
+    /// The operation was cancelled through its cancellation flag before it completed.
+    Cancelled,
+    /// The node stored at `location` could not be decoded, for instance after a partial write.
+    CorruptNode {
+        /// The location of the damaged node.
+        location: Vec<u8>,
+    },
+    /// The encoded value for `key` is `size` bytes, more than the tree's maximum value size.
+    ValueTooLarge {
+        /// The key the value was inserted under.
+        key: Vec<u8>,
+        /// The size of the encoded value.
+        size: usize,
+    },
+    /// The root of a weak `Snapshot` was reclaimed by a removal, so the snapshot can no longer be
+    /// read.
+    SnapshotExpired,
+    /// The keys of an insert would build a tree `needed` levels deep, more than the `configured`
+    /// depth of the tree.  Nothing is written; reopen the tree with a larger `depth` to insert them.
+    DepthInsufficient {
+        /// The depth the tree built from the keys would reach.
+        needed: usize,
+        /// The maximum depth of the tree.
+        configured: usize,
+    },
+    /// An I/O operation of the database failed.  Such failures may be transient, for instance
+    /// a dropped connection to a networked backend, so the operation may succeed if retried.
+    Io,
+    /// An insert with collision checking enabled found a different node already stored at the
+    /// location of one it was writing, so two distinct nodes hash to the same location.
+    HashCollision {
+        /// The location both nodes hash to.
+        location: Vec<u8>,
+    },
+    /// A `get` or `insert` asked for `keys` keys, more than the tree's `limit` on keys per batch.
+    /// Nothing is read or written.
+    BatchTooLarge {
+        /// The number of keys in the batch.
+        keys: usize,
+        /// The maximum number of keys per batch.
+        limit: usize,
+    },
+    /// Code the tree called into, such as a custom `Encode`, panicked while the tree was set to
+    /// catch panics, so the panic was turned into this error instead of unwinding to the caller.
+    WorkerPanic,
+    /// A `PartialTree` was asked for a key whose path reaches the node at `location`, which no
+    /// imported proof covers.
+    MissingNode {
+        /// The location of the missing node.
+        location: Vec<u8>,
+    },
+}
+
 /// A generic error that implements `Error`.
 /// Mostly intended to be used to standardize errors across the crate.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Exception {
     /// The details of an exception
     details: String,
+    /// The kind of exception
+    kind: ErrorKind,
 }
 
 impl Exception {
@@ -245,10 +635,63 @@ impl Exception {
     #[inline]
     #[must_use]
     pub fn new(details: &str) -> Self {
+        Self::with_kind(ErrorKind::Other, details)
+    }
+
+    /// Creates a new `Exception` of the given `ErrorKind`.
+    #[inline]
+    #[must_use]
+    pub fn with_kind(kind: ErrorKind, details: &str) -> Self {
         Self {
             details: details.to_string(),
+            kind,
         }
     }
+
+    /// Creates a new `Exception` of kind `CorruptNode` for the node at `location`, keeping the
+    /// details of the underlying `error`.
+    #[inline]
+    #[must_use]
+    pub fn corrupt_node(location: &[u8], error: &Self) -> Self {
+        Self::with_kind(
+            ErrorKind::CorruptNode {
+                location: location.to_vec(),
+            },
+            &format!("Corrupt node at {:?}: {}", location, error.details),
+        )
+    }
+
+    /// Creates a new `Exception` of kind `HashCollision` for the node at `location`.
+    #[inline]
+    #[must_use]
+    pub fn hash_collision(location: &[u8]) -> Self {
+        Self::with_kind(
+            ErrorKind::HashCollision {
+                location: location.to_vec(),
+            },
+            &format!("Hash collision at {location:?}: a different node is already stored there"),
+        )
+    }
+
+    /// Creates a new `Exception` of kind `WorkerPanic` from the `payload` of a caught panic, keeping
+    /// its message if it has one.
+    #[inline]
+    #[must_use]
+    pub fn worker_panic(payload: &(dyn Any + Send)) -> Self {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic");
+        Self::with_kind(ErrorKind::WorkerPanic, &format!("Panicked: {message}"))
+    }
+
+    /// Gets the kind of this `Exception`.
+    #[inline]
+    #[must_use]
+    pub const fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
 }
 
 impl Display for Exception {
@@ -272,6 +715,13 @@ impl From<Infallible> for Exception {
     }
 }
 
+impl From<std::io::Error> for Exception {
+    #[inline]
+    fn from(err: std::io::Error) -> Self {
+        Self::with_kind(ErrorKind::Io, &err.to_string())
+    }
+}
+
 impl From<TryFromIntError> for Exception {
     #[inline]
     fn from(err: TryFromIntError) -> Self {
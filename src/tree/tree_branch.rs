@@ -3,6 +3,8 @@ use std::string::FromUtf8Error;
 
 #[cfg(feature = "use_bincode")]
 use bincode::{deserialize, serialize};
+#[cfg(feature = "use_borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
 #[cfg(feature = "use_ron")]
 use ron;
 #[cfg(feature = "use_serde")]
@@ -18,15 +20,18 @@ use serde_pickle;
 #[cfg(feature = "use_yaml")]
 use serde_yaml;
 
-#[cfg(feature = "use_serde")]
+#[cfg(any(feature = "use_serde", feature = "use_borsh"))]
 use crate::merkle_bit::BinaryMerkleTreeResult;
-use crate::traits::{Array, Branch};
 #[cfg(feature = "use_serde")]
-use crate::traits::{Decode, Encode, Exception};
+use crate::traits::Exception;
+use crate::traits::{Array, Branch};
+#[cfg(any(feature = "use_serde", feature = "use_borsh"))]
+use crate::traits::{Decode, Encode};
 
 /// A struct representing a branch in the tree.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(any(feature = "use_serde"), derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "use_borsh", derive(BorshSerialize, BorshDeserialize))]
 pub struct TreeBranch<ArrayType>
 where
     ArrayType: Array,
@@ -362,3 +367,25 @@ where
         Ok(ron::de::from_bytes(buffer)?)
     }
 }
+
+#[cfg(feature = "use_borsh")]
+impl<ArrayType> Encode for TreeBranch<ArrayType>
+where
+    ArrayType: Array + BorshSerialize,
+{
+    #[inline]
+    fn encode(&self) -> BinaryMerkleTreeResult<Vec<u8>> {
+        Ok(self.try_to_vec()?)
+    }
+}
+
+#[cfg(feature = "use_borsh")]
+impl<ArrayType> Decode for TreeBranch<ArrayType>
+where
+    ArrayType: Array + BorshDeserialize,
+{
+    #[inline]
+    fn decode(buffer: &[u8]) -> BinaryMerkleTreeResult<Self> {
+        Ok(Self::try_from_slice(buffer)?)
+    }
+}
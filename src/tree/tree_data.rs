@@ -1,5 +1,7 @@
 #[cfg(feature = "use_bincode")]
 use bincode::{deserialize, serialize};
+#[cfg(feature = "use_borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
 #[cfg(feature = "use_ron")]
 use ron;
 #[cfg(feature = "use_serde")]
@@ -13,24 +15,31 @@ use serde_pickle;
 #[cfg(feature = "use_yaml")]
 use serde_yaml;
 
-#[cfg(feature = "use_serde")]
+#[cfg(any(feature = "use_serde", feature = "use_borsh"))]
 use crate::merkle_bit::BinaryMerkleTreeResult;
 use crate::traits::Data;
-#[cfg(feature = "use_serde")]
+#[cfg(any(feature = "use_serde", feature = "use_borsh"))]
 use crate::traits::{Decode, Encode};
 
 /// `TreeData` represents the data to be stored in the tree for a given key.
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(any(feature = "use_serde"), derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "use_borsh", derive(BorshSerialize, BorshDeserialize))]
 pub struct TreeData {
     /// The value to be stored in the tree.
     value: Vec<u8>,
+    /// True if `value` is the key of the value in a `BlobStore`.
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    blob_ref: bool,
 }
 
 impl TreeData {
     /// Creates a new `TreeData` node.
     fn new() -> Self {
-        Self { value: vec![] }
+        Self {
+            value: vec![],
+            blob_ref: false,
+        }
     }
 
     /// Gets the value for this node.
@@ -42,6 +51,16 @@ impl TreeData {
     fn set_value(&mut self, value: Vec<u8>) {
         self.value = value
     }
+
+    /// Checks whether the value is the key of a blob.
+    const fn is_blob_ref(&self) -> bool {
+        self.blob_ref
+    }
+
+    /// Sets whether the value is the key of a blob.
+    fn set_blob_ref(&mut self, blob_ref: bool) {
+        self.blob_ref = blob_ref
+    }
 }
 
 impl Data for TreeData {
@@ -59,6 +78,16 @@ impl Data for TreeData {
     fn set_value(&mut self, value: &[u8]) {
         Self::set_value(self, value.to_vec())
     }
+
+    #[inline]
+    fn is_blob_ref(&self) -> bool {
+        Self::is_blob_ref(self)
+    }
+
+    #[inline]
+    fn set_blob_ref(&mut self, blob_ref: bool) {
+        Self::set_blob_ref(self, blob_ref)
+    }
 }
 
 #[cfg(feature = "use_bincode")]
@@ -159,3 +188,19 @@ impl Decode for TreeData {
         Ok(ron::de::from_bytes(buffer)?)
     }
 }
+
+#[cfg(feature = "use_borsh")]
+impl Encode for TreeData {
+    #[inline]
+    fn encode(&self) -> BinaryMerkleTreeResult<Vec<u8>> {
+        Ok(self.try_to_vec()?)
+    }
+}
+
+#[cfg(feature = "use_borsh")]
+impl Decode for TreeData {
+    #[inline]
+    fn decode(buffer: &[u8]) -> BinaryMerkleTreeResult<Self> {
+        Ok(Self::try_from_slice(buffer)?)
+    }
+}
@@ -1,5 +1,7 @@
 #[cfg(feature = "use_bincode")]
 use bincode::{deserialize, serialize};
+#[cfg(feature = "use_borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
 #[cfg(feature = "use_ron")]
 use ron;
 #[cfg(feature = "use_serde")]
@@ -21,9 +23,11 @@ use crate::traits::{Array, Leaf};
 #[cfg(feature = "use_serialization")]
 use crate::traits::{Decode, Encode};
 
-/// Represents a leaf of the tree.  Holds a pointer to the location of the underlying `Data` node.
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+/// Represents a leaf of the tree.  Holds a pointer to the location of the underlying `Data` node,
+/// and optionally a copy of a small value inlined to save reading the `Data` node.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "use_borsh", derive(BorshSerialize, BorshDeserialize))]
 pub struct TreeLeaf<ArrayType>
 where
     ArrayType: Array,
@@ -32,6 +36,9 @@ where
     key: ArrayType,
     /// The location of the `Data` node in the tree.
     data: ArrayType,
+    /// The encoded value, if it was small enough to be inlined into the leaf.
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    inline_value: Option<Vec<u8>>,
 }
 
 impl<ArrayType> TreeLeaf<ArrayType>
@@ -45,6 +52,7 @@ where
         Self {
             key: ArrayType::default(),
             data: ArrayType::default(),
+            inline_value: None,
         }
     }
 
@@ -68,6 +76,16 @@ where
         self.data = data;
     }
 
+    /// Gets the inlined value, if any.
+    fn get_inline_value(&self) -> Option<&[u8]> {
+        self.inline_value.as_deref()
+    }
+
+    /// Sets the inlined value.
+    fn set_inline_value(&mut self, value: &[u8]) {
+        self.inline_value = Some(value.to_vec());
+    }
+
     /// Decomposes the `TreeLeaf` into its constituent parts.
     fn decompose(self) -> (ArrayType, ArrayType) {
         (self.key, self.data)
@@ -108,6 +126,18 @@ where
         Self::set_data(self, data)
     }
 
+    /// Gets the inlined value, if any.
+    #[inline]
+    fn get_inline_value(&self) -> Option<&[u8]> {
+        Self::get_inline_value(self)
+    }
+
+    /// Sets the inlined value.
+    #[inline]
+    fn set_inline_value(&mut self, value: &[u8]) {
+        Self::set_inline_value(self, value)
+    }
+
     /// Decomposes the struct into its constituent parts.
     #[inline]
     fn decompose(self) -> (ArrayType, ArrayType) {
@@ -249,3 +279,25 @@ where
         Ok(ron::de::from_bytes(buffer)?)
     }
 }
+
+#[cfg(feature = "use_borsh")]
+impl<ArrayType> Encode for TreeLeaf<ArrayType>
+where
+    ArrayType: Array + BorshSerialize,
+{
+    #[inline]
+    fn encode(&self) -> BinaryMerkleTreeResult<Vec<u8>> {
+        Ok(self.try_to_vec()?)
+    }
+}
+
+#[cfg(feature = "use_borsh")]
+impl<ArrayType> Decode for TreeLeaf<ArrayType>
+where
+    ArrayType: Array + BorshDeserialize,
+{
+    #[inline]
+    fn decode(buffer: &[u8]) -> BinaryMerkleTreeResult<Self> {
+        Ok(Self::try_from_slice(buffer)?)
+    }
+}
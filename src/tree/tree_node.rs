@@ -1,5 +1,9 @@
+use std::mem::size_of;
+
 #[cfg(feature = "use_bincode")]
 use bincode::{deserialize, serialize};
+#[cfg(feature = "use_borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
 #[cfg(feature = "use_ron")]
 use ron;
 #[cfg(feature = "use_serde")]
@@ -15,9 +19,9 @@ use serde_pickle;
 #[cfg(feature = "use_yaml")]
 use serde_yaml;
 
-#[cfg(feature = "use_serde")]
+#[cfg(any(feature = "use_serde", feature = "use_borsh"))]
 use crate::merkle_bit::BinaryMerkleTreeResult;
-use crate::traits::{Array, Node, NodeVariant};
+use crate::traits::{Array, Data, Leaf, MemorySize, Node, NodeVariant};
 #[cfg(feature = "use_serialization")]
 use crate::traits::{Decode, Encode};
 use crate::tree::tree_branch::TreeBranch;
@@ -26,9 +30,14 @@ use crate::tree::tree_leaf::TreeLeaf;
 #[cfg(feature = "use_evmap")]
 use evmap::ShallowCopy;
 
+/// The `NodeVariant` held by a `TreeNode`.
+pub type TreeNodeVariant<ArrayType> =
+    NodeVariant<TreeBranch<ArrayType>, TreeLeaf<ArrayType>, TreeData, ArrayType>;
+
 /// A node in the tree.
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(any(feature = "use_serde"), derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "use_borsh", derive(BorshSerialize, BorshDeserialize))]
 pub struct TreeNode<ArrayType>
 where
     ArrayType: Array,
@@ -121,6 +130,21 @@ where
     }
 }
 
+impl<ArrayType> MemorySize for TreeNode<ArrayType>
+where
+    ArrayType: Array,
+{
+    #[inline]
+    fn memory_bytes(&self) -> usize {
+        let heap_bytes = match &self.node {
+            NodeVariant::Data(d) => d.get_value().len(),
+            NodeVariant::Leaf(l) => l.get_inline_value().map_or(0, <[u8]>::len),
+            NodeVariant::Branch(_) | NodeVariant::Phantom(_) => 0,
+        };
+        size_of::<Self>() + heap_bytes
+    }
+}
+
 #[cfg(feature = "use_bincode")]
 impl<ArrayType> Encode for TreeNode<ArrayType>
 where
@@ -255,3 +279,25 @@ where
         Ok(ron::de::from_bytes(buffer)?)
     }
 }
+
+#[cfg(feature = "use_borsh")]
+impl<ArrayType> Encode for TreeNode<ArrayType>
+where
+    ArrayType: Array + BorshSerialize,
+{
+    #[inline]
+    fn encode(&self) -> BinaryMerkleTreeResult<Vec<u8>> {
+        Ok(self.try_to_vec()?)
+    }
+}
+
+#[cfg(feature = "use_borsh")]
+impl<ArrayType> Decode for TreeNode<ArrayType>
+where
+    ArrayType: Array + BorshDeserialize,
+{
+    #[inline]
+    fn decode(buffer: &[u8]) -> BinaryMerkleTreeResult<Self> {
+        Ok(Self::try_from_slice(buffer)?)
+    }
+}
@@ -0,0 +1,79 @@
+#[cfg(not(any(feature = "use_hashbrown")))]
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[cfg(feature = "use_hashbrown")]
+use hashbrown::HashMap;
+
+use crate::traits::{Array, BlobStore, Exception};
+
+/// A `BlobStore` keeping its blobs in memory.
+#[derive(Debug, Default)]
+pub struct MemoryBlobStore<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// The stored blobs.
+    blobs: Mutex<HashMap<ArrayType, Vec<u8>>>,
+}
+
+impl<ArrayType> MemoryBlobStore<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// Creates a new, empty `MemoryBlobStore`.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            blobs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Gets the number of blobs stored.
+    /// # Errors
+    /// `Exception` generated if the store lock is poisoned.
+    #[inline]
+    pub fn len(&self) -> Result<usize, Exception> {
+        Ok(self.lock()?.len())
+    }
+
+    /// Checks whether no blobs are stored.
+    /// # Errors
+    /// `Exception` generated if the store lock is poisoned.
+    #[inline]
+    pub fn is_empty(&self) -> Result<bool, Exception> {
+        Ok(self.lock()?.is_empty())
+    }
+
+    /// Locks the stored blobs.
+    /// # Errors
+    /// `Exception` generated if the store lock is poisoned.
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<ArrayType, Vec<u8>>>, Exception> {
+        self.blobs
+            .lock()
+            .map_err(|e| Exception::new(&format!("Blob store lock is poisoned: {e}")))
+    }
+}
+
+impl<ArrayType> BlobStore<ArrayType> for MemoryBlobStore<ArrayType>
+where
+    ArrayType: Array,
+{
+    #[inline]
+    fn get_blob(&self, key: &ArrayType) -> Result<Option<Vec<u8>>, Exception> {
+        Ok(self.lock()?.get(key).cloned())
+    }
+
+    #[inline]
+    fn put_blob(&self, key: ArrayType, blob: &[u8]) -> Result<(), Exception> {
+        self.lock()?.insert(key, blob.to_vec());
+        Ok(())
+    }
+
+    #[inline]
+    fn delete(&self, key: &ArrayType) -> Result<(), Exception> {
+        self.lock()?.remove(key);
+        Ok(())
+    }
+}
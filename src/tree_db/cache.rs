@@ -0,0 +1,474 @@
+use std::collections::BTreeMap;
+#[cfg(not(any(feature = "use_hashbrown")))]
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+#[cfg(feature = "use_hashbrown")]
+use hashbrown::HashMap;
+
+use crate::traits::{Array, CachingDatabase, Database, Encode, Exception, MemorySize, NodeScan};
+
+/// The default number of bytes a `CachedDatabase` may hold.
+pub const DEFAULT_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// The cached nodes of a `CachedDatabase`, ordered by when they were last used.
+struct CachedNodes<ArrayType, NodeType> {
+    /// Each cached node, with the tick of its last use.
+    nodes: HashMap<ArrayType, (NodeType, u64)>,
+    /// The location of each cached node by the tick of its last use, least recently used first.
+    recency: BTreeMap<u64, ArrayType>,
+    /// The tick given to the next use of a node.
+    tick: u64,
+    /// The number of bytes the cached nodes are estimated to use.
+    bytes: usize,
+}
+
+impl<ArrayType, NodeType> CachedNodes<ArrayType, NodeType>
+where
+    ArrayType: Array,
+{
+    /// Creates an empty set of cached nodes.
+    fn new() -> Self {
+        Self {
+            nodes: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Gets the node cached at `key`, marking it as the most recently used.
+    fn get(&mut self, key: &ArrayType) -> Option<&NodeType> {
+        let tick = self.tick;
+        let (node, last_used) = self.nodes.get_mut(key)?;
+        self.recency.remove(last_used);
+        self.recency.insert(tick, *key);
+        *last_used = tick;
+        self.tick += 1;
+        Some(node)
+    }
+
+    /// Caches `node` at `key` as the most recently used node, charging it `bytes`.
+    fn insert(&mut self, key: ArrayType, node: NodeType, bytes: usize) {
+        self.recency.insert(self.tick, key);
+        self.nodes.insert(key, (node, self.tick));
+        self.tick += 1;
+        self.bytes += bytes;
+    }
+
+    /// Removes the node cached at `key`, returning it.
+    fn remove(&mut self, key: &ArrayType) -> Option<NodeType> {
+        let (node, last_used) = self.nodes.remove(key)?;
+        self.recency.remove(&last_used);
+        Some(node)
+    }
+
+    /// Removes the least recently used node, returning it.
+    fn remove_oldest(&mut self) -> Option<NodeType> {
+        let oldest = *self.recency.keys().next()?;
+        let key = self.recency.remove(&oldest)?;
+        self.nodes.remove(&key).map(|(node, _)| node)
+    }
+
+    /// Removes every cached node, returning the number removed.
+    fn clear(&mut self) -> usize {
+        let removed = self.nodes.len();
+        self.nodes.clear();
+        self.recency.clear();
+        self.bytes = 0;
+        removed
+    }
+}
+
+/// A read-through cache in front of another `Database`.
+///
+/// Nodes read from the inner database are kept in memory within the cache's byte budget.  Once the
+/// budget is spent, a node read by `get_node` or `get_nodes` evicts the least recently used
+/// nodes to make room, while `warm` only fills the room left, so warming never pushes out nodes
+/// that are in use.  Each node is charged the size of its key plus its `MemorySize`, so the value
+/// of a data node counts towards the budget.  Pinned nodes are held apart from the budget and
+/// survive eviction until they are unpinned.
+pub struct CachedDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    ArrayType: Array,
+{
+    /// The database being cached.
+    inner: DatabaseType,
+    /// The cached nodes and the number of bytes they are estimated to use.
+    cache: Mutex<CachedNodes<ArrayType, DatabaseType::NodeType>>,
+    /// The maximum number of bytes the cache may hold.
+    byte_budget: usize,
+    /// The pinned nodes, which are never evicted.
+    pinned: Mutex<HashMap<ArrayType, DatabaseType::NodeType>>,
+}
+
+impl<DatabaseType, ArrayType> CachedDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    DatabaseType::NodeType: Clone + MemorySize,
+    ArrayType: Array,
+{
+    /// Creates a new `CachedDatabase` over `inner`, holding at most `byte_budget` bytes of nodes.
+    #[inline]
+    pub fn new(inner: DatabaseType, byte_budget: usize) -> Self {
+        Self {
+            inner,
+            cache: Mutex::new(CachedNodes::new()),
+            byte_budget,
+            pinned: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Gets the database being cached.
+    #[inline]
+    pub const fn inner(&self) -> &DatabaseType {
+        &self.inner
+    }
+
+    /// Gets the number of nodes currently cached.
+    /// # Errors
+    /// `Exception` generated if the cache lock is poisoned.
+    #[inline]
+    pub fn cached_nodes(&self) -> Result<usize, Exception> {
+        Ok(self.lock()?.nodes.len())
+    }
+
+    /// Gets the estimated number of bytes currently cached.  Pinned nodes are not counted.
+    /// # Errors
+    /// `Exception` generated if the cache lock is poisoned.
+    #[inline]
+    pub fn cached_bytes(&self) -> Result<usize, Exception> {
+        Ok(self.lock()?.bytes)
+    }
+
+    /// Gets the number of nodes currently pinned.
+    /// # Errors
+    /// `Exception` generated if the pin lock is poisoned.
+    #[inline]
+    pub fn pinned_nodes(&self) -> Result<usize, Exception> {
+        Ok(self.lock_pinned()?.len())
+    }
+
+    /// The estimated number of bytes used by caching `node`.
+    fn entry_bytes(node: &DatabaseType::NodeType) -> usize {
+        size_of::<ArrayType>() + node.memory_bytes()
+    }
+
+    /// Caches `node` at `key` if it fits within the byte budget, first evicting the least recently
+    /// used nodes to make room for it if `evict` is set.  Returns whether the node is cached.
+    /// # Errors
+    /// `Exception` generated if the cache lock is poisoned.
+    fn cache_node(
+        &self,
+        key: ArrayType,
+        node: &DatabaseType::NodeType,
+        evict: bool,
+    ) -> Result<bool, Exception> {
+        let bytes = Self::entry_bytes(node);
+        if bytes > self.byte_budget {
+            return Ok(false);
+        }
+        let mut cache = self.lock()?;
+        if cache.nodes.contains_key(&key) {
+            return Ok(true);
+        }
+        while evict && cache.bytes + bytes > self.byte_budget {
+            if let Some(evicted) = cache.remove_oldest() {
+                cache.bytes -= Self::entry_bytes(&evicted);
+            } else {
+                break;
+            }
+        }
+        if cache.bytes + bytes > self.byte_budget {
+            return Ok(false);
+        }
+        cache.insert(key, node.clone(), bytes);
+        drop(cache);
+        Ok(true)
+    }
+
+    /// Gets the node at `key` from the pinned nodes, the cache, or else the inner database, caching
+    /// a node read from the inner database as `cache_node` does.  Returns the node and whether it
+    /// is held by the cache.
+    /// # Errors
+    /// `Exception` generated if a lock is poisoned, or the inner database cannot be read.
+    fn read(
+        &self,
+        key: ArrayType,
+        evict: bool,
+    ) -> Result<Option<(DatabaseType::NodeType, bool)>, Exception> {
+        // Each lock is released before the next, and before reading the inner database
+        if let Some(node) = self.cached(&key)? {
+            return Ok(Some((node, true)));
+        }
+        if let Some(node) = self.inner.get_node(key)? {
+            let kept = self.cache_node(key, &node, evict)?;
+            return Ok(Some((node, kept)));
+        }
+        Ok(None)
+    }
+
+    /// Gets the node at `key` if it is pinned or cached.
+    /// # Errors
+    /// `Exception` generated if a lock is poisoned.
+    fn cached(&self, key: &ArrayType) -> Result<Option<DatabaseType::NodeType>, Exception> {
+        let pinned = self.lock_pinned()?.get(key).cloned();
+        if pinned.is_some() {
+            return Ok(pinned);
+        }
+        Ok(self.lock()?.get(key).cloned())
+    }
+
+    /// Locks the cache.
+    /// # Errors
+    /// `Exception` generated if the cache lock is poisoned.
+    fn lock(
+        &self,
+    ) -> Result<std::sync::MutexGuard<'_, CachedNodes<ArrayType, DatabaseType::NodeType>>, Exception>
+    {
+        self.cache
+            .lock()
+            .map_err(|e| Exception::new(&format!("Cache lock is poisoned: {e}")))
+    }
+
+    /// Locks the pinned nodes.
+    /// # Errors
+    /// `Exception` generated if the pin lock is poisoned.
+    fn lock_pinned(
+        &self,
+    ) -> Result<std::sync::MutexGuard<'_, HashMap<ArrayType, DatabaseType::NodeType>>, Exception>
+    {
+        self.pinned
+            .lock()
+            .map_err(|e| Exception::new(&format!("Pin lock is poisoned: {e}")))
+    }
+
+    /// Removes a node from the cache.  A pinned node is left pinned.
+    /// # Errors
+    /// `Exception` generated if the cache lock is poisoned.
+    fn invalidate(&self, key: &ArrayType) -> Result<(), Exception> {
+        let mut cache = self.lock()?;
+        if let Some(node) = cache.remove(key) {
+            cache.bytes -= Self::entry_bytes(&node);
+        }
+        drop(cache);
+        Ok(())
+    }
+}
+
+impl<DatabaseType, ArrayType> Database<ArrayType> for CachedDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    DatabaseType::NodeType: Clone + MemorySize,
+    ArrayType: Array,
+{
+    type NodeType = DatabaseType::NodeType;
+    type EntryType = DatabaseType::EntryType;
+
+    #[inline]
+    fn open(path: &PathBuf) -> Result<Self, Exception> {
+        Ok(Self::new(DatabaseType::open(path)?, DEFAULT_CACHE_BYTES))
+    }
+
+    #[inline]
+    fn open_with_capacity(path: &PathBuf, capacity: usize) -> Result<Self, Exception> {
+        Ok(Self::new(
+            DatabaseType::open_with_capacity(path, capacity)?,
+            DEFAULT_CACHE_BYTES,
+        ))
+    }
+
+    #[inline]
+    fn get_node(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception> {
+        Ok(self.read(key, true)?.map(|(node, _)| node))
+    }
+
+    #[inline]
+    fn get_nodes(&self, keys: &[ArrayType]) -> Result<Vec<Option<Self::NodeType>>, Exception> {
+        let mut nodes = Vec::with_capacity(keys.len());
+        let mut missing = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            let node = self.cached(key)?;
+            if node.is_none() {
+                missing.push(i);
+            }
+            nodes.push(node);
+        }
+        if missing.is_empty() {
+            return Ok(nodes);
+        }
+
+        // The nodes missing from the cache are read from the inner database in one call
+        let missing_keys = missing.iter().map(|&i| keys[i]).collect::<Vec<_>>();
+        let read = self.inner.get_nodes(&missing_keys)?;
+        for (i, node) in missing.into_iter().zip(read) {
+            if let Some(n) = &node {
+                self.cache_node(keys[i], n, true)?;
+            }
+            nodes[i] = node;
+        }
+        Ok(nodes)
+    }
+
+    #[inline]
+    fn contains(&self, key: &ArrayType) -> Result<bool, Exception> {
+        let pinned = self.lock_pinned()?.contains_key(key);
+        if pinned || self.lock()?.nodes.contains_key(key) {
+            return Ok(true);
+        }
+        self.inner.contains(key)
+    }
+
+    #[inline]
+    fn node_size(&self, key: ArrayType) -> Result<Option<u64>, Exception>
+    where
+        Self::NodeType: Encode,
+    {
+        self.inner.node_size(key)
+    }
+
+    #[inline]
+    fn node_locations(&self) -> Result<Vec<ArrayType>, Exception> {
+        self.inner.node_locations()
+    }
+
+    #[inline]
+    fn scan<'a>(&'a self) -> NodeScan<'a, ArrayType, Self::NodeType>
+    where
+        ArrayType: 'a,
+        Self::NodeType: 'a,
+    {
+        self.inner.scan()
+    }
+
+    #[inline]
+    fn get_root(&self) -> Result<Option<ArrayType>, Exception> {
+        self.inner.get_root()
+    }
+
+    #[inline]
+    fn set_root(&mut self, root: ArrayType) -> Result<(), Exception> {
+        self.inner.set_root(root)
+    }
+
+    #[inline]
+    fn get_depth(&self) -> Result<Option<usize>, Exception> {
+        self.inner.get_depth()
+    }
+
+    #[inline]
+    fn set_depth(&mut self, depth: usize) -> Result<(), Exception> {
+        self.inner.set_depth(depth)
+    }
+
+    #[inline]
+    fn compare_and_set_root(
+        &mut self,
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> Result<bool, Exception> {
+        self.inner.compare_and_set_root(expected, new)
+    }
+
+    #[inline]
+    fn get_root_in(&self, namespace: &[u8]) -> Result<Option<ArrayType>, Exception> {
+        self.inner.get_root_in(namespace)
+    }
+
+    #[inline]
+    fn set_root_in(&mut self, namespace: &[u8], root: ArrayType) -> Result<(), Exception> {
+        self.inner.set_root_in(namespace, root)
+    }
+
+    #[inline]
+    fn compare_and_set_root_in(
+        &mut self,
+        namespace: &[u8],
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> Result<bool, Exception> {
+        self.inner.compare_and_set_root_in(namespace, expected, new)
+    }
+
+    #[inline]
+    fn insert(&mut self, key: ArrayType, node: Self::NodeType) -> Result<(), Exception> {
+        self.invalidate(&key)?;
+        if let Some(pinned) = self.lock_pinned()?.get_mut(&key) {
+            *pinned = node.clone();
+        }
+        self.inner.insert(key, node)
+    }
+
+    #[inline]
+    fn remove(&mut self, key: &ArrayType) -> Result<(), Exception> {
+        self.invalidate(key)?;
+        self.lock_pinned()?.remove(key);
+        self.inner.remove(key)
+    }
+
+    #[inline]
+    fn batch_write(&mut self) -> Result<(), Exception> {
+        self.inner.batch_write()
+    }
+}
+
+impl<DatabaseType, ArrayType> CachingDatabase<ArrayType> for CachedDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    DatabaseType::NodeType: Clone + MemorySize,
+    ArrayType: Array,
+{
+    #[inline]
+    fn cache_is_full(&self) -> Result<bool, Exception> {
+        // No node takes less than the size of its key and node types
+        let smallest_entry = size_of::<ArrayType>() + size_of::<DatabaseType::NodeType>();
+        Ok(self.lock()?.bytes + smallest_entry > self.byte_budget)
+    }
+
+    #[inline]
+    fn warm(&self, key: ArrayType) -> Result<Option<(Self::NodeType, bool)>, Exception> {
+        self.read(key, false)
+    }
+
+    #[inline]
+    fn pin(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception> {
+        let pinned = self.lock_pinned()?.get(&key).cloned();
+        if pinned.is_some() {
+            return Ok(pinned);
+        }
+
+        let cached = {
+            let mut cache = self.lock()?;
+            let cached = cache.remove(&key);
+            if let Some(node) = &cached {
+                cache.bytes -= Self::entry_bytes(node);
+            }
+            cached
+        };
+        let node = match cached {
+            Some(node) => Some(node),
+            None => self.inner.get_node(key)?,
+        };
+        if let Some(n) = &node {
+            self.lock_pinned()?.insert(key, n.clone());
+        }
+        Ok(node)
+    }
+
+    #[inline]
+    fn unpin(&self, key: &ArrayType) -> Result<Option<Self::NodeType>, Exception> {
+        let node = self.lock_pinned()?.remove(key);
+        if let Some(n) = &node {
+            self.cache_node(*key, n, false)?;
+        }
+        Ok(node)
+    }
+
+    #[inline]
+    fn evict_unpinned(&self) -> Result<usize, Exception> {
+        Ok(self.lock()?.clear())
+    }
+}
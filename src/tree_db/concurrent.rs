@@ -0,0 +1,362 @@
+#[cfg(not(any(feature = "use_hashbrown")))]
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(feature = "use_hashbrown")]
+use hashbrown::HashMap;
+
+use crate::traits::{Array, Database, Exception};
+
+/// The writing handle to a `Database` shared between one writer and any number of readers.
+///
+/// Inserts and removes made through the handle are staged in it until `batch_write`, which
+/// applies all of them to the shared database under a single write lock.  The readers, created
+/// with `handle` as `ConcurrentReader`s, therefore never see part of a batch: a tree built by one
+/// `insert` becomes visible to readers all at once, when the insert commits.  Since an insert
+/// never changes the contents of existing nodes other than their reference counts, and never
+/// deletes a node, readers querying a root committed earlier keep resolving the same values while
+/// a writer prepares the next root.  Only `remove` deletes nodes, so readers of a root must be
+/// done with it before it is removed.
+///
+/// The root pointer is not staged: `set_root` and `compare_and_set_root` update the shared
+/// database as soon as they are called, and `compare_and_set_root` compares and updates under a
+/// single write lock.  Nodes are staged whole, with their absolute reference counts, so two
+/// handles writing trees that share nodes would overwrite each other's counts: only this handle
+/// can write, and `handle` only hands out readers.  Writers sharing this handle (for instance
+/// through a `Mutex` around the tree) can still build roots optimistically and publish them with
+/// `compare_and_set_root`, removing the root they built whenever the swap fails.
+pub struct ConcurrentDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    ArrayType: Array,
+{
+    /// The database shared between the handles.
+    inner: Arc<RwLock<DatabaseType>>,
+    /// The changes made through this handle not yet applied to `inner`.  A `None` marks a removed
+    /// node.
+    pending: HashMap<ArrayType, Option<DatabaseType::NodeType>>,
+}
+
+impl<DatabaseType, ArrayType> ConcurrentDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    DatabaseType::NodeType: Clone,
+    ArrayType: Array,
+{
+    /// Creates a new `ConcurrentDatabase` sharing `inner`.
+    #[inline]
+    pub fn new(inner: DatabaseType) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(inner)),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Creates a read only handle to the same shared database.  The reader sees every change
+    /// committed by `batch_write`, but none of the changes staged in this handle.
+    #[inline]
+    #[must_use]
+    pub fn handle(&self) -> ConcurrentReader<DatabaseType> {
+        ConcurrentReader {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+
+    /// Gets the number of changes staged in this handle and not yet committed.
+    #[inline]
+    #[must_use]
+    pub fn pending_changes(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Locks the shared database for reading.
+    /// # Errors
+    /// `Exception` generated if the lock is poisoned.
+    fn read(&self) -> Result<RwLockReadGuard<'_, DatabaseType>, Exception> {
+        read_lock(&self.inner)
+    }
+
+    /// Locks the shared database for writing.
+    /// # Errors
+    /// `Exception` generated if the lock is poisoned.
+    fn write(&self) -> Result<RwLockWriteGuard<'_, DatabaseType>, Exception> {
+        write_lock(&self.inner)
+    }
+}
+
+impl<DatabaseType, ArrayType> Database<ArrayType> for ConcurrentDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    DatabaseType::NodeType: Clone,
+    ArrayType: Array,
+{
+    type NodeType = DatabaseType::NodeType;
+    type EntryType = DatabaseType::EntryType;
+
+    #[inline]
+    fn open(path: &PathBuf) -> Result<Self, Exception> {
+        Ok(Self::new(DatabaseType::open(path)?))
+    }
+
+    #[inline]
+    fn open_with_capacity(path: &PathBuf, capacity: usize) -> Result<Self, Exception> {
+        Ok(Self::new(DatabaseType::open_with_capacity(path, capacity)?))
+    }
+
+    #[inline]
+    fn get_node(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception> {
+        if let Some(pending) = self.pending.get(&key) {
+            return Ok(pending.clone());
+        }
+        self.read()?.get_node(key)
+    }
+
+    #[inline]
+    fn contains(&self, key: &ArrayType) -> Result<bool, Exception> {
+        if let Some(pending) = self.pending.get(key) {
+            return Ok(pending.is_some());
+        }
+        self.read()?.contains(key)
+    }
+
+    #[inline]
+    fn node_locations(&self) -> Result<Vec<ArrayType>, Exception> {
+        let mut locations = self.read()?.node_locations()?;
+        locations.retain(|location| !self.pending.contains_key(location));
+        for (location, change) in &self.pending {
+            if change.is_some() {
+                locations.push(*location);
+            }
+        }
+        Ok(locations)
+    }
+
+    #[inline]
+    fn get_root(&self) -> Result<Option<ArrayType>, Exception> {
+        self.read()?.get_root()
+    }
+
+    #[inline]
+    fn set_root(&mut self, root: ArrayType) -> Result<(), Exception> {
+        self.write()?.set_root(root)
+    }
+
+    #[inline]
+    fn get_depth(&self) -> Result<Option<usize>, Exception> {
+        self.read()?.get_depth()
+    }
+
+    #[inline]
+    fn set_depth(&mut self, depth: usize) -> Result<(), Exception> {
+        self.write()?.set_depth(depth)
+    }
+
+    #[inline]
+    fn compare_and_set_root(
+        &mut self,
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> Result<bool, Exception> {
+        // Holding the write lock across both steps keeps other handles from swapping in between
+        self.write()?.compare_and_set_root(expected, new)
+    }
+
+    #[inline]
+    fn get_root_in(&self, namespace: &[u8]) -> Result<Option<ArrayType>, Exception> {
+        self.read()?.get_root_in(namespace)
+    }
+
+    #[inline]
+    fn set_root_in(&mut self, namespace: &[u8], root: ArrayType) -> Result<(), Exception> {
+        self.write()?.set_root_in(namespace, root)
+    }
+
+    #[inline]
+    fn compare_and_set_root_in(
+        &mut self,
+        namespace: &[u8],
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> Result<bool, Exception> {
+        // Holding the write lock across both steps keeps other handles from swapping in between
+        self.write()?
+            .compare_and_set_root_in(namespace, expected, new)
+    }
+
+    #[inline]
+    fn insert(&mut self, key: ArrayType, node: Self::NodeType) -> Result<(), Exception> {
+        self.pending.insert(key, Some(node));
+        Ok(())
+    }
+
+    #[inline]
+    fn remove(&mut self, key: &ArrayType) -> Result<(), Exception> {
+        self.pending.insert(*key, None);
+        Ok(())
+    }
+
+    #[inline]
+    fn batch_write(&mut self) -> Result<(), Exception> {
+        // Locking the field directly leaves `pending` free to drain
+        let mut inner = write_lock(&self.inner)?;
+        for (key, change) in self.pending.drain() {
+            if let Some(node) = change {
+                inner.insert(key, node)?;
+            } else {
+                inner.remove(&key)?;
+            }
+        }
+        inner.batch_write()
+    }
+}
+
+/// A read only handle to a `Database` shared with the writing `ConcurrentDatabase` it was created
+/// from.
+///
+/// Reads take the shared read lock, so they see each batch committed by the writer whole.
+/// Inserts, removes and changes of the root pointer and depth fail.
+pub struct ConcurrentReader<DatabaseType> {
+    /// The database shared with the writer.
+    inner: Arc<RwLock<DatabaseType>>,
+}
+
+impl<DatabaseType> ConcurrentReader<DatabaseType> {
+    /// Creates another read only handle to the same shared database.
+    #[inline]
+    #[must_use]
+    pub fn handle(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+
+    /// Locks the shared database for reading.
+    /// # Errors
+    /// `Exception` generated if the lock is poisoned.
+    fn read(&self) -> Result<RwLockReadGuard<'_, DatabaseType>, Exception> {
+        read_lock(&self.inner)
+    }
+}
+
+impl<DatabaseType, ArrayType> Database<ArrayType> for ConcurrentReader<DatabaseType>
+where
+    DatabaseType: Database<ArrayType>,
+    ArrayType: Array,
+{
+    type NodeType = DatabaseType::NodeType;
+    type EntryType = DatabaseType::EntryType;
+
+    #[inline]
+    fn open(path: &PathBuf) -> Result<Self, Exception> {
+        Ok(Self {
+            inner: Arc::new(RwLock::new(DatabaseType::open(path)?)),
+        })
+    }
+
+    #[inline]
+    fn get_node(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception> {
+        self.read()?.get_node(key)
+    }
+
+    #[inline]
+    fn get_nodes(&self, keys: &[ArrayType]) -> Result<Vec<Option<Self::NodeType>>, Exception> {
+        self.read()?.get_nodes(keys)
+    }
+
+    #[inline]
+    fn contains(&self, key: &ArrayType) -> Result<bool, Exception> {
+        self.read()?.contains(key)
+    }
+
+    #[inline]
+    fn node_locations(&self) -> Result<Vec<ArrayType>, Exception> {
+        self.read()?.node_locations()
+    }
+
+    #[inline]
+    fn get_root(&self) -> Result<Option<ArrayType>, Exception> {
+        self.read()?.get_root()
+    }
+
+    #[inline]
+    fn set_root(&mut self, _root: ArrayType) -> Result<(), Exception> {
+        Err(Exception::new("Database is read only"))
+    }
+
+    #[inline]
+    fn get_depth(&self) -> Result<Option<usize>, Exception> {
+        self.read()?.get_depth()
+    }
+
+    #[inline]
+    fn set_depth(&mut self, _depth: usize) -> Result<(), Exception> {
+        Err(Exception::new("Database is read only"))
+    }
+
+    #[inline]
+    fn compare_and_set_root(
+        &mut self,
+        _expected: Option<ArrayType>,
+        _new: ArrayType,
+    ) -> Result<bool, Exception> {
+        Err(Exception::new("Database is read only"))
+    }
+
+    #[inline]
+    fn get_root_in(&self, namespace: &[u8]) -> Result<Option<ArrayType>, Exception> {
+        self.read()?.get_root_in(namespace)
+    }
+
+    #[inline]
+    fn set_root_in(&mut self, _namespace: &[u8], _root: ArrayType) -> Result<(), Exception> {
+        Err(Exception::new("Database is read only"))
+    }
+
+    #[inline]
+    fn compare_and_set_root_in(
+        &mut self,
+        _namespace: &[u8],
+        _expected: Option<ArrayType>,
+        _new: ArrayType,
+    ) -> Result<bool, Exception> {
+        Err(Exception::new("Database is read only"))
+    }
+
+    #[inline]
+    fn insert(&mut self, _key: ArrayType, _node: Self::NodeType) -> Result<(), Exception> {
+        Err(Exception::new("Database is read only"))
+    }
+
+    #[inline]
+    fn remove(&mut self, _key: &ArrayType) -> Result<(), Exception> {
+        Err(Exception::new("Database is read only"))
+    }
+
+    #[inline]
+    fn batch_write(&mut self) -> Result<(), Exception> {
+        Ok(())
+    }
+}
+
+/// Locks `inner` for reading.
+/// # Errors
+/// `Exception` generated if the lock is poisoned.
+fn read_lock<DatabaseType>(
+    inner: &RwLock<DatabaseType>,
+) -> Result<RwLockReadGuard<'_, DatabaseType>, Exception> {
+    inner
+        .read()
+        .map_err(|e| Exception::new(&format!("Database lock is poisoned: {e}")))
+}
+
+/// Locks `inner` for writing.
+/// # Errors
+/// `Exception` generated if the lock is poisoned.
+fn write_lock<DatabaseType>(
+    inner: &RwLock<DatabaseType>,
+) -> Result<RwLockWriteGuard<'_, DatabaseType>, Exception> {
+    inner
+        .write()
+        .map_err(|e| Exception::new(&format!("Database lock is poisoned: {e}")))
+}
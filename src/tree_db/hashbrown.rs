@@ -2,14 +2,17 @@ use std::path::PathBuf;
 
 use hashbrown::HashMap;
 
-use crate::traits::{Array, Database, Exception};
+use crate::traits::{Array, Database, Exception, NodeScan, SharedDatabase};
 use crate::tree::tree_node::TreeNode;
+use crate::utils::shared_value::{SharedValue, StoredNode};
 
 pub struct HashDB<ArrayType>
 where
     ArrayType: Array,
 {
-    map: HashMap<ArrayType, TreeNode<ArrayType>>,
+    map: HashMap<ArrayType, StoredNode<ArrayType>>,
+    roots: HashMap<Vec<u8>, ArrayType>,
+    depth: Option<usize>,
 }
 
 impl<ArrayType> HashDB<ArrayType>
@@ -18,7 +21,23 @@ where
 {
     #[inline]
     pub fn new(map: HashMap<ArrayType, TreeNode<ArrayType>>) -> Self {
-        Self { map }
+        Self {
+            map: map
+                .into_iter()
+                .map(|(key, node)| (key, StoredNode::new(node)))
+                .collect(),
+            roots: HashMap::new(),
+            depth: None,
+        }
+    }
+
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: HashMap::with_capacity(capacity),
+            roots: HashMap::new(),
+            depth: None,
+        }
     }
 }
 
@@ -34,19 +53,79 @@ where
         Ok(Self::new(HashMap::new()))
     }
 
+    #[inline]
+    fn open_with_capacity(_path: &PathBuf, capacity: usize) -> Result<Self, Exception> {
+        Ok(Self::with_capacity(capacity))
+    }
+
     #[inline]
     fn get_node(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception> {
         if let Some(m) = self.map.get(&key) {
-            let node = m.clone();
+            let node = m.node().clone();
             Ok(Some(node))
         } else {
             Ok(None)
         }
     }
 
+    #[inline]
+    fn contains(&self, key: &ArrayType) -> Result<bool, Exception> {
+        Ok(self.map.contains_key(key))
+    }
+
+    #[inline]
+    fn node_locations(&self) -> Result<Vec<ArrayType>, Exception> {
+        Ok(self.map.keys().copied().collect())
+    }
+
+    #[inline]
+    fn scan<'a>(&'a self) -> NodeScan<'a, ArrayType, Self::NodeType>
+    where
+        ArrayType: 'a,
+        Self::NodeType: 'a,
+    {
+        Box::new(
+            self.map
+                .iter()
+                .map(|(location, node)| Ok((*location, node.node().clone()))),
+        )
+    }
+
+    #[inline]
+    fn get_root(&self) -> Result<Option<ArrayType>, Exception> {
+        self.get_root_in(&[])
+    }
+
+    #[inline]
+    fn set_root(&mut self, root: ArrayType) -> Result<(), Exception> {
+        self.set_root_in(&[], root)
+    }
+
+    #[inline]
+    fn get_root_in(&self, namespace: &[u8]) -> Result<Option<ArrayType>, Exception> {
+        Ok(self.roots.get(namespace).copied())
+    }
+
+    #[inline]
+    fn set_root_in(&mut self, namespace: &[u8], root: ArrayType) -> Result<(), Exception> {
+        self.roots.insert(namespace.to_vec(), root);
+        Ok(())
+    }
+
+    #[inline]
+    fn get_depth(&self) -> Result<Option<usize>, Exception> {
+        Ok(self.depth)
+    }
+
+    #[inline]
+    fn set_depth(&mut self, depth: usize) -> Result<(), Exception> {
+        self.depth = Some(depth);
+        Ok(())
+    }
+
     #[inline]
     fn insert(&mut self, key: ArrayType, value: Self::NodeType) -> Result<(), Exception> {
-        self.map.insert(key, value);
+        self.map.insert(key, StoredNode::new(value));
         Ok(())
     }
 
@@ -61,3 +140,19 @@ where
         Ok(())
     }
 }
+
+impl<ArrayType> SharedDatabase<ArrayType> for HashDB<ArrayType>
+where
+    ArrayType: Array,
+{
+    type ValueRef = SharedValue<ArrayType>;
+
+    #[inline]
+    fn get_value_ref(&self, key: ArrayType) -> Result<Option<Self::ValueRef>, Exception> {
+        if let Some(m) = self.map.get(&key) {
+            Ok(Some(m.value_ref()?))
+        } else {
+            Ok(None)
+        }
+    }
+}
@@ -2,8 +2,9 @@ use std::collections::hash_map::HashMap;
 use std::path::PathBuf;
 
 use crate::constants::KEY_LEN;
-use crate::traits::{Array, Database, Exception};
+use crate::traits::{Array, Database, Exception, NodeScan, SharedDatabase};
 use crate::tree::tree_node::TreeNode;
+use crate::utils::shared_value::{SharedValue, StoredNode};
 
 /// A database consisting of a `HashMap`.
 pub struct HashDB<ArrayType>
@@ -11,7 +12,12 @@ where
     ArrayType: Array,
 {
     /// The internal `HashMap` for storing nodes.
-    map: HashMap<ArrayType, TreeNode<ArrayType>>,
+    map: HashMap<ArrayType, StoredNode<ArrayType>>,
+    /// The current root of each namespace, recorded by `set_root_in`.  The empty namespace holds
+    /// the root recorded by `set_root`.
+    roots: HashMap<Vec<u8>, ArrayType>,
+    /// The depth recorded by `set_depth`.
+    depth: Option<usize>,
 }
 
 impl<ArrayType> HashDB<ArrayType>
@@ -22,7 +28,25 @@ where
     #[inline]
     #[must_use]
     pub fn new(map: HashMap<ArrayType, TreeNode<ArrayType>>) -> Self {
-        Self { map }
+        Self {
+            map: map
+                .into_iter()
+                .map(|(key, node)| (key, StoredNode::new(node)))
+                .collect(),
+            roots: HashMap::new(),
+            depth: None,
+        }
+    }
+
+    /// Creates a new, empty `HashDB` with room for `capacity` nodes before it reallocates.
+    #[inline]
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: HashMap::with_capacity(capacity),
+            roots: HashMap::new(),
+            depth: None,
+        }
     }
 }
 
@@ -38,19 +62,79 @@ where
         Ok(Self::new(HashMap::new()))
     }
 
+    #[inline]
+    fn open_with_capacity(_path: &PathBuf, capacity: usize) -> Result<Self, Exception> {
+        Ok(Self::with_capacity(capacity))
+    }
+
     #[inline]
     fn get_node(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception> {
         if let Some(m) = self.map.get(&key) {
-            let node = m.clone();
+            let node = m.node().clone();
             Ok(Some(node))
         } else {
             Ok(None)
         }
     }
 
+    #[inline]
+    fn contains(&self, key: &ArrayType) -> Result<bool, Exception> {
+        Ok(self.map.contains_key(key))
+    }
+
+    #[inline]
+    fn node_locations(&self) -> Result<Vec<ArrayType>, Exception> {
+        Ok(self.map.keys().copied().collect())
+    }
+
+    #[inline]
+    fn scan<'a>(&'a self) -> NodeScan<'a, ArrayType, Self::NodeType>
+    where
+        ArrayType: 'a,
+        Self::NodeType: 'a,
+    {
+        Box::new(
+            self.map
+                .iter()
+                .map(|(location, node)| Ok((*location, node.node().clone()))),
+        )
+    }
+
+    #[inline]
+    fn get_root(&self) -> Result<Option<ArrayType>, Exception> {
+        self.get_root_in(&[])
+    }
+
+    #[inline]
+    fn set_root(&mut self, root: ArrayType) -> Result<(), Exception> {
+        self.set_root_in(&[], root)
+    }
+
+    #[inline]
+    fn get_root_in(&self, namespace: &[u8]) -> Result<Option<ArrayType>, Exception> {
+        Ok(self.roots.get(namespace).copied())
+    }
+
+    #[inline]
+    fn set_root_in(&mut self, namespace: &[u8], root: ArrayType) -> Result<(), Exception> {
+        self.roots.insert(namespace.to_vec(), root);
+        Ok(())
+    }
+
+    #[inline]
+    fn get_depth(&self) -> Result<Option<usize>, Exception> {
+        Ok(self.depth)
+    }
+
+    #[inline]
+    fn set_depth(&mut self, depth: usize) -> Result<(), Exception> {
+        self.depth = Some(depth);
+        Ok(())
+    }
+
     #[inline]
     fn insert(&mut self, key: ArrayType, value: Self::NodeType) -> Result<(), Exception> {
-        self.map.insert(key, value);
+        self.map.insert(key, StoredNode::new(value));
         Ok(())
     }
 
@@ -65,3 +149,19 @@ where
         Ok(())
     }
 }
+
+impl<ArrayType> SharedDatabase<ArrayType> for HashDB<ArrayType>
+where
+    ArrayType: Array,
+{
+    type ValueRef = SharedValue<ArrayType>;
+
+    #[inline]
+    fn get_value_ref(&self, key: ArrayType) -> Result<Option<Self::ValueRef>, Exception> {
+        if let Some(m) = self.map.get(&key) {
+            Ok(Some(m.value_ref()?))
+        } else {
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,481 @@
+#[cfg(not(any(feature = "use_hashbrown")))]
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::fs::{rename, File, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+#[cfg(feature = "use_hashbrown")]
+use hashbrown::HashMap;
+use memmap2::Mmap;
+
+use crate::traits::{Array, Database, Decode, Encode, Exception, NodeScan};
+use crate::tree::tree_node::TreeNode;
+
+/// Marks an appended node.
+const NODE_RECORD: u8 = 0;
+/// Marks a tombstone for a removed node.
+const TOMBSTONE_RECORD: u8 = 1;
+/// Marks a change of the recorded root.
+const ROOT_RECORD: u8 = 2;
+/// Marks a change of the recorded depth.
+const DEPTH_RECORD: u8 = 3;
+/// Marks a change of the recorded root of a non-empty namespace.
+const NAMESPACED_ROOT_RECORD: u8 = 4;
+
+/// A database storing nodes in an append-only region of a single memory mapped file, for large
+/// read-mostly trees.
+///
+/// The file is mapped into memory rather than read, and an index maps each node location to the
+/// offset of its encoded node, so `get_node` decodes straight from the mapped bytes and the
+/// memory used grows with the number of nodes rather than the size of the file.  `insert`
+/// appends to the region, holding the records in memory until `batch_write` appends them to the
+/// file and maps it again.  `remove` appends a tombstone, so the space of removed nodes is only
+/// reclaimed by `compact`.
+///
+/// The root recorded by `set_root` is the root of the empty namespace.  The roots of other
+/// namespaces are recorded by `set_root_in` in records carrying the namespace after the root.
+///
+/// The file must not be changed by anything other than this database while it is open.
+pub struct MmapDatabase<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// The path of the file holding the region.
+    path: PathBuf,
+    /// The file holding the region.
+    file: File,
+    /// The mapping of the records flushed to the file, or `None` if the file is empty.
+    mapped: Option<Mmap>,
+    /// The records appended since the last flush, which follow the mapped records in the region.
+    pending: Vec<u8>,
+    /// The offset and length of the encoded node at each location.
+    index: HashMap<ArrayType, (usize, usize)>,
+    /// The bytes of the region taken by records that are no longer live.
+    garbage: usize,
+    /// The current root of each namespace, recorded by `set_root_in`.  The empty namespace holds
+    /// the root recorded by `set_root`.
+    roots: HashMap<Vec<u8>, ArrayType>,
+    /// The depth recorded by `set_depth`.
+    depth: Option<usize>,
+}
+
+impl<ArrayType> MmapDatabase<ArrayType>
+where
+    ArrayType: Array,
+    TreeNode<ArrayType>: Encode + Decode,
+{
+    /// Opens the region stored in the file at `path`, creating it if it does not exist, and
+    /// rebuilds the index from its records.  A truncated record at the end of the file, left by a
+    /// crash during a flush, is discarded.
+    /// # Errors
+    /// `Exception` generated if the file cannot be mapped, or holds an invalid record.
+    #[inline]
+    pub fn new(path: &PathBuf) -> Result<Self, Exception> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let mut db = Self {
+            path: path.clone(),
+            file,
+            mapped: None,
+            pending: Vec::new(),
+            index: HashMap::new(),
+            garbage: 0,
+            roots: HashMap::new(),
+            depth: None,
+        };
+        let mapped = map(&db.file)?;
+        let region = mapped.as_deref().unwrap_or(&[]);
+        let length = region.len();
+        let complete = db.replay(region)?;
+        if complete < length {
+            // The mapping must be gone before the file shrinks under it
+            drop(mapped);
+            db.file.set_len(u64::try_from(complete)?)?;
+            db.file.sync_data()?;
+            db.mapped = map(&db.file)?;
+        } else {
+            db.mapped = mapped;
+        }
+        Ok(db)
+    }
+
+    /// Applies the records in `region` to the index, roots and depth, returning the length of the
+    /// complete records.
+    /// # Errors
+    /// `Exception` generated if a record is invalid.
+    fn replay(&mut self, region: &[u8]) -> Result<usize, Exception> {
+        let key_len = ArrayType::default().as_ref().len();
+        let mut offset = 0;
+        while offset + 1 + key_len <= region.len() {
+            let record_type = region[offset];
+            let record_start = offset;
+            let mut key = ArrayType::default();
+            key.as_mut()
+                .copy_from_slice(&region[offset + 1..offset + 1 + key_len]);
+            offset += 1 + key_len;
+
+            match record_type {
+                NODE_RECORD => {
+                    if offset + 4 > region.len() {
+                        return Ok(record_start);
+                    }
+                    let mut len = [0_u8; 4];
+                    len.copy_from_slice(&region[offset..offset + 4]);
+                    let len = usize::try_from(u32::from_be_bytes(len))?;
+                    offset += 4;
+                    if offset + len > region.len() {
+                        return Ok(record_start);
+                    }
+                    self.index_node(key, offset, len);
+                    offset += len;
+                }
+                TOMBSTONE_RECORD => self.index_tombstone(&key),
+                ROOT_RECORD => self.index_root(Vec::new(), key, offset - record_start),
+                NAMESPACED_ROOT_RECORD => {
+                    if offset + 4 > region.len() {
+                        return Ok(record_start);
+                    }
+                    let mut len = [0_u8; 4];
+                    len.copy_from_slice(&region[offset..offset + 4]);
+                    let len = usize::try_from(u32::from_be_bytes(len))?;
+                    offset += 4;
+                    if offset + len > region.len() {
+                        return Ok(record_start);
+                    }
+                    let namespace = region[offset..offset + len].to_vec();
+                    offset += len;
+                    self.index_root(namespace, key, offset - record_start);
+                }
+                DEPTH_RECORD => {
+                    if offset + 8 > region.len() {
+                        return Ok(record_start);
+                    }
+                    let mut depth = [0_u8; 8];
+                    depth.copy_from_slice(&region[offset..offset + 8]);
+                    offset += 8;
+                    if self.depth.is_some() {
+                        self.garbage += offset - record_start;
+                    }
+                    self.depth = Some(usize::try_from(u64::from_be_bytes(depth))?);
+                }
+                _ => return Err(Exception::new("Corrupt mmap database record")),
+            }
+        }
+        Ok(offset)
+    }
+
+    /// Points the index at the node of `len` bytes at `offset`, counting the record of any node it
+    /// replaces as garbage.
+    fn index_node(&mut self, key: ArrayType, offset: usize, len: usize) {
+        if let Some((_, replaced)) = self.index.insert(key, (offset, len)) {
+            self.garbage += Self::node_header_len() + replaced;
+        }
+    }
+
+    /// Drops `key` from the index on behalf of a tombstone, counting the tombstone and the record
+    /// of the node it removes as garbage.
+    fn index_tombstone(&mut self, key: &ArrayType) {
+        self.garbage += 1 + key.as_ref().len();
+        if let Some((_, removed)) = self.index.remove(key) {
+            self.garbage += Self::node_header_len() + removed;
+        }
+    }
+
+    /// Records `root` as the current root of `namespace`, counting the record of `record_len` bytes
+    /// that set it as garbage if it replaces another root.
+    fn index_root(&mut self, namespace: Vec<u8>, root: ArrayType, record_len: usize) {
+        if self.roots.insert(namespace, root).is_some() {
+            self.garbage += record_len;
+        }
+    }
+
+    /// Gets the length of the record type, location and length preceding each encoded node.
+    fn node_header_len() -> usize {
+        1 + ArrayType::default().as_ref().len() + 4
+    }
+
+    /// Gets the records flushed to the file.
+    fn mapped_region(&self) -> &[u8] {
+        self.mapped.as_deref().unwrap_or(&[])
+    }
+
+    /// Gets the length of the region, including the records not yet flushed to the file.
+    fn region_len(&self) -> usize {
+        self.mapped_region().len() + self.pending.len()
+    }
+
+    /// Gets the encoded node of `len` bytes at `offset` in the region, from the mapping if it was
+    /// flushed and from the pending records otherwise.
+    fn encoded_node(&self, offset: usize, len: usize) -> &[u8] {
+        let mapped = self.mapped_region();
+        if offset < mapped.len() {
+            &mapped[offset..offset + len]
+        } else {
+            let offset = offset - mapped.len();
+            &self.pending[offset..offset + len]
+        }
+    }
+
+    /// Writes the pending records to the file at `mapped_len`, the end of the mapped records,
+    /// where the index expects them, and syncs the file.  Anything past `mapped_len`, such as the
+    /// remains of an earlier failed flush, is cut off first.
+    /// # Errors
+    /// `Exception` generated if the records cannot be written or synced.
+    fn append_pending(&mut self, mapped_len: u64) -> Result<(), Exception> {
+        self.file.set_len(mapped_len)?;
+        self.file.seek(SeekFrom::Start(mapped_len))?;
+        self.file.write_all(&self.pending)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Gets the number of bytes of the region taken by removed or replaced nodes, tombstones, and
+    /// superseded roots and depths, all of which `compact` reclaims.
+    #[inline]
+    #[must_use]
+    pub const fn garbage_bytes(&self) -> usize {
+        self.garbage
+    }
+
+    /// Rewrites the file with only the live nodes and the current roots and depth, dropping
+    /// tombstones and the nodes they removed.  Unflushed changes are included.  The compacted
+    /// region is streamed and synced to a temporary file next to the original, which is then
+    /// renamed over it and mapped, so a crash during compaction leaves either the original file
+    /// or the compacted one.
+    /// # Errors
+    /// `Exception` generated if the compacted file cannot be written, renamed over the original,
+    /// or mapped.
+    #[inline]
+    pub fn compact(&mut self) -> Result<(), Exception> {
+        let mut locations = self.index.keys().copied().collect::<Vec<_>>();
+        locations.sort();
+
+        let mut temporary_path = OsString::from(self.path.as_os_str());
+        temporary_path.push(".compact");
+        let temporary_path = PathBuf::from(temporary_path);
+        let mut temporary = BufWriter::new(
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&temporary_path)?,
+        );
+
+        let mut written = 0;
+        let mut index = HashMap::with_capacity(locations.len());
+        for location in locations {
+            let (offset, len) = self.index[&location];
+            temporary.write_all(&[NODE_RECORD])?;
+            temporary.write_all(location.as_ref())?;
+            temporary.write_all(&u32::try_from(len)?.to_be_bytes())?;
+            written += Self::node_header_len();
+            index.insert(location, (written, len));
+            temporary.write_all(self.encoded_node(offset, len))?;
+            written += len;
+        }
+        let mut namespaces = self.roots.keys().collect::<Vec<_>>();
+        namespaces.sort();
+        for namespace in namespaces {
+            let root = self.roots[namespace];
+            if namespace.is_empty() {
+                temporary.write_all(&[ROOT_RECORD])?;
+                temporary.write_all(root.as_ref())?;
+            } else {
+                temporary.write_all(&[NAMESPACED_ROOT_RECORD])?;
+                temporary.write_all(root.as_ref())?;
+                temporary.write_all(&u32::try_from(namespace.len())?.to_be_bytes())?;
+                temporary.write_all(namespace)?;
+            }
+        }
+        if let Some(depth) = self.depth {
+            temporary.write_all(&[DEPTH_RECORD])?;
+            temporary.write_all(ArrayType::default().as_ref())?;
+            temporary.write_all(&u64::try_from(depth)?.to_be_bytes())?;
+        }
+        temporary.flush()?;
+        temporary.get_ref().sync_all()?;
+        drop(temporary);
+
+        rename(&temporary_path, &self.path)?;
+        self.file = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        self.mapped = map(&self.file)?;
+        self.pending.clear();
+        self.index = index;
+        self.garbage = 0;
+        Ok(())
+    }
+}
+
+impl<ArrayType> Database<ArrayType> for MmapDatabase<ArrayType>
+where
+    ArrayType: Array,
+    TreeNode<ArrayType>: Encode + Decode,
+{
+    type NodeType = TreeNode<ArrayType>;
+    type EntryType = (usize, usize);
+
+    #[inline]
+    fn open(path: &PathBuf) -> Result<Self, Exception> {
+        Self::new(path)
+    }
+
+    #[inline]
+    fn get_node(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception> {
+        if let Some(&(offset, len)) = self.index.get(&key) {
+            let node = TreeNode::decode(self.encoded_node(offset, len))
+                .map_err(|e| Exception::corrupt_node(key.as_ref(), &e))?;
+            Ok(Some(node))
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[inline]
+    fn contains(&self, key: &ArrayType) -> Result<bool, Exception> {
+        Ok(self.index.contains_key(key))
+    }
+
+    #[inline]
+    fn node_size(&self, key: ArrayType) -> Result<Option<u64>, Exception> {
+        if let Some(&(_, len)) = self.index.get(&key) {
+            return Ok(Some(u64::try_from(len)?));
+        }
+        Ok(None)
+    }
+
+    #[inline]
+    fn node_locations(&self) -> Result<Vec<ArrayType>, Exception> {
+        Ok(self.index.keys().copied().collect())
+    }
+
+    #[inline]
+    fn scan<'a>(&'a self) -> NodeScan<'a, ArrayType, Self::NodeType>
+    where
+        ArrayType: 'a,
+        Self::NodeType: 'a,
+    {
+        Box::new(self.index.iter().map(move |(location, &(offset, len))| {
+            let node = TreeNode::decode(self.encoded_node(offset, len))
+                .map_err(|e| Exception::corrupt_node(location.as_ref(), &e))?;
+            Ok((*location, node))
+        }))
+    }
+
+    #[inline]
+    fn get_root(&self) -> Result<Option<ArrayType>, Exception> {
+        self.get_root_in(&[])
+    }
+
+    #[inline]
+    fn set_root(&mut self, root: ArrayType) -> Result<(), Exception> {
+        self.set_root_in(&[], root)
+    }
+
+    #[inline]
+    fn get_root_in(&self, namespace: &[u8]) -> Result<Option<ArrayType>, Exception> {
+        Ok(self.roots.get(namespace).copied())
+    }
+
+    #[inline]
+    fn set_root_in(&mut self, namespace: &[u8], root: ArrayType) -> Result<(), Exception> {
+        let start = self.pending.len();
+        if namespace.is_empty() {
+            self.pending.push(ROOT_RECORD);
+            self.pending.extend_from_slice(root.as_ref());
+        } else {
+            let namespace_len = u32::try_from(namespace.len())?;
+            self.pending.push(NAMESPACED_ROOT_RECORD);
+            self.pending.extend_from_slice(root.as_ref());
+            self.pending.extend_from_slice(&namespace_len.to_be_bytes());
+            self.pending.extend_from_slice(namespace);
+        }
+        let record_len = self.pending.len() - start;
+        self.index_root(namespace.to_vec(), root, record_len);
+        Ok(())
+    }
+
+    #[inline]
+    fn get_depth(&self) -> Result<Option<usize>, Exception> {
+        Ok(self.depth)
+    }
+
+    #[inline]
+    fn set_depth(&mut self, depth: usize) -> Result<(), Exception> {
+        let key_len = ArrayType::default().as_ref().len();
+        if self.depth.is_some() {
+            self.garbage += 1 + key_len + 8;
+        }
+        self.pending.push(DEPTH_RECORD);
+        self.pending
+            .extend_from_slice(ArrayType::default().as_ref());
+        self.pending
+            .extend_from_slice(&u64::try_from(depth)?.to_be_bytes());
+        self.depth = Some(depth);
+        Ok(())
+    }
+
+    #[inline]
+    fn insert(&mut self, key: ArrayType, node: Self::NodeType) -> Result<(), Exception> {
+        let encoded = node.encode()?;
+        self.pending.push(NODE_RECORD);
+        self.pending.extend_from_slice(key.as_ref());
+        self.pending
+            .extend_from_slice(&u32::try_from(encoded.len())?.to_be_bytes());
+        let offset = self.region_len();
+        self.pending.extend_from_slice(&encoded);
+        self.index_node(key, offset, encoded.len());
+        Ok(())
+    }
+
+    #[inline]
+    fn remove(&mut self, key: &ArrayType) -> Result<(), Exception> {
+        if !self.index.contains_key(key) {
+            return Ok(());
+        }
+        self.pending.push(TOMBSTONE_RECORD);
+        self.pending.extend_from_slice(key.as_ref());
+        self.index_tombstone(key);
+        Ok(())
+    }
+
+    #[inline]
+    fn batch_write(&mut self) -> Result<(), Exception> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mapped_len = u64::try_from(self.mapped_region().len())?;
+        if let Err(e) = self.append_pending(mapped_len) {
+            // Drop whatever part of the records reached the file, so a failed flush neither
+            // leaves partial records to be replayed nor shifts the offsets of the next one
+            self.file.set_len(mapped_len)?;
+            return Err(e);
+        }
+        self.mapped = map(&self.file)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+/// Maps the contents of `file` into memory, or returns `None` if the file is empty.
+/// # Errors
+/// `Exception` generated if the file cannot be mapped.
+#[allow(unsafe_code)]
+fn map(file: &File) -> Result<Option<Mmap>, Exception> {
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+    // SAFETY: The mapped bytes must not change while they are mapped.  The database only ever
+    // writes past the end of its mapping, never shrinks the file below the mapping without
+    // dropping it first, and compacts by renaming a new file over the old one, which leaves the
+    // old mapping intact.
+    // Changing the file from outside the database while it is open is not supported.
+    let mapped = unsafe { Mmap::map(file)? };
+    Ok(Some(mapped))
+}
@@ -1,10 +1,27 @@
+/// The module containing a database storing nodes in an append-only memory mapped file.
+#[cfg(feature = "use_mmap")]
+pub mod mmap;
+/// The module containing an in-memory blob store.
+pub mod blob;
+/// The module containing a caching wrapper for other databases.
+pub mod cache;
+/// The module containing a handle sharing a database between a writer and readers.
+pub mod concurrent;
 #[cfg(feature = "use_hashbrown")]
 pub mod hashbrown;
 /// The module containing the implementation of a DB using a `HashMap`.
 #[cfg(not(feature = "use_hashbrown"))]
 pub mod hashmap;
+/// The module containing a read only handle to a shared database.
+pub mod read_only;
+/// The module containing a wrapper retrying the transient failures of other databases.
+pub mod retry;
 #[cfg(feature = "use_rocksdb")]
 pub mod rocksdb;
+/// The module containing a wrapper expiring the nodes of other databases.
+pub mod ttl;
+/// The module containing a write ahead log wrapper for other databases.
+pub mod wal;
 
 /// The type of database for the `HashTree`.
 #[cfg(not(feature = "use_hashbrown"))]
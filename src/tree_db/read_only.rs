@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::traits::{Array, Database, Exception, NodeScan};
+
+/// A read only handle to a `Database` shared through an `Arc`.
+///
+/// Reads are passed to the shared database, while inserts and removes fail, so any number of
+/// handles can read the same database without a lock.
+pub struct ReadOnlyDatabase<DatabaseType> {
+    /// The shared database.
+    inner: Arc<DatabaseType>,
+}
+
+impl<DatabaseType> ReadOnlyDatabase<DatabaseType> {
+    /// Creates a new `ReadOnlyDatabase` reading from `inner`.
+    #[inline]
+    pub const fn new(inner: Arc<DatabaseType>) -> Self {
+        Self { inner }
+    }
+
+    /// Gets the shared database.
+    #[inline]
+    pub const fn inner(&self) -> &Arc<DatabaseType> {
+        &self.inner
+    }
+}
+
+impl<DatabaseType, ArrayType> Database<ArrayType> for ReadOnlyDatabase<DatabaseType>
+where
+    DatabaseType: Database<ArrayType>,
+    ArrayType: Array,
+{
+    type NodeType = DatabaseType::NodeType;
+    type EntryType = DatabaseType::EntryType;
+
+    #[inline]
+    fn open(path: &PathBuf) -> Result<Self, Exception> {
+        Ok(Self::new(Arc::new(DatabaseType::open(path)?)))
+    }
+
+    #[inline]
+    fn get_node(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception> {
+        self.inner.get_node(key)
+    }
+
+    #[inline]
+    fn get_nodes(&self, keys: &[ArrayType]) -> Result<Vec<Option<Self::NodeType>>, Exception> {
+        self.inner.get_nodes(keys)
+    }
+
+    #[inline]
+    fn contains(&self, key: &ArrayType) -> Result<bool, Exception> {
+        self.inner.contains(key)
+    }
+
+    #[inline]
+    fn node_locations(&self) -> Result<Vec<ArrayType>, Exception> {
+        self.inner.node_locations()
+    }
+
+    #[inline]
+    fn scan<'a>(&'a self) -> NodeScan<'a, ArrayType, Self::NodeType>
+    where
+        ArrayType: 'a,
+        Self::NodeType: 'a,
+    {
+        self.inner.scan()
+    }
+
+    #[inline]
+    fn get_root(&self) -> Result<Option<ArrayType>, Exception> {
+        self.inner.get_root()
+    }
+
+    #[inline]
+    fn set_root(&mut self, _root: ArrayType) -> Result<(), Exception> {
+        Err(Exception::new("Database is read only"))
+    }
+
+    #[inline]
+    fn get_root_in(&self, namespace: &[u8]) -> Result<Option<ArrayType>, Exception> {
+        self.inner.get_root_in(namespace)
+    }
+
+    #[inline]
+    fn set_root_in(&mut self, _namespace: &[u8], _root: ArrayType) -> Result<(), Exception> {
+        Err(Exception::new("Database is read only"))
+    }
+
+    #[inline]
+    fn get_depth(&self) -> Result<Option<usize>, Exception> {
+        self.inner.get_depth()
+    }
+
+    #[inline]
+    fn set_depth(&mut self, _depth: usize) -> Result<(), Exception> {
+        Err(Exception::new("Database is read only"))
+    }
+
+    #[inline]
+    fn insert(&mut self, _key: ArrayType, _node: Self::NodeType) -> Result<(), Exception> {
+        Err(Exception::new("Database is read only"))
+    }
+
+    #[inline]
+    fn remove(&mut self, _key: &ArrayType) -> Result<(), Exception> {
+        Err(Exception::new("Database is read only"))
+    }
+
+    #[inline]
+    fn batch_write(&mut self) -> Result<(), Exception> {
+        Ok(())
+    }
+}
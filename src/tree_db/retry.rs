@@ -0,0 +1,224 @@
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::traits::{Array, Database, ErrorKind, Exception};
+
+/// The default number of attempts a `RetryingDatabase` makes at each operation.
+pub const DEFAULT_MAX_ATTEMPTS: usize = 3;
+/// The default time a `RetryingDatabase` waits before its first retry.
+pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(10);
+
+/// A wrapper retrying the operations of another `Database` which fail with a transient error.
+///
+/// An operation failing with `ErrorKind::Io` is attempted again after a backoff, which doubles
+/// after every retry, until it succeeds or `max_attempts` attempts have been made.  Any other
+/// error is returned immediately, since trying again would not change the outcome.
+///
+/// Only operations which are safe to repeat after a failed attempt are retried: opening, the
+/// reads, and the writes which leave the same state however many times they are applied, namely
+/// `insert` (nodes are content addressed, so a location always receives the same node),
+/// `remove`, `set_root`, `set_root_in` and `set_depth`.  `batch_write` is not retried, as a
+/// backend may have applied part of the batch, or dropped its pending changes, before failing,
+/// and `compare_and_set_root` and `compare_and_set_root_in` are not retried, as a failed attempt
+/// may still have updated the root.  Their errors are returned for the caller to handle.
+pub struct RetryingDatabase<DatabaseType> {
+    /// The database the operations are applied to.
+    inner: DatabaseType,
+    /// The number of attempts made at each operation, including the first.
+    max_attempts: usize,
+    /// How long to wait before the first retry.
+    backoff: Duration,
+}
+
+impl<DatabaseType> RetryingDatabase<DatabaseType> {
+    /// Creates a new `RetryingDatabase` over `inner`, making up to `max_attempts` attempts at each
+    /// operation and waiting `backoff` before the first retry.  At least one attempt is always made.
+    #[inline]
+    pub const fn new(inner: DatabaseType, max_attempts: usize, backoff: Duration) -> Self {
+        Self {
+            inner,
+            max_attempts,
+            backoff,
+        }
+    }
+
+    /// Gets the database the operations are applied to.
+    #[inline]
+    pub const fn inner(&self) -> &DatabaseType {
+        &self.inner
+    }
+
+    /// Gets the number of attempts made at each operation, including the first.
+    #[inline]
+    pub const fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    /// Gets how long to wait before the first retry.
+    #[inline]
+    pub const fn backoff(&self) -> Duration {
+        self.backoff
+    }
+}
+
+/// Runs `operation` until it succeeds, fails with an error other than `ErrorKind::Io`, or
+/// `max_attempts` attempts have been made, sleeping between attempts.  `operation` is told whether
+/// the attempt is the last one, after which it will not be called again.
+/// # Errors
+/// `Exception` generated by the last attempt of `operation`.
+fn retry<T, OperationType>(
+    max_attempts: usize,
+    backoff: Duration,
+    mut operation: OperationType,
+) -> Result<T, Exception>
+where
+    OperationType: FnMut(bool) -> Result<T, Exception>,
+{
+    let mut delay = backoff;
+    let mut attempt = 1;
+    loop {
+        match operation(attempt >= max_attempts) {
+            Err(e) if *e.kind() == ErrorKind::Io && attempt < max_attempts => {
+                sleep(delay);
+                delay = delay.checked_mul(2).unwrap_or(delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+impl<DatabaseType, ArrayType> Database<ArrayType> for RetryingDatabase<DatabaseType>
+where
+    DatabaseType: Database<ArrayType>,
+    DatabaseType::NodeType: Clone,
+    ArrayType: Array,
+{
+    type NodeType = DatabaseType::NodeType;
+    type EntryType = DatabaseType::EntryType;
+
+    #[inline]
+    fn open(path: &PathBuf) -> Result<Self, Exception> {
+        let inner = retry(DEFAULT_MAX_ATTEMPTS, DEFAULT_BACKOFF, |_| {
+            DatabaseType::open(path)
+        })?;
+        Ok(Self::new(inner, DEFAULT_MAX_ATTEMPTS, DEFAULT_BACKOFF))
+    }
+
+    #[inline]
+    fn open_with_capacity(path: &PathBuf, capacity: usize) -> Result<Self, Exception> {
+        let inner = retry(DEFAULT_MAX_ATTEMPTS, DEFAULT_BACKOFF, |_| {
+            DatabaseType::open_with_capacity(path, capacity)
+        })?;
+        Ok(Self::new(inner, DEFAULT_MAX_ATTEMPTS, DEFAULT_BACKOFF))
+    }
+
+    #[inline]
+    fn get_node(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception> {
+        retry(self.max_attempts, self.backoff, |_| {
+            self.inner.get_node(key)
+        })
+    }
+
+    #[inline]
+    fn get_nodes(&self, keys: &[ArrayType]) -> Result<Vec<Option<Self::NodeType>>, Exception> {
+        retry(self.max_attempts, self.backoff, |_| {
+            self.inner.get_nodes(keys)
+        })
+    }
+
+    #[inline]
+    fn contains(&self, key: &ArrayType) -> Result<bool, Exception> {
+        retry(self.max_attempts, self.backoff, |_| {
+            self.inner.contains(key)
+        })
+    }
+
+    #[inline]
+    fn node_locations(&self) -> Result<Vec<ArrayType>, Exception> {
+        retry(self.max_attempts, self.backoff, |_| {
+            self.inner.node_locations()
+        })
+    }
+
+    #[inline]
+    fn get_root(&self) -> Result<Option<ArrayType>, Exception> {
+        retry(self.max_attempts, self.backoff, |_| self.inner.get_root())
+    }
+
+    #[inline]
+    fn set_root(&mut self, root: ArrayType) -> Result<(), Exception> {
+        let inner = &mut self.inner;
+        retry(self.max_attempts, self.backoff, |_| inner.set_root(root))
+    }
+
+    #[inline]
+    fn get_depth(&self) -> Result<Option<usize>, Exception> {
+        retry(self.max_attempts, self.backoff, |_| self.inner.get_depth())
+    }
+
+    #[inline]
+    fn set_depth(&mut self, depth: usize) -> Result<(), Exception> {
+        let inner = &mut self.inner;
+        retry(self.max_attempts, self.backoff, |_| inner.set_depth(depth))
+    }
+
+    #[inline]
+    fn compare_and_set_root(
+        &mut self,
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> Result<bool, Exception> {
+        self.inner.compare_and_set_root(expected, new)
+    }
+
+    #[inline]
+    fn get_root_in(&self, namespace: &[u8]) -> Result<Option<ArrayType>, Exception> {
+        retry(self.max_attempts, self.backoff, |_| {
+            self.inner.get_root_in(namespace)
+        })
+    }
+
+    #[inline]
+    fn set_root_in(&mut self, namespace: &[u8], root: ArrayType) -> Result<(), Exception> {
+        let inner = &mut self.inner;
+        retry(self.max_attempts, self.backoff, |_| {
+            inner.set_root_in(namespace, root)
+        })
+    }
+
+    #[inline]
+    fn compare_and_set_root_in(
+        &mut self,
+        namespace: &[u8],
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> Result<bool, Exception> {
+        self.inner.compare_and_set_root_in(namespace, expected, new)
+    }
+
+    #[inline]
+    fn insert(&mut self, key: ArrayType, node: Self::NodeType) -> Result<(), Exception> {
+        let inner = &mut self.inner;
+        // The node is only cloned for an attempt which may be followed by a retry, and is moved
+        // into the last one
+        let mut unsent = Some(node);
+        retry(self.max_attempts, self.backoff, |last| {
+            let attempt_node = if last { unsent.take() } else { unsent.clone() }
+                .ok_or_else(|| Exception::new("Node was moved into an earlier attempt"))?;
+            inner.insert(key, attempt_node)
+        })
+    }
+
+    #[inline]
+    fn remove(&mut self, key: &ArrayType) -> Result<(), Exception> {
+        let inner = &mut self.inner;
+        retry(self.max_attempts, self.backoff, |_| inner.remove(key))
+    }
+
+    #[inline]
+    fn batch_write(&mut self) -> Result<(), Exception> {
+        self.inner.batch_write()
+    }
+}
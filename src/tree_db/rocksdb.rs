@@ -1,12 +1,33 @@
+use std::convert::TryFrom;
 use std::error::Error;
 use std::path::PathBuf;
 
-use rocksdb::{WriteBatch, DB};
+use rocksdb::{IteratorMode, WriteBatch, DB};
 
-use crate::traits::{Array, Database, Decode, Encode, Exception};
+use crate::traits::{Array, Database, Decode, Encode, Exception, NodeScan};
 use crate::tree::tree_node::TreeNode;
 use std::marker::PhantomData;
 
+/// The key the current root is stored under, followed by the namespace of the root pointer.  It is
+/// longer than any node location, so it never collides with a node.
+const ROOT_KEY: &[u8] = b"starling::merkle_bit::current_root";
+/// The key the depth of the stored trees is stored under, which like `ROOT_KEY` never collides
+/// with a node.
+const DEPTH_KEY: &[u8] = b"starling::merkle_bit::depth";
+
+/// Gets the key the current root of `namespace` is stored under.
+fn root_key(namespace: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(ROOT_KEY.len() + namespace.len());
+    key.extend_from_slice(ROOT_KEY);
+    key.extend_from_slice(namespace);
+    key
+}
+
+/// Checks whether `key` holds a root pointer or the depth rather than a node.
+fn is_metadata_key(key: &[u8]) -> bool {
+    key.starts_with(ROOT_KEY) || key == DEPTH_KEY
+}
+
 impl From<rocksdb::Error> for Exception {
     #[inline]
     fn from(error: rocksdb::Error) -> Self {
@@ -53,12 +74,130 @@ where
     #[inline]
     fn get_node(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception> {
         if let Some(buffer) = self.db.get(&key)? {
-            Ok(Some(Self::NodeType::decode(buffer.as_ref())?))
+            let node = Self::NodeType::decode(buffer.as_ref())
+                .map_err(|e| Exception::corrupt_node(key.as_ref(), &e))?;
+            Ok(Some(node))
         } else {
             Ok(None)
         }
     }
 
+    #[inline]
+    fn get_nodes(&self, keys: &[ArrayType]) -> Result<Vec<Option<Self::NodeType>>, Exception> {
+        let mut nodes = Vec::with_capacity(keys.len());
+        for (key, buffer) in keys.iter().zip(self.db.multi_get(keys)) {
+            if let Some(buffer) = buffer? {
+                let node = Self::NodeType::decode(buffer.as_ref())
+                    .map_err(|e| Exception::corrupt_node(key.as_ref(), &e))?;
+                nodes.push(Some(node));
+            } else {
+                nodes.push(None);
+            }
+        }
+        Ok(nodes)
+    }
+
+    #[inline]
+    fn contains(&self, key: &ArrayType) -> Result<bool, Exception> {
+        Ok(self.db.get_pinned(key)?.is_some())
+    }
+
+    #[inline]
+    fn node_size(&self, key: ArrayType) -> Result<Option<u64>, Exception> {
+        if let Some(buffer) = self.db.get_pinned(&key)? {
+            return Ok(Some(u64::try_from(buffer.len())?));
+        }
+        Ok(None)
+    }
+
+    #[inline]
+    fn node_locations(&self) -> Result<Vec<ArrayType>, Exception> {
+        let mut locations = Vec::new();
+        for (key, _) in self.db.iterator(IteratorMode::Start) {
+            if is_metadata_key(&key) {
+                continue;
+            }
+            let mut location = ArrayType::default();
+            if location.as_ref().len() != key.len() {
+                return Err(Exception::new("Stored key has the wrong length"));
+            }
+            location.as_mut().copy_from_slice(&key);
+            locations.push(location);
+        }
+        Ok(locations)
+    }
+
+    #[inline]
+    fn scan<'a>(&'a self) -> NodeScan<'a, ArrayType, Self::NodeType>
+    where
+        ArrayType: 'a,
+        Self::NodeType: 'a,
+    {
+        Box::new(
+            self.db
+                .iterator(IteratorMode::Start)
+                .filter(|(key, _)| !is_metadata_key(key))
+                .map(|(key, buffer)| {
+                    let mut location = ArrayType::default();
+                    if location.as_ref().len() != key.len() {
+                        return Err(Exception::new("Stored key has the wrong length"));
+                    }
+                    location.as_mut().copy_from_slice(&key);
+                    let node = Self::NodeType::decode(buffer.as_ref())
+                        .map_err(|e| Exception::corrupt_node(location.as_ref(), &e))?;
+                    Ok((location, node))
+                }),
+        )
+    }
+
+    #[inline]
+    fn get_root(&self) -> Result<Option<ArrayType>, Exception> {
+        self.get_root_in(&[])
+    }
+
+    #[inline]
+    fn set_root(&mut self, root: ArrayType) -> Result<(), Exception> {
+        self.set_root_in(&[], root)
+    }
+
+    #[inline]
+    fn get_root_in(&self, namespace: &[u8]) -> Result<Option<ArrayType>, Exception> {
+        if let Some(buffer) = self.db.get_pinned(root_key(namespace))? {
+            let mut root = ArrayType::default();
+            if root.as_ref().len() != buffer.len() {
+                return Err(Exception::new("Stored root has the wrong length"));
+            }
+            root.as_mut().copy_from_slice(&buffer);
+            return Ok(Some(root));
+        }
+        Ok(None)
+    }
+
+    #[inline]
+    fn set_root_in(&mut self, namespace: &[u8], root: ArrayType) -> Result<(), Exception> {
+        Ok(self.db.put(root_key(namespace), root)?)
+    }
+
+    #[inline]
+    fn get_depth(&self) -> Result<Option<usize>, Exception> {
+        if let Some(buffer) = self.db.get_pinned(DEPTH_KEY)? {
+            let mut depth = [0_u8; 8];
+            if depth.len() != buffer.len() {
+                return Err(Exception::new("Stored depth has the wrong length"));
+            }
+            depth.copy_from_slice(&buffer);
+            return Ok(Some(usize::try_from(u64::from_be_bytes(depth))?));
+        }
+        Ok(None)
+    }
+
+    #[inline]
+    fn set_depth(&mut self, depth: usize) -> Result<(), Exception> {
+        Ok(self
+            .db
+            .put(DEPTH_KEY, u64::try_from(depth)?.to_be_bytes())?)
+    }
+
     #[inline]
     fn insert(&mut self, key: ArrayType, value: Self::NodeType) -> Result<(), Exception> {
         let serialized = value.encode()?;
@@ -0,0 +1,208 @@
+#[cfg(not(any(feature = "use_hashbrown")))]
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "use_hashbrown")]
+use hashbrown::HashMap;
+
+use crate::traits::{Array, Database, Exception};
+
+/// The default time a `TtlDatabase` keeps a node for.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+/// A wrapper expiring the nodes inserted into another `Database` after a fixed time to live.
+///
+/// The time each node was inserted is recorded, and `get_node` treats a node older than the time
+/// to live as absent.  Nodes which were already in the inner database when it was wrapped have no
+/// recorded time and never expire.  Expired nodes stay in the inner database until
+/// `purge_expired` removes them, which keeps an in-memory database bounded by age.
+pub struct TtlDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    ArrayType: Array,
+{
+    /// The database the nodes are stored in.
+    inner: DatabaseType,
+    /// The time each node was inserted.
+    inserted: HashMap<ArrayType, Instant>,
+    /// How long a node is kept after it is inserted.
+    ttl: Duration,
+}
+
+impl<DatabaseType, ArrayType> TtlDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    ArrayType: Array,
+{
+    /// Creates a new `TtlDatabase` over `inner`, expiring nodes `ttl` after they are inserted.
+    #[inline]
+    pub fn new(inner: DatabaseType, ttl: Duration) -> Self {
+        Self {
+            inner,
+            inserted: HashMap::new(),
+            ttl,
+        }
+    }
+
+    /// Gets the database the nodes are stored in.
+    #[inline]
+    pub const fn inner(&self) -> &DatabaseType {
+        &self.inner
+    }
+
+    /// Gets how long a node is kept after it is inserted.
+    #[inline]
+    pub const fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Checks whether the node at `key` was inserted more than the time to live ago.
+    fn is_expired(&self, key: &ArrayType) -> bool {
+        self.inserted
+            .get(key)
+            .is_some_and(|inserted| inserted.elapsed() >= self.ttl)
+    }
+
+    /// Removes every expired node from the inner database, returning the number removed.
+    /// # Errors
+    /// `Exception` generated if a node cannot be removed, or the removal cannot be written.
+    #[inline]
+    pub fn purge_expired(&mut self) -> Result<usize, Exception> {
+        let ttl = self.ttl;
+        let expired = self
+            .inserted
+            .iter()
+            .filter(|(_, inserted)| inserted.elapsed() >= ttl)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        for key in &expired {
+            self.inserted.remove(key);
+            self.inner.remove(key)?;
+        }
+        self.inner.batch_write()?;
+        Ok(expired.len())
+    }
+}
+
+impl<DatabaseType, ArrayType> Database<ArrayType> for TtlDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    ArrayType: Array,
+{
+    type NodeType = DatabaseType::NodeType;
+    type EntryType = DatabaseType::EntryType;
+
+    #[inline]
+    fn open(path: &PathBuf) -> Result<Self, Exception> {
+        Ok(Self::new(DatabaseType::open(path)?, DEFAULT_TTL))
+    }
+
+    #[inline]
+    fn open_with_capacity(path: &PathBuf, capacity: usize) -> Result<Self, Exception> {
+        Ok(Self::new(
+            DatabaseType::open_with_capacity(path, capacity)?,
+            DEFAULT_TTL,
+        ))
+    }
+
+    #[inline]
+    fn get_node(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception> {
+        if self.is_expired(&key) {
+            return Ok(None);
+        }
+        self.inner.get_node(key)
+    }
+
+    #[inline]
+    fn get_nodes(&self, keys: &[ArrayType]) -> Result<Vec<Option<Self::NodeType>>, Exception> {
+        let mut nodes = self.inner.get_nodes(keys)?;
+        for (key, node) in keys.iter().zip(nodes.iter_mut()) {
+            if self.is_expired(key) {
+                *node = None;
+            }
+        }
+        Ok(nodes)
+    }
+
+    #[inline]
+    fn contains(&self, key: &ArrayType) -> Result<bool, Exception> {
+        if self.is_expired(key) {
+            return Ok(false);
+        }
+        self.inner.contains(key)
+    }
+
+    #[inline]
+    fn node_locations(&self) -> Result<Vec<ArrayType>, Exception> {
+        let mut locations = self.inner.node_locations()?;
+        locations.retain(|location| !self.is_expired(location));
+        Ok(locations)
+    }
+
+    #[inline]
+    fn get_root(&self) -> Result<Option<ArrayType>, Exception> {
+        self.inner.get_root()
+    }
+
+    #[inline]
+    fn set_root(&mut self, root: ArrayType) -> Result<(), Exception> {
+        self.inner.set_root(root)
+    }
+
+    #[inline]
+    fn get_depth(&self) -> Result<Option<usize>, Exception> {
+        self.inner.get_depth()
+    }
+
+    #[inline]
+    fn set_depth(&mut self, depth: usize) -> Result<(), Exception> {
+        self.inner.set_depth(depth)
+    }
+
+    #[inline]
+    fn compare_and_set_root(
+        &mut self,
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> Result<bool, Exception> {
+        self.inner.compare_and_set_root(expected, new)
+    }
+
+    #[inline]
+    fn get_root_in(&self, namespace: &[u8]) -> Result<Option<ArrayType>, Exception> {
+        self.inner.get_root_in(namespace)
+    }
+
+    #[inline]
+    fn set_root_in(&mut self, namespace: &[u8], root: ArrayType) -> Result<(), Exception> {
+        self.inner.set_root_in(namespace, root)
+    }
+
+    #[inline]
+    fn compare_and_set_root_in(
+        &mut self,
+        namespace: &[u8],
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> Result<bool, Exception> {
+        self.inner.compare_and_set_root_in(namespace, expected, new)
+    }
+
+    #[inline]
+    fn insert(&mut self, key: ArrayType, node: Self::NodeType) -> Result<(), Exception> {
+        self.inserted.insert(key, Instant::now());
+        self.inner.insert(key, node)
+    }
+
+    #[inline]
+    fn remove(&mut self, key: &ArrayType) -> Result<(), Exception> {
+        self.inserted.remove(key);
+        self.inner.remove(key)
+    }
+
+    #[inline]
+    fn batch_write(&mut self) -> Result<(), Exception> {
+        self.inner.batch_write()
+    }
+}
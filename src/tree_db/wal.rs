@@ -0,0 +1,315 @@
+#[cfg(not(any(feature = "use_hashbrown")))]
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::{read, File, OpenOptions};
+use std::io::Write;
+use std::mem::take;
+use std::path::PathBuf;
+
+#[cfg(feature = "use_hashbrown")]
+use hashbrown::HashMap;
+
+use crate::traits::{Array, Database, Decode, Encode, Exception};
+
+/// Marks a logged insert.
+const INSERT_RECORD: u8 = 0;
+/// Marks a logged remove.
+const REMOVE_RECORD: u8 = 1;
+/// Marks the end of a batch, written by `batch_write` once every change of the batch is logged.
+const COMMIT_RECORD: u8 = 2;
+
+/// A wrapper adding a write ahead log to a `Database` that lacks its own.
+///
+/// Inserts and removes are appended to the log and buffered in memory until `batch_write`, which
+/// appends a commit record, syncs the log, applies the changes to the inner database and then
+/// truncates the log.  If the wrapper is dropped before `batch_write` completes (for instance on a
+/// crash), the log is replayed into the inner database the next time it is opened.  Only batches
+/// ending in a commit record are replayed, so a batch is either applied whole or not at all, and
+/// changes never confirmed by `batch_write` are discarded.
+pub struct WalDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    ArrayType: Array,
+{
+    /// The database the changes are applied to.
+    inner: DatabaseType,
+    /// The log of changes not yet applied to `inner`.
+    log: File,
+    /// The changes not yet applied to `inner`.  A `None` marks a removed node.
+    pending: HashMap<ArrayType, Option<DatabaseType::NodeType>>,
+}
+
+impl<DatabaseType, ArrayType> WalDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    DatabaseType::NodeType: Clone + Encode + Decode,
+    ArrayType: Array,
+{
+    /// Creates a new `WalDatabase` over `inner`, logging to the file at `log_path`.  Any changes
+    /// left in the log by an unclean shutdown are applied to `inner` first.
+    /// # Errors
+    /// `Exception` generated if the log cannot be read, replayed, or truncated.
+    #[inline]
+    pub fn new(mut inner: DatabaseType, log_path: &PathBuf) -> Result<Self, Exception> {
+        let log = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(log_path)?;
+
+        let buffer = read(log_path)?;
+        if !buffer.is_empty() {
+            Self::replay(&mut inner, &buffer)?;
+            inner.batch_write()?;
+            log.set_len(0)?;
+            log.sync_data()?;
+        }
+
+        Ok(Self {
+            inner,
+            log,
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Gets the path of the log used for a database opened at `path`.
+    #[inline]
+    #[must_use]
+    pub fn log_path(path: &PathBuf) -> PathBuf {
+        let mut log_path = path.clone().into_os_string();
+        log_path.push(".wal");
+        PathBuf::from(log_path)
+    }
+
+    /// Applies the committed batches of records in `buffer` to `inner`.  The records following
+    /// the last commit record, including a truncated record left by a crash during an append,
+    /// belong to a batch that was never committed and are ignored.
+    /// # Errors
+    /// `Exception` generated if a record is invalid or cannot be applied.
+    fn replay(inner: &mut DatabaseType, buffer: &[u8]) -> Result<(), Exception> {
+        let key_len = ArrayType::default().as_ref().len();
+        // The changes of the current batch, as the range of each encoded node or `None` for a
+        // remove, decoded only once the batch is known to be committed
+        let mut batch = Vec::new();
+        let mut offset = 0;
+        while offset + 1 + key_len <= buffer.len() {
+            let record_type = buffer[offset];
+            let mut key = ArrayType::default();
+            key.as_mut()
+                .copy_from_slice(&buffer[offset + 1..offset + 1 + key_len]);
+            offset += 1 + key_len;
+
+            match record_type {
+                INSERT_RECORD => {
+                    if offset + 4 > buffer.len() {
+                        break;
+                    }
+                    let mut len = [0_u8; 4];
+                    len.copy_from_slice(&buffer[offset..offset + 4]);
+                    let len = usize::try_from(u32::from_be_bytes(len))?;
+                    offset += 4;
+                    if offset + len > buffer.len() {
+                        break;
+                    }
+                    batch.push((key, Some(offset..offset + len)));
+                    offset += len;
+                }
+                REMOVE_RECORD => batch.push((key, None)),
+                COMMIT_RECORD => {
+                    for (location, change) in take(&mut batch) {
+                        if let Some(range) = change {
+                            let node = DatabaseType::NodeType::decode(&buffer[range])
+                                .map_err(|e| Exception::corrupt_node(location.as_ref(), &e))?;
+                            inner.insert(location, node)?;
+                        } else {
+                            inner.remove(&location)?;
+                        }
+                    }
+                }
+                _ => return Err(Exception::new("Corrupt write ahead log record")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends a record to the log.  The log is only synced to disk by `batch_write`, since
+    /// changes that were never confirmed do not need to survive a crash.
+    /// # Errors
+    /// `Exception` generated if the record cannot be written.
+    fn append(&mut self, record: &[u8]) -> Result<(), Exception> {
+        self.log.write_all(record)?;
+        Ok(())
+    }
+}
+
+impl<DatabaseType, ArrayType> Database<ArrayType> for WalDatabase<DatabaseType, ArrayType>
+where
+    DatabaseType: Database<ArrayType>,
+    DatabaseType::NodeType: Clone + Encode + Decode,
+    ArrayType: Array,
+{
+    type NodeType = DatabaseType::NodeType;
+    type EntryType = DatabaseType::EntryType;
+
+    #[inline]
+    fn open(path: &PathBuf) -> Result<Self, Exception> {
+        Self::new(DatabaseType::open(path)?, &Self::log_path(path))
+    }
+
+    #[inline]
+    fn open_with_capacity(path: &PathBuf, capacity: usize) -> Result<Self, Exception> {
+        Self::new(
+            DatabaseType::open_with_capacity(path, capacity)?,
+            &Self::log_path(path),
+        )
+    }
+
+    #[inline]
+    fn get_node(&self, key: ArrayType) -> Result<Option<Self::NodeType>, Exception> {
+        if let Some(pending) = self.pending.get(&key) {
+            return Ok(pending.clone());
+        }
+        self.inner.get_node(key)
+    }
+
+    #[inline]
+    fn get_nodes(&self, keys: &[ArrayType]) -> Result<Vec<Option<Self::NodeType>>, Exception> {
+        let mut nodes = Vec::with_capacity(keys.len());
+        let mut missing = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            if let Some(pending) = self.pending.get(key) {
+                nodes.push(pending.clone());
+            } else {
+                missing.push(i);
+                nodes.push(None);
+            }
+        }
+        if missing.is_empty() {
+            return Ok(nodes);
+        }
+
+        // The nodes without pending changes are read from the inner database in one call
+        let missing_keys = missing.iter().map(|&i| keys[i]).collect::<Vec<_>>();
+        for (i, node) in missing.into_iter().zip(self.inner.get_nodes(&missing_keys)?) {
+            nodes[i] = node;
+        }
+        Ok(nodes)
+    }
+
+    #[inline]
+    fn contains(&self, key: &ArrayType) -> Result<bool, Exception> {
+        if let Some(pending) = self.pending.get(key) {
+            return Ok(pending.is_some());
+        }
+        self.inner.contains(key)
+    }
+
+    #[inline]
+    fn node_locations(&self) -> Result<Vec<ArrayType>, Exception> {
+        let mut locations = self.inner.node_locations()?;
+        locations.retain(|location| !self.pending.contains_key(location));
+        for (location, change) in &self.pending {
+            if change.is_some() {
+                locations.push(*location);
+            }
+        }
+        Ok(locations)
+    }
+
+    #[inline]
+    fn get_root(&self) -> Result<Option<ArrayType>, Exception> {
+        self.inner.get_root()
+    }
+
+    #[inline]
+    fn set_root(&mut self, root: ArrayType) -> Result<(), Exception> {
+        self.inner.set_root(root)
+    }
+
+    #[inline]
+    fn get_depth(&self) -> Result<Option<usize>, Exception> {
+        self.inner.get_depth()
+    }
+
+    #[inline]
+    fn set_depth(&mut self, depth: usize) -> Result<(), Exception> {
+        self.inner.set_depth(depth)
+    }
+
+    #[inline]
+    fn compare_and_set_root(
+        &mut self,
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> Result<bool, Exception> {
+        self.inner.compare_and_set_root(expected, new)
+    }
+
+    #[inline]
+    fn get_root_in(&self, namespace: &[u8]) -> Result<Option<ArrayType>, Exception> {
+        self.inner.get_root_in(namespace)
+    }
+
+    #[inline]
+    fn set_root_in(&mut self, namespace: &[u8], root: ArrayType) -> Result<(), Exception> {
+        self.inner.set_root_in(namespace, root)
+    }
+
+    #[inline]
+    fn compare_and_set_root_in(
+        &mut self,
+        namespace: &[u8],
+        expected: Option<ArrayType>,
+        new: ArrayType,
+    ) -> Result<bool, Exception> {
+        self.inner.compare_and_set_root_in(namespace, expected, new)
+    }
+
+    #[inline]
+    fn insert(&mut self, key: ArrayType, node: Self::NodeType) -> Result<(), Exception> {
+        let encoded = node.encode()?;
+        let mut record = Vec::with_capacity(5 + key.as_ref().len() + encoded.len());
+        record.push(INSERT_RECORD);
+        record.extend_from_slice(key.as_ref());
+        record.extend_from_slice(&u32::try_from(encoded.len())?.to_be_bytes());
+        record.extend_from_slice(&encoded);
+        self.append(&record)?;
+        self.pending.insert(key, Some(node));
+        Ok(())
+    }
+
+    #[inline]
+    fn remove(&mut self, key: &ArrayType) -> Result<(), Exception> {
+        let mut record = Vec::with_capacity(1 + key.as_ref().len());
+        record.push(REMOVE_RECORD);
+        record.extend_from_slice(key.as_ref());
+        self.append(&record)?;
+        self.pending.insert(*key, None);
+        Ok(())
+    }
+
+    #[inline]
+    fn batch_write(&mut self) -> Result<(), Exception> {
+        if self.pending.is_empty() {
+            return self.inner.batch_write();
+        }
+        let mut record = Vec::with_capacity(1 + ArrayType::default().as_ref().len());
+        record.push(COMMIT_RECORD);
+        record.extend_from_slice(ArrayType::default().as_ref());
+        self.append(&record)?;
+        self.log.sync_data()?;
+        // The changes stay pending until the inner database has them, so a failed batch can be
+        // retried, or is still served by this wrapper and replayed from the log on reopen
+        for (key, change) in &self.pending {
+            if let Some(node) = change {
+                self.inner.insert(*key, node.clone())?;
+            } else {
+                self.inner.remove(key)?;
+            }
+        }
+        self.inner.batch_write()?;
+        self.pending.clear();
+        self.log.set_len(0)?;
+        self.log.sync_data()?;
+        Ok(())
+    }
+}
@@ -20,6 +20,7 @@ where
 
     #[inline]
     fn finalize(self) -> ArrayType {
+        // The byte order only decides the node locations produced, never the order of keys
         let value = Self::finish(&self).to_le_bytes();
         let mut v = ArrayType::default();
         let length = v.as_ref().len();
@@ -29,3 +29,41 @@ where
         res
     }
 }
+
+/// A Keccak-256 hasher keeping the last bytes of the digest when the output is shorter than 32
+/// bytes.  With 20 byte arrays this matches how Ethereum derives addresses from public keys, so
+/// node locations in a tree keyed by addresses are 20 bytes long as well.  Longer outputs are
+/// padded with zeros after the digest.
+pub struct Keccak256Hasher(Keccak);
+
+impl<ArrayType> crate::traits::Hasher<ArrayType> for Keccak256Hasher
+where
+    ArrayType: Array,
+{
+    type HashType = Self;
+
+    #[inline]
+    fn new(_size: usize) -> Self {
+        Self(Keccak::v256())
+    }
+
+    #[inline]
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    #[inline]
+    fn finalize(self) -> ArrayType {
+        let mut digest = [0_u8; 32];
+        self.0.finalize(&mut digest);
+        let mut res = ArrayType::default();
+        let length = res.as_ref().len();
+        if length <= digest.len() {
+            res.as_mut()
+                .copy_from_slice(&digest[digest.len() - length..]);
+        } else {
+            res.as_mut()[..digest.len()].copy_from_slice(&digest);
+        }
+        res
+    }
+}
@@ -0,0 +1,321 @@
+use std::convert::TryFrom;
+
+use crate::traits::{Array, Exception, Hasher};
+
+/// The tag hashed before the key and value of a combined leaf.
+pub const COMBINED_LEAF_TAG: &[u8] = b"kv";
+
+/// The tags hashed before the contents of each kind of node, keeping the location of a node of
+/// one kind from colliding with that of another.  The defaults are `b"d"`, `b"l"` and `b"b"`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DomainTags {
+    /// The tag hashed before the contents of a data node.
+    data: Vec<u8>,
+    /// The tag hashed before the contents of a leaf node.
+    leaf: Vec<u8>,
+    /// The tag hashed before the contents of a branch node.
+    branch: Vec<u8>,
+}
+
+impl DomainTags {
+    /// Creates a new `DomainTags` from the tags of data, leaf and branch nodes.
+    #[inline]
+    #[must_use]
+    pub fn new(data: &[u8], leaf: &[u8], branch: &[u8]) -> Self {
+        Self {
+            data: data.to_vec(),
+            leaf: leaf.to_vec(),
+            branch: branch.to_vec(),
+        }
+    }
+
+    /// Creates a `DomainTags` using the single byte discriminants `0x00`, `0x01` and `0x02` for
+    /// data, leaf and branch nodes respectively.
+    #[inline]
+    #[must_use]
+    pub fn discriminants() -> Self {
+        Self::new(&[0x00], &[0x01], &[0x02])
+    }
+
+    /// Gets the tag hashed before the contents of a data node.
+    #[inline]
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Gets the tag hashed before the contents of a leaf node.
+    #[inline]
+    #[must_use]
+    pub fn leaf(&self) -> &[u8] {
+        &self.leaf
+    }
+
+    /// Gets the tag hashed before the contents of a branch node.
+    #[inline]
+    #[must_use]
+    pub fn branch(&self) -> &[u8] {
+        &self.branch
+    }
+}
+
+impl Default for DomainTags {
+    #[inline]
+    fn default() -> Self {
+        Self::new(b"d", b"l", b"b")
+    }
+}
+
+/// Describes how node locations are computed from node contents.  All hashing of tree locations
+/// goes through a `HashScheme`, so that trees configured differently remain internally consistent.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HashScheme {
+    /// If true, data nodes are addressed by their value only (`hash(tag || value)`), allowing
+    /// identical values under different keys to share a single data node.  Otherwise data nodes
+    /// are addressed by `hash(tag || key || value)`.
+    value_addressed_data: bool,
+    /// If true, a leaf holds its value itself and is addressed by
+    /// `hash(COMBINED_LEAF_TAG || key || value)`, so a key takes a single node instead of a leaf
+    /// and a data node.
+    combined_leaves: bool,
+    /// If true, the children of a branch are hashed smaller location first, as specifications
+    /// sorting the children of a node expect.  Otherwise the zero child is hashed before the one
+    /// child.
+    sorted_children: bool,
+    /// The domain separation prefix hashed before every node, as the big endian `u32` length of
+    /// the domain followed by the domain itself.  Empty when there is no domain, in which case
+    /// nothing is added to the hashes.
+    domain_prefix: Vec<u8>,
+    /// The tags hashed before the contents of each kind of node.
+    tags: DomainTags,
+}
+
+impl HashScheme {
+    /// Creates a new `HashScheme` with the default (key addressed) layout.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets whether data nodes are addressed by their value only.
+    #[inline]
+    #[must_use]
+    pub const fn value_addressed_data(&self) -> bool {
+        self.value_addressed_data
+    }
+
+    /// Sets whether data nodes are addressed by their value only.
+    #[inline]
+    pub fn set_value_addressed_data(&mut self, value_addressed: bool) {
+        self.value_addressed_data = value_addressed;
+    }
+
+    /// Gets whether leaves hold their values and are addressed by their key and value.
+    #[inline]
+    #[must_use]
+    pub const fn combined_leaves(&self) -> bool {
+        self.combined_leaves
+    }
+
+    /// Sets whether leaves hold their values and are addressed by their key and value.
+    #[inline]
+    pub fn set_combined_leaves(&mut self, combined: bool) {
+        self.combined_leaves = combined;
+    }
+
+    /// Gets whether the children of a branch are hashed smaller location first.
+    #[inline]
+    #[must_use]
+    pub const fn sorted_children(&self) -> bool {
+        self.sorted_children
+    }
+
+    /// Sets whether the children of a branch are hashed smaller location first.
+    #[inline]
+    pub fn set_sorted_children(&mut self, sorted: bool) {
+        self.sorted_children = sorted;
+    }
+
+    /// Orders the `zero` and `one` children of a branch as they are hashed: smaller location first
+    /// when children are sorted, and otherwise the zero child first.
+    #[inline]
+    pub fn hash_order<'a, ArrayType>(
+        &self,
+        zero: &'a ArrayType,
+        one: &'a ArrayType,
+    ) -> (&'a ArrayType, &'a ArrayType)
+    where
+        ArrayType: Array,
+    {
+        if self.sorted_children && one < zero {
+            (one, zero)
+        } else {
+            (zero, one)
+        }
+    }
+
+    /// Gets the domain separation prefix of this scheme.
+    #[inline]
+    #[must_use]
+    pub fn domain(&self) -> &[u8] {
+        self.domain_prefix.get(4..).unwrap_or(&[])
+    }
+
+    /// Gets the bytes hashed before the node tag of every location: the length of the domain as a
+    /// big endian `u32` followed by the domain, or nothing when there is no domain.
+    #[inline]
+    #[must_use]
+    pub fn domain_prefix(&self) -> &[u8] {
+        &self.domain_prefix
+    }
+
+    /// Sets the domain separation prefix.  A non empty `domain` is hashed, preceded by its length,
+    /// before the node tag of every location, so trees with different domains never share nodes.
+    /// # Errors
+    /// `Exception` generated if `domain` is longer than `u32::MAX` bytes.
+    #[inline]
+    pub fn set_domain(&mut self, domain: &[u8]) -> Result<(), Exception> {
+        self.domain_prefix.clear();
+        if !domain.is_empty() {
+            let length = u32::try_from(domain.len())?;
+            self.domain_prefix.extend_from_slice(&length.to_be_bytes());
+            self.domain_prefix.extend_from_slice(domain);
+        }
+        Ok(())
+    }
+
+    /// Gets the tags hashed before the contents of each kind of node.
+    #[inline]
+    #[must_use]
+    pub const fn tags(&self) -> &DomainTags {
+        &self.tags
+    }
+
+    /// Sets the tags hashed before the contents of each kind of node.
+    #[inline]
+    pub fn set_tags(&mut self, tags: DomainTags) {
+        self.tags = tags;
+    }
+
+    /// Creates a hasher for a location of `size` bytes, already fed with the domain prefix.
+    fn start<HasherType, ArrayType>(&self, size: usize) -> HasherType
+    where
+        HasherType: Hasher<ArrayType, HashType = HasherType>,
+        ArrayType: Array,
+    {
+        let mut hasher = HasherType::new(size);
+        if !self.domain_prefix.is_empty() {
+            hasher.update(&self.domain_prefix);
+        }
+        hasher
+    }
+
+    /// Computes the location of a data node holding `value` for `key`.
+    #[inline]
+    pub fn data_location<HasherType, ArrayType>(&self, key: &ArrayType, value: &[u8]) -> ArrayType
+    where
+        HasherType: Hasher<ArrayType, HashType = HasherType>,
+        ArrayType: Array,
+    {
+        let mut data_hasher = self.start::<HasherType, ArrayType>(key.as_ref().len());
+        data_hasher.update(&self.tags.data);
+        if !self.value_addressed_data {
+            data_hasher.update(key.as_ref());
+        }
+        data_hasher.update(value);
+        data_hasher.finalize()
+    }
+
+    /// Computes the location a data node holding `value` has under value addressing, which
+    /// identifies the value independently of the key it is stored under.
+    #[inline]
+    pub fn value_location<HasherType, ArrayType>(&self, value: &[u8]) -> ArrayType
+    where
+        HasherType: Hasher<ArrayType, HashType = HasherType>,
+        ArrayType: Array,
+    {
+        let mut value_hasher =
+            self.start::<HasherType, ArrayType>(ArrayType::default().as_ref().len());
+        value_hasher.update(&self.tags.data);
+        value_hasher.update(value);
+        value_hasher.finalize()
+    }
+
+    /// Computes the location of a leaf node for `key` pointing at the data node at `data`.
+    #[inline]
+    pub fn leaf_location<HasherType, ArrayType>(
+        &self,
+        key: &ArrayType,
+        data: &ArrayType,
+    ) -> ArrayType
+    where
+        HasherType: Hasher<ArrayType, HashType = HasherType>,
+        ArrayType: Array,
+    {
+        let mut leaf_hasher = self.start::<HasherType, ArrayType>(key.as_ref().len());
+        leaf_hasher.update(&self.tags.leaf);
+        leaf_hasher.update(key.as_ref());
+        leaf_hasher.update(data.as_ref());
+        leaf_hasher.finalize()
+    }
+
+    /// Computes the location of a combined leaf holding `value` for `key`.
+    #[inline]
+    pub fn combined_leaf_location<HasherType, ArrayType>(
+        &self,
+        key: &ArrayType,
+        value: &[u8],
+    ) -> ArrayType
+    where
+        HasherType: Hasher<ArrayType, HashType = HasherType>,
+        ArrayType: Array,
+    {
+        let mut leaf_hasher = self.start::<HasherType, ArrayType>(key.as_ref().len());
+        leaf_hasher.update(COMBINED_LEAF_TAG);
+        leaf_hasher.update(key.as_ref());
+        leaf_hasher.update(value);
+        leaf_hasher.finalize()
+    }
+
+    /// Computes the location of the leaf for `key` holding `value`: the combined leaf location
+    /// when leaves are combined, and otherwise the location of a leaf pointing at the data node
+    /// of `value`.
+    #[inline]
+    pub fn leaf_location_of_value<HasherType, ArrayType>(
+        &self,
+        key: &ArrayType,
+        value: &[u8],
+    ) -> ArrayType
+    where
+        HasherType: Hasher<ArrayType, HashType = HasherType>,
+        ArrayType: Array,
+    {
+        if self.combined_leaves {
+            self.combined_leaf_location::<HasherType, _>(key, value)
+        } else {
+            let data = self.data_location::<HasherType, _>(key, value);
+            self.leaf_location::<HasherType, _>(key, &data)
+        }
+    }
+
+    /// Computes the location of a branch node with the given `zero` and `one` children, hashed in
+    /// the order given by `hash_order`.
+    #[inline]
+    pub fn branch_location<HasherType, ArrayType>(
+        &self,
+        zero: &ArrayType,
+        one: &ArrayType,
+    ) -> ArrayType
+    where
+        HasherType: Hasher<ArrayType, HashType = HasherType>,
+        ArrayType: Array,
+    {
+        let (first, second) = self.hash_order(zero, one);
+        let mut branch_hasher = self.start::<HasherType, ArrayType>(zero.as_ref().len());
+        branch_hasher.update(&self.tags.branch);
+        branch_hasher.update(first.as_ref());
+        branch_hasher.update(second.as_ref());
+        branch_hasher.finalize()
+    }
+}
@@ -0,0 +1,427 @@
+use std::convert::TryFrom;
+
+use crate::traits::{Array, Exception, Hasher};
+use crate::utils::hash_scheme::{HashScheme, COMBINED_LEAF_TAG};
+use crate::utils::proof::Proof;
+
+/// The release of the `cosmos/ics23` protobuf definitions (`proofs.proto`) the proofs and specs
+/// of this module are encoded against.
+pub const ICS23_SPEC_VERSION: &str = "0.9.0";
+
+/// The hash functions an ICS23 verifier can apply.  The operation named in a proof must be the
+/// one the tree's `Hasher` computes, with an output of the tree's key length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashOp {
+    /// No hashing.
+    NoHash,
+    /// SHA-256.
+    Sha256,
+    /// SHA-512.
+    Sha512,
+    /// Keccak-256.
+    Keccak,
+    /// RIPEMD-160.
+    Ripemd160,
+    /// RIPEMD-160 of SHA-256.
+    Bitcoin,
+    /// SHA-512/256.
+    Sha512256,
+    /// `BLAKE2b` with a 512 bit output.
+    Blake2b512,
+    /// `BLAKE2s` with a 256 bit output.
+    Blake2s256,
+    /// BLAKE3.
+    Blake3,
+}
+
+/// The length prefixes an ICS23 verifier can add before hashed data.  The tree hashes keys and
+/// values without a length prefix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LengthOp {
+    /// No length prefix.
+    NoPrefix,
+    /// A protobuf varint length prefix.
+    VarProto,
+    /// An RLP length prefix.
+    VarRlp,
+    /// A fixed 32 bit big endian length prefix.
+    Fixed32Big,
+    /// A fixed 32 bit little endian length prefix.
+    Fixed32Little,
+    /// A fixed 64 bit big endian length prefix.
+    Fixed64Big,
+    /// A fixed 64 bit little endian length prefix.
+    Fixed64Little,
+    /// No prefix, but the data must be exactly 32 bytes.
+    Require32Bytes,
+    /// No prefix, but the data must be exactly 64 bytes.
+    Require64Bytes,
+}
+
+impl From<HashOp> for u64 {
+    /// Gets the number of the operation in `proofs.proto`.
+    #[inline]
+    fn from(op: HashOp) -> Self {
+        match op {
+            HashOp::NoHash => 0,
+            HashOp::Sha256 => 1,
+            HashOp::Sha512 => 2,
+            HashOp::Keccak => 3,
+            HashOp::Ripemd160 => 4,
+            HashOp::Bitcoin => 5,
+            HashOp::Sha512256 => 6,
+            HashOp::Blake2b512 => 7,
+            HashOp::Blake2s256 => 8,
+            HashOp::Blake3 => 9,
+        }
+    }
+}
+
+impl From<LengthOp> for u64 {
+    /// Gets the number of the operation in `proofs.proto`.
+    #[inline]
+    fn from(op: LengthOp) -> Self {
+        match op {
+            LengthOp::NoPrefix => 0,
+            LengthOp::VarProto => 1,
+            LengthOp::VarRlp => 2,
+            LengthOp::Fixed32Big => 3,
+            LengthOp::Fixed32Little => 4,
+            LengthOp::Fixed64Big => 5,
+            LengthOp::Fixed64Little => 6,
+            LengthOp::Require32Bytes => 7,
+            LengthOp::Require64Bytes => 8,
+        }
+    }
+}
+
+/// How an ICS23 verifier hashes the key and value of a proof into a leaf:
+/// `hash(prefix || length(prehash_key(key)) || length(prehash_value(value)))`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LeafOp {
+    /// The hash applied to the whole leaf.
+    pub hash: HashOp,
+    /// The hash applied to the key before it is added to the leaf.
+    pub prehash_key: HashOp,
+    /// The hash applied to the value before it is added to the leaf.
+    pub prehash_value: HashOp,
+    /// The length prefix added before the key and the value.
+    pub length: LengthOp,
+    /// The bytes hashed before the key.
+    pub prefix: Vec<u8>,
+}
+
+/// How an ICS23 verifier hashes a child into its parent: `hash(prefix || child || suffix)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InnerOp {
+    /// The hash applied to the parent.
+    pub hash: HashOp,
+    /// The bytes hashed before the child.
+    pub prefix: Vec<u8>,
+    /// The bytes hashed after the child.
+    pub suffix: Vec<u8>,
+}
+
+/// An ICS23 existence proof for a key of a `MerkleBIT`.
+///
+/// The leaf of a key commits to the location of its data node rather than to its value, so the
+/// `value` of the proof is that location (`HashScheme::data_location` of the key and encoded
+/// value).  A verifier checking membership of a value must compare against the data location of
+/// the value, not the value itself.  A combined leaf commits to the value directly, so under a
+/// scheme combining leaves the `value` of the proof is the encoded value.  Each inner operation
+/// places the sibling of a branch in the prefix or suffix, following the path from the leaf to the
+/// root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExistenceProof {
+    /// The key proven to exist.
+    pub key: Vec<u8>,
+    /// The location of the data node of the key, or its encoded value for combined leaves.
+    pub value: Vec<u8>,
+    /// How the leaf is hashed.
+    pub leaf: LeafOp,
+    /// How each branch is hashed, from the leaf to the root.
+    pub path: Vec<InnerOp>,
+}
+
+impl ExistenceProof {
+    /// Maps `proof` onto an ICS23 existence proof for a tree hashing with `scheme`, whose
+    /// `HasherType` computes `hash`.
+    #[inline]
+    pub fn from_proof<HasherType, ArrayType>(
+        proof: &Proof<ArrayType>,
+        scheme: &HashScheme,
+        hash: HashOp,
+    ) -> Self
+    where
+        HasherType: Hasher<ArrayType, HashType = HasherType>,
+        ArrayType: Array,
+    {
+        let key = proof.key();
+        let value = if scheme.combined_leaves() {
+            proof.value().to_vec()
+        } else {
+            scheme
+                .data_location::<HasherType, _>(key, proof.value())
+                .as_ref()
+                .to_vec()
+        };
+
+        let mut branch_prefix = scheme.domain_prefix().to_vec();
+        branch_prefix.extend_from_slice(scheme.tags().branch());
+
+        // With sorted children the side of each sibling depends on the locations on the path, so
+        // the path is recomputed from the leaf up
+        let mut current = scheme.leaf_location_of_value::<HasherType, _>(key, proof.value());
+        let path = proof
+            .siblings()
+            .iter()
+            .map(|sibling| {
+                let (zero, one) = if sibling.direction {
+                    (current, sibling.location)
+                } else {
+                    (sibling.location, current)
+                };
+                let sibling_first = if scheme.sorted_children() {
+                    sibling.location < current
+                } else {
+                    !sibling.direction
+                };
+                current = scheme.branch_location::<HasherType, _>(&zero, &one);
+                if sibling_first {
+                    let mut prefix = branch_prefix.clone();
+                    prefix.extend_from_slice(sibling.location.as_ref());
+                    InnerOp {
+                        hash,
+                        prefix,
+                        suffix: Vec::new(),
+                    }
+                } else {
+                    InnerOp {
+                        hash,
+                        prefix: branch_prefix.clone(),
+                        suffix: sibling.location.as_ref().to_vec(),
+                    }
+                }
+            })
+            .collect();
+
+        Self {
+            key: key.as_ref().to_vec(),
+            value,
+            leaf: leaf_op(scheme, hash),
+            path,
+        }
+    }
+
+    /// Computes the root the proof commits to with `HasherType`, applying the operations the way
+    /// an ICS23 verifier does.  Only the operations produced by `from_proof` are supported:
+    /// leaves without prehashing or length prefixes.
+    /// # Errors
+    /// `Exception` generated if the leaf prehashes or length prefixes its key or value.
+    #[inline]
+    pub fn calculate_root<HasherType, ArrayType>(&self) -> Result<ArrayType, Exception>
+    where
+        HasherType: Hasher<ArrayType, HashType = HasherType>,
+        ArrayType: Array,
+    {
+        if self.leaf.prehash_key != HashOp::NoHash
+            || self.leaf.prehash_value != HashOp::NoHash
+            || self.leaf.length != LengthOp::NoPrefix
+        {
+            return Err(Exception::new(
+                "Unsupported ICS23 leaf operation: prehashing and length prefixes are not used",
+            ));
+        }
+
+        let size = ArrayType::default().as_ref().len();
+        let mut leaf_hasher = HasherType::new(size);
+        leaf_hasher.update(&self.leaf.prefix);
+        leaf_hasher.update(&self.key);
+        leaf_hasher.update(&self.value);
+        let mut current = leaf_hasher.finalize();
+
+        for inner in &self.path {
+            let mut inner_hasher = HasherType::new(size);
+            inner_hasher.update(&inner.prefix);
+            inner_hasher.update(current.as_ref());
+            inner_hasher.update(&inner.suffix);
+            current = inner_hasher.finalize();
+        }
+        Ok(current)
+    }
+
+    /// Encodes the proof as an ICS23 `ExistenceProof` protobuf message.
+    /// # Errors
+    /// `Exception` generated if a field is too long to encode.
+    #[inline]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Exception> {
+        let mut bytes = Vec::new();
+        put_bytes(&mut bytes, 1, &self.key)?;
+        put_bytes(&mut bytes, 2, &self.value)?;
+        put_message(&mut bytes, 3, &encode_leaf_op(&self.leaf)?)?;
+        for inner in &self.path {
+            let mut inner_bytes = Vec::new();
+            put_varint_field(&mut inner_bytes, 1, u64::from(inner.hash));
+            put_bytes(&mut inner_bytes, 2, &inner.prefix)?;
+            put_bytes(&mut inner_bytes, 3, &inner.suffix)?;
+            put_message(&mut bytes, 4, &inner_bytes)?;
+        }
+        Ok(bytes)
+    }
+}
+
+/// The layout of the branches of a tree, as an ICS23 verifier checks the inner operations of a
+/// proof against it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InnerSpec {
+    /// The order the children of a branch are hashed in.
+    pub child_order: Vec<i32>,
+    /// The size of each child.
+    pub child_size: i32,
+    /// The shortest prefix of an inner operation.
+    pub min_prefix_length: i32,
+    /// The longest prefix of an inner operation.
+    pub max_prefix_length: i32,
+    /// The placeholder of a missing child, empty since every branch has both children.
+    pub empty_child: Vec<u8>,
+    /// The hash applied to each branch.
+    pub hash: HashOp,
+}
+
+/// An ICS23 `ProofSpec` describing the proofs of a tree, which a verifier checks proofs against
+/// before computing their root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofSpec {
+    /// The expected leaf operation.
+    pub leaf_spec: LeafOp,
+    /// The expected layout of the branches.
+    pub inner_spec: InnerSpec,
+    /// The most inner operations in a proof, or zero for no limit.
+    pub max_depth: i32,
+    /// The fewest inner operations in a proof.
+    pub min_depth: i32,
+}
+
+impl ProofSpec {
+    /// Creates the `ProofSpec` of a tree with `ArrayType` locations hashing with `scheme`, whose
+    /// `HasherType` computes `hash`.  The tags of the scheme must differ in their first byte, so a
+    /// verifier can tell a branch prefix from the leaf prefix.
+    /// # Errors
+    /// `Exception` generated if the location size or the prefixes are too long for the spec.
+    #[inline]
+    pub fn for_scheme<ArrayType>(scheme: &HashScheme, hash: HashOp) -> Result<Self, Exception>
+    where
+        ArrayType: Array,
+    {
+        let child_size = ArrayType::default().as_ref().len();
+        let min_prefix_length = scheme.domain_prefix().len() + scheme.tags().branch().len();
+        Ok(Self {
+            leaf_spec: leaf_op(scheme, hash),
+            inner_spec: InnerSpec {
+                child_order: vec![0, 1],
+                child_size: i32::try_from(child_size)?,
+                min_prefix_length: i32::try_from(min_prefix_length)?,
+                max_prefix_length: i32::try_from(min_prefix_length + child_size)?,
+                empty_child: Vec::new(),
+                hash,
+            },
+            max_depth: 0,
+            min_depth: 0,
+        })
+    }
+
+    /// Encodes the spec as an ICS23 `ProofSpec` protobuf message.
+    /// # Errors
+    /// `Exception` generated if a length in the spec is negative, or a field is too long to encode.
+    #[inline]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Exception> {
+        let inner = &self.inner_spec;
+        let mut packed_order = Vec::new();
+        for &child in &inner.child_order {
+            put_varint(&mut packed_order, u64::try_from(child)?);
+        }
+        let mut inner_bytes = Vec::new();
+        put_bytes(&mut inner_bytes, 1, &packed_order)?;
+        put_varint_field(&mut inner_bytes, 2, u64::try_from(inner.child_size)?);
+        put_varint_field(&mut inner_bytes, 3, u64::try_from(inner.min_prefix_length)?);
+        put_varint_field(&mut inner_bytes, 4, u64::try_from(inner.max_prefix_length)?);
+        put_bytes(&mut inner_bytes, 5, &inner.empty_child)?;
+        put_varint_field(&mut inner_bytes, 6, u64::from(inner.hash));
+
+        let mut bytes = Vec::new();
+        put_message(&mut bytes, 1, &encode_leaf_op(&self.leaf_spec)?)?;
+        put_message(&mut bytes, 2, &inner_bytes)?;
+        put_varint_field(&mut bytes, 3, u64::try_from(self.max_depth)?);
+        put_varint_field(&mut bytes, 4, u64::try_from(self.min_depth)?);
+        Ok(bytes)
+    }
+}
+
+/// The leaf operation of a tree hashing with `scheme`: the domain prefix and leaf tag followed by
+/// the key and the data location (or the value, for combined leaves), none of them prehashed or
+/// length prefixed.
+fn leaf_op(scheme: &HashScheme, hash: HashOp) -> LeafOp {
+    let mut prefix = scheme.domain_prefix().to_vec();
+    if scheme.combined_leaves() {
+        prefix.extend_from_slice(COMBINED_LEAF_TAG);
+    } else {
+        prefix.extend_from_slice(scheme.tags().leaf());
+    }
+    LeafOp {
+        hash,
+        prehash_key: HashOp::NoHash,
+        prehash_value: HashOp::NoHash,
+        length: LengthOp::NoPrefix,
+        prefix,
+    }
+}
+
+/// Encodes `leaf` as an ICS23 `LeafOp` protobuf message.
+/// # Errors
+/// `Exception` generated if the prefix is too long to encode.
+fn encode_leaf_op(leaf: &LeafOp) -> Result<Vec<u8>, Exception> {
+    let mut bytes = Vec::new();
+    put_varint_field(&mut bytes, 1, u64::from(leaf.hash));
+    put_varint_field(&mut bytes, 2, u64::from(leaf.prehash_key));
+    put_varint_field(&mut bytes, 3, u64::from(leaf.prehash_value));
+    put_varint_field(&mut bytes, 4, u64::from(leaf.length));
+    put_bytes(&mut bytes, 5, &leaf.prefix)?;
+    Ok(bytes)
+}
+
+/// Appends `value` as a protobuf varint.
+fn put_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value.to_le_bytes()[0] | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value.to_le_bytes()[0]);
+}
+
+/// Appends a varint field, omitted when `value` is the proto3 default of zero.
+fn put_varint_field(bytes: &mut Vec<u8>, field: u64, value: u64) {
+    if value != 0 {
+        put_varint(bytes, field << 3);
+        put_varint(bytes, value);
+    }
+}
+
+/// Appends a length delimited bytes field, omitted when `value` is the proto3 default of empty.
+/// # Errors
+/// `Exception` generated if `value` is too long to encode.
+fn put_bytes(bytes: &mut Vec<u8>, field: u64, value: &[u8]) -> Result<(), Exception> {
+    if !value.is_empty() {
+        put_message(bytes, field, value)?;
+    }
+    Ok(())
+}
+
+/// Appends an embedded message field, which is kept even when the message is empty.
+/// # Errors
+/// `Exception` generated if `message` is too long to encode.
+fn put_message(bytes: &mut Vec<u8>, field: u64, message: &[u8]) -> Result<(), Exception> {
+    put_varint(bytes, (field << 3) | 2);
+    put_varint(bytes, u64::try_from(message.len())?);
+    bytes.extend_from_slice(message);
+    Ok(())
+}
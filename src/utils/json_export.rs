@@ -0,0 +1,230 @@
+use std::convert::TryFrom;
+
+use crate::traits::Exception;
+
+/// The alphabet of standard, padded base64.
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Each key read back from a JSON export, with its encoded value.
+pub type JsonEntries = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// A tree read back from its JSON export: the recorded root, and each key with its encoded value.
+pub type JsonExport = (Vec<u8>, JsonEntries);
+
+/// Encodes `bytes` as standard, padded base64.
+#[inline]
+#[must_use]
+pub fn to_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let indices = [
+            b[0] >> 2,
+            ((b[0] & 0x03) << 4) | (b[1] >> 4),
+            ((b[1] & 0x0F) << 2) | (b[2] >> 6),
+            b[2] & 0x3F,
+        ];
+        for (i, &index) in indices.iter().enumerate() {
+            if i <= chunk.len() {
+                encoded.push(char::from(BASE64_ALPHABET[usize::from(index)]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes standard, padded base64.
+/// # Errors
+/// `Exception` generated if `encoded` is not valid padded base64.
+#[inline]
+pub fn from_base64(encoded: &str) -> Result<Vec<u8>, Exception> {
+    let encoded = encoded.as_bytes();
+    if encoded.len() % 4 != 0 {
+        return Err(Exception::new(
+            "Invalid base64: length is not a multiple of 4",
+        ));
+    }
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let chunks = encoded.len() / 4;
+    for (n, chunk) in encoded.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && n + 1 != chunks) {
+            return Err(Exception::new("Invalid base64: misplaced padding"));
+        }
+        let mut sextets = [0_u8; 4];
+        for (i, &c) in chunk[..4 - padding].iter().enumerate() {
+            sextets[i] = match BASE64_ALPHABET.iter().position(|&a| a == c) {
+                Some(position) => u8::try_from(position)?,
+                None => return Err(Exception::new("Invalid base64: unexpected character")),
+            };
+        }
+        let bytes = [
+            (sextets[0] << 2) | (sextets[1] >> 4),
+            ((sextets[1] & 0x0F) << 4) | (sextets[2] >> 2),
+            ((sextets[2] & 0x03) << 6) | sextets[3],
+        ];
+        decoded.extend_from_slice(&bytes[..3 - padding]);
+    }
+    Ok(decoded)
+}
+
+/// Decodes lowercase or uppercase hexadecimal, two digits per byte.
+/// # Errors
+/// `Exception` generated if `encoded` is not valid hexadecimal.
+#[inline]
+pub fn from_hex(encoded: &str) -> Result<Vec<u8>, Exception> {
+    if encoded.len() % 2 != 0 {
+        return Err(Exception::new("Invalid hex: odd number of digits"));
+    }
+    let mut decoded = Vec::with_capacity(encoded.len() / 2);
+    for pair in encoded.as_bytes().chunks(2) {
+        let digits =
+            std::str::from_utf8(pair).map_err(|e| Exception::new(&format!("Invalid hex: {e}")))?;
+        decoded.push(
+            u8::from_str_radix(digits, 16)
+                .map_err(|e| Exception::new(&format!("Invalid hex: {e}")))?,
+        );
+    }
+    Ok(decoded)
+}
+
+/// Parses the JSON export written by `MerkleBIT::write_json`: an object with a `root` string
+/// holding the hex root hash, and an `entries` object mapping hex keys to base64 encoded values.
+/// Whitespace between tokens is allowed, but string escapes are not, since hex and base64 never
+/// need them.
+/// # Errors
+/// `Exception` generated if `json` is not a valid export.
+#[inline]
+pub fn parse_json_export(json: &str) -> Result<JsonExport, Exception> {
+    let mut cursor = JsonCursor {
+        bytes: json.as_bytes(),
+        position: 0,
+    };
+    let mut root = None;
+    let mut entries = None;
+
+    cursor.expect(b'{')?;
+    if !cursor.consume(b'}') {
+        loop {
+            match cursor.string()? {
+                "root" => {
+                    cursor.expect(b':')?;
+                    root = Some(from_hex(cursor.string()?)?);
+                }
+                "entries" => {
+                    cursor.expect(b':')?;
+                    entries = Some(cursor.entries()?);
+                }
+                _ => return Err(Exception::new("Invalid JSON export: unexpected field")),
+            }
+            if cursor.consume(b'}') {
+                break;
+            }
+            cursor.expect(b',')?;
+        }
+    }
+    cursor.skip_whitespace();
+    if cursor.position != cursor.bytes.len() {
+        return Err(Exception::new("Invalid JSON export: trailing characters"));
+    }
+
+    match (root, entries) {
+        (Some(r), Some(e)) => Ok((r, e)),
+        _ => Err(Exception::new(
+            "Invalid JSON export: missing root or entries",
+        )),
+    }
+}
+
+/// A position in the JSON being parsed.
+struct JsonCursor<'a> {
+    /// The JSON being parsed.
+    bytes: &'a [u8],
+    /// The offset of the next unread byte.
+    position: usize,
+}
+
+impl<'a> JsonCursor<'a> {
+    /// Skips whitespace up to the next token.
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.position += 1;
+        }
+    }
+
+    /// Consumes the next token if it is `token`, returning whether it was.
+    fn consume(&mut self, token: u8) -> bool {
+        self.skip_whitespace();
+        if self.bytes.get(self.position) == Some(&token) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Consumes the next token, which must be `token`.
+    /// # Errors
+    /// `Exception` generated if the next token is not `token`.
+    fn expect(&mut self, token: u8) -> Result<(), Exception> {
+        if self.consume(token) {
+            return Ok(());
+        }
+        Err(Exception::new(&format!(
+            "Invalid JSON export: expected '{}' at offset {}",
+            char::from(token),
+            self.position
+        )))
+    }
+
+    /// Consumes a string without escapes, returning its contents.
+    /// # Errors
+    /// `Exception` generated if the next token is not such a string.
+    fn string(&mut self) -> Result<&'a str, Exception> {
+        self.expect(b'"')?;
+        let start = self.position;
+        while let Some(&c) = self.bytes.get(self.position) {
+            match c {
+                b'"' => {
+                    self.position += 1;
+                    return std::str::from_utf8(&self.bytes[start..self.position - 1])
+                        .map_err(|e| Exception::new(&format!("Invalid JSON export: {e}")));
+                }
+                b'\\' => return Err(Exception::new("Invalid JSON export: unexpected escape")),
+                _ => self.position += 1,
+            }
+        }
+        Err(Exception::new("Invalid JSON export: unterminated string"))
+    }
+
+    /// Consumes the `entries` object, decoding each key and value.
+    /// # Errors
+    /// `Exception` generated if the next token is not a valid `entries` object.
+    fn entries(&mut self) -> Result<JsonEntries, Exception> {
+        let mut entries = Vec::new();
+        self.expect(b'{')?;
+        if self.consume(b'}') {
+            return Ok(entries);
+        }
+        loop {
+            let key = from_hex(self.string()?)?;
+            self.expect(b':')?;
+            let value = from_base64(self.string()?)?;
+            entries.push((key, value));
+            if self.consume(b'}') {
+                return Ok(entries);
+            }
+            self.expect(b',')?;
+        }
+    }
+}
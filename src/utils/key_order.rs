@@ -0,0 +1,45 @@
+use crate::traits::{Array, KeyOrder};
+use crate::utils::tree_utils::sort_keys;
+
+/// The default `KeyOrder`, arranging keys in byte order: the bits of each key are taken from the
+/// most significant bit of its first byte, as a big-endian number.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BigEndianOrder;
+
+impl<ArrayType> KeyOrder<ArrayType> for BigEndianOrder
+where
+    ArrayType: Array,
+{
+    #[inline]
+    fn order_key(key: &ArrayType) -> ArrayType {
+        *key
+    }
+
+    #[inline]
+    fn compare(a: &ArrayType, b: &ArrayType) -> std::cmp::Ordering {
+        a.cmp(b)
+    }
+
+    #[inline]
+    fn sort_keys(keys: &mut [ArrayType]) {
+        sort_keys(keys);
+    }
+}
+
+/// A `KeyOrder` arranging keys in descending byte order, by inverting every bit of the key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReversedOrder;
+
+impl<ArrayType> KeyOrder<ArrayType> for ReversedOrder
+where
+    ArrayType: Array,
+{
+    #[inline]
+    fn order_key(key: &ArrayType) -> ArrayType {
+        let mut inverted = *key;
+        for byte in inverted.as_mut() {
+            *byte = !*byte;
+        }
+        inverted
+    }
+}
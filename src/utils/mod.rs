@@ -1,5 +1,23 @@
+/// Holds the `HashScheme` struct
+pub mod hash_scheme;
+/// Holds the ICS23 proof adapter
+pub mod ics23;
+/// Holds the reader of the JSON export of a tree
+pub mod json_export;
+/// Holds the `KeyOrder` implementations
+pub mod key_order;
+/// Holds the `Proof` struct
+pub mod proof;
+/// Holds the `SharedValue` struct
+pub mod shared_value;
+/// Holds the `Snapshot` struct
+pub mod snapshot;
+/// Holds the `TreeBuildScratch` struct
+pub mod tree_build_scratch;
 /// Holds the `TreeCell` struct
 pub mod tree_cell;
+/// Holds the `TreeOptions` struct
+pub mod tree_options;
 /// Holds the `TreeRef` struct
 pub mod tree_ref;
 /// Holds a collection of useful functions for tree operations
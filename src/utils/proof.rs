@@ -0,0 +1,246 @@
+use std::convert::TryFrom;
+
+use crate::traits::{Array, Exception};
+
+/// The current version of the `Proof` byte layout.
+pub const PROOF_VERSION: u8 = 1;
+
+/// A sibling encountered on the path from a leaf to the root of the tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProofSibling<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// The location of the sibling node.
+    pub location: ArrayType,
+    /// True if the path to the leaf takes the zero branch, meaning the sibling is the one branch.
+    pub direction: bool,
+    /// The split index of the branch joining the path and the sibling.
+    pub split_index: usize,
+}
+
+/// A self contained inclusion proof for a single key, with a stable binary encoding.
+///
+/// Version 1 of the layout is, with all integers big endian:
+/// - the version byte
+/// - the key length as a `u8`, followed by the key
+/// - the encoded value length as a `u32`, followed by the encoded value
+/// - the number of siblings as a `u16`, followed by each sibling from the leaf to the root as a
+///   direction byte (`1` for the zero branch, `0` for the one branch), its split index as a
+///   `u16` and its location
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proof<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// The key this proof is for.
+    key: ArrayType,
+    /// The encoded value stored under the key.
+    value: Vec<u8>,
+    /// The siblings on the path from the leaf to the root.
+    siblings: Vec<ProofSibling<ArrayType>>,
+}
+
+impl<ArrayType> Proof<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// Creates a new `Proof`.
+    #[inline]
+    #[must_use]
+    pub fn new(key: ArrayType, value: Vec<u8>, siblings: Vec<ProofSibling<ArrayType>>) -> Self {
+        Self {
+            key,
+            value,
+            siblings,
+        }
+    }
+
+    /// Gets the key this proof is for.
+    #[inline]
+    pub const fn key(&self) -> &ArrayType {
+        &self.key
+    }
+
+    /// Gets the encoded value this proof is for.
+    #[inline]
+    #[must_use]
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    /// Gets the siblings on the path from the leaf to the root.
+    #[inline]
+    #[must_use]
+    pub fn siblings(&self) -> &[ProofSibling<ArrayType>] {
+        &self.siblings
+    }
+
+    /// Encodes the proof into its binary layout.
+    /// # Errors
+    /// `Exception` generated if the key, value, sibling count or a split index is too large for
+    /// the layout.
+    #[inline]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Exception> {
+        let key = self.key.as_ref();
+        let key_len = u8::try_from(key.len())?;
+        let value_len = u32::try_from(self.value.len())?;
+        let sibling_count = u16::try_from(self.siblings.len())?;
+
+        let mut bytes = Vec::with_capacity(
+            8 + key.len() + self.value.len() + self.siblings.len() * (3 + key.len()),
+        );
+        bytes.push(PROOF_VERSION);
+        bytes.push(key_len);
+        bytes.extend_from_slice(key);
+        bytes.extend_from_slice(&value_len.to_be_bytes());
+        bytes.extend_from_slice(&self.value);
+        bytes.extend_from_slice(&sibling_count.to_be_bytes());
+        for sibling in &self.siblings {
+            bytes.push(u8::from(sibling.direction));
+            bytes.extend_from_slice(&u16::try_from(sibling.split_index)?.to_be_bytes());
+            bytes.extend_from_slice(sibling.location.as_ref());
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes a proof from its binary layout.
+    /// # Errors
+    /// `Exception` generated if the version is unknown, or the bytes are truncated, have trailing
+    /// data or do not match the key length of `ArrayType`.
+    #[inline]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Exception> {
+        let mut reader = ProofReader { bytes, offset: 0 };
+
+        let version = reader.take(1)?[0];
+        if version != PROOF_VERSION {
+            return Err(Exception::new("Unsupported proof version"));
+        }
+
+        let key_len = usize::from(reader.take(1)?[0]);
+        let key = reader.take_array::<ArrayType>(key_len)?;
+
+        let mut value_len = [0_u8; 4];
+        value_len.copy_from_slice(reader.take(4)?);
+        let value = reader.take(usize::try_from(u32::from_be_bytes(value_len))?)?;
+
+        let mut sibling_count = [0_u8; 2];
+        sibling_count.copy_from_slice(reader.take(2)?);
+        let sibling_count = usize::from(u16::from_be_bytes(sibling_count));
+
+        // Avoid trusting the count for the allocation size until the bytes are known to exist
+        let mut siblings = Vec::with_capacity(sibling_count.min(bytes.len() / (3 + key_len)));
+        for _ in 0..sibling_count {
+            let direction = match reader.take(1)?[0] {
+                0 => false,
+                1 => true,
+                _ => return Err(Exception::new("Invalid proof sibling direction")),
+            };
+            let mut split_index = [0_u8; 2];
+            split_index.copy_from_slice(reader.take(2)?);
+            let location = reader.take_array::<ArrayType>(key_len)?;
+            siblings.push(ProofSibling {
+                location,
+                direction,
+                split_index: usize::from(u16::from_be_bytes(split_index)),
+            });
+        }
+
+        if reader.offset != bytes.len() {
+            return Err(Exception::new("Trailing bytes after proof"));
+        }
+
+        Ok(Self::new(key, value.to_vec(), siblings))
+    }
+}
+
+/// A two-level inclusion proof for a key of a subtree whose root is committed as the value of a
+/// key in a global tree, as built by `commit_subtrees`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubtreeProof<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// The proof of the subtree root under its key in the global tree.
+    global: Proof<ArrayType>,
+    /// The proof of the key in the subtree.
+    subtree: Proof<ArrayType>,
+}
+
+impl<ArrayType> SubtreeProof<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// Creates a new `SubtreeProof` from the proof of a subtree root in the global tree and the
+    /// proof of a key in that subtree.
+    #[inline]
+    #[must_use]
+    pub const fn new(global: Proof<ArrayType>, subtree: Proof<ArrayType>) -> Self {
+        Self { global, subtree }
+    }
+
+    /// Gets the proof of the subtree root under its key in the global tree.
+    #[inline]
+    pub const fn global(&self) -> &Proof<ArrayType> {
+        &self.global
+    }
+
+    /// Gets the proof of the key in the subtree.
+    #[inline]
+    pub const fn subtree(&self) -> &Proof<ArrayType> {
+        &self.subtree
+    }
+
+    /// Gets the subtree root the global proof commits to, which the subtree proof must lead to.
+    /// # Errors
+    /// `Exception` generated if the value of the global proof is not a root of `ArrayType`'s
+    /// length.
+    #[inline]
+    pub fn subtree_root(&self) -> Result<ArrayType, Exception> {
+        committed_root(self.global.value())
+    }
+}
+
+/// Reads the subtree root committed as the encoded `value` of a key of a global tree.
+/// # Errors
+/// `Exception` generated if `value` is not a root of `ArrayType`'s length.
+pub(crate) fn committed_root<ArrayType: Array>(value: &[u8]) -> Result<ArrayType, Exception> {
+    let mut root = ArrayType::default();
+    if root.as_ref().len() != value.len() {
+        return Err(Exception::new("Committed value is not a subtree root"));
+    }
+    root.as_mut().copy_from_slice(value);
+    Ok(root)
+}
+
+/// Tracks the position while decoding a `Proof`.
+struct ProofReader<'a> {
+    /// The bytes being decoded.
+    bytes: &'a [u8],
+    /// The position of the next unread byte.
+    offset: usize,
+}
+
+impl<'a> ProofReader<'a> {
+    /// Takes the next `len` bytes.
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Exception> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| Exception::new("Proof is truncated"))?;
+        let taken = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(taken)
+    }
+
+    /// Takes the next `len` bytes as an `ArrayType`.
+    fn take_array<ArrayType: Array>(&mut self, len: usize) -> Result<ArrayType, Exception> {
+        let mut array = ArrayType::default();
+        if array.as_ref().len() != len {
+            return Err(Exception::new("Proof key length does not match"));
+        }
+        array.as_mut().copy_from_slice(self.take(len)?);
+        Ok(array)
+    }
+}
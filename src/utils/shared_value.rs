@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use crate::traits::{Array, Data, Exception, Leaf, NodeVariant};
+use crate::tree::tree_node::TreeNode;
+
+/// A shared handle to the value of a data node, or of a leaf holding an inlined value, held by an
+/// in-memory database.  Cloning it only bumps a reference count, and the value is read in place
+/// rather than copied out of the node.
+#[derive(Clone, Debug)]
+pub struct SharedValue<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// The shared data or leaf node holding the value.
+    node: Arc<TreeNode<ArrayType>>,
+}
+
+impl<ArrayType> SharedValue<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// Creates a new `SharedValue` from a shared data node, or a leaf node with an inlined value.
+    /// # Errors
+    /// `Exception` generated if `node` is neither a data node nor a leaf with an inlined value, or
+    /// if its value is held in a `BlobStore`.
+    #[inline]
+    pub fn new(node: Arc<TreeNode<ArrayType>>) -> Result<Self, Exception> {
+        if holds_value(&node) {
+            Ok(Self { node })
+        } else {
+            Err(Exception::new(
+                "Corrupt merkle tree: Found non data node after leaf",
+            ))
+        }
+    }
+}
+
+impl<ArrayType> AsRef<[u8]> for SharedValue<ArrayType>
+where
+    ArrayType: Array,
+{
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        match &self.node.node {
+            NodeVariant::Data(data) => data.get_value(),
+            NodeVariant::Leaf(leaf) => leaf.get_inline_value().unwrap_or(&[]),
+            NodeVariant::Branch(_) | NodeVariant::Phantom(_) => &[],
+        }
+    }
+}
+
+/// A node held by an in-memory database.  Only the nodes holding a value are put behind an `Arc`
+/// for `SharedValue`s to share, so storing a branch or a leaf without an inlined value costs no
+/// more than storing the node itself.
+#[derive(Debug)]
+pub(crate) enum StoredNode<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// A node without a value.
+    Owned(TreeNode<ArrayType>),
+    /// A data node, or a leaf with an inlined value.
+    Shared(Arc<TreeNode<ArrayType>>),
+}
+
+impl<ArrayType> StoredNode<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// Creates a new `StoredNode`, sharing `node` only if it holds a value.
+    pub(crate) fn new(node: TreeNode<ArrayType>) -> Self {
+        if holds_value(&node) {
+            Self::Shared(Arc::new(node))
+        } else {
+            Self::Owned(node)
+        }
+    }
+
+    /// Gets the stored node.
+    pub(crate) fn node(&self) -> &TreeNode<ArrayType> {
+        match self {
+            Self::Owned(node) => node,
+            Self::Shared(node) => node,
+        }
+    }
+
+    /// Gets a `SharedValue` over the value of the stored node.
+    /// # Errors
+    /// `Exception` generated if the node does not hold a value.
+    pub(crate) fn value_ref(&self) -> Result<SharedValue<ArrayType>, Exception> {
+        match self {
+            Self::Shared(node) => SharedValue::new(Arc::clone(node)),
+            Self::Owned(_) => Err(Exception::new(
+                "Corrupt merkle tree: Found non data node after leaf",
+            )),
+        }
+    }
+}
+
+/// Checks whether `node` is a data node, or a leaf with an inlined value, whose value is held in
+/// the node rather than in a `BlobStore`.
+fn holds_value<ArrayType>(node: &TreeNode<ArrayType>) -> bool
+where
+    ArrayType: Array,
+{
+    match &node.node {
+        NodeVariant::Data(data) => !data.is_blob_ref(),
+        NodeVariant::Leaf(leaf) => leaf.get_inline_value().is_some(),
+        NodeVariant::Branch(_) | NodeVariant::Phantom(_) => false,
+    }
+}
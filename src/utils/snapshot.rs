@@ -0,0 +1,71 @@
+use std::sync::{Arc, Weak};
+
+use crate::traits::Array;
+
+/// A handle pinning a root of the tree.  While a `Snapshot` (or any of its clones) is alive, the
+/// nodes reachable from its root will not be removed, even if the root itself is removed from the tree.
+///
+/// A weak `Snapshot` does not pin its root.  Instead it records the removal epoch of the tree it
+/// was taken from, so reading it after its root has been reclaimed fails with
+/// `ErrorKind::SnapshotExpired` rather than returning incomplete results.
+#[derive(Clone, Debug)]
+pub struct Snapshot<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// The root pinned by this snapshot.
+    root: ArrayType,
+    /// Shared marker whose lifetime determines how long the pin is held.
+    token: Arc<()>,
+    /// The removal epoch of the tree when a weak snapshot was taken, or `None` for a pinned one.
+    epoch: Option<u64>,
+}
+
+impl<ArrayType> Snapshot<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// Creates a new `Snapshot`.
+    #[inline]
+    pub(crate) fn new(root: ArrayType) -> Self {
+        Self {
+            root,
+            token: Arc::new(()),
+            epoch: None,
+        }
+    }
+
+    /// Creates a new weak `Snapshot`, taken at the given removal epoch.
+    #[inline]
+    pub(crate) fn new_weak(root: ArrayType, epoch: u64) -> Self {
+        Self {
+            root,
+            token: Arc::new(()),
+            epoch: Some(epoch),
+        }
+    }
+
+    /// Gets a weak reference to the snapshot marker, used for detecting when the snapshot is dropped.
+    #[inline]
+    pub(crate) fn pin(&self) -> Weak<()> {
+        Arc::downgrade(&self.token)
+    }
+
+    /// Gets the root pinned by this snapshot.
+    #[inline]
+    pub const fn root(&self) -> &ArrayType {
+        &self.root
+    }
+
+    /// Checks whether this snapshot is weak, leaving its root free to be reclaimed.
+    #[inline]
+    pub const fn is_weak(&self) -> bool {
+        self.epoch.is_some()
+    }
+
+    /// Gets the removal epoch a weak snapshot was taken at.
+    #[inline]
+    pub(crate) const fn epoch(&self) -> Option<u64> {
+        self.epoch
+    }
+}
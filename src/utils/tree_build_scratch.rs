@@ -0,0 +1,80 @@
+#[cfg(not(any(feature = "use_hashbrown")))]
+use std::collections::HashMap;
+
+#[cfg(feature = "use_hashbrown")]
+use hashbrown::HashMap;
+
+use crate::traits::Array;
+use crate::utils::tree_ref::TreeRef;
+
+/// Working memory for building a tree out of `TreeRef`s, which can be reused across inserts.
+///
+/// Building a tree from a batch needs a list of every new and shared `TreeRef`, and a list of the
+/// adjacent pairs to merge at each split index, both as large as the batch.  Passing the same
+/// `TreeBuildScratch` to a series of inserts (see `MerkleBIT::insert_with_scratch`) keeps these
+/// allocations between them, so a loop of large inserts doesn't reallocate them every time.
+#[derive(Clone, Debug, Default)]
+pub struct TreeBuildScratch<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// The `TreeRef`s being merged into a tree.
+    pub(crate) tree_refs: Vec<TreeRef<ArrayType>>,
+    /// The pairs of adjacent `TreeRef`s to merge, grouped by split index.  The lists are emptied
+    /// rather than removed after each build so that their allocations are kept.
+    pub(crate) levels: HashMap<usize, Vec<(usize, usize, usize)>>,
+}
+
+impl<ArrayType> TreeBuildScratch<ArrayType>
+where
+    ArrayType: Array,
+{
+    /// Creates a new, empty `TreeBuildScratch`.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            tree_refs: Vec::new(),
+            levels: HashMap::new(),
+        }
+    }
+
+    /// Creates a new `TreeBuildScratch` with room for building a tree of `capacity` `TreeRef`s
+    /// before it reallocates.
+    #[inline]
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            tree_refs: Vec::with_capacity(capacity),
+            levels: HashMap::new(),
+        }
+    }
+
+    /// Gets the number of `TreeRef`s that can be held before reallocating.
+    #[inline]
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.tree_refs.capacity()
+    }
+
+    /// Empties the scratch for the next build, keeping its allocations.
+    pub(crate) fn clear(&mut self) {
+        self.tree_refs.clear();
+        for level in self.levels.values_mut() {
+            level.clear();
+        }
+    }
+}
+
+impl<ArrayType> From<Vec<TreeRef<ArrayType>>> for TreeBuildScratch<ArrayType>
+where
+    ArrayType: Array,
+{
+    #[inline]
+    fn from(tree_refs: Vec<TreeRef<ArrayType>>) -> Self {
+        Self {
+            tree_refs,
+            levels: HashMap::new(),
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use crate::utils::hash_scheme::HashScheme;
+
+/// The checks an insert makes before writing, grouped apart from the other options.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct InsertChecks {
+    /// If true, inserting on top of a previous root missing from the database fails.
+    previous_root: bool,
+    /// If true, inserting no keys fails.
+    empty_inserts: bool,
+    /// If true, inserts check that any node already stored at a location they write to matches
+    /// the node being written.
+    collisions: bool,
+}
+
+impl Default for InsertChecks {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            previous_root: true,
+            empty_inserts: true,
+            collisions: false,
+        }
+    }
+}
+
+/// The configuration of a `MerkleBIT`: its `HashScheme` and the settings chosen with its setters.
+///
+/// A tree keeps its options together so they can be handed to a tree opened later or to a
+/// `MerkleBITReader`, which must compute locations and read values the same way as the tree that
+/// wrote them.  None of the options are recorded in the database.  Each setter is described by
+/// the `MerkleBIT` setter of the same name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeOptions {
+    /// The scheme used for computing node locations.
+    scheme: HashScheme,
+    /// The maximum size of an encoded value accepted on insert, or zero for no limit.
+    max_value_bytes: usize,
+    /// The maximum number of keys accepted by a single `get` or `insert`, or zero for no limit.
+    max_batch_keys: usize,
+    /// The number of nodes an insert buffers before writing them with an intermediate
+    /// `batch_write`, or zero to write them all at the end of the insert.
+    flush_every: usize,
+    /// Encoded values shorter than this many bytes are inlined into their leaf, or zero to never
+    /// inline values.
+    inline_value_bytes: usize,
+    /// The namespace of the root pointer `current_root` and `compare_and_set_root` use.
+    root_namespace: Vec<u8>,
+    /// If true, a panic while encoding the values of an insert is caught and returned as an
+    /// `Exception` of kind `ErrorKind::WorkerPanic`.
+    catch_panics: bool,
+    /// If false, nodes are written with a single reference and never shared by bumping their
+    /// references, and roots cannot be removed.
+    refcounting: bool,
+    /// If true, data nodes store the value location of their value instead of the value, so roots
+    /// and commitments can be computed but values cannot be read back.
+    commit_only: bool,
+    /// The checks an insert makes before writing.
+    checks: InsertChecks,
+}
+
+impl TreeOptions {
+    /// Creates a new `TreeOptions` with the defaults of a newly opened `MerkleBIT`.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the scheme used for computing node locations.
+    #[inline]
+    #[must_use]
+    pub const fn scheme(&self) -> &HashScheme {
+        &self.scheme
+    }
+
+    /// Gets the scheme used for computing node locations, for changing it.
+    #[inline]
+    pub fn scheme_mut(&mut self) -> &mut HashScheme {
+        &mut self.scheme
+    }
+
+    /// Gets the maximum size of an encoded value accepted on insert, or zero for no limit.
+    #[inline]
+    #[must_use]
+    pub const fn max_value_bytes(&self) -> usize {
+        self.max_value_bytes
+    }
+
+    /// Sets the maximum size of an encoded value accepted on insert.
+    #[inline]
+    pub fn set_max_value_bytes(&mut self, max_value_bytes: usize) {
+        self.max_value_bytes = max_value_bytes;
+    }
+
+    /// Gets the maximum number of keys accepted by a single `get` or `insert`, or zero for no
+    /// limit.
+    #[inline]
+    #[must_use]
+    pub const fn max_batch_keys(&self) -> usize {
+        self.max_batch_keys
+    }
+
+    /// Sets the maximum number of keys accepted by a single `get` or `insert`.
+    #[inline]
+    pub fn set_max_batch_keys(&mut self, max_batch_keys: usize) {
+        self.max_batch_keys = max_batch_keys;
+    }
+
+    /// Gets the number of nodes an insert buffers before an intermediate `batch_write`, or zero.
+    #[inline]
+    #[must_use]
+    pub const fn flush_every(&self) -> usize {
+        self.flush_every
+    }
+
+    /// Sets the number of nodes an insert buffers before an intermediate `batch_write`.
+    #[inline]
+    pub fn set_flush_every(&mut self, flush_every: usize) {
+        self.flush_every = flush_every;
+    }
+
+    /// Gets the size below which encoded values are inlined into their leaf, or zero.
+    #[inline]
+    #[must_use]
+    pub const fn inline_value_bytes(&self) -> usize {
+        self.inline_value_bytes
+    }
+
+    /// Sets the size below which encoded values are inlined into their leaf.
+    #[inline]
+    pub fn set_inline_value_bytes(&mut self, inline_value_bytes: usize) {
+        self.inline_value_bytes = inline_value_bytes;
+    }
+
+    /// Gets the namespace of the root pointer `current_root` and `compare_and_set_root` use.
+    #[inline]
+    #[must_use]
+    pub fn root_namespace(&self) -> &[u8] {
+        &self.root_namespace
+    }
+
+    /// Sets the namespace of the root pointer `current_root` and `compare_and_set_root` use.
+    #[inline]
+    pub fn set_root_namespace(&mut self, namespace: &[u8]) {
+        self.root_namespace = namespace.to_vec();
+    }
+
+    /// Gets whether a panic while encoding the values of an insert is caught.
+    #[inline]
+    #[must_use]
+    pub const fn catch_panics(&self) -> bool {
+        self.catch_panics
+    }
+
+    /// Sets whether a panic while encoding the values of an insert is caught.
+    #[inline]
+    pub fn set_catch_panics(&mut self, catch_panics: bool) {
+        self.catch_panics = catch_panics;
+    }
+
+    /// Gets whether nodes are reference counted.
+    #[inline]
+    #[must_use]
+    pub const fn refcounting(&self) -> bool {
+        self.refcounting
+    }
+
+    /// Sets whether nodes are reference counted.
+    #[inline]
+    pub fn set_refcounting(&mut self, enabled: bool) {
+        self.refcounting = enabled;
+    }
+
+    /// Gets whether data nodes store only the value location of their value.
+    #[inline]
+    #[must_use]
+    pub const fn commit_only(&self) -> bool {
+        self.commit_only
+    }
+
+    /// Sets whether data nodes store only the value location of their value.
+    #[inline]
+    pub fn set_commit_only(&mut self, commit_only: bool) {
+        self.commit_only = commit_only;
+    }
+
+    /// Gets whether inserting on top of a previous root missing from the database fails.
+    #[inline]
+    #[must_use]
+    pub const fn strict_previous_root(&self) -> bool {
+        self.checks.previous_root
+    }
+
+    /// Sets whether inserting on top of a previous root missing from the database fails.
+    #[inline]
+    pub fn set_strict_previous_root(&mut self, strict: bool) {
+        self.checks.previous_root = strict;
+    }
+
+    /// Gets whether inserting no keys fails.
+    #[inline]
+    #[must_use]
+    pub const fn strict_empty_inserts(&self) -> bool {
+        self.checks.empty_inserts
+    }
+
+    /// Sets whether inserting no keys fails.
+    #[inline]
+    pub fn set_strict_empty_inserts(&mut self, strict: bool) {
+        self.checks.empty_inserts = strict;
+    }
+
+    /// Gets whether inserts check for hash collisions.
+    #[inline]
+    #[must_use]
+    pub const fn collision_check(&self) -> bool {
+        self.checks.collisions
+    }
+
+    /// Sets whether inserts check for hash collisions.
+    #[inline]
+    pub fn set_collision_check(&mut self, enabled: bool) {
+        self.checks.collisions = enabled;
+    }
+}
+
+impl Default for TreeOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            scheme: HashScheme::new(),
+            max_value_bytes: 0,
+            max_batch_keys: 0,
+            flush_every: 0,
+            inline_value_bytes: 0,
+            root_namespace: Vec::new(),
+            catch_panics: false,
+            refcounting: true,
+            commit_only: false,
+            checks: InsertChecks::default(),
+        }
+    }
+}
+
+impl From<HashScheme> for TreeOptions {
+    #[inline]
+    fn from(scheme: HashScheme) -> Self {
+        Self {
+            scheme,
+            ..Self::default()
+        }
+    }
+}
@@ -6,8 +6,11 @@ use hashbrown::HashMap;
 
 use crate::constants::{KEY_LEN_BITS, MULTIPLY_DE_BRUIJN_BIT_POSITION};
 use crate::merkle_bit::BinaryMerkleTreeResult;
-use crate::traits::{Array, Exception};
+use crate::traits::{Array, Exception, KeyOrder};
+use crate::utils::key_order::BigEndianOrder;
 use crate::utils::tree_ref::TreeRef;
+use core::fmt::Write as _;
+use std::cmp::Ordering;
 use std::convert::TryFrom;
 
 #[cfg(feature = "use_hashbrown")]
@@ -15,7 +18,9 @@ use hashbrown::HashSet;
 #[cfg(not(feature = "use_hashbrown"))]
 use std::collections::HashSet;
 
-/// This function checks if the given key should go down the zero branch at the given bit.
+/// This function checks if the given key should go down the zero branch at the given bit.  Bits
+/// are numbered from the most significant bit of the first byte, so the zero branch holds the keys
+/// that sort first in byte order.
 /// # Errors
 /// `Exception` generated from a failure to convert an `u8` to an `usize`
 #[inline]
@@ -23,15 +28,265 @@ pub fn choose_zero<ArrayType>(key_array: ArrayType, bit: usize) -> Result<bool,
 where
     ArrayType: Array,
 {
-    let key = key_array.as_ref();
+    choose_zero_ordered::<BigEndianOrder, _>(key_array, bit)
+}
+
+/// This function checks if the given key should go down the zero branch at the given bit, with
+/// bits taken from the `order_key` of `KeyOrderType`.
+/// # Errors
+/// `Exception` generated from a failure to convert an `u8` to an `usize`
+#[inline]
+pub fn choose_zero_ordered<KeyOrderType, ArrayType>(
+    key_array: ArrayType,
+    bit: usize,
+) -> Result<bool, Exception>
+where
+    KeyOrderType: KeyOrder<ArrayType>,
+    ArrayType: Array,
+{
+    let ordered = KeyOrderType::order_key(&key_array);
+    let key = ordered.as_ref();
     let index = bit >> 3;
     let shift = bit % 8;
     let extracted_bit = usize::try_from(key[index])? >> (7 - shift) & 1;
     Ok(extracted_bit == 0)
 }
 
+/// This function checks if the first `bits` bits of `key` are the same as those of `prefix`.
+/// Both `key` and `prefix` must hold at least `bits` bits.
+#[inline]
+#[must_use]
+pub fn prefix_matches(key: &[u8], prefix: &[u8], bits: usize) -> bool {
+    let whole_bytes = bits >> 3;
+    if key[..whole_bytes] != prefix[..whole_bytes] {
+        return false;
+    }
+    let remaining_bits = bits % 8;
+    if remaining_bits == 0 {
+        return true;
+    }
+    let mask = 0xFF_u8 << (8 - remaining_bits);
+    key[whole_bytes] & mask == prefix[whole_bytes] & mask
+}
+
+/// This function compares the first `bits` bits of `key` with those of `other`, in the big-endian
+/// order the tree sorts keys in.  Both `key` and `other` must hold at least `bits` bits.
+#[inline]
+#[must_use]
+pub fn compare_prefix(key: &[u8], other: &[u8], bits: usize) -> Ordering {
+    let whole_bytes = bits >> 3;
+    let ordering = key[..whole_bytes].cmp(&other[..whole_bytes]);
+    let remaining_bits = bits % 8;
+    if ordering != Ordering::Equal || remaining_bits == 0 {
+        return ordering;
+    }
+    let mask = 0xFF_u8 << (8 - remaining_bits);
+    (key[whole_bytes] & mask).cmp(&(other[whole_bytes] & mask))
+}
+
+/// This function counts the leading bits `key` and `other` have in common, in the big-endian order
+/// the tree sorts keys in.  Only the bytes of the shorter slice are compared.
+#[inline]
+#[must_use]
+pub fn common_prefix_bits(key: &[u8], other: &[u8]) -> usize {
+    for (i, (a, b)) in key.iter().zip(other.iter()).enumerate() {
+        let difference = a ^ b;
+        if difference != 0 {
+            let mut bit = 0;
+            while difference << bit & 0x80 == 0 {
+                bit += 1;
+            }
+            return i * 8 + bit;
+        }
+    }
+    key.len().min(other.len()) * 8
+}
+
+/// This function calculates the depth of the tree built from `sorted_keys`, as the number of
+/// branches on its longest path from the root to a leaf.  Each pair of adjacent keys is separated
+/// by the branch splitting at their first differing bit, and that branch sits below every branch
+/// splitting at an earlier bit between them, so the branches form a Cartesian tree over the
+/// adjacent common prefix lengths.  Repeated keys share a leaf and add no branch.
+#[inline]
+#[must_use]
+pub fn tree_depth<ArrayType>(sorted_keys: &[ArrayType]) -> usize
+where
+    ArrayType: Array,
+{
+    tree_depth_ordered::<BigEndianOrder, _>(sorted_keys)
+}
+
+/// This function calculates the depth of the tree built from `sorted_keys`, which are sorted in the
+/// order of `KeyOrderType`.
+#[inline]
+#[must_use]
+pub fn tree_depth_ordered<KeyOrderType, ArrayType>(sorted_keys: &[ArrayType]) -> usize
+where
+    KeyOrderType: KeyOrder<ArrayType>,
+    ArrayType: Array,
+{
+    let prefixes = sorted_keys
+        .windows(2)
+        .filter(|pair| pair[0] != pair[1])
+        .map(|pair| {
+            common_prefix_bits(
+                KeyOrderType::order_key(&pair[0]).as_ref(),
+                KeyOrderType::order_key(&pair[1]).as_ref(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Link each branch to its parent, the nearest branch on either side splitting at an earlier bit
+    let mut parents = vec![None; prefixes.len()];
+    let mut stack: Vec<usize> = Vec::with_capacity(prefixes.len());
+    for (i, &prefix) in prefixes.iter().enumerate() {
+        let mut last = None;
+        while let Some(&top) = stack.last() {
+            if prefixes[top] <= prefix {
+                break;
+            }
+            last = stack.pop();
+        }
+        if let Some(child) = last {
+            parents[child] = Some(i);
+        }
+        parents[i] = stack.last().copied();
+        stack.push(i);
+    }
+
+    // Parents always split at an earlier bit, so visiting branches by split bit visits them first
+    let mut order = (0..prefixes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| prefixes[i]);
+    let mut depths = vec![0; prefixes.len()];
+    let mut max_depth = 0;
+    for i in order {
+        depths[i] = parents[i].map_or(1, |parent| depths[parent] + 1);
+        max_depth = max_depth.max(depths[i]);
+    }
+    max_depth
+}
+
+/// Buckets of the radix sort holding fewer keys than this are finished with a comparison sort,
+/// which is faster than another counting pass over so few keys.
+const RADIX_SORT_CUTOFF: usize = 64;
+
+/// This function sorts keys for `insert` and `get`: with an MSB radix sort under the
+/// `use_radix_sort` feature, and with the standard comparison sort otherwise.
+#[inline]
+pub fn sort_keys<ArrayType>(keys: &mut [ArrayType])
+where
+    ArrayType: Array,
+{
+    #[cfg(feature = "use_radix_sort")]
+    radix_sort_keys(keys);
+    #[cfg(not(feature = "use_radix_sort"))]
+    keys.sort();
+}
+
+/// This function sorts fixed-length keys in byte order with an in-place MSB radix sort.  Each pass
+/// counts the keys by one byte and swaps them into their buckets, then sorts each bucket by the
+/// next byte, so no keys are compared until a bucket is small and nothing is allocated.  Equal
+/// keys are indistinguishable, so the lack of stability does not matter.
+#[inline]
+pub fn radix_sort_keys<ArrayType>(keys: &mut [ArrayType])
+where
+    ArrayType: Array,
+{
+    radix_sort_from(keys, 0);
+}
+
+/// Sorts `keys`, which all share their first `byte` bytes, by their remaining bytes.
+fn radix_sort_from<ArrayType>(keys: &mut [ArrayType], byte: usize)
+where
+    ArrayType: Array,
+{
+    if keys.len() < RADIX_SORT_CUTOFF || byte >= ArrayType::default().as_ref().len() {
+        keys.sort_unstable();
+        return;
+    }
+
+    let mut counts = [0_usize; 256];
+    for key in keys.iter() {
+        counts[usize::from(key.as_ref()[byte])] += 1;
+    }
+
+    let mut starts = [0_usize; 256];
+    let mut ends = [0_usize; 256];
+    let mut offset = 0;
+    for (bucket, count) in counts.iter().enumerate() {
+        starts[bucket] = offset;
+        offset += count;
+        ends[bucket] = offset;
+    }
+
+    // Swap each key into the next free slot of its bucket until every bucket is filled
+    let mut next = starts;
+    for bucket in 0..256 {
+        while next[bucket] < ends[bucket] {
+            let target = usize::from(keys[next[bucket]].as_ref()[byte]);
+            if target == bucket {
+                next[bucket] += 1;
+            } else {
+                keys.swap(next[bucket], next[target]);
+                next[target] += 1;
+            }
+        }
+    }
+
+    for bucket in 0..256 {
+        if counts[bucket] > 1 {
+            radix_sort_from(&mut keys[starts[bucket]..ends[bucket]], byte + 1);
+        }
+    }
+}
+
+/// This function formats `bytes` as lowercase hexadecimal, two digits per byte.
+#[inline]
+#[must_use]
+pub fn to_hex(bytes: &[u8]) -> String {
+    // Writing to a `String` cannot fail, so the empty fallback is never returned
+    bytes
+        .iter()
+        .try_fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+            write!(hex, "{byte:02x}").map(|()| hex)
+        })
+        .unwrap_or_default()
+}
+
 /// This function splits the list of sorted pairs into two lists, one for going down the zero branch,
 /// and the other for going down the one branch.
+///
+/// The pairs must be partitioned at `bit`: every key with a zero at `bit` must come before every key
+/// with a one.  Sorted keys sharing a prefix above `bit` always are, but keys fed from a corrupt
+/// branch may not be.  Such input is rejected by an assertion in debug builds; in release builds the
+/// binary search settles on an arbitrary boundary, and the split is wrong.  Bits are numbered as in
+/// `choose_zero`, and either list may be empty.
+/// # Examples
+/// ```
+/// # use starling::traits::Exception;
+/// use starling::utils::tree_utils::split_pairs;
+///
+/// # fn main() -> Result<(), Exception> {
+/// let mut keys = [[0_u8; 32]; 4];
+/// keys[0][0] = 0x10;
+/// keys[1][0] = 0x60;
+/// keys[2][0] = 0x80;
+/// keys[3][0] = 0xC0;
+///
+/// // The keys share no prefix, so they split at the first bit.
+/// let (zeros, ones) = split_pairs(&keys, 0)?;
+/// assert_eq!(zeros, &keys[..2]);
+/// assert_eq!(ones, &keys[2..]);
+///
+/// // The keys going down the zero branch split again at the second bit.
+/// let (zeros, ones) = split_pairs(zeros, 1)?;
+/// assert_eq!(zeros, &keys[..1]);
+/// assert_eq!(ones, &keys[1..2]);
+/// # Ok(())
+/// # }
+/// ```
+/// # Panics
+/// Panics in debug builds if `sorted_pairs` is not partitioned at `bit`.
 /// # Errors
 /// `Exception` generated from a failure to convert an `u8` to an `usize`
 #[inline]
@@ -41,6 +296,25 @@ pub fn split_pairs<ArrayType>(
 ) -> Result<(&[ArrayType], &[ArrayType]), Exception>
 where
     ArrayType: Array,
+{
+    split_pairs_ordered::<BigEndianOrder, _>(sorted_pairs, bit)
+}
+
+/// This function splits the list of pairs sorted in the order of `KeyOrderType` into two lists,
+/// one for going down the zero branch, and the other for going down the one branch.  The pairs must
+/// be partitioned at `bit` in that order, as for `split_pairs`.
+/// # Panics
+/// Panics in debug builds if `sorted_pairs` is not partitioned at `bit`.
+/// # Errors
+/// `Exception` generated from a failure to convert an `u8` to an `usize`
+#[inline]
+pub fn split_pairs_ordered<KeyOrderType, ArrayType>(
+    sorted_pairs: &[ArrayType],
+    bit: usize,
+) -> Result<(&[ArrayType], &[ArrayType]), Exception>
+where
+    KeyOrderType: KeyOrder<ArrayType>,
+    ArrayType: Array,
 {
     if sorted_pairs.is_empty() {
         return Ok((&[], &[]));
@@ -49,27 +323,72 @@ where
     let mut min = 0;
     let mut max = sorted_pairs.len();
 
-    if choose_zero(sorted_pairs[max - 1], bit)? {
+    if choose_zero_ordered::<KeyOrderType, _>(sorted_pairs[max - 1], bit)? {
         return Ok((&sorted_pairs[..], &[]));
     }
 
-    if !choose_zero(sorted_pairs[0], bit)? {
+    if !choose_zero_ordered::<KeyOrderType, _>(sorted_pairs[0], bit)? {
         return Ok((&[], &sorted_pairs[..]));
     }
 
     while max - min > 1 {
         let bisect = (max - min) / 2 + min;
-        if choose_zero(sorted_pairs[bisect], bit)? {
+        if choose_zero_ordered::<KeyOrderType, _>(sorted_pairs[bisect], bit)? {
             min = bisect;
         } else {
             max = bisect;
         }
     }
 
+    #[cfg(debug_assertions)]
+    {
+        let mut monotone = true;
+        for (i, pair) in sorted_pairs.iter().enumerate() {
+            if choose_zero_ordered::<KeyOrderType, _>(*pair, bit)? != (i < max) {
+                monotone = false;
+                break;
+            }
+        }
+        debug_assert!(
+            monotone,
+            "split_pairs input is not partitioned at bit {}",
+            bit
+        );
+    }
+
     Ok(sorted_pairs.split_at(max))
 }
 
 /// This function checks to see if a section of keys need to go down this branch.
+///
+/// A key descends from the branch at `branch_key` if it matches `branch_key` on every bit before
+/// `branch_split_index`.  The bits before `min_split_index` are assumed to be shared already, as they
+/// are for every key reaching the branch through its parents, and are not compared.  The keys must be
+/// sorted, so that the descendants form one contiguous run; that run is returned, and any later
+/// descendants after a gap are not.  The run is empty if no key descends from the branch.
+/// # Examples
+/// ```
+/// # use starling::traits::Exception;
+/// use starling::utils::tree_utils::check_descendants;
+///
+/// # fn main() -> Result<(), Exception> {
+/// let mut keys = [[0_u8; 32]; 4];
+/// keys[0][0] = 0x10;
+/// keys[1][0] = 0x80;
+/// keys[2][0] = 0x90;
+/// keys[3][0] = 0xC0;
+///
+/// // A branch splitting at the second bit below a key starting with a one holds every key starting
+/// // with a one.
+/// let mut branch_key = [0_u8; 32];
+/// branch_key[0] = 0x80;
+/// assert_eq!(check_descendants(&keys, 1, &branch_key, 0)?, &keys[1..]);
+///
+/// // A branch splitting at the third bit only holds the keys starting with `10`.
+/// assert_eq!(check_descendants(&keys, 2, &branch_key, 0)?, &keys[1..3]);
+/// # Ok(())
+/// # }
+/// ```
 /// # Errors
 /// `Exception` generated from a failure to convert an `u8` to an `usize`
 #[inline]
@@ -82,15 +401,42 @@ pub fn check_descendants<'a, ArrayType>(
 where
     ArrayType: Array,
 {
-    let b_key = branch_key.as_ref();
+    check_descendants_ordered::<BigEndianOrder, _>(
+        keys,
+        branch_split_index,
+        branch_key,
+        min_split_index,
+    )
+}
+
+/// This function checks to see if a section of keys need to go down this branch, with bits taken
+/// from the `order_key` of `KeyOrderType`.  The keys must be sorted in that order, as for
+/// `check_descendants`.
+/// # Errors
+/// `Exception` generated from a failure to convert an `u8` to an `usize`
+#[inline]
+pub fn check_descendants_ordered<'a, KeyOrderType, ArrayType>(
+    keys: &'a [ArrayType],
+    branch_split_index: usize,
+    branch_key: &ArrayType,
+    min_split_index: usize,
+) -> Result<&'a [ArrayType], Exception>
+where
+    KeyOrderType: KeyOrder<ArrayType>,
+    ArrayType: Array,
+{
+    let ordered_branch_key = KeyOrderType::order_key(branch_key);
+    let b_key = ordered_branch_key.as_ref();
     let mut start = 0;
     let mut end = 0;
     let mut found_start = false;
     for (i, k) in keys.iter().enumerate() {
-        let key = k.as_ref();
+        let ordered = KeyOrderType::order_key(k);
+        let key = ordered.as_ref();
         let mut descendant = true;
-        for j in (min_split_index..branch_split_index).step_by(8) {
-            let byte = j >> 3;
+        // Compare every byte holding a bit in `min_split_index..branch_split_index`, which stepping
+        // through the bits eight at a time misses when `min_split_index` is not aligned to a byte
+        for byte in (min_split_index >> 3)..((branch_split_index + 7) >> 3) {
             if b_key[byte] == key[byte] {
                 continue;
             }
@@ -119,6 +465,27 @@ where
 
 /// This function calculates the minimum index upon which the given keys diverge.  It also includes
 /// the given branch key when calculating the minimum split index.
+///
+/// The index is the first bit at which the smallest and largest of the keys and `branch_key`
+/// differ, so all of them agree on every bit before it.  The keys need not be sorted.  If they are
+/// all equal to `branch_key`, the index of the last bit is returned.
+/// # Examples
+/// ```
+/// # use starling::traits::Exception;
+/// use starling::utils::tree_utils::calc_min_split_index;
+///
+/// # fn main() -> Result<(), Exception> {
+/// let mut keys = [[0_u8; 32]; 2];
+/// keys[0][0] = 0xC0;
+/// keys[1][0] = 0x80;
+/// let mut branch_key = [0_u8; 32];
+/// branch_key[0] = 0x90;
+///
+/// // Every key starts with a one, and `0x80` and `0xC0` differ at the second bit.
+/// assert_eq!(calc_min_split_index(&keys, &branch_key)?, 1);
+/// # Ok(())
+/// # }
+/// ```
 /// # Errors
 /// May return an `Exception` if the supplied `keys` is empty.
 #[inline]
@@ -128,23 +495,43 @@ pub fn calc_min_split_index<ArrayType>(
 ) -> Result<usize, Exception>
 where
     ArrayType: Array,
+{
+    calc_min_split_index_ordered::<BigEndianOrder, _>(keys, branch_key)
+}
+
+/// This function calculates the minimum index upon which the given keys diverge, with bits taken
+/// from the `order_key` of `KeyOrderType`.  It also includes the given branch key when calculating
+/// the minimum split index.
+/// # Errors
+/// May return an `Exception` if the supplied `keys` is empty.
+#[inline]
+pub fn calc_min_split_index_ordered<KeyOrderType, ArrayType>(
+    keys: &[ArrayType],
+    branch_key: &ArrayType,
+) -> Result<usize, Exception>
+where
+    KeyOrderType: KeyOrder<ArrayType>,
+    ArrayType: Array,
 {
     if keys.is_empty() {
         return Err(Exception::new("keys must not be empty."));
     }
-    let b_key = branch_key.as_ref();
-    let mut min_key;
-    let mut max_key;
-    if let Some(key) = keys.iter().min() {
-        min_key = key.as_ref();
+    let ordered_branch_key = KeyOrderType::order_key(branch_key);
+    let b_key = ordered_branch_key.as_ref();
+    let ordered_min_key;
+    let ordered_max_key;
+    if let Some(key) = keys.iter().map(KeyOrderType::order_key).min() {
+        ordered_min_key = key;
     } else {
         return Err(Exception::new("Failed to get min key from list of keys."));
     }
-    if let Some(key) = keys.iter().max() {
-        max_key = key.as_ref();
+    let mut min_key = ordered_min_key.as_ref();
+    if let Some(key) = keys.iter().map(KeyOrderType::order_key).max() {
+        ordered_max_key = key;
     } else {
         return Err(Exception::new("Failed to get max key from list of keys."));
     }
+    let mut max_key = ordered_max_key.as_ref();
 
     if b_key < min_key {
         min_key = b_key;
@@ -180,10 +567,16 @@ where
     leaf_map
 }
 
-/// This function performs a fast log2 operation for single byte unsigned integers.
+/// This function performs a fast log2 operation for single byte unsigned integers, returning the
+/// index of the highest set bit of `num`, from 0 for `0x01` up to 7 for `0x80` and above.  The
+/// split bit math relies on the result being at most 7.
+///
+/// `num` must not be zero, since zero has no set bit: callers XOR two key bytes and only call this
+/// for bytes that differ.  Zero panics in debug builds and returns 0 otherwise.
 #[inline]
 #[must_use]
 pub const fn fast_log_2(num: u8) -> u8 {
+    debug_assert!(num != 0, "fast_log_2 called with zero");
     let mut log = num;
     log |= log >> 1;
     log |= log >> 2;
@@ -199,10 +592,29 @@ pub fn generate_tree_ref_queue<ArrayType: Array>(
     tree_refs: &mut Vec<TreeRef<ArrayType>>,
     tree_ref_queue: &mut HashMap<usize, Vec<(usize, usize, usize)>>,
 ) -> BinaryMerkleTreeResult<HashSet<usize>> {
+    generate_tree_ref_queue_ordered::<BigEndianOrder, _>(tree_refs, tree_ref_queue)
+}
+
+/// Generates the `TreeRef`s that will be made into the new tree, from `tree_refs` sorted in the
+/// order of `KeyOrderType`.  The pairs sharing a split bit are queued in increasing index order,
+/// so the tree built from a set of keys never depends on how ties between them are broken.
+/// # Errors
+/// `Exception` generated from a failure to convert a `u8` to a `usize`
+#[inline]
+pub fn generate_tree_ref_queue_ordered<KeyOrderType, ArrayType>(
+    tree_refs: &mut Vec<TreeRef<ArrayType>>,
+    tree_ref_queue: &mut HashMap<usize, Vec<(usize, usize, usize)>>,
+) -> BinaryMerkleTreeResult<HashSet<usize>>
+where
+    KeyOrderType: KeyOrder<ArrayType>,
+    ArrayType: Array,
+{
     let mut unique_split_bits = HashSet::new();
     for i in 0..tree_refs.len() - 1 {
-        let left_key = tree_refs[i].key.as_ref();
-        let right_key = tree_refs[i + 1].key.as_ref();
+        let ordered_left_key = KeyOrderType::order_key(&tree_refs[i].key);
+        let ordered_right_key = KeyOrderType::order_key(&tree_refs[i + 1].key);
+        let left_key = ordered_left_key.as_ref();
+        let right_key = ordered_right_key.as_ref();
         let key_len = left_key.len();
 
         for j in 0..key_len {
@@ -217,7 +629,8 @@ pub fn generate_tree_ref_queue<ArrayType: Array>(
                 continue;
             }
 
-            // Find the bit index of the first difference
+            // Find the bit index of the first difference.  The bytes differ, so their XOR is
+            // never zero
             let xor_key: u8 = left_key[j] ^ right_key[j];
             let split_bit = (j * 8) + 7 - usize::try_from(fast_log_2(xor_key))?;
             unique_split_bits.insert(split_bit);
@@ -1,6 +1,8 @@
 #[cfg(test)]
 pub mod integration_tests {
+    use std::collections::HashSet;
     use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
 
     use rand::rngs::StdRng;
     use rand::{Rng, SeedableRng};
@@ -11,7 +13,11 @@ pub mod integration_tests {
     use starling::merkle_bit::BinaryMerkleTreeResult;
     #[cfg(feature = "use_rocksdb")]
     use starling::rocks_tree::RocksTree;
-    use starling::traits::Exception;
+    use starling::traits::{ErrorKind, Exception};
+    use starling::utils::ics23::{
+        ExistenceProof, HashOp, InnerOp, InnerSpec, LeafOp, LengthOp, ProofSpec,
+    };
+    use starling::utils::proof::{Proof, ProofSibling};
 
     #[cfg(feature = "use_rocksdb")]
     type Tree = RocksTree;
@@ -2668,6 +2674,5851 @@ pub mod integration_tests {
         Ok(())
     }
 
+    #[test]
+    fn it_reads_through_a_snapshot_after_the_root_is_removed() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x8Au8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+        let mut bmt = Tree::open(&path, 160)?;
+        let first_root = bmt.insert(None, &mut keys, &values)?;
+        let snapshot = bmt.snapshot(&first_root)?;
+
+        let updated_values = vec![vec![0xFFu8]; keys.len()];
+        let second_root = bmt.insert(Some(&first_root), &mut keys, &updated_values)?;
+        bmt.remove(&first_root)?;
+        bmt.remove(&second_root)?;
+
+        let items = bmt.get_snapshot(&snapshot, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        drop(snapshot);
+        bmt.remove(&first_root)?;
+        let items = bmt.get(&first_root, &mut keys)?;
+        tear_down(&path);
+        for key in keys.iter() {
+            assert_eq!(items[key], None);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_to_insert_when_the_depth_is_exceeded() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x8Bu8; KEY_LEN];
+        let path = generate_path(seed);
+
+        // Each key splits off at the next bit of the shared prefix, producing a chain of branches.
+        let mut keys = vec![[0x00u8; KEY_LEN]];
+        for i in 0..6 {
+            let mut key = [0x00u8; KEY_LEN];
+            key[0] = 0x80 >> i;
+            keys.push(key);
+        }
+        let values = vec![vec![0x00u8]; keys.len()];
+
+        let mut bmt = Tree::open(&path, 2)?;
+        let result = bmt.insert(None, &mut keys.clone(), &values);
+        match result {
+            Err(e) => assert_eq!(
+                *e.kind(),
+                ErrorKind::DepthInsufficient {
+                    needed: 6,
+                    configured: 2,
+                }
+            ),
+            Ok(_) => return Err(Exception::new("Failed to detect insufficient depth")),
+        }
+
+        // Inserted one at a time, each batch fits, until an insert traverses the grown chain
+        let mut root = None;
+        let mut result = Ok(());
+        for (key, value) in keys.iter().zip(values.iter()) {
+            match bmt.insert(root.as_ref(), &mut [*key], &[value.clone()]) {
+                Ok(new_root) => root = Some(new_root),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        tear_down(&path);
+        match result {
+            Err(e) => assert_eq!(*e.kind(), ErrorKind::DepthExceeded),
+            Ok(_) => return Err(Exception::new("Failed to detect exceeded depth")),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_shares_data_nodes_for_identical_values_when_value_addressed() -> BinaryMerkleTreeResult<()>
+    {
+        let seed = [0x8Cu8; KEY_LEN];
+        let path = generate_path(seed);
+
+        let mut keys = vec![[0x00u8; KEY_LEN], [0xFFu8; KEY_LEN]];
+        let value = vec![0xAAu8; 16];
+        let values = vec![value.clone(), value.clone()];
+
+        let mut bmt = Tree::open(&path, 160)?;
+        bmt.set_value_addressed_data(true);
+        let root = bmt.insert(None, &mut keys, &values)?;
+
+        let stats = *bmt.dedup_stats();
+        assert_eq!(stats.written_data_nodes, 1);
+        assert_eq!(stats.shared_data_nodes, 1);
+        assert_eq!(stats.bytes_saved, 16);
+
+        let items = bmt.get(&root, &mut keys)?;
+        for key in keys.iter() {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        let proof = bmt.generate_inclusion_proof(&root, keys[0])?;
+        bmt.verify_proof(&root, keys[0], &value, &proof)?;
+        if Tree::verify_inclusion_proof(&root, keys[0], &value, &proof).is_ok() {
+            return Err(Exception::new(
+                "Verified a proof with the wrong hash scheme",
+            ));
+        }
+
+        // Removing one root must not remove the shared data node
+        let second_root = bmt.insert(Some(&root), &mut [keys[1]], &[vec![0x01u8]])?;
+        bmt.remove(&root)?;
+        let items = bmt.get(&second_root, &mut keys)?;
+        tear_down(&path);
+        assert_eq!(items[&keys[0]], Some(value));
+        assert_eq!(items[&keys[1]], Some(vec![0x01u8]));
+        Ok(())
+    }
+
+    /// A change buffered by `DeferredDB`, with `None` for a remove.
+    type DeferredChange = (
+        [u8; KEY_LEN],
+        Option<starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>>,
+    );
+
+    /// A database applying inserts and removes only on `batch_write`, like a database writing
+    /// through write batches, so nodes written earlier in a batch cannot be read back yet.
+    struct DeferredDB {
+        inner: starling::tree_db::HashTreeDB<[u8; KEY_LEN]>,
+        pending: Vec<DeferredChange>,
+    }
+
+    impl starling::traits::Database<[u8; KEY_LEN]> for DeferredDB {
+        type NodeType = starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>;
+        type EntryType = ();
+
+        fn open(path: &PathBuf) -> Result<Self, Exception> {
+            Ok(Self {
+                inner: starling::traits::Database::open(path)?,
+                pending: Vec::new(),
+            })
+        }
+
+        fn get_node(&self, key: [u8; KEY_LEN]) -> Result<Option<Self::NodeType>, Exception> {
+            self.inner.get_node(key)
+        }
+
+        fn insert(&mut self, key: [u8; KEY_LEN], node: Self::NodeType) -> Result<(), Exception> {
+            self.pending.push((key, Some(node)));
+            Ok(())
+        }
+
+        fn remove(&mut self, key: &[u8; KEY_LEN]) -> Result<(), Exception> {
+            self.pending.push((*key, None));
+            Ok(())
+        }
+
+        fn batch_write(&mut self) -> Result<(), Exception> {
+            for (key, change) in std::mem::take(&mut self.pending) {
+                if let Some(node) = change {
+                    self.inner.insert(key, node)?;
+                } else {
+                    self.inner.remove(&key)?;
+                }
+            }
+            self.inner.batch_write()
+        }
+    }
+
+    #[test]
+    fn it_counts_data_nodes_shared_within_a_deferred_batch() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+        use starling::utils::tree_options::TreeOptions;
+
+        type DeferredTree = starling::merkle_bit::MerkleBIT<
+            DeferredDB,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+            starling::tree::tree_data::TreeData,
+            starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+            starling::tree_hasher::TreeHasher,
+            Vec<u8>,
+            [u8; KEY_LEN],
+        >;
+
+        let seed = [0x8Eu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut keys = vec![[0x00u8; KEY_LEN], [0xFFu8; KEY_LEN]];
+        let value = vec![0xAAu8; 16];
+
+        let mut options = TreeOptions::new();
+        options.scheme_mut().set_value_addressed_data(true);
+        let mut bmt = DeferredTree::from_db_with_options(DeferredDB::open(&path)?, 160, options)?;
+        let root = bmt.insert(None, &mut keys, &[value.clone(), value.clone()])?;
+        let stats = *bmt.dedup_stats();
+        assert_eq!(stats.written_data_nodes, 1);
+        assert_eq!(stats.shared_data_nodes, 1);
+
+        // Both leaves of the batch hold a reference to the data node, so removing the first root
+        // keeps it for the key the second root still shares
+        let second_root = bmt.insert(Some(&root), &mut [keys[1]], &[vec![0x01u8]])?;
+        bmt.remove(&root)?;
+        assert_eq!(bmt.get_one(&second_root, &keys[0])?, Some(value));
+        assert_eq!(bmt.get_one(&second_root, &keys[1])?, Some(vec![0x01u8]));
+
+        bmt.remove(&second_root)?;
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_does_not_share_data_nodes_across_keys_by_default() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x8Du8; KEY_LEN];
+        let path = generate_path(seed);
+
+        let mut keys = vec![[0x00u8; KEY_LEN], [0xFFu8; KEY_LEN]];
+        let values = vec![vec![0xAAu8; 16], vec![0xAAu8; 16]];
+
+        let mut bmt = Tree::open(&path, 160)?;
+        bmt.insert(None, &mut keys, &values)?;
+        let stats = *bmt.dedup_stats();
+        tear_down(&path);
+        assert_eq!(stats.written_data_nodes, 2);
+        assert_eq!(stats.shared_data_nodes, 0);
+        assert_eq!(stats.bytes_saved, 0);
+        Ok(())
+    }
+
+    /// A hasher producing different locations than the default one, for migration tests.
+    struct SaltedHasher(std::collections::hash_map::DefaultHasher);
+
+    impl starling::traits::Hasher<[u8; KEY_LEN]> for SaltedHasher {
+        type HashType = Self;
+
+        fn new(_size: usize) -> Self {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            std::hash::Hasher::write(&mut hasher, b"salt");
+            Self(hasher)
+        }
+
+        fn update(&mut self, data: &[u8]) {
+            std::hash::Hasher::write(&mut self.0, data);
+        }
+
+        fn finalize(self) -> [u8; KEY_LEN] {
+            let mut v = [0u8; KEY_LEN];
+            v[..8].copy_from_slice(&std::hash::Hasher::finish(&self.0).to_le_bytes());
+            v
+        }
+    }
+
+    #[test]
+    fn it_migrates_a_tree_to_a_new_hasher() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x8Eu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+        let migrated_root = tree.migrate_hasher::<SaltedHasher>(&root)?;
+        assert_ne!(migrated_root, root);
+
+        let original = tree.get(&root, &mut keys)?;
+        let migrated = tree.get(&migrated_root, &mut keys)?;
+        assert_eq!(migrated, original);
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(migrated[key], Some(value.clone()));
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_merges_roots_over_disjoint_keys() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x8Fu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let (low_keys, high_keys) = keys.split_at_mut(32);
+        let (low_values, high_values) = values.split_at(32);
+        let low_root = tree.insert(None, low_keys, low_values)?;
+        let high_root = tree.insert(None, high_keys, high_values)?;
+
+        let merged_root = tree.merge_roots(&low_root, &high_root)?;
+        let expected_root = tree.insert(None, &mut keys, &values)?;
+        assert_eq!(merged_root, expected_root);
+
+        // The merged root remains readable once the originals are gone
+        tree.remove(&low_root)?;
+        tree.remove(&high_root)?;
+        let items = tree.get(&merged_root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_to_merge_roots_with_overlapping_keys() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x90u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(16, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root_a = tree.insert(None, &mut keys[..10], &values[..10])?;
+        let root_b = tree.insert(None, &mut keys[8..], &values[8..])?;
+
+        assert!(tree.merge_roots(&root_a, &root_b).is_err());
+        assert!(tree.find_orphans(&[root_a, root_b])?.is_empty());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_round_trips_a_compact_proof() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x91u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(256, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        for key in keys.iter().take(16) {
+            let proof = tree.generate_proof(&root, *key)?;
+            assert_eq!(
+                proof.siblings().len() + 2,
+                tree.generate_inclusion_proof(&root, *key)?.len()
+            );
+            let decoded = Proof::from_bytes(&proof.to_bytes()?)?;
+            assert_eq!(decoded, proof);
+            tree.verify_compact_proof(&root, &decoded)?;
+        }
+
+        let mut tampered = tree.generate_proof(&root, keys[0])?.to_bytes()?;
+        tampered[2] ^= 0x01;
+        assert!(tree
+            .verify_compact_proof(&root, &Proof::from_bytes(&tampered)?)
+            .is_err());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_pins_the_compact_proof_byte_layout() -> BinaryMerkleTreeResult<()> {
+        let sibling = ProofSibling {
+            location: [0x22u8; KEY_LEN],
+            direction: true,
+            split_index: 0x0103,
+        };
+        let proof = Proof::new([0x11u8; KEY_LEN], vec![0xAA, 0xBB], vec![sibling]);
+        let bytes = proof.to_bytes()?;
+
+        let mut expected = vec![0x01, 0x20];
+        expected.extend_from_slice(&[0x11u8; KEY_LEN]);
+        expected.extend_from_slice(&[0x00, 0x00, 0x00, 0x02, 0xAA, 0xBB]);
+        expected.extend_from_slice(&[0x00, 0x01]);
+        expected.extend_from_slice(&[0x01, 0x01, 0x03]);
+        expected.extend_from_slice(&[0x22u8; KEY_LEN]);
+        assert_eq!(bytes, expected);
+
+        assert_eq!(Proof::<[u8; KEY_LEN]>::from_bytes(&bytes)?, proof);
+
+        let mut unknown_version = bytes.clone();
+        unknown_version[0] = 0x02;
+        assert!(Proof::<[u8; KEY_LEN]>::from_bytes(&unknown_version).is_err());
+        assert!(Proof::<[u8; KEY_LEN]>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes;
+        trailing.push(0x00);
+        assert!(Proof::<[u8; KEY_LEN]>::from_bytes(&trailing).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_cancels_a_removal_without_changing_the_tree() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x92u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        let cancel = AtomicBool::new(true);
+        match tree.remove_cancellable(&root, &cancel) {
+            Err(e) => assert_eq!(e.kind(), &ErrorKind::Cancelled),
+            Ok(_) => panic!("Removal should have been cancelled"),
+        }
+        let items = tree.get(&root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        cancel.store(false, Ordering::Relaxed);
+        tree.remove_cancellable(&root, &cancel)?;
+        assert_eq!(tree.get_one(&root, &keys[0])?, None);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_updates_a_value_changing_only_the_path_to_its_key() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x93u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        let key = keys[10];
+        let new_value = vec![0xFFu8; 8];
+        let new_root = tree.update_value(&root, &key, &new_value)?;
+        assert_eq!(tree.get_one(&new_root, &key)?, Some(new_value));
+        assert_eq!(tree.get_one(&root, &key)?, Some(values[10].clone()));
+
+        // Every node except the root appears in the proof of some key
+        let mut old_nodes = HashSet::new();
+        let mut new_nodes = HashSet::new();
+        old_nodes.insert(root);
+        new_nodes.insert(new_root);
+        for k in &keys {
+            for (location, _) in tree.generate_inclusion_proof(&root, *k)? {
+                old_nodes.insert(location);
+            }
+            for (location, _) in tree.generate_inclusion_proof(&new_root, *k)? {
+                new_nodes.insert(location);
+            }
+        }
+
+        let path_length = tree.generate_inclusion_proof(&new_root, key)?.len();
+        assert_eq!(new_nodes.difference(&old_nodes).count(), path_length);
+        assert_eq!(old_nodes.difference(&new_nodes).count(), path_length);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_to_update_a_missing_key() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x94u8; KEY_LEN];
+        let path = generate_path(seed);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert_one(None, &[0x01u8; KEY_LEN], &vec![0x01u8])?;
+        assert!(tree
+            .update_value(&root, &[0x02u8; KEY_LEN], &vec![0x02u8])
+            .is_err());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_filters_a_tree_by_a_predicate() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x95u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        let matches = |key: &[u8; KEY_LEN], value: &Vec<u8>| key[0] < 0x80 && value[0] % 2 == 0;
+        let filtered_root = tree.filter(&root, matches)?;
+
+        let filtered = tree.get(&filtered_root, &mut keys)?;
+        let mut matched = 0;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            if matches(key, value) {
+                matched += 1;
+                assert_eq!(filtered[key], Some(value.clone()));
+            } else {
+                assert_eq!(filtered[key], None);
+            }
+        }
+        assert!(matched > 0);
+
+        // The original tree is untouched, even once the filtered root is removed
+        tree.remove(&filtered_root)?;
+        let original = tree.get(&root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(original[key], Some(value.clone()));
+        }
+
+        assert!(tree.filter(&root, |_, _| false).is_err());
+
+        // Keeping every key reproduces the original root, which then holds two references
+        assert_eq!(tree.filter(&root, |_, _| true)?, root);
+        tree.remove(&root)?;
+        let original = tree.get(&root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(original[key], Some(value.clone()));
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "use_mmap", feature = "use_serialization"))]
+    fn it_replays_the_write_ahead_log_after_a_crash() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Data, Database, Encode, NodeVariant};
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_db::mmap::MmapDatabase;
+        use starling::tree_db::wal::WalDatabase;
+
+        type DiskDB = MmapDatabase<[u8; KEY_LEN]>;
+        type WalDB = WalDatabase<DiskDB, [u8; KEY_LEN]>;
+
+        let seed = [0x96u8; KEY_LEN];
+        let path = generate_path(seed);
+        let location = [0x01u8; KEY_LEN];
+        let removed_location = [0x02u8; KEY_LEN];
+        let uncommitted_location = [0x03u8; KEY_LEN];
+
+        let mut data = TreeData::new();
+        data.set_value(&[0xAAu8; 4]);
+        let node = TreeNode::new(NodeVariant::Data(data));
+        let encoded = node.encode()?;
+
+        {
+            let mut db = WalDB::open(&path)?;
+            db.insert(removed_location, node.clone())?;
+            db.batch_write()?;
+
+            // Simulate a crash by dropping the database before the batch is written
+            db.insert(location, node.clone())?;
+            db.remove(&removed_location)?;
+            assert!(db.get_node(location)?.is_some());
+        }
+
+        // A batch that was never committed is discarded
+        {
+            let db = WalDB::open(&path)?;
+            assert_eq!(db.get_node(location)?, None);
+            assert_eq!(db.get_node(removed_location)?, Some(node.clone()));
+        }
+
+        // A committed batch, which crashed before it was applied, followed by a batch cut short
+        // in the middle of a record
+        let mut log = vec![0u8];
+        log.extend_from_slice(&location);
+        log.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+        log.extend_from_slice(&encoded);
+        log.push(1);
+        log.extend_from_slice(&removed_location);
+        log.push(2);
+        log.extend_from_slice(&[0u8; KEY_LEN]);
+        log.push(0);
+        log.extend_from_slice(&uncommitted_location);
+        log.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+        log.extend_from_slice(&encoded[..encoded.len() / 2]);
+        std::fs::write(WalDB::log_path(&path), &log).unwrap();
+
+        {
+            let db = WalDB::open(&path)?;
+            assert_eq!(db.get_node(location)?, Some(node.clone()));
+            assert_eq!(db.get_node(removed_location)?, None);
+            assert_eq!(db.get_node(uncommitted_location)?, None);
+        }
+
+        // The committed batch reached the database on disk, and the log was truncated once
+        // replayed
+        let disk = DiskDB::open(&path)?;
+        assert_eq!(disk.get_node(location)?, Some(node));
+        assert_eq!(disk.get_node(removed_location)?, None);
+        assert_eq!(disk.get_node(uncommitted_location)?, None);
+        drop(disk);
+        assert_eq!(std::fs::metadata(WalDB::log_path(&path)).unwrap().len(), 0);
+
+        std::fs::remove_file(WalDB::log_path(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "use_rocksdb")))]
+    fn it_gets_shared_references_to_values() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x97u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        let mut missing_keys = keys.clone();
+        missing_keys.push([0xFFu8; KEY_LEN]);
+        let items = tree.get_ref(&root, &mut missing_keys)?;
+        assert_eq!(items.len(), keys.len() + 1);
+        assert!(items[&[0xFFu8; KEY_LEN]].is_none());
+        for (key, value) in keys.iter().zip(values.iter()) {
+            if let Some(shared) = &items[key] {
+                assert_eq!(shared.as_ref(), &value[..]);
+            } else {
+                panic!("Failed to get a shared value");
+            }
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_computes_equal_structural_roots_for_differently_built_trees() -> BinaryMerkleTreeResult<()>
+    {
+        let seed = [0x98u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let batch_root = tree.insert(None, &mut keys, &values)?;
+
+        // Build the same tree a key at a time, overwriting and discarding roots along the way
+        let other_path = generate_path([0x99u8; KEY_LEN]);
+        let mut other_tree = Tree::open(&other_path, 160)?;
+        let mut root = other_tree.insert_one(None, &keys[0], &vec![0x00u8])?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            let new_root = other_tree.insert_one(Some(&root), key, value)?;
+            other_tree.remove(&root)?;
+            root = new_root;
+        }
+
+        let structural_root = tree.structural_root(&batch_root)?;
+        assert_eq!(structural_root, batch_root);
+        assert_eq!(other_tree.structural_root(&root)?, structural_root);
+
+        let changed_root = tree.update_value(&batch_root, &keys[3], &vec![0x01u8])?;
+        assert_ne!(tree.structural_root(&changed_root)?, structural_root);
+
+        tear_down(&path);
+        tear_down(&other_path);
+        Ok(())
+    }
+
+    /// A database counting the operations that reach it, which can be made to fail a number of
+    /// operations.  Clones share the nodes and counters, so they can still be inspected after the
+    /// database is handed to a tree.
+    #[derive(Clone)]
+    struct CountingDB {
+        inner: std::sync::Arc<std::sync::Mutex<starling::tree_db::HashTreeDB<[u8; KEY_LEN]>>>,
+        reads: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        location_reads:
+            std::sync::Arc<std::sync::Mutex<std::collections::HashMap<[u8; KEY_LEN], usize>>>,
+        batch_writes: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        /// The number of inserts of nodes that were not already in the database.
+        new_nodes: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        /// The number of reads, inserts and batch writes left to fail.
+        failures: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        kind: ErrorKind,
+    }
+
+    impl CountingDB {
+        fn db(&self) -> std::sync::MutexGuard<'_, starling::tree_db::HashTreeDB<[u8; KEY_LEN]>> {
+            self.inner.lock().expect("Test database is poisoned")
+        }
+
+        fn fail(&self) -> Result<(), Exception> {
+            let remaining = self.failures.load(Ordering::Relaxed);
+            if remaining > 0 {
+                self.failures.store(remaining - 1, Ordering::Relaxed);
+                return Err(Exception::with_kind(self.kind.clone(), "Flaky failure"));
+            }
+            Ok(())
+        }
+    }
+
+    impl starling::traits::Database<[u8; KEY_LEN]> for CountingDB {
+        type NodeType = starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>;
+        type EntryType = ();
+
+        fn open(path: &PathBuf) -> Result<Self, Exception> {
+            Ok(Self {
+                inner: std::sync::Arc::new(std::sync::Mutex::new(
+                    starling::traits::Database::open(path)?,
+                )),
+                reads: std::sync::Arc::default(),
+                location_reads: std::sync::Arc::default(),
+                batch_writes: std::sync::Arc::default(),
+                new_nodes: std::sync::Arc::default(),
+                failures: std::sync::Arc::default(),
+                kind: ErrorKind::Io,
+            })
+        }
+
+        fn get_node(&self, key: [u8; KEY_LEN]) -> Result<Option<Self::NodeType>, Exception> {
+            self.fail()?;
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            if let Ok(mut location_reads) = self.location_reads.lock() {
+                *location_reads.entry(key).or_insert(0) += 1;
+            }
+            self.db().get_node(key)
+        }
+
+        fn insert(&mut self, key: [u8; KEY_LEN], node: Self::NodeType) -> Result<(), Exception> {
+            self.fail()?;
+            let mut inner = self.db();
+            if inner.get_node(key)?.is_none() {
+                self.new_nodes.fetch_add(1, Ordering::SeqCst);
+            }
+            inner.insert(key, node)
+        }
+
+        fn remove(&mut self, key: &[u8; KEY_LEN]) -> Result<(), Exception> {
+            self.db().remove(key)
+        }
+
+        fn batch_write(&mut self) -> Result<(), Exception> {
+            self.fail()?;
+            self.batch_writes.fetch_add(1, Ordering::Relaxed);
+            self.db().batch_write()
+        }
+
+        fn node_locations(&self) -> Result<Vec<[u8; KEY_LEN]>, Exception> {
+            self.db().node_locations()
+        }
+    }
+
+    type CountedTree = starling::merkle_bit::MerkleBIT<
+        CountingDB,
+        starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+        starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+        starling::tree::tree_data::TreeData,
+        starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+        starling::tree_hasher::TreeHasher,
+        Vec<u8>,
+        [u8; KEY_LEN],
+    >;
+
+    type CachedTree = starling::merkle_bit::MerkleBIT<
+        starling::tree_db::cache::CachedDatabase<CountingDB, [u8; KEY_LEN]>,
+        starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+        starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+        starling::tree::tree_data::TreeData,
+        starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+        starling::tree_hasher::TreeHasher,
+        Vec<u8>,
+        [u8; KEY_LEN],
+    >;
+
+    #[test]
+    fn it_serves_gets_from_a_warmed_cache() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+        use starling::tree_db::cache::CachedDatabase;
+
+        let seed = [0x9Au8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let db = CountingDB::open(&path)?;
+        let reads = std::sync::Arc::clone(&db.reads);
+        let mut tree = CachedTree::from_db(CachedDatabase::new(db, usize::MAX), 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        // Every branch, leaf and data node is warmed
+        assert_eq!(tree.warm_cache(&root, 160)?, 63 + 64 + 64);
+
+        reads.store(0, Ordering::Relaxed);
+        let items = tree.get(&root, &mut keys)?;
+        assert_eq!(reads.load(Ordering::Relaxed), 0);
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        // Warming stops at the depth limit, and once the byte budget is spent
+        let db = CountingDB::open(&path)?;
+        let mut shallow_tree =
+            CachedTree::from_db(CachedDatabase::new(db, usize::MAX), 160)?;
+        let root = shallow_tree.insert(None, &mut keys, &values)?;
+        assert_eq!(shallow_tree.warm_cache(&root, 2)?, 7);
+
+        let db = CountingDB::open(&path)?;
+        let mut small_tree = CachedTree::from_db(CachedDatabase::new(db, 1), 160)?;
+        let root = small_tree.insert(None, &mut keys, &values)?;
+        assert_eq!(small_tree.warm_cache(&root, 160)?, 0);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_a_warmed_cache_within_its_byte_budget() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{CachingDatabase, Data, Database, NodeVariant};
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_db::cache::CachedDatabase;
+        use starling::tree_db::HashTreeDB;
+
+        let seed = [0xD8u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, _) = prepare_inserts(64, &mut rng);
+        let values = vec![vec![0x5Au8; 4096]; 64];
+        let budget = 32 * 1024;
+
+        // The values alone would take eight times the budget
+        let db = CountingDB::open(&path)?;
+        let reads = std::sync::Arc::clone(&db.reads);
+        let mut tree = CachedTree::from_db(CachedDatabase::new(db, budget), 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+        reads.store(0, Ordering::Relaxed);
+        let warmed = tree.warm_cache(&root, 160)?;
+        assert!(warmed > 0);
+        assert!(warmed < 63 + 64 + 8);
+        // Only the node found not to fit is read past the budget
+        assert!(reads.load(Ordering::Relaxed) <= warmed + 1);
+
+        // Each cached data node is charged its value
+        let mut db = CachedDatabase::new(HashTreeDB::open(&path)?, budget);
+        for (key, value) in keys.iter().zip(values.iter()) {
+            let mut data = TreeData::new();
+            data.set_value(value);
+            db.insert(*key, TreeNode::new(NodeVariant::Data(data)))?;
+        }
+        db.batch_write()?;
+        let mut kept = 0;
+        for key in &keys {
+            if let Some((_, true)) = db.warm(*key)? {
+                kept += 1;
+            }
+            assert!(db.cached_bytes()? <= budget);
+        }
+        assert!(kept > 0);
+        assert!(kept < 8);
+        assert_eq!(db.cached_nodes()?, kept);
+        assert!(db.cached_bytes()? >= kept * 4096);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_evicts_the_least_recently_used_nodes() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Data, Database, NodeVariant};
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_db::cache::CachedDatabase;
+
+        let seed = [0xD9u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (keys, _) = prepare_inserts(64, &mut rng);
+        let budget = 32 * 1024;
+
+        let db = CountingDB::open(&path)?;
+        let reads = std::sync::Arc::clone(&db.reads);
+        let mut db = CachedDatabase::new(db, budget);
+        for key in &keys {
+            let mut data = TreeData::new();
+            data.set_value(&[0x5Au8; 4096]);
+            db.insert(*key, TreeNode::new(NodeVariant::Data(data)))?;
+        }
+        db.batch_write()?;
+
+        // Reads past the budget push out the oldest nodes, keeping the last ones read
+        for key in &keys {
+            assert!(db.get_node(*key)?.is_some());
+            assert!(db.cached_bytes()? <= budget);
+        }
+        let kept = db.cached_nodes()?;
+        assert!(kept >= 3);
+        assert!(kept < 8);
+        let oldest = keys.len() - kept;
+
+        // Reading a cached node makes it the most recently used, so the next oldest is evicted
+        reads.store(0, Ordering::Relaxed);
+        db.get_node(keys[oldest])?;
+        assert_eq!(reads.load(Ordering::Relaxed), 0);
+        db.get_node(keys[0])?;
+        assert_eq!(reads.load(Ordering::Relaxed), 1);
+        assert_eq!(db.cached_nodes()?, kept);
+        db.get_node(keys[oldest])?;
+        assert_eq!(reads.load(Ordering::Relaxed), 1);
+        db.get_node(keys[oldest + 1])?;
+        assert_eq!(reads.load(Ordering::Relaxed), 2);
+
+        // Batched reads are served from the cache where they can be, and cache what they read
+        let nodes = db.get_nodes(&[keys[0], keys[oldest], keys[1]])?;
+        assert!(nodes.iter().all(Option::is_some));
+        assert_eq!(reads.load(Ordering::Relaxed), 3);
+        db.get_node(keys[1])?;
+        assert_eq!(reads.load(Ordering::Relaxed), 3);
+        assert!(db.cached_bytes()? <= budget);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_finds_the_subtree_root_of_a_prefix() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x9Bu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+        for key in keys.iter_mut().take(16) {
+            key[0] = 0xAA;
+        }
+        for key in keys.iter_mut().skip(16) {
+            if key[0] == 0xAA {
+                key[0] = 0xAB;
+            }
+        }
+        keys.sort();
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        // The subtree of the prefix is exactly the tree holding only the matching keys
+        let mut prefixed_keys = keys
+            .iter()
+            .copied()
+            .filter(|k| k[0] == 0xAA)
+            .collect::<Vec<_>>();
+        assert_eq!(prefixed_keys.len(), 16);
+        let prefixed_values = prefixed_keys
+            .iter()
+            .map(|k| values[keys.iter().position(|key| key == k).unwrap()].clone())
+            .collect::<Vec<_>>();
+        let prefixed_root = tree.insert(None, &mut prefixed_keys, &prefixed_values)?;
+
+        assert_eq!(tree.subtree_root(&root, &[0xAA], 8)?, Some(prefixed_root));
+        assert_eq!(
+            tree.subtree_root(&root, &[0xAA, 0xFF], 8)?,
+            Some(prefixed_root)
+        );
+        assert_eq!(tree.subtree_root(&root, &[], 0)?, Some(root));
+
+        let single_key = keys[20];
+        let leaf = tree.generate_inclusion_proof(&root, single_key)?[1].0;
+        assert_eq!(
+            tree.subtree_root(&root, &single_key, (KEY_LEN * 8) as u32)?,
+            Some(leaf)
+        );
+
+        let mut missing = [0u8; KEY_LEN];
+        missing[0] = 0xAA;
+        missing[1] = 0x5A;
+        missing[2] = 0x5A;
+        assert_eq!(
+            tree.subtree_root(&root, &missing, (KEY_LEN * 8) as u32)?,
+            None
+        );
+        assert!(tree.subtree_root(&root, &[0xAA], 9).is_err());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(any(
+        feature = "use_bincode",
+        feature = "use_json",
+        feature = "use_cbor",
+        feature = "use_yaml",
+        feature = "use_pickle",
+        feature = "use_ron",
+        feature = "use_borsh"
+    ))]
+    fn it_reports_the_location_of_a_truncated_node() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Data, Database, Encode, NodeVariant};
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_db::wal::WalDatabase;
+        use starling::tree_db::HashTreeDB;
+
+        type WalDB = WalDatabase<HashTreeDB<[u8; KEY_LEN]>, [u8; KEY_LEN]>;
+
+        let seed = [0x62u8; KEY_LEN];
+        let path = generate_path(seed);
+        let location = [0x03u8; KEY_LEN];
+
+        let mut data = TreeData::new();
+        data.set_value(&[0xAAu8; 32]);
+        let encoded = TreeNode::<[u8; KEY_LEN]>::new(NodeVariant::Data(data)).encode()?;
+        let truncated = &encoded[..encoded.len() / 2];
+
+        // A committed insert record whose node was only partially written
+        let mut record = vec![0u8];
+        record.extend_from_slice(&location);
+        record.extend_from_slice(&(truncated.len() as u32).to_be_bytes());
+        record.extend_from_slice(truncated);
+        record.push(2);
+        record.extend_from_slice(&[0u8; KEY_LEN]);
+        std::fs::write(WalDB::log_path(&path), &record).unwrap();
+
+        match WalDB::open(&path) {
+            Err(e) => assert_eq!(
+                e.kind(),
+                &ErrorKind::CorruptNode {
+                    location: location.to_vec()
+                }
+            ),
+            Ok(_) => panic!("Opened a database with a truncated node"),
+        }
+
+        std::fs::remove_file(WalDB::log_path(&path)).unwrap();
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(any(
+        feature = "use_bincode",
+        feature = "use_json",
+        feature = "use_cbor",
+        feature = "use_yaml",
+        feature = "use_pickle",
+        feature = "use_ron",
+        feature = "use_borsh"
+    ))]
+    fn it_keeps_a_failed_write_ahead_batch_pending() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Data, Database, NodeVariant};
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_db::wal::WalDatabase;
+
+        type WalDB = WalDatabase<CountingDB, [u8; KEY_LEN]>;
+
+        let seed = [0x64u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut data = TreeData::new();
+        data.set_value(&[0xAAu8; 32]);
+        let node = TreeNode::<[u8; KEY_LEN]>::new(NodeVariant::Data(data));
+
+        let inner = CountingDB::open(&path)?;
+        let failures = std::sync::Arc::clone(&inner.failures);
+        let mut db = WalDB::new(inner, &WalDB::log_path(&path))?;
+        db.insert([0x01u8; KEY_LEN], node.clone())?;
+        db.insert([0x02u8; KEY_LEN], node.clone())?;
+
+        // The inner database refuses the batch, which stays pending for the next batch_write
+        failures.store(1, Ordering::Relaxed);
+        assert!(db.batch_write().is_err());
+        assert_eq!(db.get_node([0x01u8; KEY_LEN])?, Some(node.clone()));
+        db.batch_write()?;
+        assert_eq!(db.get_node([0x01u8; KEY_LEN])?, Some(node.clone()));
+        assert_eq!(db.get_node([0x02u8; KEY_LEN])?, Some(node.clone()));
+        assert_eq!(std::fs::metadata(WalDB::log_path(&path)).unwrap().len(), 0);
+
+        // Batched reads see pending changes over the nodes of the inner database
+        db.insert([0x03u8; KEY_LEN], node.clone())?;
+        db.remove(&[0x02u8; KEY_LEN])?;
+        let keys = [
+            [0x01u8; KEY_LEN],
+            [0x02u8; KEY_LEN],
+            [0x03u8; KEY_LEN],
+            [0x04u8; KEY_LEN],
+        ];
+        assert_eq!(
+            db.get_nodes(&keys)?,
+            vec![Some(node.clone()), None, Some(node), None]
+        );
+
+        std::fs::remove_file(WalDB::log_path(&path)).unwrap();
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_finds_the_keys_changed_since_a_base_root() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x63u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(200, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let base_root = bmt.insert(None, &mut keys, &values)?;
+        assert_eq!(bmt.changed_keys_since(&base_root, &base_root).count(), 0);
+
+        // Change five existing values, rewrite one unchanged value and add three new keys
+        let (mut new_keys, mut new_values) = prepare_inserts(3, &mut rng);
+        for i in (0..50).step_by(10) {
+            new_keys.push(keys[i]);
+            new_values.push(vec![0xFFu8; 4]);
+        }
+        let mut expected = new_keys.clone();
+        expected.sort();
+
+        let mut pairs = new_keys.into_iter().zip(new_values).collect::<Vec<_>>();
+        pairs.push((keys[100], values[100].clone()));
+        pairs.sort();
+        let (mut update_keys, update_values): (Vec<_>, Vec<_>) = pairs.into_iter().unzip();
+        let new_root = bmt.insert(Some(&base_root), &mut update_keys, &update_values)?;
+
+        let changed = bmt
+            .changed_keys_since(&base_root, &new_root)
+            .collect::<BinaryMerkleTreeResult<Vec<_>>>()?;
+        assert_eq!(changed, expected);
+        assert!(!changed.contains(&keys[100]));
+
+        // Removed keys are not reported
+        let mut removed = vec![keys[5]];
+        let empty_root = bmt.insert(None, &mut removed, &[values[5].clone()])?;
+        assert_eq!(bmt.changed_keys_since(&base_root, &empty_root).count(), 0);
+        assert_eq!(
+            bmt.changed_keys_since(&empty_root, &base_root).count(),
+            keys.len() - 1
+        );
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_values_over_the_maximum_size() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Encode;
+
+        let seed = [0x64u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut bmt = Tree::open(&path, 160)?;
+
+        let mut keys = vec![[0x01u8; KEY_LEN], [0x02u8; KEY_LEN]];
+        let allowed = vec![0xAAu8; 8];
+        let limit = allowed.encode()?.len();
+        bmt.set_max_value_bytes(limit);
+
+        let root = bmt.insert(None, &mut keys, &[allowed.clone(), allowed.clone()])?;
+
+        let oversized = vec![0xAAu8; 9];
+        assert_eq!(oversized.encode()?.len(), limit + 1);
+        let mut update = vec![[0x03u8; KEY_LEN]];
+        match bmt.insert(Some(&root), &mut update, &[oversized.clone()]) {
+            Err(e) => assert_eq!(
+                e.kind(),
+                &ErrorKind::ValueTooLarge {
+                    key: vec![0x03u8; KEY_LEN],
+                    size: limit + 1
+                }
+            ),
+            Ok(_) => panic!("Inserted a value over the maximum size"),
+        }
+
+        // Zero removes the limit
+        bmt.set_max_value_bytes(0);
+        let new_root = bmt.insert(Some(&root), &mut update, &[oversized.clone()])?;
+        assert_eq!(bmt.get_one(&new_root, &[0x03u8; KEY_LEN])?, Some(oversized));
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "use_rocksdb")))]
+    fn it_shares_a_read_only_view_between_threads() -> BinaryMerkleTreeResult<()> {
+        use std::sync::Arc;
+        use std::thread;
+
+        let seed = [0x65u8; KEY_LEN];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(100, &mut rng);
+
+        let mut bmt = HashTree::new(160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+        let reader = Arc::new(bmt.into_reader()?);
+
+        let handles = (0..4)
+            .map(|_| {
+                let reader = Arc::clone(&reader);
+                let keys = keys.clone();
+                let values = values.clone();
+                thread::spawn(move || -> BinaryMerkleTreeResult<()> {
+                    for (key, value) in keys.iter().zip(&values) {
+                        assert!(reader.contains_key(&root, key)?);
+                        assert_eq!(reader.get_one(&root, key)?.as_ref(), Some(value));
+                    }
+                    let proof = reader.generate_proof(&root, keys[0])?;
+                    assert_eq!(proof.key(), &keys[0]);
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        let entries = reader
+            .iter(&root)
+            .collect::<BinaryMerkleTreeResult<Vec<_>>>()?;
+        assert_eq!(
+            entries,
+            keys.iter()
+                .cloned()
+                .zip(values.iter().cloned())
+                .collect::<Vec<_>>()
+        );
+        assert!(!reader.contains_key(&root, &[0x00u8; KEY_LEN])?);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "use_rocksdb")))]
+    fn it_generates_the_same_proofs_from_a_reader() -> BinaryMerkleTreeResult<()> {
+        use std::sync::Arc;
+
+        use starling::hash_tree::HashTreeReader;
+        use starling::traits::Database;
+        use starling::tree_db::HashTreeDB;
+        use starling::utils::hash_scheme::{DomainTags, HashScheme};
+
+        let seed = [0x6Cu8; KEY_LEN];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(32, &mut rng);
+
+        let mut bmt = HashTree::new(160)?;
+        bmt.set_domain_tags(DomainTags::new(b"D", b"L", b"B"));
+        bmt.set_sorted_children(true);
+        let root = bmt.insert(None, &mut keys, &values)?;
+        let mut tree_proofs = Vec::with_capacity(keys.len());
+        for key in &keys {
+            tree_proofs.push(bmt.generate_proof(&root, *key)?);
+        }
+
+        let reader = bmt.into_reader()?;
+        for ((key, value), tree_proof) in keys.iter().zip(&values).zip(&tree_proofs) {
+            let proof = reader.generate_proof(&root, *key)?;
+            assert_eq!(&proof, tree_proof);
+            assert_eq!(proof.value(), value.as_slice());
+        }
+
+        let mut checker = HashTree::new(160)?;
+        checker.set_domain_tags(DomainTags::new(b"D", b"L", b"B"));
+        checker.set_sorted_children(true);
+        let path = reader.generate_inclusion_proof(&root, keys[3])?;
+        checker.verify_proof(&root, keys[3], &values[3], &path)?;
+
+        let mut scheme = HashScheme::new();
+        scheme.set_tags(DomainTags::new(b"D", b"L", b"B"));
+        scheme.set_sorted_children(true);
+        assert_eq!(reader.options().scheme(), &scheme);
+        let db = Arc::new(HashTreeDB::open(&std::path::PathBuf::new())?);
+        let scheme_reader: HashTreeReader = HashTreeReader::new_with_scheme(db, 160, scheme)?;
+        assert_eq!(scheme_reader.options(), reader.options());
+        Ok(())
+    }
+
+    #[test]
+    fn it_recounts_inflated_references() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x66u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(50, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+        // Inserting the same tree again bumps every count, although only one root is kept
+        assert_eq!(bmt.insert(None, &mut keys, &values)?, root);
+
+        let (mut new_keys, new_values) = prepare_inserts(5, &mut rng);
+        let new_root = bmt.insert(Some(&root), &mut new_keys, &new_values)?;
+
+        bmt.recount_references(&[root, new_root])?;
+
+        // One removal per live root now frees the orphaned nodes
+        bmt.remove(&root)?;
+        assert_eq!(bmt.get_one(&root, &keys[0])?, None);
+        for (key, value) in keys.iter().zip(&values) {
+            assert_eq!(bmt.get_one(&new_root, key)?.as_ref(), Some(value));
+        }
+        bmt.remove(&new_root)?;
+        assert_eq!(bmt.get_one(&new_root, &keys[0])?, None);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_stores_values_under_20_byte_addresses() -> BinaryMerkleTreeResult<()> {
+        use starling::constants::ADDRESS_LEN;
+        use starling::merkle_bit::MerkleBIT20;
+        use starling::tree_db::HashTreeDB;
+        use starling::tree_hasher::TreeHasher;
+
+        type AddressTree = MerkleBIT20<HashTreeDB<[u8; ADDRESS_LEN]>, TreeHasher>;
+
+        let seed = [0x67u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut addresses = (0..64)
+            .map(|_| {
+                let mut address = [0u8; ADDRESS_LEN];
+                rng.fill(&mut address);
+                address
+            })
+            .collect::<Vec<_>>();
+        // Addresses sharing all but the last bit exercise splits at the end of the key
+        addresses.push([0xFEu8; ADDRESS_LEN]);
+        let mut last = [0xFEu8; ADDRESS_LEN];
+        last[ADDRESS_LEN - 1] = 0xFF;
+        addresses.push(last);
+        addresses.sort();
+        let values = addresses
+            .iter()
+            .map(|a| a[..4].to_vec())
+            .collect::<Vec<_>>();
+
+        let mut tree = AddressTree::new(&path, 160)?;
+        let root = tree.insert(None, &mut addresses.clone(), &values)?;
+        assert_eq!(root.len(), ADDRESS_LEN);
+
+        let items = tree.get(&root, &mut addresses.clone())?;
+        for (address, value) in addresses.iter().zip(&values) {
+            assert_eq!(items[address].as_ref(), Some(value));
+            assert_eq!(tree.get_one(&root, address)?.as_ref(), Some(value));
+            let proof = tree.generate_inclusion_proof(&root, *address)?;
+            AddressTree::verify_inclusion_proof(&root, *address, value, &proof)?;
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "use_keccak")]
+    fn it_keeps_the_last_20_bytes_of_the_keccak_digest() {
+        use starling::constants::ADDRESS_LEN;
+        use starling::traits::Hasher;
+        use starling::tree_hasher::keccak::Keccak256Hasher;
+
+        // keccak256("") = c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470
+        let hasher = <Keccak256Hasher as Hasher<[u8; ADDRESS_LEN]>>::new(ADDRESS_LEN);
+        let address: [u8; ADDRESS_LEN] = hasher.finalize();
+        assert_eq!(
+            address,
+            [
+                0xdc, 0xc7, 0x03, 0xc0, 0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa,
+                0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70
+            ]
+        );
+    }
+
+    #[test]
+    fn it_gets_the_raw_encoded_value() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Encode;
+
+        let seed = [0x68u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(16, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+
+        for (key, value) in keys.iter().zip(&values) {
+            assert_eq!(bmt.get_raw(&root, key)?, Some(value.encode()?));
+        }
+        assert_eq!(bmt.get_raw(&root, &[0x00u8; KEY_LEN])?, None);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "use_rocksdb")))]
+    fn it_separates_trees_with_different_domains() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x69u8; KEY_LEN];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (keys, values) = prepare_inserts(32, &mut rng);
+
+        let mut roots = Vec::new();
+        let mut locations = Vec::new();
+        for domain in &[&b""[..], b"accounts", b"storage"] {
+            let mut tree = HashTree::with_domain(160, domain)?;
+            let root = tree.insert(None, &mut keys.clone(), &values)?;
+            let mut tree_locations = HashSet::new();
+            tree_locations.insert(root);
+            for key in &keys {
+                for (location, _) in tree.generate_inclusion_proof(&root, *key)? {
+                    tree_locations.insert(location);
+                }
+            }
+            roots.push(root);
+            locations.push(tree_locations);
+        }
+
+        // No domain gives the same locations as an ordinary tree
+        let mut plain = HashTree::new(160)?;
+        assert_eq!(plain.insert(None, &mut keys.clone(), &values)?, roots[0]);
+
+        for i in 0..locations.len() {
+            for j in i + 1..locations.len() {
+                assert_ne!(roots[i], roots[j]);
+                assert!(locations[i].is_disjoint(&locations[j]));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "use_rocksdb")))]
+    fn it_opens_a_tree_with_the_options_of_another() -> BinaryMerkleTreeResult<()> {
+        use starling::utils::hash_scheme::DomainTags;
+        use starling::utils::tree_options::TreeOptions;
+
+        let seed = [0x6Bu8; KEY_LEN];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(32, &mut rng);
+
+        let mut configured = HashTree::new(160)?;
+        configured.set_domain_tags(DomainTags::discriminants());
+        configured.set_sorted_children(true);
+        configured.set_max_batch_keys(64);
+        configured.set_strict_empty_inserts(false);
+        configured.set_collision_check(true);
+        let root = configured.insert(None, &mut keys, &values)?;
+
+        let options = configured.options().clone();
+        assert_ne!(options, TreeOptions::new());
+        assert!(!options.strict_empty_inserts());
+        assert!(options.collision_check());
+        assert!(options.strict_previous_root());
+
+        let mut reopened = HashTree::with_options(160, options.clone())?;
+        assert_eq!(reopened.options(), &options);
+        assert_eq!(reopened.insert(None, &mut keys, &values)?, root);
+        assert_eq!(
+            reopened.insert(None, &mut [], &[])?,
+            HashTree::<[u8; KEY_LEN], Vec<u8>>::empty_root()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_treats_an_unknown_previous_root_as_empty_when_lenient() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x6Au8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(16, &mut rng);
+        let bogus_root = [0xEEu8; KEY_LEN];
+
+        let mut bmt = Tree::open(&path, 160)?;
+        assert!(bmt.insert(Some(&bogus_root), &mut keys, &values).is_err());
+
+        bmt.set_strict_previous_root(false);
+        let root = bmt.insert(Some(&bogus_root), &mut keys, &values)?;
+        assert_eq!(root, bmt.insert(None, &mut keys, &values)?);
+        for (key, value) in keys.iter().zip(&values) {
+            assert_eq!(bmt.get_one(&root, key)?.as_ref(), Some(value));
+        }
+
+        // Known roots are still built upon
+        let (mut new_keys, new_values) = prepare_inserts(4, &mut rng);
+        let new_root = bmt.insert(Some(&root), &mut new_keys, &new_values)?;
+        assert_eq!(bmt.get_one(&new_root, &keys[0])?, Some(values[0].clone()));
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_gets_the_nodes_at_a_depth() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x6Bu8; KEY_LEN];
+        let path = generate_path(seed);
+
+        // Sixteen keys differing in their first four bits form a balanced tree
+        let mut keys = (0..16u8)
+            .map(|i| {
+                let mut key = [0x00u8; KEY_LEN];
+                key[0] = i << 4;
+                key
+            })
+            .collect::<Vec<_>>();
+        let values = keys.iter().map(|k| vec![k[0]]).collect::<Vec<_>>();
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+
+        assert_eq!(bmt.nodes_at_depth(&root, 0)?, vec![root]);
+
+        let quarters = bmt.nodes_at_depth(&root, 2)?;
+        assert_eq!(quarters.len(), 4);
+        let mut found = Vec::new();
+        for (i, location) in quarters.iter().enumerate() {
+            let prefix = [(i as u8) << 6];
+            assert_eq!(bmt.subtree_root(&root, &prefix, 2)?, Some(*location));
+            for entry in bmt.iter(location) {
+                found.push(entry?.0);
+            }
+        }
+        assert_eq!(found, keys);
+
+        // Leaves above the requested depth are returned as they are
+        let leaves = bmt.nodes_at_depth(&root, 4)?;
+        assert_eq!(leaves.len(), 16);
+        assert_eq!(bmt.nodes_at_depth(&root, 6)?, leaves);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_finds_the_keys_holding_a_value() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x6Cu8; KEY_LEN];
+        let path = generate_path(seed);
+        let first = [0x01u8; KEY_LEN];
+        let second = [0x02u8; KEY_LEN];
+        let third = [0x03u8; KEY_LEN];
+        let a = vec![0xAAu8; 4];
+        let b = vec![0xBBu8; 4];
+
+        let mut bmt = Tree::open(&path, 160)?;
+        assert!(bmt.keys_for_value(&[0x00u8; KEY_LEN], &a).is_err());
+        bmt.set_value_index(true)?;
+
+        let mut keys = vec![first, second, third];
+        let root = bmt.insert(None, &mut keys, &[a.clone(), a.clone(), b.clone()])?;
+        assert_eq!(bmt.keys_for_value(&root, &a)?, vec![first, second]);
+        assert_eq!(bmt.keys_for_value(&root, &b)?, vec![third]);
+        assert!(bmt.keys_for_value(&root, &vec![0xCCu8; 4])?.is_empty());
+
+        // Overwriting a key moves it to its new value under the new root only
+        let new_root = bmt.insert_one(Some(&root), &first, &b)?;
+        assert_eq!(bmt.keys_for_value(&new_root, &a)?, vec![second]);
+        assert_eq!(bmt.keys_for_value(&new_root, &b)?, vec![first, third]);
+        assert_eq!(bmt.keys_for_value(&root, &a)?, vec![first, second]);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_inlines_small_values_into_leaves() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Encode;
+
+        let seed = [0x6Du8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut inlined = Tree::open(&path, 160)?;
+        let external_path = generate_path([0x6Eu8; KEY_LEN]);
+        let mut external = Tree::open(&external_path, 160)?;
+
+        let small = vec![0x11u8; 4];
+        let large = vec![0x22u8; 64];
+        inlined.set_inline_value_bytes(large.encode()?.len());
+
+        let mut keys = vec![
+            [0x01u8; KEY_LEN],
+            [0x02u8; KEY_LEN],
+            [0x03u8; KEY_LEN],
+            [0x04u8; KEY_LEN],
+        ];
+        let values = vec![small.clone(), large.clone(), small.clone(), large.clone()];
+        let root = inlined.insert(None, &mut keys.clone(), &values)?;
+        let external_root = external.insert(None, &mut keys.clone(), &values)?;
+
+        // Inlining does not change the root, and only the large values get data nodes
+        assert_eq!(root, external_root);
+        assert_eq!(inlined.dedup_stats().written_data_nodes, 2);
+        assert_eq!(external.dedup_stats().written_data_nodes, 4);
+
+        let items = inlined.get(&root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+            assert_eq!(inlined.get_one(&root, key)?, Some(value.clone()));
+
+            let proof = inlined.generate_inclusion_proof(&root, *key)?;
+            assert_eq!(
+                proof,
+                external.generate_inclusion_proof(&external_root, *key)?
+            );
+            Tree::verify_inclusion_proof(&root, *key, value, &proof)?;
+        }
+
+        inlined.remove(&root)?;
+        assert_eq!(inlined.get_one(&root, &keys[0])?, None);
+
+        tear_down(&path);
+        tear_down(&external_path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "use_rocksdb")))]
+    fn it_gets_shared_references_to_inlined_values() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x6Fu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        tree.set_inline_value_bytes(values[0].len() + 1);
+        let root = tree.insert(None, &mut keys, &values)?;
+        assert_eq!(tree.dedup_stats().written_data_nodes, 0);
+
+        let items = tree.get_ref(&root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            if let Some(shared) = &items[key] {
+                assert_eq!(shared.as_ref(), &value[..]);
+            } else {
+                panic!("Failed to get a shared value");
+            }
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_iterates_keys_in_big_endian_order() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x70u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut bmt = Tree::open(&path, 160)?;
+
+        // These numbers sort differently as little-endian bytes, so only big-endian encoding
+        // keeps their numeric order
+        let numbers = [1u64, 256, 2, 65536, 3, 255];
+        let mut keys = numbers
+            .iter()
+            .map(|n| {
+                let mut key = [0u8; KEY_LEN];
+                key[..8].copy_from_slice(&n.to_be_bytes());
+                key
+            })
+            .collect::<Vec<_>>();
+        let values = numbers
+            .iter()
+            .map(|n| n.to_be_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let root = bmt.insert(None, &mut keys, &values)?;
+
+        let mut sorted_numbers = numbers.to_vec();
+        sorted_numbers.sort();
+        let iterated = bmt
+            .iter(&root)
+            .map(|entry| {
+                let (key, _) = entry?;
+                let mut number = [0u8; 8];
+                number.copy_from_slice(&key[..8]);
+                Ok(u64::from_be_bytes(number))
+            })
+            .collect::<BinaryMerkleTreeResult<Vec<_>>>()?;
+        assert_eq!(iterated, sorted_numbers);
+
+        // The same holds for arbitrary keys compared byte by byte
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut random_keys, random_values) = prepare_inserts(256, &mut rng);
+        let random_root = bmt.insert(None, &mut random_keys, &random_values)?;
+        let iterated = bmt
+            .iter(&random_root)
+            .map(|entry| entry.map(|(key, _)| key))
+            .collect::<BinaryMerkleTreeResult<Vec<_>>>()?;
+        let mut expected = random_keys.clone();
+        expected.sort();
+        assert_eq!(iterated, expected);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_imports_proven_nodes_into_an_empty_tree() -> BinaryMerkleTreeResult<()> {
+        use starling::utils::proof::Proof;
+
+        let seed = [0x71u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut source = Tree::open(&path, 160)?;
+        let root = source.insert(None, &mut keys, &values)?;
+
+        let import_path = generate_path([0x72u8; KEY_LEN]);
+        let mut local = Tree::open(&import_path, 160)?;
+
+        // A proof for a different value is rejected without writing anything
+        let proof = source.generate_proof(&root, keys[3])?;
+        let forged = Proof::new(keys[3], vec![0xFFu8; 4], proof.siblings().to_vec());
+        assert!(local.import_proof(&root, &forged).is_err());
+        assert_eq!(local.get_one(&root, &keys[3])?, None);
+
+        let received = Proof::from_bytes(&proof.to_bytes()?)?;
+        local.import_proof(&root, &received)?;
+        local.import_proof(&root, &source.generate_proof(&root, keys[40])?)?;
+
+        assert_eq!(local.get_one(&root, &keys[3])?, Some(values[3].clone()));
+        let mut proven = vec![keys[3], keys[40]];
+        let items = local.get(&root, &mut proven)?;
+        assert_eq!(items[&keys[3]], Some(values[3].clone()));
+        assert_eq!(items[&keys[40]], Some(values[40].clone()));
+        assert_eq!(
+            local.generate_proof(&root, keys[40])?,
+            source.generate_proof(&root, keys[40])?
+        );
+
+        tear_down(&path);
+        tear_down(&import_path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_treats_nodes_older_than_the_ttl_as_absent() -> BinaryMerkleTreeResult<()> {
+        use std::time::Duration;
+
+        use starling::traits::{Data, Database, NodeVariant};
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_db::ttl::TtlDatabase;
+        use starling::tree_db::HashTreeDB;
+
+        let seed = [0x73u8; KEY_LEN];
+        let path = generate_path(seed);
+        let location = [0x01u8; KEY_LEN];
+
+        let mut data = TreeData::new();
+        data.set_value(&[0xAAu8; 4]);
+        let node = TreeNode::new(NodeVariant::Data(data));
+
+        let ttl = Duration::from_millis(50);
+        let mut db = TtlDatabase::new(HashTreeDB::open(&path)?, ttl);
+        db.insert(location, node.clone())?;
+        db.batch_write()?;
+        assert_eq!(db.get_node(location)?, Some(node.clone()));
+        let missing = [0x02u8; KEY_LEN];
+        assert_eq!(
+            db.get_nodes(&[location, missing])?,
+            vec![Some(node.clone()), None]
+        );
+
+        std::thread::sleep(ttl * 2);
+        assert_eq!(db.get_node(location)?, None);
+        assert_eq!(db.get_nodes(&[location, missing])?, vec![None, None]);
+        assert!(db.inner().get_node(location)?.is_some());
+
+        assert_eq!(db.purge_expired()?, 1);
+        assert_eq!(db.inner().get_node(location)?, None);
+
+        // Inserting again restarts the time to live
+        db.insert(location, node.clone())?;
+        assert_eq!(db.get_node(location)?, Some(node));
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rebuilds_lost_branches_from_leaves() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Data, Database, Encode, Leaf, Node, NodeVariant};
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_leaf::TreeLeaf;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_db::HashTreeDB;
+        use starling::tree_hasher::TreeHasher;
+        use starling::utils::hash_scheme::HashScheme;
+
+        type HashDBTree = starling::merkle_bit::MerkleBIT<
+            HashTreeDB<[u8; KEY_LEN]>,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            TreeLeaf<[u8; KEY_LEN]>,
+            TreeData,
+            TreeNode<[u8; KEY_LEN]>,
+            TreeHasher,
+            Vec<u8>,
+            [u8; KEY_LEN],
+        >;
+
+        let seed = [0x74u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+
+        // A database holding only the leaf and data nodes, as if every branch was lost
+        let scheme = HashScheme::new();
+        let mut db = HashTreeDB::open(&path)?;
+        let mut leaf_locations = Vec::with_capacity(keys.len());
+        for (key, value) in keys.iter().zip(values.iter()) {
+            let encoded = value.encode()?;
+            let data_location = scheme.data_location::<TreeHasher, _>(key, &encoded);
+            let mut data = TreeData::new();
+            data.set_value(&encoded);
+            let mut data_node = TreeNode::<[u8; KEY_LEN]>::new(NodeVariant::Data(data));
+            data_node.set_references(1);
+            db.insert(data_location, data_node)?;
+
+            let mut leaf = TreeLeaf::new();
+            leaf.set_key(*key);
+            leaf.set_data(data_location);
+            let leaf_location = scheme.leaf_location::<TreeHasher, _>(key, &data_location);
+            let mut leaf_node = TreeNode::new(NodeVariant::Leaf(leaf));
+            leaf_node.set_references(1);
+            db.insert(leaf_location, leaf_node)?;
+            leaf_locations.push(leaf_location);
+        }
+
+        let mut recovered = HashDBTree::from_db(db, 160)?;
+        assert_eq!(recovered.get_one(&root, &keys[0])?, None);
+
+        leaf_locations.reverse();
+        let recovered_root = recovered.rebuild_from_leaves(&leaf_locations)?;
+        assert_eq!(recovered_root, root);
+        let items = recovered.get(&root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        // A location holding anything but a leaf is rejected
+        let data_location = scheme.data_location::<TreeHasher, _>(&keys[0], &values[0].encode()?);
+        assert!(recovered.rebuild_from_leaves(&[data_location]).is_err());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_expires_a_weak_snapshot_once_its_root_is_reclaimed() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x75u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+        let mut bmt = Tree::open(&path, 160)?;
+        let first_root = bmt.insert(None, &mut keys, &values)?;
+        let snapshot = bmt.weak_snapshot(&first_root)?;
+        assert!(snapshot.is_weak());
+
+        // Removing an unrelated root advances the epoch, but the snapshot root survives
+        let mut other_keys = vec![[0x01u8; KEY_LEN]];
+        let other_root = bmt.insert(None, &mut other_keys, &[vec![0x01u8]])?;
+        bmt.remove(&other_root)?;
+        let items = bmt.get_snapshot(&snapshot, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        // The weak snapshot does not keep the root alive
+        bmt.remove(&first_root)?;
+        match bmt.get_snapshot(&snapshot, &mut keys) {
+            Err(e) => assert_eq!(e.kind(), &ErrorKind::SnapshotExpired),
+            Ok(_) => panic!("Read a weak snapshot after its root was reclaimed"),
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "use_rocksdb")))]
+    fn it_inserts_into_a_tree_created_with_capacity() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x76u8; KEY_LEN];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut sized = Tree::with_capacity(160, keys.len() * 3)?;
+        let mut unsized_tree = Tree::new(160)?;
+        let root = sized.insert(None, &mut keys, &values)?;
+        assert_eq!(root, unsized_tree.insert(None, &mut keys, &values)?);
+
+        let items = sized.get(&root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_shares_all_but_the_changed_path_after_a_single_insert() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x77u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let num_keys = 1024;
+        let (mut keys, values) = prepare_inserts(num_keys, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+
+        // Every data node, leaf and branch of the tree
+        let total = 3 * num_keys - 1;
+        assert_eq!(bmt.shared_node_count(&root, &root)?, total);
+
+        let mut new_key = [0u8; KEY_LEN];
+        rng.fill(&mut new_key);
+        let new_root = bmt.insert_one(Some(&root), &new_key, &vec![0x01u8])?;
+
+        // Only the branches on the path to the new key are rewritten
+        let shared = bmt.shared_node_count(&root, &new_root)?;
+        assert!(shared < total);
+        assert!(total - shared <= 2 * 10 + 2);
+
+        let mut other_keys = vec![[0x00u8; KEY_LEN]];
+        let other_root = bmt.insert(None, &mut other_keys, &[vec![0xEEu8; 3]])?;
+        assert_eq!(bmt.shared_node_count(&root, &other_root)?, 0);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_stores_large_values_in_a_blob_store() -> BinaryMerkleTreeResult<()> {
+        use std::sync::Arc;
+
+        use starling::traits::{BlobStore, Data, Database, Encode, NodeVariant};
+        use starling::tree_db::blob::MemoryBlobStore;
+        use starling::tree_hasher::TreeHasher;
+        use starling::utils::hash_scheme::HashScheme;
+
+        let seed = [0x78u8; KEY_LEN];
+        let path = generate_path(seed);
+        let db = CountingDB::open(&path)?;
+        let mut bmt = CountedTree::from_db(db.clone(), 160)?;
+        let store = Arc::new(MemoryBlobStore::new());
+
+        let small = vec![0x11u8; 8];
+        let large = vec![0x22u8; 4096];
+        bmt.set_blob_store(store.clone(), 1024);
+
+        let mut keys = vec![[0x01u8; KEY_LEN], [0x02u8; KEY_LEN]];
+        let values = vec![small.clone(), large.clone()];
+        let root = bmt.insert(None, &mut keys, &values)?;
+        assert_eq!(store.len()?, 1);
+
+        // The data node of the large value holds only the key of its blob, which is its location
+        let scheme = HashScheme::new();
+        let encoded = large.encode()?;
+        let data_location = scheme.data_location::<TreeHasher, _>(&keys[1], &encoded);
+        let blob_key = data_location;
+        match db.get_node(data_location)?.map(|node| node.node) {
+            Some(NodeVariant::Data(data)) => {
+                assert!(data.is_blob_ref());
+                assert_eq!(data.get_value(), &blob_key[..]);
+            }
+            _ => panic!("Failed to find the data node"),
+        }
+        assert_eq!(store.get_blob(&blob_key)?, Some(encoded));
+
+        let items = bmt.get(&root, &mut keys)?;
+        assert_eq!(items[&keys[0]], Some(small.clone()));
+        assert_eq!(items[&keys[1]], Some(large.clone()));
+        assert_eq!(bmt.get_one(&root, &keys[1])?, Some(large.clone()));
+
+        // Proofs still cover the whole value
+        let proof = bmt.generate_inclusion_proof(&root, keys[1])?;
+        CountedTree::verify_inclusion_proof(&root, keys[1], &large, &proof)?;
+
+        // Without the blob store, the value cannot be read
+        let without_store = CountedTree::from_db(db, 160)?;
+        assert!(without_store.get_one(&root, &keys[1]).is_err());
+        assert_eq!(without_store.get_one(&root, &keys[0])?, Some(small));
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_deletes_the_blob_of_a_removed_data_node() -> BinaryMerkleTreeResult<()> {
+        use std::sync::Arc;
+
+        use starling::traits::{BlobStore, Encode};
+        use starling::tree_db::blob::MemoryBlobStore;
+        use starling::tree_hasher::TreeHasher;
+        use starling::utils::hash_scheme::HashScheme;
+
+        let seed = [0x7Au8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut bmt = Tree::open(&path, 160)?;
+        let store = Arc::new(MemoryBlobStore::new());
+        bmt.set_blob_store(store.clone(), 1024);
+
+        let large = vec![0x22u8; 4096];
+        let other = vec![0x33u8; 4096];
+        let root = bmt.insert(None, &mut [[0x01u8; KEY_LEN]], std::slice::from_ref(&large))?;
+        // The second root shares the data node, and with it the blob, of the first
+        let mut keys = vec![[0x01u8; KEY_LEN], [0x02u8; KEY_LEN]];
+        let second_root = bmt.insert(None, &mut keys, &[large.clone(), other])?;
+        assert_eq!(store.len()?, 2);
+
+        // A blob outlives a removed root while another root still references its data node
+        let blob_key = HashScheme::new().data_location::<TreeHasher, _>(&keys[0], &large.encode()?);
+        bmt.remove(&root)?;
+        assert_eq!(store.len()?, 2);
+        assert_eq!(store.get_blob(&blob_key)?, Some(large.encode()?));
+        assert_eq!(bmt.get_one(&second_root, &keys[0])?, Some(large));
+
+        bmt.remove(&second_root)?;
+        assert!(store.is_empty()?);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_paginates_entries_with_a_cursor() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x79u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(95, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+        let expected = bmt
+            .iter(&root)
+            .collect::<BinaryMerkleTreeResult<Vec<_>>>()?;
+
+        let mut paged = Vec::new();
+        let mut cursor = None;
+        let mut pages = 0;
+        loop {
+            let (page, next) = bmt.iter_from(&root, cursor, 10)?;
+            assert!(page.len() <= 10);
+            paged.extend(page);
+            pages += 1;
+            cursor = next;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages, 10);
+        assert_eq!(paged, expected);
+
+        // A cursor between keys resumes at the next larger key
+        let mut between = expected[41].0;
+        between[KEY_LEN - 1] = between[KEY_LEN - 1].wrapping_add(1);
+        let (page, _) = bmt.iter_from(&root, Some(between), 3)?;
+        assert_eq!(page, expected[42..45].to_vec());
+
+        // Nothing sorts after the largest key
+        let (page, next) = bmt.iter_from(&root, Some(expected[94].0), 10)?;
+        assert!(page.is_empty());
+        assert_eq!(next, None);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_dumps_the_tree_structure() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x7Au8; KEY_LEN];
+        let path = generate_path(seed);
+        let key_a = [0x00u8; KEY_LEN];
+        let mut key_b = [0x00u8; KEY_LEN];
+        let mut key_c = [0x00u8; KEY_LEN];
+        key_b[0] = 0x80;
+        key_c[0] = 0xC0;
+        let values = vec![vec![0x01u8], vec![0x02u8, 0x02u8], vec![0x03u8; 3]];
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut [key_a, key_b, key_c], &values)?;
+
+        let mut dump = Vec::new();
+        bmt.debug_dump(&root, &mut dump)?;
+        let dump = String::from_utf8(dump).unwrap();
+        let lines = dump.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 8);
+
+        let hex =
+            |key: &[u8; KEY_LEN]| key.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert!(lines[0].starts_with("branch "));
+        assert!(lines[0].ends_with(" split=0 count=3"));
+        assert!(lines[1].starts_with("  leaf "));
+        assert!(lines[1].ends_with(&format!(" key={}", hex(&key_a))));
+        assert!(lines[2].starts_with("    data "));
+        assert!(lines[2].ends_with(" len=1"));
+        assert!(lines[3].starts_with("  branch "));
+        assert!(lines[3].ends_with(" split=1 count=2"));
+        assert!(lines[4].starts_with("    leaf "));
+        assert!(lines[4].ends_with(&format!(" key={}", hex(&key_b))));
+        assert!(lines[5].starts_with("      data "));
+        assert!(lines[5].ends_with(" len=2"));
+        assert!(lines[6].starts_with("    leaf "));
+        assert!(lines[6].ends_with(&format!(" key={}", hex(&key_c))));
+        assert!(lines[7].starts_with("      data "));
+        assert!(lines[7].ends_with(" len=3"));
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_hashes_nodes_with_configurable_domain_tags() -> BinaryMerkleTreeResult<()> {
+        use starling::tree_hasher::TreeHasher;
+        use starling::utils::hash_scheme::{DomainTags, HashScheme};
+
+        let seed = [0x7Bu8; KEY_LEN];
+        let path_a = generate_path(seed);
+        let path_b = generate_path([0x7Cu8; KEY_LEN]);
+        let path_c = generate_path([0x7Du8; KEY_LEN]);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(32, &mut rng);
+
+        let mut default_tree = Tree::open(&path_a, 160)?;
+        let default_root = default_tree.insert(None, &mut keys, &values)?;
+        let mut tagged_tree = Tree::open(&path_b, 160)?;
+        tagged_tree.set_domain_tags(DomainTags::discriminants());
+        let tagged_root = tagged_tree.insert(None, &mut keys, &values)?;
+        assert_ne!(default_root, tagged_root);
+
+        // The same tags always produce the same locations, and the defaults match the old tags
+        let mut explicit_tree = Tree::open(&path_c, 160)?;
+        explicit_tree.set_domain_tags(DomainTags::new(b"d", b"l", b"b"));
+        assert_eq!(
+            explicit_tree.insert(None, &mut keys, &values)?,
+            default_root
+        );
+        explicit_tree.set_domain_tags(DomainTags::discriminants());
+        assert_eq!(explicit_tree.insert(None, &mut keys, &values)?, tagged_root);
+
+        let proof = tagged_tree.generate_inclusion_proof(&tagged_root, keys[5])?;
+        tagged_tree.verify_proof(&tagged_root, keys[5], &values[5], &proof)?;
+        assert!(tagged_tree
+            .verify_proof(&default_root, keys[5], &values[5], &proof)
+            .is_err());
+
+        // A single key tree is rooted at its leaf, hashed with the configured tags
+        let mut scheme = HashScheme::new();
+        scheme.set_tags(DomainTags::discriminants());
+        let single_root = tagged_tree.insert(None, &mut [keys[0]], &[values[0].clone()])?;
+        let data_location = scheme.data_location::<TreeHasher, _>(&keys[0], &values[0]);
+        assert_eq!(
+            single_root,
+            scheme.leaf_location::<TreeHasher, _>(&keys[0], &data_location)
+        );
+
+        tear_down(&path_a);
+        tear_down(&path_b);
+        tear_down(&path_c);
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_an_old_root_while_inserting() -> BinaryMerkleTreeResult<()> {
+        use std::sync::Arc;
+
+        use starling::traits::Database;
+        use starling::tree_db::concurrent::{ConcurrentDatabase, ConcurrentReader};
+        use starling::tree_db::HashTreeDB;
+
+        type ConcurrentTree = starling::merkle_bit::MerkleBIT<
+            ConcurrentDatabase<HashTreeDB<[u8; KEY_LEN]>, [u8; KEY_LEN]>,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+            starling::tree::tree_data::TreeData,
+            starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+            starling::tree_hasher::TreeHasher,
+            Vec<u8>,
+            [u8; KEY_LEN],
+        >;
+        type ReaderTree = starling::merkle_bit::MerkleBIT<
+            ConcurrentReader<HashTreeDB<[u8; KEY_LEN]>>,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+            starling::tree::tree_data::TreeData,
+            starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+            starling::tree_hasher::TreeHasher,
+            Vec<u8>,
+            [u8; KEY_LEN],
+        >;
+
+        let seed = [0x7Eu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(256, &mut rng);
+
+        let db = ConcurrentDatabase::new(HashTreeDB::open(&path)?);
+        let reader_db = db.handle();
+        let late_db = db.handle();
+        let mut writer = ConcurrentTree::from_db(db, 160)?;
+        let old_root = writer.insert(None, &mut keys, &values)?;
+        let expected = keys
+            .iter()
+            .copied()
+            .zip(values.iter().cloned())
+            .collect::<Vec<_>>();
+
+        let done = Arc::new(AtomicBool::new(false));
+        let reader_done = Arc::clone(&done);
+        let reader_expected = expected.clone();
+        let reader = std::thread::spawn(move || -> BinaryMerkleTreeResult<usize> {
+            let reader = ReaderTree::from_db(reader_db, 160)?;
+            let mut reads = 0;
+            while !reader_done.load(Ordering::SeqCst) || reads == 0 {
+                let entries = reader
+                    .iter(&old_root)
+                    .collect::<BinaryMerkleTreeResult<Vec<_>>>()?;
+                assert_eq!(entries.len(), reader_expected.len());
+                for ((key, value), (expected_key, expected_value)) in
+                    entries.iter().zip(reader_expected.iter())
+                {
+                    assert_eq!(key, expected_key);
+                    assert_eq!(value, expected_value);
+                }
+                reads += 1;
+            }
+            Ok(reads)
+        });
+
+        let mut root = old_root;
+        for _ in 0..16 {
+            let (mut new_keys, new_values) = prepare_inserts(64, &mut rng);
+            root = writer.insert(Some(&root), &mut new_keys, &new_values)?;
+        }
+        done.store(true, Ordering::SeqCst);
+        let reads = reader.join().expect("reader thread panicked")?;
+        assert!(reads > 0);
+
+        // Once the writes commit, readers see the new root in full
+        let mut reader = ReaderTree::from_db(late_db, 160)?;
+        assert_eq!(
+            reader
+                .iter(&root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+                .len(),
+            256 + 16 * 64
+        );
+
+        // Handles only read, so the writer is the only tree changing the database
+        assert!(reader
+            .insert(Some(&root), &mut [[0x00u8; KEY_LEN]], &[vec![0x00u8]])
+            .is_err());
+        assert_eq!(
+            writer
+                .iter(&root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+                .len(),
+            256 + 16 * 64
+        );
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "use_borsh")]
+    fn it_round_trips_nodes_through_borsh() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Branch, Data, Decode, Encode, Leaf, Node, NodeVariant};
+        use starling::tree::tree_branch::TreeBranch;
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_leaf::TreeLeaf;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_hasher::TreeHasher;
+        use starling::utils::hash_scheme::HashScheme;
+
+        let mut branch = TreeBranch::new();
+        branch.set_count(3);
+        branch.set_zero([0x01u8; KEY_LEN]);
+        branch.set_one([0x02u8; KEY_LEN]);
+        branch.set_split_index(7);
+        branch.set_key([0x03u8; KEY_LEN]);
+
+        let mut leaf = TreeLeaf::new();
+        leaf.set_key([0x04u8; KEY_LEN]);
+        leaf.set_data([0x05u8; KEY_LEN]);
+        let mut inline_leaf = leaf.clone();
+        inline_leaf.set_inline_value(&[0x06u8; 5]);
+
+        let mut data = TreeData::new();
+        data.set_value(&[0x07u8; 12]);
+
+        for variant in vec![
+            NodeVariant::Branch(branch),
+            NodeVariant::Leaf(leaf),
+            NodeVariant::Leaf(inline_leaf),
+            NodeVariant::Data(data),
+        ] {
+            let mut node = TreeNode::new(variant);
+            node.set_references(9);
+            let encoded = node.encode()?;
+            assert_eq!(TreeNode::<[u8; KEY_LEN]>::decode(&encoded)?, node);
+        }
+
+        // The tree hashes node fields rather than their encoding
+        let seed = [0x7Fu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut bmt = Tree::open(&path, 160)?;
+        let key = [0xAAu8; KEY_LEN];
+        let value = vec![0xBBu8; 4];
+        let root = bmt.insert(None, &mut [key], &[value.clone()])?;
+        let scheme = HashScheme::new();
+        let data_location = scheme.data_location::<TreeHasher, _>(&key, &value);
+        assert_eq!(
+            root,
+            scheme.leaf_location::<TreeHasher, _>(&key, &data_location)
+        );
+        assert_eq!(bmt.get_one(&root, &key)?, Some(value));
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(any(
+        feature = "use_bincode",
+        feature = "use_json",
+        feature = "use_cbor",
+        feature = "use_yaml",
+        feature = "use_pickle",
+        feature = "use_ron",
+        feature = "use_borsh"
+    ))]
+    fn it_measures_the_storage_of_a_root() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Data, Encode, Leaf, Node, NodeVariant};
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_leaf::TreeLeaf;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_hasher::TreeHasher;
+        use starling::utils::hash_scheme::HashScheme;
+
+        let seed = [0x80u8; KEY_LEN];
+        let path = generate_path(seed);
+        let fresh_path = generate_path([0x81u8; KEY_LEN]);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        // A single key tree holds exactly one leaf and one data node
+        let mut bmt = Tree::open(&path, 160)?;
+        let key = [0x42u8; KEY_LEN];
+        let value = vec![0x43u8; 10];
+        let single_root = bmt.insert(None, &mut [key], &[value.clone()])?;
+        let scheme = HashScheme::new();
+        let data_location = scheme.data_location::<TreeHasher, _>(&key, &value);
+        let mut data = TreeData::new();
+        data.set_value(&value);
+        let mut data_node = TreeNode::<[u8; KEY_LEN]>::new(NodeVariant::Data(data));
+        data_node.set_references(1);
+        let mut leaf = TreeLeaf::new();
+        leaf.set_key(key);
+        leaf.set_data(data_location);
+        let mut leaf_node = TreeNode::new(NodeVariant::Leaf(leaf));
+        leaf_node.set_references(1);
+        let expected = leaf_node.encode()?.len() + data_node.encode()?.len();
+        assert_eq!(bmt.storage_bytes(&single_root)?, expected as u64);
+
+        // A tree built on another only reports the nodes reachable from its own root once
+        let root = bmt.insert(None, &mut keys, &values)?;
+        let base_bytes = bmt.storage_bytes(&root)?;
+        let mut update_key = [keys[10]];
+        let updated_root = bmt.insert(Some(&root), &mut update_key, &[vec![0x44u8; 10]])?;
+        let updated_bytes = bmt.storage_bytes(&updated_root)?;
+
+        let mut updated_values = values.clone();
+        updated_values[10] = vec![0x44u8; 10];
+        let mut fresh = Tree::open(&fresh_path, 160)?;
+        let fresh_root = fresh.insert(None, &mut keys, &updated_values)?;
+        assert_eq!(fresh_root, updated_root);
+        assert_eq!(updated_bytes, fresh.storage_bytes(&fresh_root)?);
+        let fresh_base_root = fresh.insert(None, &mut keys, &values)?;
+        assert_eq!(base_bytes, fresh.storage_bytes(&fresh_base_root)?);
+
+        tear_down(&path);
+        tear_down(&fresh_path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_overwrites_identical_values_without_writing_nodes() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+
+        let seed = [0x82u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(128, &mut rng);
+
+        let db = CountingDB::open(&path)?;
+        let mut bmt = CountedTree::from_db(db.clone(), 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+        let written = db.new_nodes.load(Ordering::SeqCst);
+
+        let same_root = bmt.insert(Some(&root), &mut keys, &values)?;
+        assert_eq!(same_root, root);
+        let same_root = bmt.insert_one(Some(&root), &keys[3], &values[3])?;
+        assert_eq!(same_root, root);
+        assert_eq!(db.new_nodes.load(Ordering::SeqCst), written);
+
+        // Only the changed key is written when the batch also holds unchanged ones
+        let mut changed_values = values.clone();
+        changed_values[7] = vec![0xEEu8; 4];
+        let changed_root = bmt.insert(Some(&root), &mut keys, &changed_values)?;
+        assert_ne!(changed_root, root);
+        assert_eq!(bmt.get_one(&changed_root, &keys[7])?, Some(vec![0xEEu8; 4]));
+        assert!(db.new_nodes.load(Ordering::SeqCst) - written <= 2 + 22);
+
+        // Each insert returning the old root holds its own reference to it
+        bmt.remove(&root)?;
+        bmt.remove(&root)?;
+        assert_eq!(bmt.get_one(&root, &keys[0])?, Some(values[0].clone()));
+        bmt.remove(&root)?;
+        assert_eq!(bmt.get_one(&root, &keys[0])?, None);
+        assert_eq!(
+            bmt.get_one(&changed_root, &keys[0])?,
+            Some(values[0].clone())
+        );
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_reuses_a_build_scratch_across_inserts() -> BinaryMerkleTreeResult<()> {
+        use starling::utils::tree_build_scratch::TreeBuildScratch;
+
+        let seed = [0x83u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(200, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let mut scratch = TreeBuildScratch::new();
+        let mut root = None;
+        for (key_chunk, value_chunk) in keys.clone().chunks_mut(40).zip(values.chunks(40)) {
+            root = Some(bmt.insert_with_scratch(
+                root.as_ref(),
+                key_chunk,
+                value_chunk,
+                &mut scratch,
+            )?);
+        }
+        assert!(scratch.capacity() >= 40);
+
+        let root = root.unwrap();
+        let mut fresh = Tree::open(&path, 160)?;
+        assert_eq!(fresh.insert(None, &mut keys, &values)?, root);
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(bmt.get_one(&root, key)?, Some(value.clone()));
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_finds_orphaned_nodes_without_removing_them() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x84u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut live_keys, live_values) = prepare_inserts(48, &mut rng);
+        let (mut orphan_keys, orphan_values) = prepare_inserts(16, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let live_root = bmt.insert(None, &mut live_keys, &live_values)?;
+        let orphan_root = bmt.insert(None, &mut orphan_keys, &orphan_values)?;
+
+        // Every branch, leaf and data node of the unreferenced tree is reported
+        let orphans = bmt.find_orphans(&[live_root])?;
+        assert_eq!(orphans.len(), 3 * orphan_keys.len() - 1);
+        assert_eq!(
+            orphans.len(),
+            bmt.shared_node_count(&orphan_root, &orphan_root)?
+        );
+        assert!(orphans.binary_search(&orphan_root).is_ok());
+        assert!(orphans.binary_search(&live_root).is_err());
+        assert!(orphans.windows(2).all(|pair| pair[0] < pair[1]));
+
+        assert!(bmt.find_orphans(&[live_root, orphan_root])?.is_empty());
+        assert_eq!(
+            bmt.find_orphans(&[])?.len(),
+            3 * (live_keys.len() + orphan_keys.len()) - 2
+        );
+
+        // Nothing was removed
+        for (key, value) in orphan_keys.iter().zip(orphan_values.iter()) {
+            assert_eq!(bmt.get_one(&orphan_root, key)?, Some(value.clone()));
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_gets_values_with_the_depth_they_resolved_at() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x85u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut clustered_a = [0x40u8; KEY_LEN];
+        clustered_a[KEY_LEN - 1] = 0x00;
+        let mut clustered_b = [0x40u8; KEY_LEN];
+        clustered_b[KEY_LEN - 1] = 0x01;
+        let shallow = [0x80u8; KEY_LEN];
+        let middle = [0x00u8; KEY_LEN];
+        let missing = [0xFFu8; KEY_LEN];
+
+        let mut keys = vec![clustered_a, clustered_b, shallow, middle];
+        let values = vec![vec![0x01u8], vec![0x02u8], vec![0x03u8], vec![0x04u8]];
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+
+        let mut lookup = vec![clustered_a, clustered_b, shallow, middle, missing];
+        let items = bmt.get_with_depth(&root, &mut lookup)?;
+        assert_eq!(items.len(), 5);
+        assert_eq!(items[&shallow], Some((vec![0x03u8], 1)));
+        assert_eq!(items[&middle], Some((vec![0x04u8], 2)));
+        assert_eq!(items[&clustered_a], Some((vec![0x01u8], 3)));
+        assert_eq!(items[&clustered_b], Some((vec![0x02u8], 3)));
+        assert_eq!(items[&missing], None);
+
+        // The values agree with a plain get
+        let plain = bmt.get(&root, &mut lookup)?;
+        for (key, item) in &items {
+            assert_eq!(plain[key], item.as_ref().map(|(value, _)| value.clone()));
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_retries_an_insert_when_another_writer_swaps_the_root() -> BinaryMerkleTreeResult<()> {
+        use std::sync::{Arc, Barrier, Mutex};
+        use std::thread;
+
+        use starling::traits::Database;
+        use starling::tree_db::concurrent::ConcurrentDatabase;
+        use starling::tree_db::HashTreeDB;
+
+        type ConcurrentTree = starling::merkle_bit::MerkleBIT<
+            ConcurrentDatabase<HashTreeDB<[u8; KEY_LEN]>, [u8; KEY_LEN]>,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+            starling::tree::tree_data::TreeData,
+            starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+            starling::tree_hasher::TreeHasher,
+            Vec<u8>,
+            [u8; KEY_LEN],
+        >;
+
+        let seed = [0x86u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let batches = (0..2)
+            .map(|_| {
+                (0..4)
+                    .map(|_| prepare_inserts(8, &mut rng))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let db = ConcurrentDatabase::new(HashTreeDB::open(&path)?);
+        let tree = Arc::new(Mutex::new(ConcurrentTree::from_db(db, 160)?));
+        // Both writers read the same current root before either publishes, so one of them
+        // loses its first swap
+        let barrier = Arc::new(Barrier::new(2));
+
+        let writers = batches
+            .iter()
+            .cloned()
+            .map(|writer_batches| {
+                let tree = Arc::clone(&tree);
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(
+                    move || -> BinaryMerkleTreeResult<(Vec<[u8; KEY_LEN]>, usize)> {
+                        let mut published = Vec::new();
+                        let mut retries = 0;
+                        for (i, (mut keys, values)) in writer_batches.into_iter().enumerate() {
+                            loop {
+                                let read = tree.lock().unwrap().current_root()?;
+                                if i == 0 && retries == 0 {
+                                    barrier.wait();
+                                }
+                                let mut writer = tree.lock().unwrap();
+                                let root = writer.insert(read.as_ref(), &mut keys, &values)?;
+                                if writer.compare_and_set_root(read, root)? {
+                                    published.push(root);
+                                    break;
+                                }
+                                // Free the nodes of the stale root before retrying
+                                writer.remove(&root)?;
+                                retries += 1;
+                            }
+                            thread::yield_now();
+                        }
+                        Ok((published, retries))
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut published = Vec::new();
+        let mut retries = 0;
+        for writer in writers {
+            let (roots, writer_retries) = writer.join().unwrap()?;
+            published.extend(roots);
+            retries += writer_retries;
+        }
+        assert!(retries >= 1);
+        assert_eq!(published.len(), 8);
+
+        let mut tree = tree.lock().unwrap();
+        let current = tree.current_root()?.unwrap();
+        for (keys, values) in batches.iter().flatten() {
+            for (key, value) in keys.iter().zip(values.iter()) {
+                assert_eq!(tree.get_one(&current, key)?, Some(value.clone()));
+            }
+        }
+
+        // The stale roots left nothing behind, and every published root frees its nodes
+        assert!(tree.find_orphans(&published)?.is_empty());
+        for root in &published {
+            tree.remove(root)?;
+        }
+        assert!(tree.find_orphans(&[])?.is_empty());
+
+        assert!(tree.compare_and_set_root(None, [0xFFu8; KEY_LEN]).is_err());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_a_root_pointer_in_a_hash_tree() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x87u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(16, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        assert_eq!(bmt.current_root()?, None);
+        let root = bmt.insert(None, &mut keys, &values)?;
+        assert!(!bmt.compare_and_set_root(Some(root), root)?);
+        assert!(bmt.compare_and_set_root(None, root)?);
+        assert_eq!(bmt.current_root()?, Some(root));
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_splits_a_tree_at_a_key_boundary() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x88u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(100, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+
+        let mut sorted = keys.clone();
+        sorted.sort();
+        let boundary = sorted[50];
+        let (lower_root, upper_root) = bmt.split(&root, &boundary)?;
+
+        let lower = bmt
+            .iter(&lower_root)
+            .collect::<BinaryMerkleTreeResult<Vec<_>>>()?;
+        let upper = bmt
+            .iter(&upper_root)
+            .collect::<BinaryMerkleTreeResult<Vec<_>>>()?;
+        assert_eq!(lower.len(), 50);
+        assert_eq!(upper.len(), 50);
+        assert!(lower.iter().all(|(key, _)| *key < boundary));
+        assert!(upper.iter().all(|(key, _)| *key >= boundary));
+
+        let mut union = lower.into_iter().chain(upper).collect::<Vec<_>>();
+        union.sort();
+        assert_eq!(
+            union,
+            bmt.iter(&root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+        );
+
+        // The shared leaves survive removing the original root
+        bmt.remove(&root)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            let shard = if *key < boundary {
+                &lower_root
+            } else {
+                &upper_root
+            };
+            assert_eq!(bmt.get_one(shard, key)?, Some(value.clone()));
+        }
+
+        assert!(bmt.split(&lower_root, &boundary).is_err());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_streams_a_value_through_a_reader() -> BinaryMerkleTreeResult<()> {
+        use std::io::Read;
+
+        use starling::traits::Encode;
+
+        let seed = [0x89u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let key = [0x42u8; KEY_LEN];
+        let value = (0..65_536).map(|_| rng.gen()).collect::<Vec<u8>>();
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut [key], &[value.clone()])?;
+
+        let mut reader = bmt
+            .get_value_reader(&root, &key)?
+            .expect("the key was inserted");
+        let mut streamed = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let read = reader.read(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            streamed.extend_from_slice(&chunk[..read]);
+        }
+        assert_eq!(streamed, value.encode()?);
+
+        assert!(bmt.get_value_reader(&root, &[0x00u8; KEY_LEN])?.is_none());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_checks_whether_a_node_is_stored() -> BinaryMerkleTreeResult<()> {
+        use std::time::Duration;
+
+        use starling::traits::{Data, Database, NodeVariant};
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_db::concurrent::ConcurrentDatabase;
+        use starling::tree_db::ttl::TtlDatabase;
+        use starling::tree_db::HashTreeDB;
+
+        let seed = [0x38u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (keys, values) = prepare_inserts(2, &mut rng);
+        let present = keys[0];
+        let missing = keys[1];
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut [present], &values[..1])?;
+
+        let mut db = ConcurrentDatabase::new(HashTreeDB::open(&path)?);
+        let node = TreeNode::<[u8; KEY_LEN]>::new(NodeVariant::Data(TreeData::new()));
+        db.insert(present, node.clone())?;
+        let other = db.handle();
+        assert!(db.contains(&present)?);
+        assert!(!other.contains(&present)?);
+        db.batch_write()?;
+        assert!(other.contains(&present)?);
+        db.remove(&present)?;
+        assert!(!db.contains(&present)?);
+        assert!(!db.contains(&missing)?);
+
+        let mut expiring = TtlDatabase::new(HashTreeDB::open(&path)?, Duration::from_secs(0));
+        expiring.insert(present, node)?;
+        assert!(!expiring.contains(&present)?);
+
+        assert!(bmt.contains_key(&root, &present)?);
+        assert!(!bmt.contains_key(&root, &missing)?);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_keys_needing_a_deeper_tree_before_writing() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x39u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut low = [0x5Au8; KEY_LEN];
+        low[KEY_LEN - 1] = 0xFE;
+        let mut high = low;
+        high[KEY_LEN - 1] = 0xFF;
+        let values = vec![vec![0x01u8], vec![0x02u8]];
+
+        let mut bmt = Tree::open(&path, 0)?;
+        let error = bmt
+            .insert(None, &mut [high, low], &values)
+            .expect_err("two keys need a branch");
+        assert_eq!(
+            error.kind(),
+            &ErrorKind::DepthInsufficient {
+                needed: 1,
+                configured: 0,
+            }
+        );
+        assert!(bmt.find_orphans(&[])?.is_empty());
+
+        // A single key needs no branch
+        bmt.insert(None, &mut [low], &values[..1])?;
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_predicts_the_depth_of_the_tree_it_builds() -> BinaryMerkleTreeResult<()> {
+        use starling::utils::tree_utils::tree_depth;
+
+        let seed = [0x3Au8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+        // Cluster some keys behind long shared prefixes
+        for (i, key) in keys.iter_mut().enumerate().take(16) {
+            *key = [0x33u8; KEY_LEN];
+            key[KEY_LEN - 1] = i as u8;
+        }
+        keys.sort();
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+        let depths = bmt.get_with_depth(&root, &mut keys.clone())?;
+        let deepest = depths
+            .values()
+            .map(|item| item.as_ref().map_or(0, |(_, depth)| *depth))
+            .max();
+        assert_eq!(deepest, Some(tree_depth(&keys)));
+
+        let mut shallow = Tree::open(&path, tree_depth(&keys) - 1)?;
+        assert!(shallow.insert(None, &mut keys, &values).is_err());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_gets_the_node_at_a_location() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Branch, Leaf, NodeVariant};
+
+        let seed = [0x3Bu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut keys = vec![[0x00u8; KEY_LEN], [0xFFu8; KEY_LEN]];
+        let values = vec![vec![0x01u8], vec![0x02u8]];
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+        let children = bmt.nodes_at_depth(&root, 1)?;
+
+        if let Some(NodeVariant::Branch(branch)) = bmt.get_node_at(&root)? {
+            assert_eq!(branch.get_split_index(), 0);
+            assert_eq!(vec![*branch.get_zero(), *branch.get_one()], children);
+        } else {
+            return Err(Exception::new("The root is not a branch"));
+        }
+
+        for (location, key) in children.iter().zip(keys.iter()) {
+            if let Some(NodeVariant::Leaf(leaf)) = bmt.get_node_at(location)? {
+                assert_eq!(leaf.get_key(), key);
+            } else {
+                return Err(Exception::new("The child is not a leaf"));
+            }
+        }
+
+        assert!(bmt.get_node_at(&[0x42u8; KEY_LEN])?.is_none());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_an_empty_insert_by_default() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x3Cu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(8, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+        assert!(bmt.insert(None, &mut [], &[]).is_err());
+        assert!(bmt.insert(Some(&root), &mut [], &[]).is_err());
+        assert!(bmt.get(&root, &mut [])?.is_empty());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_passes_roots_through_empty_inserts_when_not_strict() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x3Du8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(8, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        bmt.set_strict_empty_inserts(false);
+
+        // With nothing to build on, the empty root stands in for a tree and holds no keys
+        let empty = bmt.insert(None, &mut [], &[])?;
+        assert_eq!(empty, Tree::empty_root());
+        assert_eq!(bmt.get_one(&empty, &keys[0])?, None);
+
+        // Building on the empty root builds a fresh tree
+        let root = bmt.insert(Some(&empty), &mut keys, &values)?;
+
+        // An empty insert returns its previous root, which then survives one removal
+        assert_eq!(bmt.insert(Some(&root), &mut [], &[])?, root);
+        bmt.remove(&root)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(bmt.get_one(&root, key)?, Some(value.clone()));
+        }
+        assert_eq!(bmt.insert(None, &mut keys.clone(), &values)?, root);
+
+        assert!(bmt.insert(Some(&[0x42u8; KEY_LEN]), &mut [], &[]).is_err());
+        bmt.set_strict_previous_root(false);
+        assert_eq!(
+            bmt.insert(Some(&[0x42u8; KEY_LEN]), &mut [], &[])?,
+            Tree::empty_root()
+        );
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_generates_ics23_proofs_that_calculate_the_root() -> BinaryMerkleTreeResult<()> {
+        use starling::tree_hasher::TreeHasher;
+
+        let seed = [0x3Eu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        // The hash operation only labels the proofs, whose roots are recomputed with `TreeHasher`
+        // whatever it is; `it_matches_an_ics23_existence_proof_vector` checks real SHA-256 proofs
+        for key in &keys {
+            let proof = tree.generate_ics23_proof(&root, *key, HashOp::Sha256)?;
+            assert_eq!(proof.key, key.to_vec());
+            assert_eq!(proof.calculate_root::<TreeHasher, [u8; KEY_LEN]>()?, root);
+        }
+
+        let mut first = [keys[0]];
+        let single_root = tree.insert(None, &mut first, &values[..1])?;
+        let single = tree.generate_ics23_proof(&single_root, keys[0], HashOp::Sha256)?;
+        assert!(single.path.is_empty());
+        assert_eq!(
+            single.calculate_root::<TreeHasher, [u8; KEY_LEN]>()?,
+            single_root
+        );
+
+        let mut tampered = tree.generate_ics23_proof(&root, keys[1], HashOp::Sha256)?;
+        tampered.value[0] ^= 0x01;
+        assert_ne!(
+            tampered.calculate_root::<TreeHasher, [u8; KEY_LEN]>()?,
+            root
+        );
+
+        let spec = tree.ics23_proof_spec(HashOp::Sha256)?;
+        assert_eq!(spec.leaf_spec, single.leaf);
+        assert_eq!(spec.inner_spec.child_size, 32);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_encodes_ics23_messages_like_the_reference_implementation() -> BinaryMerkleTreeResult<()> {
+        // The IAVL spec from `cosmos/ics23` 0.9.0, and its encoding by the reference implementation.
+        let spec = ProofSpec {
+            leaf_spec: LeafOp {
+                hash: HashOp::Sha256,
+                prehash_key: HashOp::NoHash,
+                prehash_value: HashOp::Sha256,
+                length: LengthOp::VarProto,
+                prefix: vec![0x00],
+            },
+            inner_spec: InnerSpec {
+                child_order: vec![0, 1],
+                child_size: 33,
+                min_prefix_length: 4,
+                max_prefix_length: 12,
+                empty_child: Vec::new(),
+                hash: HashOp::Sha256,
+            },
+            max_depth: 0,
+            min_depth: 0,
+        };
+        assert_eq!(
+            spec.to_bytes()?,
+            vec![
+                0x0A, 0x09, 0x08, 0x01, 0x18, 0x01, 0x20, 0x01, 0x2A, 0x01, 0x00, 0x12, 0x0C, 0x0A,
+                0x02, 0x00, 0x01, 0x10, 0x21, 0x18, 0x04, 0x20, 0x0C, 0x30, 0x01
+            ]
+        );
+
+        let proof = ExistenceProof {
+            key: b"k".to_vec(),
+            value: b"v".to_vec(),
+            leaf: LeafOp {
+                hash: HashOp::Sha256,
+                prehash_key: HashOp::NoHash,
+                prehash_value: HashOp::NoHash,
+                length: LengthOp::NoPrefix,
+                prefix: vec![0x00],
+            },
+            path: vec![InnerOp {
+                hash: HashOp::Sha256,
+                prefix: vec![0x01],
+                suffix: vec![0x02, 0x02],
+            }],
+        };
+        assert_eq!(
+            proof.to_bytes()?,
+            vec![
+                0x0A, 0x01, 0x6B, 0x12, 0x01, 0x76, 0x1A, 0x05, 0x08, 0x01, 0x2A, 0x01, 0x00, 0x22,
+                0x09, 0x08, 0x01, 0x12, 0x01, 0x01, 0x1A, 0x02, 0x02, 0x02
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "use_sha2")]
+    fn it_matches_an_ics23_existence_proof_vector() -> BinaryMerkleTreeResult<()> {
+        use std::convert::TryFrom;
+
+        use starling::tree_hasher::TreeHasher;
+        use starling::utils::tree_utils::to_hex;
+
+        // Computed outside the crate with SHA-256 and the default hash scheme, where a leaf is
+        // `sha256("l" || key || sha256("d" || key || value))` and a branch is
+        // `sha256("b" || zero || one)`.  The tree holds three keys: the root splits 0x00.. and
+        // 0x40.. from 0x80.., and the proof is of 0x40.. and its value "one".
+        const ROOT: &str = "5baac38f32062de988349fdffd1fc8be59d3ece6c1507ba30c2b936c34438dd4";
+        const PROOF: &str = concat!(
+            // key
+            "0a20",
+            "4040404040404040404040404040404040404040404040404040404040404040",
+            // value: the data location of the key
+            "1220",
+            "ad7f91243eaaec1aca8ac4f6d16c2bb3a43113ea4506cee6d25849c5da6a2d2f",
+            // leaf: SHA-256 with prefix "l"
+            "1a05",
+            "08012a016c",
+            // inner: SHA-256 with prefix "b" and the leaf of 0x00.. hashed before the child
+            "2225",
+            "0801122162",
+            "f0c81c957d2e6c85bd5f92333a32fa462cc57951d25e69414cf455c2c6e85c3f",
+            // inner: SHA-256 with prefix "b" and the leaf of 0x80.. hashed after the child
+            "2227",
+            "08011201621a20",
+            "adb17d0823a7e5bcdc050bb8d91d218524a61da5ae81b90a80bde57fdcc70387",
+        );
+
+        let seed = [0xD9u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut keys = [[0x00u8; KEY_LEN], [0x40u8; KEY_LEN], [0x80u8; KEY_LEN]];
+        let values = vec![b"zero".to_vec(), b"one".to_vec(), b"two".to_vec()];
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+        assert_eq!(to_hex(&root), ROOT);
+
+        let proof = tree.generate_ics23_proof(&root, keys[1], HashOp::Sha256)?;
+        assert_eq!(to_hex(&proof.to_bytes()?), PROOF);
+        assert_eq!(proof.calculate_root::<TreeHasher, [u8; KEY_LEN]>()?, root);
+
+        // The checks the reference verifier makes of each operation against the spec
+        let spec = tree.ics23_proof_spec(HashOp::Sha256)?;
+        let inner_spec = &spec.inner_spec;
+        let child_size = usize::try_from(inner_spec.child_size)?;
+        let max_prefix_length = usize::try_from(inner_spec.max_prefix_length)?
+            + (inner_spec.child_order.len() - 1) * child_size;
+        assert_eq!(proof.leaf, spec.leaf_spec);
+        for inner in &proof.path {
+            assert_eq!(inner.hash, inner_spec.hash);
+            assert!(!inner.prefix.starts_with(&spec.leaf_spec.prefix));
+            assert!(inner.prefix.len() >= usize::try_from(inner_spec.min_prefix_length)?);
+            assert!(inner.prefix.len() <= max_prefix_length);
+            assert_eq!(inner.suffix.len() % child_size, 0);
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_counts_the_distinct_values_under_a_root() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x3Fu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, _) = prepare_inserts(12, &mut rng);
+        let values = (0..12_u8).map(|i| vec![i % 3]).collect::<Vec<_>>();
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+        // Data nodes are addressed by key and value, so repeated values under different keys are
+        // still distinct
+        assert_eq!(tree.distinct_value_count(&root)?, 12);
+
+        tree.set_value_addressed_data(true);
+        let value_addressed_root = tree.insert(None, &mut keys, &values)?;
+        assert_eq!(tree.distinct_value_count(&value_addressed_root)?, 3);
+
+        let single_root = tree.insert(None, &mut [keys[0]], &values[..1])?;
+        assert_eq!(tree.distinct_value_count(&single_root)?, 1);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    type RetryingTree = starling::merkle_bit::MerkleBIT<
+        starling::tree_db::retry::RetryingDatabase<CountingDB>,
+        starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+        starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+        starling::tree::tree_data::TreeData,
+        starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+        starling::tree_hasher::TreeHasher,
+        Vec<u8>,
+        [u8; KEY_LEN],
+    >;
+
+    #[test]
+    fn it_retries_transient_database_errors() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Data, Database, NodeVariant};
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_db::retry::RetryingDatabase;
+        use std::time::Duration;
+
+        let seed = [0x40u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(16, &mut rng);
+
+        let db = CountingDB::open(&path)?;
+        let failures = std::sync::Arc::clone(&db.failures);
+        let retrying = RetryingDatabase::new(db, 3, Duration::from_millis(1));
+        let mut tree = RetryingTree::from_db(retrying, 160)?;
+
+        // Two failures are absorbed by the third attempt
+        failures.store(2, Ordering::Relaxed);
+        let root = tree.insert(None, &mut keys, &values)?;
+        assert_eq!(failures.load(Ordering::Relaxed), 0);
+
+        failures.store(2, Ordering::Relaxed);
+        let items = tree.get(&root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        // Running out of attempts returns the last error
+        failures.store(3, Ordering::Relaxed);
+        match tree.get(&root, &mut keys) {
+            Err(e) => assert_eq!(*e.kind(), ErrorKind::Io),
+            Ok(_) => panic!("Read through more failures than attempts"),
+        }
+
+        // Errors which are not transient are returned without retrying
+        let mut db = CountingDB::open(&path)?;
+        db.kind = ErrorKind::Other;
+        let failures = std::sync::Arc::clone(&db.failures);
+        let retrying = RetryingDatabase::new(db, 3, Duration::from_millis(1));
+        let tree = RetryingTree::from_db(retrying, 160)?;
+        failures.store(2, Ordering::Relaxed);
+        match tree.get(&root, &mut keys) {
+            Err(e) => assert_eq!(*e.kind(), ErrorKind::Other),
+            Ok(_) => panic!("Read through a permanent failure"),
+        }
+        assert_eq!(failures.load(Ordering::Relaxed), 1);
+
+        // A batch which may have been partly written is not retried
+        let db = CountingDB::open(&path)?;
+        let failures = std::sync::Arc::clone(&db.failures);
+        let mut retrying = RetryingDatabase::new(db, 3, Duration::from_millis(1));
+        failures.store(1, Ordering::Relaxed);
+        match retrying.batch_write() {
+            Err(e) => assert_eq!(*e.kind(), ErrorKind::Io),
+            Ok(_) => panic!("Retried a failed batch write"),
+        }
+        assert_eq!(failures.load(Ordering::Relaxed), 0);
+        retrying.batch_write()?;
+
+        // A single attempt is given the node itself
+        let mut data = TreeData::new();
+        data.set_value(&[0xAAu8; 4]);
+        let node = TreeNode::new(NodeVariant::Data(data));
+        let mut single =
+            RetryingDatabase::new(CountingDB::open(&path)?, 1, Duration::from_millis(1));
+        single.insert(root, node.clone())?;
+        assert_eq!(single.get_node(root)?, Some(node));
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_verifies_a_proof_by_the_hash_of_its_value() -> BinaryMerkleTreeResult<()> {
+        use starling::tree_hasher::TreeHasher;
+        use starling::utils::hash_scheme::HashScheme;
+
+        let seed = [0x43u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(32, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        let scheme = HashScheme::new();
+        for (key, value) in keys.iter().zip(values.iter()) {
+            let proof = tree.generate_inclusion_proof(&root, *key)?;
+            let value_hash = scheme.data_location::<TreeHasher, [u8; KEY_LEN]>(key, value);
+            tree.verify_inclusion_by_value_hash(&root, *key, &value_hash, &proof)?;
+        }
+
+        let proof = tree.generate_inclusion_proof(&root, keys[0])?;
+        let wrong_hash = scheme.data_location::<TreeHasher, [u8; KEY_LEN]>(&keys[0], &values[1]);
+        assert!(tree
+            .verify_inclusion_by_value_hash(&root, keys[0], &wrong_hash, &proof)
+            .is_err());
+        let other_hash = scheme.data_location::<TreeHasher, [u8; KEY_LEN]>(&keys[1], &values[1]);
+        assert!(tree
+            .verify_inclusion_by_value_hash(&root, keys[1], &other_hash, &proof)
+            .is_err());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_a_tree_with_the_depth_it_was_built_with() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+        use starling::tree_db::concurrent::{ConcurrentDatabase, ConcurrentReader};
+        use starling::tree_db::HashTreeDB;
+
+        type ConcurrentTree = starling::merkle_bit::MerkleBIT<
+            ConcurrentDatabase<HashTreeDB<[u8; KEY_LEN]>, [u8; KEY_LEN]>,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+            starling::tree::tree_data::TreeData,
+            starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+            starling::tree_hasher::TreeHasher,
+            Vec<u8>,
+            [u8; KEY_LEN],
+        >;
+        type ReaderTree = starling::merkle_bit::MerkleBIT<
+            ConcurrentReader<HashTreeDB<[u8; KEY_LEN]>>,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+            starling::tree::tree_data::TreeData,
+            starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+            starling::tree_hasher::TreeHasher,
+            Vec<u8>,
+            [u8; KEY_LEN],
+        >;
+
+        let seed = [0x44u8; KEY_LEN];
+        let path = generate_path(seed);
+
+        // Keys differing from the zero key in one of the first 16 bits form a chain of 16 branches.
+        // They are built in ascending order, so sorting them keeps them in line with their values
+        let mut keys = vec![[0x00u8; KEY_LEN]];
+        for bit in (0..16).rev() {
+            let mut key = [0x00u8; KEY_LEN];
+            key[bit / 8] = 0x80 >> (bit % 8);
+            keys.push(key);
+        }
+        let values = (0..17_u8).map(|i| vec![i]).collect::<Vec<_>>();
+
+        let db = ConcurrentDatabase::new(HashTreeDB::open(&path)?);
+        let shallow_db = db.handle();
+        let mut tree = ConcurrentTree::from_db(db, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+        assert_eq!(tree.depth_of(&root)?, 16);
+
+        // Opening with a smaller depth uses the recorded one, so the deepest key can still be read
+        let shallow = ReaderTree::from_db(shallow_db, 8)?;
+        assert_eq!(shallow.depth(), 160);
+        let items = shallow.get(&root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        // A larger depth is kept, and recorded on the next insert
+        let mut deeper_db = ConcurrentDatabase::new(HashTreeDB::open(&path)?);
+        deeper_db.set_depth(160)?;
+        let last_db = deeper_db.handle();
+        let mut deeper = ConcurrentTree::from_db(deeper_db, 200)?;
+        assert_eq!(deeper.depth(), 200);
+        deeper.insert(None, &mut keys, &values)?;
+        assert_eq!(ReaderTree::from_db(last_db, 8)?.depth(), 200);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_builds_trees_without_reference_counting() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x45u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+        let (mut more_keys, more_values) = prepare_inserts(16, &mut rng);
+
+        let counted_path = generate_path([0x46u8; KEY_LEN]);
+        let mut counted = Tree::open(&counted_path, 160)?;
+        let counted_root = counted.insert(None, &mut keys, &values)?;
+        let counted_next = counted.insert(Some(&counted_root), &mut more_keys, &more_values)?;
+
+        let mut tree = Tree::open(&path, 160)?;
+        tree.set_refcounting(false);
+        let root = tree.insert(None, &mut keys, &values)?;
+        let next = tree.insert(Some(&root), &mut more_keys, &more_values)?;
+        assert_eq!(
+            tree.insert(Some(&next), &mut keys[..1], &values[..1])?,
+            next
+        );
+
+        // The same trees are built, and both roots stay readable
+        assert_eq!(root, counted_root);
+        assert_eq!(next, counted_next);
+        let items = tree.get(&root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+        let items = tree.get(&next, &mut more_keys)?;
+        for (key, value) in more_keys.iter().zip(more_values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        // Without references, roots cannot be removed
+        assert!(tree.remove(&root).is_err());
+        let snapshot = tree.snapshot(&root)?;
+        assert_eq!(
+            tree.get_snapshot(&snapshot, &mut keys[..1])?[&keys[0]],
+            Some(values[0].clone())
+        );
+
+        // Recounting makes the tree prunable again
+        tree.set_refcounting(true);
+        tree.recount_references(&[next])?;
+        tree.remove(&next)?;
+        assert_eq!(tree.get(&next, &mut more_keys[..1])?[&more_keys[0]], None);
+
+        tear_down(&path);
+        tear_down(&counted_path);
+        Ok(())
+    }
+
+    /// A broken hasher ignoring its input, so every node hashes to the same location.
+    struct ConstantHasher;
+
+    impl starling::traits::Hasher<[u8; KEY_LEN]> for ConstantHasher {
+        type HashType = Self;
+
+        fn new(_size: usize) -> Self::HashType {
+            Self
+        }
+
+        fn update(&mut self, _data: &[u8]) {}
+
+        fn finalize(self) -> [u8; KEY_LEN] {
+            [0x5Au8; KEY_LEN]
+        }
+    }
+
+    type CollidingTree = starling::merkle_bit::MerkleBIT<
+        starling::tree_db::HashTreeDB<[u8; KEY_LEN]>,
+        starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+        starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+        starling::tree::tree_data::TreeData,
+        starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+        ConstantHasher,
+        Vec<u8>,
+        [u8; KEY_LEN],
+    >;
+
+    #[test]
+    fn it_detects_hash_collisions_during_insert() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x47u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut first_key = [0x00u8; KEY_LEN];
+        first_key[0] = 0x10;
+        let mut second_key = [0x00u8; KEY_LEN];
+        second_key[0] = 0x90;
+
+        let mut tree = CollidingTree::new(&path, 160)?;
+        let first_root = tree.insert(None, &mut [first_key], &[vec![0x01u8]])?;
+
+        // Without the check, the second leaf silently overwrites the first
+        tree.insert(Some(&first_root), &mut [second_key], &[vec![0x02u8]])?;
+
+        let mut tree = CollidingTree::new(&path, 160)?;
+        tree.set_collision_check(true);
+        let first_root = tree.insert(None, &mut [first_key], &[vec![0x01u8]])?;
+        match tree.insert(Some(&first_root), &mut [second_key], &[vec![0x02u8]]) {
+            Err(e) => assert_eq!(
+                *e.kind(),
+                ErrorKind::HashCollision {
+                    location: vec![0x5Au8; KEY_LEN]
+                }
+            ),
+            Ok(_) => panic!("Inserted a node colliding with an existing one"),
+        }
+
+        // A working hasher shares identical nodes without tripping the check
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(32, &mut rng);
+        let mut tree = Tree::open(&path, 160)?;
+        tree.set_collision_check(true);
+        let root = tree.insert(None, &mut keys, &values)?;
+        let second_root = tree.insert(None, &mut keys, &values)?;
+        assert_eq!(root, second_root);
+        let updated = tree.insert(Some(&root), &mut keys[..1].to_vec(), &[vec![0xFFu8]])?;
+        assert_ne!(updated, root);
+        let items = tree.get(&updated, &mut keys)?;
+        assert_eq!(items[&keys[0]], Some(vec![0xFFu8]));
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_iterates_entries_with_their_proofs() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x48u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(256, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        let expected = tree
+            .iter(&root)
+            .collect::<BinaryMerkleTreeResult<Vec<_>>>()?;
+        let mut count = 0;
+        for (entry, (key, value)) in tree.iter_with_proofs(&root).zip(expected.iter()) {
+            let (entry_key, entry_value, proof) = entry?;
+            assert_eq!(entry_key, *key);
+            assert_eq!(entry_value, *value);
+            assert_eq!(proof, tree.generate_proof(&root, entry_key)?);
+            count += 1;
+        }
+        assert_eq!(count, expected.len());
+
+        // A single leaf has no siblings
+        let single_root = tree.insert(None, &mut [keys[0]], &[values[0].clone()])?;
+        let entries = tree.iter_with_proofs(&single_root).collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+        match &entries[0] {
+            Ok((key, _, proof)) => {
+                assert_eq!(*key, keys[0]);
+                assert!(proof.siblings().is_empty());
+            }
+            Err(e) => panic!("Failed to iterate a single leaf: {}", e),
+        }
+
+        // A missing root ends the iterator with an error
+        let mut missing = tree.iter_with_proofs(&[0xEEu8; KEY_LEN]);
+        assert!(matches!(missing.next(), Some(Err(_))));
+        assert!(missing.next().is_none());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    /// A key order arranging keys by their bytes from last to first, as little-endian numbers.
+    struct LittleEndianOrder;
+
+    impl starling::traits::KeyOrder<[u8; KEY_LEN]> for LittleEndianOrder {
+        fn order_key(key: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+            let mut reversed = *key;
+            reversed.reverse();
+            reversed
+        }
+    }
+
+    type OrderedTree<KeyOrderType> = starling::merkle_bit::MerkleBIT<
+        starling::tree_db::HashTreeDB<[u8; KEY_LEN]>,
+        starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+        starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+        starling::tree::tree_data::TreeData,
+        starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+        starling::tree_hasher::TreeHasher,
+        Vec<u8>,
+        [u8; KEY_LEN],
+        KeyOrderType,
+    >;
+
+    #[test]
+    fn it_queries_ranges_in_a_custom_key_order() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::KeyOrder;
+        use starling::utils::key_order::ReversedOrder;
+
+        let seed = [0x49u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (keys, values) = prepare_inserts(200, &mut rng);
+        let entries = keys
+            .iter()
+            .copied()
+            .zip(values.iter().cloned())
+            .collect::<Vec<_>>();
+
+        let mut reversed_tree = OrderedTree::<ReversedOrder>::new(&path, 160)?;
+        let mut reversed_keys = keys.clone();
+        let reversed_root = reversed_tree.insert(None, &mut reversed_keys, &values)?;
+        let mut descending = entries.clone();
+        descending.sort_by_key(|entry| std::cmp::Reverse(entry.0));
+        assert_eq!(
+            reversed_tree
+                .iter(&reversed_root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?,
+            descending
+        );
+
+        let mut tree = OrderedTree::<LittleEndianOrder>::new(&path, 160)?;
+        let mut ordered_keys = keys.clone();
+        let root = tree.insert(None, &mut ordered_keys, &values)?;
+        let mut expected = entries.clone();
+        expected.sort_by(|a, b| LittleEndianOrder::compare(&a.0, &b.0));
+        assert_eq!(
+            tree.iter(&root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?,
+            expected
+        );
+
+        // Every key is found, and its proof verifies
+        let mut lookup = keys.clone();
+        let items = tree.get(&root, &mut lookup)?;
+        for (key, value) in &entries {
+            assert_eq!(items[key], Some(value.clone()));
+            let proof = tree.generate_inclusion_proof(&root, *key)?;
+            tree.verify_proof(&root, *key, value, &proof)?;
+        }
+
+        // Paging follows the custom order
+        let mut paged = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (page, next) = tree.iter_from(&root, cursor, 17)?;
+            paged.extend(page);
+            if next.is_none() {
+                break;
+            }
+            cursor = next;
+        }
+        assert_eq!(paged, expected);
+        let (page, _) = tree.iter_from(&root, Some(expected[99].0), 5)?;
+        assert_eq!(page, expected[100..105].to_vec());
+
+        // Splitting at a key divides the tree in the custom order
+        let (lower, upper) = tree.split(&root, &expected[50].0)?;
+        assert_eq!(
+            tree.iter(&lower)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?,
+            expected[..50].to_vec()
+        );
+        assert_eq!(
+            tree.iter(&upper)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?,
+            expected[50..].to_vec()
+        );
+
+        // Updates land on the existing leaf
+        let mut updated_key = [expected[10].0];
+        let updated_root = tree.insert(Some(&root), &mut updated_key, &[vec![0xABu8]])?;
+        let mut all_keys = keys.clone();
+        let items = tree.get(&updated_root, &mut all_keys)?;
+        assert_eq!(items[&expected[10].0], Some(vec![0xABu8]));
+        assert_eq!(items[&expected[11].0], Some(expected[11].1.clone()));
+        assert_eq!(
+            tree.iter(&updated_root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+                .len(),
+            entries.len()
+        );
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_the_previous_root_once_per_insert() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+
+        let seed = [0x4Au8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let db = CountingDB::open(&path)?;
+        let reads = std::sync::Arc::clone(&db.location_reads);
+        let mut tree = CountedTree::from_db(db, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        let root_reads = |reads: &std::sync::Mutex<std::collections::HashMap<_, usize>>| {
+            let mut reads = reads.lock().expect("Read counts are poisoned");
+            let count = reads.get(&root).copied().unwrap_or(0);
+            reads.clear();
+            count
+        };
+        root_reads(&reads);
+
+        let updated = tree.insert(
+            Some(&root),
+            &mut [keys[3], keys[40]],
+            &[vec![0x01u8], vec![0x02u8]],
+        )?;
+        assert_eq!(root_reads(&reads), 1);
+        let one = tree.insert_one(Some(&root), &keys[20], &vec![0x03u8])?;
+        assert_eq!(root_reads(&reads), 1);
+
+        let items = tree.get(&updated, &mut keys.clone())?;
+        for (i, (key, value)) in keys.iter().zip(values.iter()).enumerate() {
+            let expected = match i {
+                3 => vec![0x01u8],
+                40 => vec![0x02u8],
+                _ => value.clone(),
+            };
+            assert_eq!(items[key], Some(expected));
+        }
+        assert_eq!(tree.get_one(&one, &keys[20])?, Some(vec![0x03u8]));
+        assert_eq!(tree.get_one(&one, &keys[21])?, Some(values[21].clone()));
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_round_trips_a_tree_through_json() -> BinaryMerkleTreeResult<()> {
+        use starling::utils::json_export::{from_base64, to_base64};
+
+        let seed = [0x4Bu8; KEY_LEN];
+        let path = generate_path(seed);
+        let import_path = generate_path([0x4Du8; KEY_LEN]);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, mut values) = prepare_inserts(100, &mut rng);
+        // Cover every base64 padding length
+        values[0] = vec![];
+        values[1] = vec![0xFFu8];
+        values[2] = vec![0xFFu8, 0xEE];
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+        let json = tree.to_json(&root)?;
+        let mut streamed = Vec::new();
+        tree.write_json(&root, &mut streamed)?;
+        assert_eq!(streamed, json.as_bytes());
+
+        let mut imported = Tree::open(&import_path, 160)?;
+        let imported_root = imported.import_json(&json)?;
+        assert_eq!(imported_root, root);
+        assert_eq!(imported.to_json(&imported_root)?, json);
+        assert_eq!(
+            imported
+                .iter(&imported_root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?,
+            tree.iter(&root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+        );
+
+        // The layout is fixed
+        let mut single = [[0x01u8; KEY_LEN]];
+        let single_root = tree.insert(None, &mut single, &[b"hey".to_vec()])?;
+        let expected = format!(
+            "{{\"root\":\"{}\",\"entries\":{{\"{}\":\"aGV5\"}}}}",
+            single_root
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>(),
+            "01".repeat(KEY_LEN)
+        );
+        assert_eq!(tree.to_json(&single_root)?, expected);
+        assert_eq!(to_base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(to_base64(b"fooba"), "Zm9vYmE=");
+        assert_eq!(from_base64("Zm9vYg==")?, b"foob".to_vec());
+        assert!(from_base64("Zm9v=mFy").is_err());
+
+        // Whitespace is accepted, but a tampered value no longer matches the root
+        let spaced = expected.replace(",", " ,\n ").replace(":", " : ");
+        assert_eq!(imported.import_json(&spaced)?, single_root);
+        let tampered = expected.replace("aGV5", "aGV6");
+        assert!(imported.import_json(&tampered).is_err());
+        let short_key = expected.replace(&"01".repeat(KEY_LEN), "0101");
+        assert!(imported.import_json(&short_key).is_err());
+        assert!(imported.import_json("{\"root\":\"00\"").is_err());
+
+        tear_down(&path);
+        tear_down(&import_path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_tracks_the_root_each_value_was_set_at() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x4Eu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (keys, values) = prepare_inserts(16, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        tree.set_value_versioning(true);
+        let first_root = tree.insert(None, &mut keys.clone(), &values)?;
+        for key in &keys {
+            assert_eq!(tree.value_version(&first_root, key)?, Some(first_root));
+        }
+
+        // Overwriting one key moves only its version, and the old root keeps the old version
+        let overwritten = keys[3];
+        let second_root = tree.insert(Some(&first_root), &mut [overwritten], &[vec![0xABu8]])?;
+        assert_eq!(
+            tree.value_version(&second_root, &overwritten)?,
+            Some(second_root)
+        );
+        assert_eq!(
+            tree.value_version(&first_root, &overwritten)?,
+            Some(first_root)
+        );
+        for key in keys.iter().filter(|key| **key != overwritten) {
+            assert_eq!(tree.value_version(&second_root, key)?, Some(first_root));
+        }
+
+        // Writing a key's current value again keeps its version
+        let third_root = tree.insert_one(Some(&second_root), &keys[5], &values[5])?;
+        assert_eq!(third_root, second_root);
+        assert_eq!(tree.value_version(&third_root, &keys[5])?, Some(first_root));
+
+        let fourth_root = tree.update_value(&third_root, &keys[5], &vec![0xCDu8])?;
+        assert_eq!(
+            tree.value_version(&fourth_root, &keys[5])?,
+            Some(fourth_root)
+        );
+        assert_eq!(
+            tree.value_version(&fourth_root, &overwritten)?,
+            Some(second_root)
+        );
+        assert_eq!(tree.value_version(&fourth_root, &[0u8; KEY_LEN])?, None);
+
+        // Setting a key back to an earlier value gives it the version of that value
+        let fifth_root = tree.insert_one(Some(&fourth_root), &keys[5], &values[5])?;
+        assert_eq!(tree.value_version(&fifth_root, &keys[5])?, Some(first_root));
+
+        tree.set_value_versioning(false);
+        assert_eq!(tree.value_version(&fifth_root, &keys[0])?, None);
+        let (mut new_keys, new_values) = prepare_inserts(4, &mut rng);
+        let sixth_root = tree.insert(Some(&fifth_root), &mut new_keys, &new_values)?;
+        tree.set_value_versioning(true);
+        assert_eq!(tree.value_version(&sixth_root, &keys[0])?, None);
+        assert_eq!(tree.value_version(&sixth_root, &new_keys[0])?, None);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_batches_over_the_maximum_batch_size() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+
+        let seed = [0x4Fu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(32, &mut rng);
+
+        let db = CountingDB::open(&path)?;
+        let reads = std::sync::Arc::clone(&db.reads);
+        let mut tree = CountedTree::from_db(db, 160)?;
+        let root = tree.insert(None, &mut keys[..16], &values[..16])?;
+        tree.set_max_batch_keys(16);
+
+        // Batches at the limit are served
+        let items = tree.get(&root, &mut keys[..16])?;
+        assert_eq!(items.len(), 16);
+
+        // Larger batches fail without reading, writing or even sorting the keys
+        let unsorted = {
+            let mut reversed = keys.clone();
+            reversed.sort();
+            reversed.reverse();
+            reversed
+        };
+        let expected = ErrorKind::BatchTooLarge {
+            keys: 32,
+            limit: 16,
+        };
+        reads.store(0, Ordering::Relaxed);
+        let mut batch = unsorted.clone();
+        match tree.get(&root, &mut batch) {
+            Err(e) => assert_eq!(e.kind(), &expected),
+            Ok(_) => panic!("Got a batch over the maximum batch size"),
+        }
+        match tree.get_with_depth(&root, &mut batch) {
+            Err(e) => assert_eq!(e.kind(), &expected),
+            Ok(_) => panic!("Got a batch over the maximum batch size"),
+        }
+        match tree.insert(Some(&root), &mut batch, &values) {
+            Err(e) => assert_eq!(e.kind(), &expected),
+            Ok(_) => panic!("Inserted a batch over the maximum batch size"),
+        }
+        assert_eq!(reads.load(Ordering::Relaxed), 0);
+        assert_eq!(batch, unsorted);
+        assert_eq!(
+            tree.iter(&root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+                .len(),
+            16
+        );
+
+        // Zero removes the limit
+        tree.set_max_batch_keys(0);
+        let new_root = tree.insert(Some(&root), &mut keys.clone(), &values)?;
+        let items = tree.get(&new_root, &mut keys.clone())?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "use_mmap", feature = "use_serialization"))]
+    fn it_persists_an_mmap_database_across_reopens() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+        use starling::tree_db::mmap::MmapDatabase;
+
+        type MmapTree = starling::merkle_bit::MerkleBIT<
+            MmapDatabase<[u8; KEY_LEN]>,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+            starling::tree::tree_data::TreeData,
+            starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+            starling::tree_hasher::TreeHasher,
+            Vec<u8>,
+            [u8; KEY_LEN],
+        >;
+
+        let seed = [0x50u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+        let (mut other_keys, other_values) = prepare_inserts(64, &mut rng);
+
+        let (root, other_root) = {
+            let mut tree = MmapTree::from_db(MmapDatabase::open(&path)?, 160)?;
+            let root = tree.insert(None, &mut keys, &values)?;
+            let other_root = tree.insert(Some(&root), &mut other_keys, &other_values)?;
+            (root, other_root)
+        };
+
+        // Nodes and the recorded depth survive a reopen
+        let mut tree = MmapTree::from_db(MmapDatabase::open(&path)?, 8)?;
+        assert_eq!(tree.depth(), 160);
+        let items = tree.get(&other_root, &mut keys.clone())?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        // Removals leave tombstones until the region is compacted
+        tree.remove(&root)?;
+        let entries = tree
+            .iter(&other_root)
+            .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+            .len();
+        drop(tree);
+        let mut db = MmapDatabase::<[u8; KEY_LEN]>::open(&path)?;
+        let live_nodes = db.node_locations()?.len();
+        assert!(db.garbage_bytes() > 0);
+        let before = std::fs::metadata(&path).unwrap().len();
+        db.compact()?;
+        assert_eq!(db.garbage_bytes(), 0);
+        assert!(std::fs::metadata(&path).unwrap().len() < before);
+        // The compacted region replaces the file by a rename, leaving no temporary file behind
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".compact");
+        assert!(!PathBuf::from(temporary).exists());
+        drop(db);
+
+        let tree = MmapTree::from_db(MmapDatabase::open(&path)?, 160)?;
+        assert_eq!(
+            tree.iter(&other_root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+                .len(),
+            entries
+        );
+        let items = tree.get(&other_root, &mut other_keys.clone())?;
+        for (key, value) in other_keys.iter().zip(other_values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+        drop(tree);
+
+        // A record cut short by a crash is discarded on open
+        let mut db = MmapDatabase::<[u8; KEY_LEN]>::open(&path)?;
+        assert_eq!(db.node_locations()?.len(), live_nodes);
+        let node = db.get_node(other_root)?.unwrap();
+        db.insert([0xFFu8; KEY_LEN], node.clone())?;
+        // Nodes are read from the pending records before a flush and from the mapping after it
+        assert_eq!(db.get_node([0xFFu8; KEY_LEN])?, Some(node.clone()));
+        db.batch_write()?;
+        assert_eq!(db.get_node([0xFFu8; KEY_LEN])?, Some(node));
+        drop(db);
+        let length = std::fs::metadata(&path).unwrap().len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(length - 1)
+            .unwrap();
+        let db = MmapDatabase::<[u8; KEY_LEN]>::open(&path)?;
+        assert!(!db.contains(&[0xFFu8; KEY_LEN])?);
+        assert_eq!(db.node_locations()?.len(), live_nodes);
+        drop(db);
+
+        // Bytes a failed flush left past the mapping are cut off by the next flush, which writes
+        // its records where the index expects them
+        let mut db = MmapDatabase::<[u8; KEY_LEN]>::open(&path)?;
+        let length = std::fs::metadata(&path).unwrap().len();
+        let node = db.get_node(other_root)?.unwrap();
+        {
+            use std::io::Write;
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap();
+            file.write_all(&[0x00u8; 3 * KEY_LEN]).unwrap();
+        }
+        db.insert([0xFEu8; KEY_LEN], node.clone())?;
+        db.batch_write()?;
+        assert_eq!(db.get_node([0xFEu8; KEY_LEN])?, Some(node.clone()));
+        drop(db);
+        let db = MmapDatabase::<[u8; KEY_LEN]>::open(&path)?;
+        assert_eq!(db.get_node([0xFEu8; KEY_LEN])?, Some(node));
+        assert_eq!(db.node_locations()?.len(), live_nodes + 1);
+        let record_len = (1 + KEY_LEN + 4) as u64 + db.node_size([0xFEu8; KEY_LEN])?.unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), length + record_len);
+        drop(db);
+
+        std::fs::remove_file(&path).unwrap();
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "use_mmap", feature = "use_serialization"))]
+    fn it_persists_namespaced_roots_in_an_mmap_database() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+        use starling::tree_db::mmap::MmapDatabase;
+
+        let seed = [0x51u8; KEY_LEN];
+        let path = generate_path(seed);
+
+        let mut db = MmapDatabase::<[u8; KEY_LEN]>::open(&path)?;
+        db.set_root([0x01u8; KEY_LEN])?;
+        db.set_root_in(b"alpha", [0x02u8; KEY_LEN])?;
+        db.set_root_in(b"beta", [0x03u8; KEY_LEN])?;
+        db.set_root_in(b"alpha", [0x04u8; KEY_LEN])?;
+        assert!(!db.compare_and_set_root_in(b"beta", None, [0x05u8; KEY_LEN])?);
+        assert!(db.compare_and_set_root_in(b"gamma", None, [0x06u8; KEY_LEN])?);
+        db.batch_write()?;
+        drop(db);
+
+        // The root of each namespace survives a reopen, and the empty namespace is `get_root`
+        let mut db = MmapDatabase::<[u8; KEY_LEN]>::open(&path)?;
+        assert_eq!(db.get_root()?, Some([0x01u8; KEY_LEN]));
+        assert_eq!(db.get_root_in(&[])?, Some([0x01u8; KEY_LEN]));
+        assert_eq!(db.get_root_in(b"alpha")?, Some([0x04u8; KEY_LEN]));
+        assert_eq!(db.get_root_in(b"beta")?, Some([0x03u8; KEY_LEN]));
+        assert_eq!(db.get_root_in(b"gamma")?, Some([0x06u8; KEY_LEN]));
+        assert_eq!(db.get_root_in(b"delta")?, None);
+
+        // The superseded root of a namespace is garbage, and compaction keeps every namespace
+        assert_eq!(db.garbage_bytes(), 1 + KEY_LEN + 4 + b"alpha".len());
+        db.compact()?;
+        assert_eq!(db.garbage_bytes(), 0);
+        drop(db);
+        let mut db = MmapDatabase::<[u8; KEY_LEN]>::open(&path)?;
+        assert_eq!(db.get_root()?, Some([0x01u8; KEY_LEN]));
+        assert_eq!(db.get_root_in(b"alpha")?, Some([0x04u8; KEY_LEN]));
+        assert_eq!(db.get_root_in(b"beta")?, Some([0x03u8; KEY_LEN]));
+        assert_eq!(db.get_root_in(b"gamma")?, Some([0x06u8; KEY_LEN]));
+
+        // A namespaced root record cut short by a crash is discarded on open
+        db.set_root_in(b"beta", [0x07u8; KEY_LEN])?;
+        db.batch_write()?;
+        drop(db);
+        let length = std::fs::metadata(&path).unwrap().len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(length - 1)
+            .unwrap();
+        let db = MmapDatabase::<[u8; KEY_LEN]>::open(&path)?;
+        assert_eq!(db.get_root_in(b"beta")?, Some([0x03u8; KEY_LEN]));
+        drop(db);
+
+        std::fs::remove_file(&path).unwrap();
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_finds_the_keys_only_in_one_root() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x52u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (keys, values) = prepare_inserts(300, &mut rng);
+
+        // A holds keys 0..200, B holds keys 100..300, with some shared keys under new values
+        let mut tree = Tree::open(&path, 160)?;
+        let root_a = tree.insert(None, &mut keys[..200].to_vec(), &values[..200])?;
+        let mut b_values = values[100..300].to_vec();
+        for value in b_values.iter_mut().step_by(7) {
+            value.push(0xFF);
+        }
+        let root_b = tree.insert(None, &mut keys[100..300].to_vec(), &b_values)?;
+
+        let mut only_a = keys[..100].to_vec();
+        only_a.sort();
+        let mut only_b = keys[200..].to_vec();
+        only_b.sort();
+        assert_eq!(
+            tree.keys_only_in(&root_a, &root_b)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?,
+            only_a
+        );
+        assert_eq!(
+            tree.keys_only_in(&root_b, &root_a)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?,
+            only_b
+        );
+
+        // Roots sharing most of their structure, where values changed but no key was added
+        let mut added = keys[200..210].to_vec();
+        let grown = tree.insert(Some(&root_a), &mut added, &values[200..210])?;
+        added.sort();
+        assert_eq!(
+            tree.keys_only_in(&grown, &root_a)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?,
+            added
+        );
+        assert_eq!(tree.keys_only_in(&root_a, &grown).count(), 0);
+        let updated = tree.insert(Some(&root_a), &mut keys[..50].to_vec(), &b_values[..50])?;
+        assert_eq!(tree.changed_keys_since(&root_a, &updated).count(), 50);
+        assert_eq!(tree.keys_only_in(&updated, &root_a).count(), 0);
+        assert_eq!(tree.keys_only_in(&root_a, &updated).count(), 0);
+        assert_eq!(tree.keys_only_in(&root_a, &root_a).count(), 0);
+
+        // The empty root holds no keys
+        let empty = Tree::empty_root();
+        let mut all_a = keys[..200].to_vec();
+        all_a.sort();
+        assert_eq!(
+            tree.keys_only_in(&root_a, &empty)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?,
+            all_a
+        );
+        assert_eq!(tree.keys_only_in(&empty, &root_a).count(), 0);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_splices_single_inserts_into_the_same_tree_as_insert() -> BinaryMerkleTreeResult<()> {
+        use std::collections::BTreeMap;
+
+        use starling::traits::Database;
+        use starling::tree_db::concurrent::{ConcurrentDatabase, ConcurrentReader};
+        use starling::tree_db::HashTreeDB;
+        use starling::utils::key_order::ReversedOrder;
+
+        type ConcurrentTree = starling::merkle_bit::MerkleBIT<
+            ConcurrentDatabase<HashTreeDB<[u8; KEY_LEN]>, [u8; KEY_LEN]>,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+            starling::tree::tree_data::TreeData,
+            starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+            starling::tree_hasher::TreeHasher,
+            Vec<u8>,
+            [u8; KEY_LEN],
+        >;
+
+        fn stored_nodes(
+            db: &ConcurrentReader<HashTreeDB<[u8; KEY_LEN]>>,
+        ) -> BinaryMerkleTreeResult<
+            BTreeMap<[u8; KEY_LEN], starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>>,
+        > {
+            let mut nodes = BTreeMap::new();
+            for location in db.node_locations()? {
+                nodes.insert(location, db.get_node(location)?.unwrap());
+            }
+            Ok(nodes)
+        }
+
+        let seed = [0x53u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (keys, values) = prepare_inserts(64, &mut rng);
+        let (new_keys, new_values) = prepare_inserts(40, &mut rng);
+
+        // New keys, keys sharing all but their last bit with an existing key, the smallest and
+        // largest keys, changed values, and unchanged values
+        let mut operations = new_keys
+            .iter()
+            .copied()
+            .zip(new_values.iter().cloned())
+            .collect::<Vec<_>>();
+        for key in &keys[..8] {
+            let mut neighbour = *key;
+            neighbour[KEY_LEN - 1] ^= 0x01;
+            operations.push((neighbour, vec![0x01u8]));
+        }
+        operations.push(([0x00u8; KEY_LEN], vec![0x02u8]));
+        operations.push(([0xFFu8; KEY_LEN], vec![0x03u8]));
+        for (key, value) in keys[8..28].iter().zip(new_values.iter()) {
+            operations.push((*key, value.clone()));
+        }
+        for (key, value) in keys[28..33].iter().zip(values[28..33].iter()) {
+            operations.push((*key, value.clone()));
+        }
+        operations.push((new_keys[0], new_values[1].clone()));
+
+        let spliced_db = ConcurrentDatabase::new(HashTreeDB::open(&path)?);
+        let spliced_nodes = spliced_db.handle();
+        let mut spliced = ConcurrentTree::from_db(spliced_db, 160)?;
+        let merged_db = ConcurrentDatabase::new(HashTreeDB::open(&path)?);
+        let merged_nodes = merged_db.handle();
+        let mut merged = ConcurrentTree::from_db(merged_db, 160)?;
+
+        let mut roots = vec![spliced.insert(None, &mut keys.clone(), &values)?];
+        assert_eq!(merged.insert(None, &mut keys.clone(), &values)?, roots[0]);
+        for (key, value) in &operations {
+            let previous = roots[roots.len() - 1];
+            let root = spliced.insert_one(Some(&previous), key, value)?;
+            assert_eq!(
+                merged.insert(Some(&previous), &mut [*key], &[value.clone()])?,
+                root
+            );
+            assert_eq!(spliced.get_one(&root, key)?, Some(value.clone()));
+            roots.push(root);
+        }
+        // Every node and reference count matches, before and after removing the older roots
+        assert_eq!(stored_nodes(&spliced_nodes)?, stored_nodes(&merged_nodes)?);
+        for root in &roots[..roots.len() - 1] {
+            spliced.remove(root)?;
+            merged.remove(root)?;
+        }
+        assert_eq!(stored_nodes(&spliced_nodes)?, stored_nodes(&merged_nodes)?);
+
+        // Inserting on top of the empty root builds a fresh tree, as `insert` does
+        let empty = ConcurrentTree::empty_root();
+        let fresh = spliced.insert_one(Some(&empty), &keys[0], &values[0])?;
+        assert_eq!(
+            merged.insert(Some(&empty), &mut [keys[0]], &[values[0].clone()])?,
+            fresh
+        );
+        assert_eq!(spliced.get_one(&fresh, &keys[0])?, Some(values[0].clone()));
+        assert_eq!(stored_nodes(&spliced_nodes)?, stored_nodes(&merged_nodes)?);
+
+        // The splice follows custom key orders
+        let mut spliced = OrderedTree::<ReversedOrder>::new(&path, 160)?;
+        let mut merged = OrderedTree::<ReversedOrder>::new(&path, 160)?;
+        let mut root = spliced.insert(None, &mut keys.clone(), &values)?;
+        assert_eq!(merged.insert(None, &mut keys.clone(), &values)?, root);
+        for (key, value) in &operations {
+            let previous = root;
+            root = spliced.insert_one(Some(&previous), key, value)?;
+            assert_eq!(
+                merged.insert(Some(&previous), &mut [*key], &[value.clone()])?,
+                root
+            );
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_combines_leaves_and_values_into_single_nodes() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Database, Encode, Hasher};
+        use starling::tree_hasher::TreeHasher;
+
+        let seed = [0x54u8; KEY_LEN];
+        let path = generate_path(seed);
+        let split_path = generate_path([0x55u8; KEY_LEN]);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let db = CountingDB::open(&path)?;
+        let reads = std::sync::Arc::clone(&db.reads);
+        let mut combined = CountedTree::from_db(db, 160)?;
+        combined.set_combined_leaves(true);
+        let split_db = CountingDB::open(&split_path)?;
+        let split_reads = std::sync::Arc::clone(&split_db.reads);
+        let mut split = CountedTree::from_db(split_db, 160)?;
+
+        let root = combined.insert(None, &mut keys, &values)?;
+        let split_root = split.insert(None, &mut keys, &values)?;
+        assert_ne!(root, split_root);
+
+        // Every key takes one node rather than a leaf and a data node
+        assert_eq!(
+            combined.shared_node_count(&root, &root)?,
+            split.shared_node_count(&split_root, &split_root)? - keys.len()
+        );
+
+        reads.store(0, Ordering::Relaxed);
+        split_reads.store(0, Ordering::Relaxed);
+        let items = combined.get(&root, &mut keys)?;
+        assert_eq!(items, split.get(&split_root, &mut keys)?);
+        assert_eq!(
+            reads.load(Ordering::Relaxed),
+            split_reads.load(Ordering::Relaxed) - keys.len()
+        );
+
+        // A single key is stored at hash(b"kv" || key || value)
+        let mut first = [keys[0]];
+        let single_root = combined.insert(None, &mut first, &values[..1])?;
+        let mut hasher = <TreeHasher as Hasher<[u8; KEY_LEN]>>::new(KEY_LEN);
+        Hasher::<[u8; KEY_LEN]>::update(&mut hasher, b"kv");
+        Hasher::<[u8; KEY_LEN]>::update(&mut hasher, &keys[0]);
+        Hasher::<[u8; KEY_LEN]>::update(&mut hasher, &values[0].encode()?);
+        let location: [u8; KEY_LEN] = Hasher::<[u8; KEY_LEN]>::finalize(hasher);
+        assert_eq!(single_root, location);
+
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(combined.get_one(&root, key)?, Some(value.clone()));
+
+            let proof = combined.generate_inclusion_proof(&root, *key)?;
+            combined.verify_proof(&root, *key, value, &proof)?;
+            assert!(CountedTree::verify_inclusion_proof(&root, *key, value, &proof).is_err());
+
+            let ics23 = combined.generate_ics23_proof(&root, *key, HashOp::Sha256)?;
+            assert_eq!(ics23.calculate_root::<TreeHasher, [u8; KEY_LEN]>()?, root);
+        }
+        let proof = combined.generate_inclusion_proof(&root, keys[0])?;
+        assert!(combined
+            .verify_proof(&root, keys[0], &values[1], &proof)
+            .is_err());
+        assert!(combined
+            .verify_inclusion_by_value_hash(&root, keys[0], &proof[0].0, &proof)
+            .is_err());
+
+        // Changing a value replaces the combined leaf
+        let new_value = vec![0xAAu8; 8];
+        let new_root = combined.insert(Some(&root), &mut first, &[new_value.clone()])?;
+        assert_eq!(combined.get_one(&new_root, &keys[0])?, Some(new_value));
+        assert_eq!(combined.get_one(&root, &keys[0])?, Some(values[0].clone()));
+
+        tear_down(&path);
+        tear_down(&split_path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_builds_the_same_root_regardless_of_input_order() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x56u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, mut values) = prepare_inserts(64, &mut rng);
+
+        // Keys differing only in their first byte put many adjacent pairs on the same split bit
+        for i in 0..64_u8 {
+            let mut key = [0x00u8; KEY_LEN];
+            key[0] = i;
+            keys.push(key);
+            values.push(vec![i]);
+        }
+
+        let mut entries = keys
+            .iter()
+            .copied()
+            .zip(values.iter().cloned())
+            .collect::<Vec<_>>();
+        let mut roots = Vec::new();
+        for round in 0..8 {
+            entries.rotate_left(round * 17);
+            if round % 2 == 1 {
+                entries.reverse();
+            }
+            let mut round_keys = entries.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+            let round_values = entries.iter().map(|(_, v)| v.clone()).collect::<Vec<_>>();
+
+            let mut tree = Tree::open(&path, 160)?;
+            roots.push(tree.insert(None, &mut round_keys, &round_values)?);
+        }
+        assert!(roots.iter().all(|root| *root == roots[0]));
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_inserts_pre_encoded_values() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Encode;
+
+        let seed = [0x57u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(32, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let mut encoded = Vec::with_capacity(values.len());
+        for value in &values {
+            encoded.push(value.encode()?);
+        }
+        let encoded = encoded.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+        let root = tree.insert_encoded(None, &mut keys, &encoded)?;
+        assert_eq!(root, tree.insert(None, &mut keys, &values)?);
+
+        let items = tree.get(&root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        // Values already held are recognised from their encoded bytes
+        assert_eq!(
+            tree.insert_encoded(Some(&root), &mut keys[..4], &encoded[..4])?,
+            root
+        );
+        let new_value = vec![0x01u8, 0x02];
+        let new_root = tree.insert_encoded(Some(&root), &mut keys[..1], &[&new_value])?;
+        assert_eq!(tree.get_one(&new_root, &keys[0])?, Some(new_value));
+        assert_eq!(tree.get_one(&new_root, &keys[1])?, Some(values[1].clone()));
+
+        assert!(tree
+            .insert_encoded(Some(&root), &mut keys[..2], &encoded[..1])
+            .is_err());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_computes_the_root_an_insert_would_return() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+        use starling::utils::key_order::ReversedOrder;
+
+        let seed = [0x58u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (keys, values) = prepare_inserts(200, &mut rng);
+
+        let mut dense_keys = Vec::new();
+        let mut dense_values = Vec::new();
+        for i in 0..64_u8 {
+            let mut key = [0x00u8; KEY_LEN];
+            key[KEY_LEN - 1] = i;
+            dense_keys.push(key);
+            dense_values.push(vec![i]);
+        }
+        dense_keys.reverse();
+
+        let db = CountingDB::open(&path)?;
+        let reads = std::sync::Arc::clone(&db.reads);
+        let mut tree = CountedTree::from_db(db, 160)?;
+        let datasets = [
+            (keys.clone(), values.clone()),
+            (dense_keys.clone(), dense_values.clone()),
+            (keys[..1].to_vec(), values[..1].to_vec()),
+        ];
+        for (dataset_keys, dataset_values) in &datasets {
+            let root = tree.root_for(dataset_keys, dataset_values)?;
+            assert_eq!(reads.load(Ordering::Relaxed), 0);
+            assert_eq!(
+                root,
+                tree.insert(None, &mut dataset_keys.clone(), dataset_values)?
+            );
+            reads.store(0, Ordering::Relaxed);
+        }
+        assert_eq!(tree.root_for(&[], &[])?, CountedTree::empty_root());
+
+        let mut duplicated = keys[..4].to_vec();
+        duplicated.push(keys[0]);
+        assert!(tree.root_for(&duplicated, &values[..5]).is_err());
+
+        // Other key orders and hashing schemes are followed
+        let mut reversed = OrderedTree::<ReversedOrder>::new(&path, 160)?;
+        let mut little_endian = OrderedTree::<LittleEndianOrder>::new(&path, 160)?;
+        let mut combined = Tree::open(&path, 160)?;
+        combined.set_combined_leaves(true);
+        for (dataset_keys, dataset_values) in &datasets {
+            let mut inserted_keys = dataset_keys.clone();
+            assert_eq!(
+                reversed.root_for(dataset_keys, dataset_values)?,
+                reversed.insert(None, &mut inserted_keys, dataset_values)?
+            );
+            let mut inserted_keys = dataset_keys.clone();
+            assert_eq!(
+                little_endian.root_for(dataset_keys, dataset_values)?,
+                little_endian.insert(None, &mut inserted_keys, dataset_values)?
+            );
+            let mut inserted_keys = dataset_keys.clone();
+            assert_eq!(
+                combined.root_for(dataset_keys, dataset_values)?,
+                combined.insert(None, &mut inserted_keys, dataset_values)?
+            );
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    /// Inserts `num_keys` keys into a tree writing every `flush_every` buffered nodes and into one
+    /// writing at the end of each insert, checking both build the same roots.
+    fn check_flushed_inserts(
+        seed: [u8; KEY_LEN],
+        num_keys: usize,
+        flush_every: usize,
+    ) -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+
+        let path = generate_path(seed);
+        let mut unflushed_seed = seed;
+        unflushed_seed[0] ^= 0xFF;
+        let unflushed_path = generate_path(unflushed_seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(num_keys, &mut rng);
+
+        let db = CountingDB::open(&path)?;
+        let batch_writes = std::sync::Arc::clone(&db.batch_writes);
+        let mut flushed = CountedTree::from_db(db, 160)?;
+        flushed.set_flush_every(flush_every);
+        let unflushed_db = CountingDB::open(&unflushed_path)?;
+        let unflushed_writes = std::sync::Arc::clone(&unflushed_db.batch_writes);
+        let mut unflushed = CountedTree::from_db(unflushed_db, 160)?;
+
+        let root = flushed.insert(None, &mut keys, &values)?;
+        assert_eq!(root, unflushed.insert(None, &mut keys, &values)?);
+
+        // Every leaf, data node and branch counts towards a flush
+        let nodes = flushed.shared_node_count(&root, &root)?;
+        assert!(
+            batch_writes.load(Ordering::Relaxed)
+                >= unflushed_writes.load(Ordering::Relaxed) + nodes / flush_every
+        );
+
+        // Inserting on top of the root also flushes the new references of the shared nodes
+        let changed = num_keys / 100;
+        let mut changed_keys = keys[..changed].to_vec();
+        let changed_values = values[changed..2 * changed].to_vec();
+        let new_root = flushed.insert(Some(&root), &mut changed_keys, &changed_values)?;
+        assert_eq!(
+            new_root,
+            unflushed.insert(Some(&root), &mut changed_keys, &changed_values)?
+        );
+
+        let items = flushed.get(&new_root, &mut keys[..2 * changed])?;
+        for (key, value) in keys[..changed].iter().zip(changed_values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+        for (key, value) in keys[changed..2 * changed]
+            .iter()
+            .zip(values[changed..2 * changed].iter())
+        {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        tear_down(&path);
+        tear_down(&unflushed_path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_flushes_long_inserts_at_the_flush_interval() -> BinaryMerkleTreeResult<()> {
+        check_flushed_inserts([0x5Bu8; KEY_LEN], 50_000, 1_000)
+    }
+
+    #[test]
+    #[ignore = "takes about a minute without optimizations; run with --ignored"]
+    fn it_flushes_a_million_key_load_at_the_flush_interval() -> BinaryMerkleTreeResult<()> {
+        check_flushed_inserts([0x5Cu8; KEY_LEN], 1_000_000, 10_000)
+    }
+
+    #[test]
+    fn it_checks_whether_a_root_exists() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x5Du8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(16, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+        let single_root = tree.insert(None, &mut keys[..1], &values[..1])?;
+        let mut unknown = [0x00u8; KEY_LEN];
+        rng.fill(&mut unknown);
+
+        assert!(tree.has_root(&root)?);
+        assert!(tree.has_root(&single_root)?);
+        assert!(tree.has_root(&Tree::empty_root())?);
+        assert!(!tree.has_root(&unknown)?);
+
+        // An unknown root and a root lacking the key both get nothing
+        assert_eq!(tree.get(&unknown, &mut keys[..1])?[&keys[0]], None);
+
+        // A data node is stored, but is not a root
+        let proof = tree.generate_inclusion_proof(&root, keys[0])?;
+        assert!(!tree.has_root(&proof[0].0)?);
+
+        tree.remove(&root)?;
+        assert!(!tree.has_root(&root)?);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_gets_entries_ordered_by_value() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Decode, Encode};
+
+        /// A score encoded little endian, so its encoding does not sort like its value.
+        #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+        struct Score(u32);
+
+        impl Encode for Score {
+            fn encode(&self) -> Result<Vec<u8>, Exception> {
+                Ok(self.0.to_le_bytes().to_vec())
+            }
+        }
+
+        impl Decode for Score {
+            fn decode(buffer: &[u8]) -> Result<Self, Exception> {
+                let mut bytes = [0_u8; 4];
+                bytes.copy_from_slice(buffer);
+                Ok(Self(u32::from_le_bytes(bytes)))
+            }
+        }
+
+        #[cfg(feature = "use_rocksdb")]
+        type ScoreTree = RocksTree<[u8; KEY_LEN], Score>;
+
+        #[cfg(not(any(feature = "use_rocksdb")))]
+        type ScoreTree = HashTree<[u8; KEY_LEN], Score>;
+
+        let seed = [0x5Eu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut tree = ScoreTree::open(&path, 160)?;
+
+        let mut keys = (1..=6_u8).map(|i| [i; KEY_LEN]).collect::<Vec<_>>();
+        let values = vec![
+            Score(300),
+            Score(7),
+            Score(256),
+            Score(7),
+            Score(1),
+            Score(65_536),
+        ];
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        let ascending = tree.entries_by_value(&root, false)?;
+        let expected = vec![
+            ([5_u8; KEY_LEN], Score(1)),
+            ([2_u8; KEY_LEN], Score(7)),
+            ([4_u8; KEY_LEN], Score(7)),
+            ([3_u8; KEY_LEN], Score(256)),
+            ([1_u8; KEY_LEN], Score(300)),
+            ([6_u8; KEY_LEN], Score(65_536)),
+        ];
+        assert_eq!(ascending, expected);
+
+        // Equal values keep their key order in either direction
+        let descending = tree.entries_by_value(&root, true)?;
+        let expected = vec![
+            ([6_u8; KEY_LEN], Score(65_536)),
+            ([1_u8; KEY_LEN], Score(300)),
+            ([3_u8; KEY_LEN], Score(256)),
+            ([2_u8; KEY_LEN], Score(7)),
+            ([4_u8; KEY_LEN], Score(7)),
+            ([5_u8; KEY_LEN], Score(1)),
+        ];
+        assert_eq!(descending, expected);
+
+        assert!(tree
+            .entries_by_value(&ScoreTree::empty_root(), false)?
+            .is_empty());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_returns_an_error_for_a_panicking_encode() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Decode, Encode};
+        use std::panic::{self, AssertUnwindSafe};
+
+        /// A value whose encoding panics for zero.
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        struct Fragile(u8);
+
+        impl Encode for Fragile {
+            fn encode(&self) -> Result<Vec<u8>, Exception> {
+                assert_ne!(self.0, 0, "Fragile value of zero");
+                Ok(vec![self.0])
+            }
+        }
+
+        impl Decode for Fragile {
+            fn decode(buffer: &[u8]) -> Result<Self, Exception> {
+                Ok(Self(buffer[0]))
+            }
+        }
+
+        #[cfg(feature = "use_rocksdb")]
+        type FragileTree = RocksTree<[u8; KEY_LEN], Fragile>;
+
+        #[cfg(not(any(feature = "use_rocksdb")))]
+        type FragileTree = HashTree<[u8; KEY_LEN], Fragile>;
+
+        let seed = [0x5Fu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut tree = FragileTree::open(&path, 160)?;
+
+        let mut keys = vec![[0x01u8; KEY_LEN], [0x02u8; KEY_LEN]];
+        let root = tree.insert(None, &mut keys, &[Fragile(1), Fragile(2)])?;
+
+        // By default the panic unwinds out of the insert
+        let mut batch = vec![[0x03u8; KEY_LEN], [0x04u8; KEY_LEN]];
+        let values = [Fragile(3), Fragile(0)];
+        let unwound = panic::catch_unwind(AssertUnwindSafe(|| {
+            tree.insert(Some(&root), &mut batch, &values)
+        }));
+        assert!(unwound.is_err());
+
+        tree.set_catch_panics(true);
+        match tree.insert(Some(&root), &mut batch, &values) {
+            Err(e) => assert_eq!(e.kind(), &ErrorKind::WorkerPanic),
+            Ok(_) => panic!("Inserted a value whose encoding panicked"),
+        }
+        match tree.insert_one(Some(&root), &[0x05u8; KEY_LEN], &Fragile(0)) {
+            Err(e) => assert_eq!(e.kind(), &ErrorKind::WorkerPanic),
+            Ok(_) => panic!("Inserted a value whose encoding panicked"),
+        }
+        match tree.root_for(&keys, &[Fragile(1), Fragile(0)]) {
+            Err(e) => assert_eq!(e.kind(), &ErrorKind::WorkerPanic),
+            Ok(_) => panic!("Computed a root for a value whose encoding panicked"),
+        }
+
+        // The tree is left as it was, and keeps accepting inserts
+        let mut items = tree.get(&root, &mut keys)?;
+        assert_eq!(items.remove(&[0x01u8; KEY_LEN]), Some(Some(Fragile(1))));
+        assert_eq!(items.remove(&[0x02u8; KEY_LEN]), Some(Some(Fragile(2))));
+        let new_root = tree.insert(Some(&root), &mut batch, &[Fragile(3), Fragile(4)])?;
+        assert_eq!(
+            tree.iter(&new_root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+                .len(),
+            4
+        );
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rekeys_a_tree_under_a_key_transformation() -> BinaryMerkleTreeResult<()> {
+        let seed = [0x60u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(100, &mut rng);
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+
+        let reverse = |key: &[u8; KEY_LEN]| {
+            let mut reversed = *key;
+            reversed.reverse();
+            reversed
+        };
+        let rekeyed_root = bmt.rekey(&root, reverse)?;
+
+        let mut rekeyed_keys = keys.iter().map(reverse).collect::<Vec<_>>();
+        let mut expected_keys = rekeyed_keys.clone();
+        let expected_root = bmt.insert(None, &mut expected_keys, &values)?;
+        assert_eq!(rekeyed_root, expected_root);
+
+        let items = bmt.get(&rekeyed_root, &mut rekeyed_keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[&reverse(key)], Some(value.clone()));
+        }
+
+        // The original tree is left intact
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(bmt.get_one(&root, key)?, Some(value.clone()));
+        }
+
+        // Two keys mapping to the same key are rejected
+        let first = keys[0];
+        let collision = bmt.rekey(&root, |key| if *key == first { keys[1] } else { *key });
+        assert!(collision.is_err());
+
+        assert_eq!(bmt.rekey(&Tree::empty_root(), reverse)?, Tree::empty_root());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_answers_proven_keys_from_a_partial_tree() -> BinaryMerkleTreeResult<()> {
+        use starling::partial_tree::PartialTree;
+        use starling::tree_hasher::TreeHasher;
+
+        type Partial = PartialTree<TreeHasher, Vec<u8>, [u8; KEY_LEN]>;
+
+        let seed = [0xB5u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        let mut partial = Partial::new(root);
+
+        // A proof for a different value is rejected without keeping anything
+        let proof = tree.generate_proof(&root, keys[3])?;
+        let forged = Proof::new(keys[3], vec![0xFFu8; 4], proof.siblings().to_vec());
+        assert!(partial.import_proof(&forged).is_err());
+        assert_eq!(partial.node_count(), 0);
+
+        partial.import_proof(&proof)?;
+        assert_eq!(partial.get(&keys[3])?, Some(values[3].clone()));
+        match partial.get(&keys[40]) {
+            Err(e) => assert!(matches!(e.kind(), ErrorKind::MissingNode { .. })),
+            Ok(_) => panic!("Answered a key without its proof"),
+        }
+
+        // A second proof extends the view, sharing the branches near the root
+        let known = partial.node_count();
+        let second = tree.generate_proof(&root, keys[40])?;
+        partial.import_proof(&second)?;
+        assert!(partial.node_count() < known + second.siblings().len() + 1);
+        assert_eq!(partial.get(&keys[3])?, Some(values[3].clone()));
+        assert_eq!(partial.get(&keys[40])?, Some(values[40].clone()));
+
+        // Proofs for another root are rejected
+        let other_root = tree.insert(None, &mut keys[..8].to_vec(), &values[..8])?;
+        let mut other = Partial::new(other_root);
+        assert!(other.import_proof(&proof).is_err());
+
+        assert_eq!(Partial::new([0u8; KEY_LEN]).get(&keys[3])?, None);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_builds_trees_from_maximally_similar_keys() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Branch, NodeVariant};
+
+        let seed = [0xB6u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut bmt = Tree::open(&path, 160)?;
+
+        // Keys differing only in their final bit split at the last bit
+        let low = [0xA4u8; KEY_LEN];
+        let mut high = low;
+        high[KEY_LEN - 1] |= 0x01;
+        let values = vec![vec![0x01u8], vec![0x02u8]];
+        let root = bmt.insert(None, &mut [low, high], &values)?;
+        if let Some(NodeVariant::Branch(branch)) = bmt.get_node_at(&root)? {
+            assert_eq!(branch.get_split_index(), KEY_LEN * 8 - 1);
+            assert_eq!(branch.get_count(), 2);
+        } else {
+            return Err(Exception::new("The root is not a branch"));
+        }
+        assert_eq!(bmt.get_one(&root, &low)?, Some(values[0].clone()));
+        assert_eq!(bmt.get_one(&root, &high)?, Some(values[1].clone()));
+        let mut near = low;
+        near[KEY_LEN - 1] ^= 0x02;
+        assert_eq!(bmt.get_one(&root, &near)?, None);
+
+        // Keys identical but for one byte split at the first differing bit of that byte
+        for byte in [0, 1, KEY_LEN / 2, KEY_LEN - 1].iter().copied() {
+            let mut keys = Vec::new();
+            for value in [0x00u8, 0x01, 0x40, 0x41, 0xFF].iter().copied() {
+                let mut key = [0x3Cu8; KEY_LEN];
+                key[byte] = value;
+                keys.push(key);
+            }
+            let values = keys.iter().map(|key| key.to_vec()).collect::<Vec<_>>();
+            let root = bmt.insert(None, &mut keys.clone(), &values)?;
+
+            // 0xFF splits from the rest at the first bit, then 0x40 and 0x41 at the second, and each
+            // pair at the last bit of the byte
+            let mut splits = Vec::new();
+            let mut nodes = vec![root];
+            while let Some(location) = nodes.pop() {
+                if let Some(NodeVariant::Branch(branch)) = bmt.get_node_at(&location)? {
+                    splits.push(branch.get_split_index() - byte * 8);
+                    nodes.push(*branch.get_zero());
+                    nodes.push(*branch.get_one());
+                }
+            }
+            splits.sort_unstable();
+            assert_eq!(splits, vec![0, 1, 7, 7]);
+
+            let mut sorted = keys.clone();
+            let items = bmt.get(&root, &mut sorted)?;
+            for (key, value) in keys.iter().zip(values.iter()) {
+                assert_eq!(items[key], Some(value.clone()));
+            }
+            assert_eq!(
+                bmt.iter(&root)
+                    .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+                    .len(),
+                keys.len()
+            );
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_scans_every_stored_node() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Data, Database, NodeVariant};
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_db::HashTreeDB;
+
+        type BackupTree = starling::merkle_bit::MerkleBIT<
+            HashTreeDB<[u8; KEY_LEN]>,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+            starling::tree::tree_data::TreeData,
+            TreeNode<[u8; KEY_LEN]>,
+            starling::tree_hasher::TreeHasher,
+            Vec<u8>,
+            [u8; KEY_LEN],
+        >;
+
+        let mut db = HashTreeDB::<[u8; KEY_LEN]>::open(&PathBuf::new())?;
+        assert_eq!(db.scan().count(), 0);
+        let mut expected = Vec::new();
+        for i in 0..16u8 {
+            let mut data = TreeData::new();
+            data.set_value(&[i; 4]);
+            let node = TreeNode::new(NodeVariant::Data(data));
+            db.insert([i; KEY_LEN], node.clone())?;
+            expected.push(([i; KEY_LEN], node));
+        }
+        db.remove(&[3u8; KEY_LEN])?;
+        expected.remove(3);
+
+        let mut scanned = db.scan().collect::<Result<Vec<_>, _>>()?;
+        scanned.sort_by_key(|entry| entry.0);
+        assert_eq!(scanned, expected);
+
+        // A backup holds the nodes of every root, not just one
+        let seed = [0xB7u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(32, &mut rng);
+        let mut tree = Tree::open(&path, 160)?;
+        let first_root = tree.insert(None, &mut keys[..16], &values[..16])?;
+        let second_root = tree.insert(None, &mut keys[16..], &values[16..])?;
+
+        let mut backup = HashTreeDB::<[u8; KEY_LEN]>::open(&PathBuf::new())?;
+        let copied = tree.backup_to(&mut backup)?;
+        assert_eq!(copied, backup.node_locations()?.len());
+
+        let restored = BackupTree::from_db(backup, 160)?;
+        for (root, range) in [(first_root, 0..16), (second_root, 16..32)].iter().cloned() {
+            for i in range {
+                assert_eq!(restored.get_one(&root, &keys[i])?, Some(values[i].clone()));
+            }
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_hashes_branch_children_in_sorted_order() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Branch, Hasher, NodeVariant};
+        use starling::tree_hasher::TreeHasher;
+
+        let seed = [0xB8u8; KEY_LEN];
+        let path = generate_path(seed);
+        let sorted_path = generate_path([0xB9u8; KEY_LEN]);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let mut sorted = Tree::open(&sorted_path, 160)?;
+        sorted.set_sorted_children(true);
+
+        let root = tree.insert(None, &mut keys, &values)?;
+        let sorted_root = sorted.insert(None, &mut keys, &values)?;
+        assert_ne!(root, sorted_root);
+
+        // Each branch hashes the smaller of its children first
+        let mut nodes = vec![sorted_root];
+        let mut swapped = 0;
+        while let Some(location) = nodes.pop() {
+            if let Some(NodeVariant::Branch(branch)) = sorted.get_node_at(&location)? {
+                let (zero, one) = (*branch.get_zero(), *branch.get_one());
+                let (first, second) = if one < zero {
+                    swapped += 1;
+                    (one, zero)
+                } else {
+                    (zero, one)
+                };
+                let mut hasher = <TreeHasher as Hasher<[u8; KEY_LEN]>>::new(KEY_LEN);
+                Hasher::<[u8; KEY_LEN]>::update(&mut hasher, b"b");
+                Hasher::<[u8; KEY_LEN]>::update(&mut hasher, &first);
+                Hasher::<[u8; KEY_LEN]>::update(&mut hasher, &second);
+                let expected: [u8; KEY_LEN] = Hasher::<[u8; KEY_LEN]>::finalize(hasher);
+                assert_eq!(location, expected);
+                nodes.push(zero);
+                nodes.push(one);
+            }
+        }
+        assert!(swapped > 0);
+
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(sorted.get_one(&sorted_root, key)?, Some(value.clone()));
+
+            let proof = sorted.generate_inclusion_proof(&sorted_root, *key)?;
+            sorted.verify_proof(&sorted_root, *key, value, &proof)?;
+
+            let compact = sorted.generate_proof(&sorted_root, *key)?;
+            sorted.verify_compact_proof(&sorted_root, &compact)?;
+
+            let ics23 = sorted.generate_ics23_proof(&sorted_root, *key, HashOp::Sha256)?;
+            assert_eq!(
+                ics23.calculate_root::<TreeHasher, [u8; KEY_LEN]>()?,
+                sorted_root
+            );
+        }
+        let proof = sorted.generate_inclusion_proof(&sorted_root, keys[0])?;
+        assert!(tree
+            .verify_proof(&sorted_root, keys[0], &values[0], &proof)
+            .is_err());
+
+        // Updates on top of a sorted tree match building the updated tree at once
+        let mut changed = keys[..8].to_vec();
+        let new_values = vec![vec![0x5Au8; 4]; 8];
+        let updated_root = sorted.insert(Some(&sorted_root), &mut changed, &new_values)?;
+        let mut all_values = values.clone();
+        for (value, new_value) in all_values.iter_mut().zip(new_values.iter()) {
+            *value = new_value.clone();
+        }
+        let rebuilt_path = generate_path([0xC2u8; KEY_LEN]);
+        let mut rebuilt = Tree::open(&rebuilt_path, 160)?;
+        rebuilt.set_sorted_children(true);
+        assert_eq!(rebuilt.insert(None, &mut keys, &all_values)?, updated_root);
+
+        tear_down(&path);
+        tear_down(&sorted_path);
+        tear_down(&rebuilt_path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_a_pinned_path_cached_through_eviction() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+        use starling::tree_db::cache::CachedDatabase;
+
+        let seed = [0xC3u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let db = CountingDB::open(&path)?;
+        let reads = std::sync::Arc::clone(&db.reads);
+        let mut tree = CachedTree::from_db(CachedDatabase::new(db, usize::MAX), 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+        tree.warm_cache(&root, 160)?;
+
+        // The path holds the branches above the leaf, the leaf and its data node
+        let pinned = tree.pin_path(&root, &keys[0])?;
+        assert!(pinned >= 3);
+        assert_eq!(tree.evict_cache()?, 63 + 64 + 64 - pinned);
+
+        reads.store(0, Ordering::Relaxed);
+        assert_eq!(tree.get_one(&root, &keys[0])?, Some(values[0].clone()));
+        assert_eq!(reads.load(Ordering::Relaxed), 0);
+        assert_eq!(tree.get_one(&root, &keys[1])?, Some(values[1].clone()));
+        assert!(reads.load(Ordering::Relaxed) > 0);
+
+        // Once unpinned, the path is evicted like any other node
+        assert_eq!(tree.unpin_path(&root, &keys[0])?, pinned);
+        tree.evict_cache()?;
+        reads.store(0, Ordering::Relaxed);
+        assert_eq!(tree.get_one(&root, &keys[0])?, Some(values[0].clone()));
+        assert_eq!(reads.load(Ordering::Relaxed), pinned);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_merges_sorted_shards_into_one_root() -> BinaryMerkleTreeResult<()> {
+        let seed = [0xC4u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(96, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let bulk_root = tree.insert(None, &mut keys, &values)?;
+
+        // The shards interleave, so the merge has to pick from every shard in turn
+        let mut pairs = keys
+            .iter()
+            .copied()
+            .zip(values.iter().cloned())
+            .collect::<Vec<_>>();
+        pairs.sort();
+        let mut shards = vec![Vec::new(), Vec::new(), Vec::new()];
+        for (i, pair) in pairs.iter().enumerate() {
+            shards[i % 3].push(pair.clone());
+        }
+        let merged_root = tree.merge_sorted_shards(
+            shards
+                .iter()
+                .cloned()
+                .map(Vec::into_iter)
+                .collect::<Vec<_>>(),
+        )?;
+        assert_eq!(merged_root, bulk_root);
+        let items = tree.get(&merged_root, &mut keys)?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+
+        // A key held by two shards, a shard out of order, or keys needing a deeper tree are
+        // rejected, releasing the leaves written before the error was found
+        let (fresh_keys, fresh_values) = prepare_inserts(12, &mut rng);
+        let fresh = fresh_keys.into_iter().zip(fresh_values).collect::<Vec<_>>();
+        let overlapping = vec![fresh[..8].to_vec(), fresh[5..].to_vec()];
+        assert!(tree
+            .merge_sorted_shards(
+                overlapping
+                    .into_iter()
+                    .map(Vec::into_iter)
+                    .collect::<Vec<_>>()
+            )
+            .is_err());
+        assert!(tree.find_orphans(&[bulk_root])?.is_empty());
+        let mut reversed = fresh[6..].to_vec();
+        reversed.reverse();
+        let unsorted = vec![fresh[..6].to_vec(), reversed];
+        assert!(tree
+            .merge_sorted_shards(unsorted.into_iter().map(Vec::into_iter).collect::<Vec<_>>())
+            .is_err());
+        assert!(tree.find_orphans(&[bulk_root])?.is_empty());
+        let shallow_path = generate_path([0xD4u8; KEY_LEN]);
+        let mut shallow = Tree::open(&shallow_path, 1)?;
+        assert!(shallow
+            .merge_sorted_shards(vec![fresh.clone().into_iter()])
+            .is_err());
+        assert!(shallow.find_orphans(&[])?.is_empty());
+        tear_down(&shallow_path);
+
+        // The leaves shared with the bulk root keep the references of both roots
+        tree.remove(&bulk_root)?;
+        tree.remove(&merged_root)?;
+        assert!(tree.find_orphans(&[])?.is_empty());
+
+        let empty: Vec<std::vec::IntoIter<([u8; KEY_LEN], Vec<u8>)>> = vec![Vec::new().into_iter()];
+        assert_eq!(tree.merge_sorted_shards(empty)?, [0; KEY_LEN]);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "debug_tools")]
+    fn it_reports_every_reference_count_change() -> BinaryMerkleTreeResult<()> {
+        use std::sync::{Arc, Mutex};
+
+        let seed = [0xC5u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(2, &mut rng);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut tree = Tree::open(&path, 160)?;
+        let recorded = Arc::clone(&events);
+        tree.set_on_refcount_change(Arc::new(move |location, old, new| {
+            recorded.lock().unwrap().push((*location, old, new));
+        }));
+
+        // Each data node, then its leaf, then the branch above them are created
+        let root = tree.insert(None, &mut keys, &values)?;
+        let created = events.lock().unwrap().drain(..).collect::<Vec<_>>();
+        assert_eq!(created.len(), 5);
+        assert!(created.iter().all(|&(_, old, new)| old == 0 && new == 1));
+        assert_eq!(created[4].0, root);
+        let mut locations = created.iter().map(|event| event.0).collect::<Vec<_>>();
+        locations.sort();
+        locations.dedup();
+        assert_eq!(locations.len(), 5);
+
+        // Inserting the same values again only adds a reference to the root
+        let same_root = tree.insert(Some(&root), &mut keys, &values)?;
+        assert_eq!(same_root, root);
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec![(root, 1, 2)]
+        );
+
+        // Removing the root twice releases that reference, and then every node
+        tree.remove(&root)?;
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec![(root, 2, 1)]
+        );
+        tree.remove(&root)?;
+        let removed = events.lock().unwrap().drain(..).collect::<Vec<_>>();
+        assert_eq!(removed.len(), 5);
+        assert!(removed.iter().all(|&(_, old, new)| old == 1 && new == 0));
+        assert_eq!(removed[0].0, root);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_a_root_pointer_per_namespace() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+        use starling::tree_db::concurrent::{ConcurrentDatabase, ConcurrentReader};
+        use starling::tree_db::HashTreeDB;
+
+        type ConcurrentTree = starling::merkle_bit::MerkleBIT<
+            ConcurrentDatabase<HashTreeDB<[u8; KEY_LEN]>, [u8; KEY_LEN]>,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+            starling::tree::tree_data::TreeData,
+            starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+            starling::tree_hasher::TreeHasher,
+            Vec<u8>,
+            [u8; KEY_LEN],
+        >;
+        type ReaderTree = starling::merkle_bit::MerkleBIT<
+            ConcurrentReader<HashTreeDB<[u8; KEY_LEN]>>,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+            starling::tree::tree_data::TreeData,
+            starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+            starling::tree_hasher::TreeHasher,
+            Vec<u8>,
+            [u8; KEY_LEN],
+        >;
+
+        let seed = [0xC6u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut alpha_keys, alpha_values) = prepare_inserts(16, &mut rng);
+        let (mut beta_keys, beta_values) = prepare_inserts(16, &mut rng);
+
+        let db = ConcurrentDatabase::new(HashTreeDB::open(&path)?);
+        let mut alpha = ReaderTree::from_db(db.handle(), 160)?;
+        alpha.set_root_namespace(b"alpha");
+        let mut beta = ReaderTree::from_db(db.handle(), 160)?;
+        beta.set_root_namespace(b"beta");
+        let unnamed = ReaderTree::from_db(db.handle(), 160)?;
+        let mut writer = ConcurrentTree::from_db(db, 160)?;
+
+        writer.set_root_namespace(b"alpha");
+        let alpha_root = writer.insert(None, &mut alpha_keys, &alpha_values)?;
+        assert!(writer.compare_and_set_root(None, alpha_root)?);
+        assert_eq!(beta.current_root()?, None);
+
+        // Each namespace swaps its own pointer, expecting only its own current root
+        writer.set_root_namespace(b"beta");
+        let beta_root = writer.insert(None, &mut beta_keys, &beta_values)?;
+        assert!(!writer.compare_and_set_root(Some(alpha_root), beta_root)?);
+        assert!(writer.compare_and_set_root(None, beta_root)?);
+        assert_eq!(alpha.current_root()?, Some(alpha_root));
+        assert_eq!(beta.current_root()?, Some(beta_root));
+
+        writer.set_root_namespace(b"alpha");
+        let alpha_next = writer.insert(Some(&alpha_root), &mut beta_keys, &beta_values)?;
+        assert!(writer.compare_and_set_root(Some(alpha_root), alpha_next)?);
+        assert_eq!(alpha.current_root()?, Some(alpha_next));
+        assert_eq!(beta.current_root()?, Some(beta_root));
+
+        // The empty namespace is the pointer the trees would share without one
+        assert_eq!(unnamed.current_root()?, None);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_proves_keys_of_subtrees_committed_into_a_global_root() -> BinaryMerkleTreeResult<()> {
+        use starling::utils::proof::SubtreeProof;
+
+        let seed = [0xC7u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut first_keys, first_values) = prepare_inserts(16, &mut rng);
+        let (mut second_keys, second_values) = prepare_inserts(16, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let first_root = tree.insert(None, &mut first_keys, &first_values)?;
+        let second_root = tree.insert(None, &mut second_keys, &second_values)?;
+        let first_account = [0x01u8; KEY_LEN];
+        let second_account = [0x02u8; KEY_LEN];
+        let global_root =
+            tree.commit_subtrees(&[(first_account, first_root), (second_account, second_root)])?;
+
+        // Each committed subtree root is the value of its key in the global tree
+        let proof = tree.generate_subtree_proof(&global_root, second_account, second_keys[3])?;
+        assert_eq!(proof.subtree_root()?, second_root);
+        assert_eq!(proof.subtree().key(), &second_keys[3]);
+        assert_eq!(proof.subtree().value(), second_values[3].as_slice());
+        tree.verify_subtree_proof(&global_root, &proof)?;
+
+        // Either level failing fails the whole proof
+        assert!(tree.verify_subtree_proof(&first_root, &proof).is_err());
+        let other = tree.generate_subtree_proof(&global_root, first_account, first_keys[0])?;
+        let mixed = SubtreeProof::new(proof.global().clone(), other.subtree().clone());
+        assert!(tree.verify_subtree_proof(&global_root, &mixed).is_err());
+        assert!(tree
+            .generate_subtree_proof(&global_root, second_account, first_keys[0])
+            .is_err());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_stores_only_value_commitments_in_commit_only_mode() -> BinaryMerkleTreeResult<()> {
+        use starling::utils::hash_scheme::HashScheme;
+
+        let seed = [0xC8u8; KEY_LEN];
+        let path = generate_path(seed);
+        let commit_path = generate_path([0xC9u8; KEY_LEN]);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(32, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+        let mut commit_tree = Tree::open(&commit_path, 160)?;
+        commit_tree.set_commit_only(true);
+        let commit_root = commit_tree.insert(None, &mut keys, &values)?;
+        assert_eq!(commit_root, root);
+
+        // Both trees commit to the hash of each value, but only the full tree can give it back
+        let scheme = HashScheme::new();
+        for (key, value) in keys.iter().zip(values.iter()) {
+            let expected =
+                scheme.value_location::<starling::tree_hasher::TreeHasher, [u8; KEY_LEN]>(value);
+            assert_eq!(
+                commit_tree.get_commitment(&commit_root, key)?,
+                Some(expected)
+            );
+            assert_eq!(tree.get_commitment(&root, key)?, Some(expected));
+            assert!(commit_tree.get_one(&commit_root, key).is_err());
+        }
+        assert_eq!(
+            commit_tree.get_commitment(&commit_root, &[0u8; KEY_LEN])?,
+            None
+        );
+
+        // Inserting the same values again leaves the root unchanged
+        let again = commit_tree.insert(Some(&commit_root), &mut keys, &values)?;
+        assert_eq!(again, commit_root);
+
+        // Inclusion proofs need no value, so a commit-only tree gives the same proofs as the full
+        // tree, while a `Proof` carrying the value cannot be made
+        for (key, value) in keys.iter().zip(values.iter()) {
+            let proof = commit_tree.generate_inclusion_proof(&commit_root, *key)?;
+            assert_eq!(proof, tree.generate_inclusion_proof(&root, *key)?);
+            Tree::verify_inclusion_proof(&commit_root, *key, value, &proof)?;
+        }
+        assert!(commit_tree.generate_proof(&commit_root, keys[0]).is_err());
+
+        tear_down(&path);
+        tear_down(&commit_path);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "use_rocksdb")))]
+    fn it_keeps_a_reader_of_a_commit_only_tree_commit_only() -> BinaryMerkleTreeResult<()> {
+        let seed = [0xCAu8; KEY_LEN];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(16, &mut rng);
+
+        let mut commit_tree = HashTree::new(160)?;
+        commit_tree.set_commit_only(true);
+        let root = commit_tree.insert(None, &mut keys, &values)?;
+
+        let reader = commit_tree.into_reader()?;
+        assert!(reader.options().commit_only());
+        for key in &keys {
+            assert!(reader.get_one(&root, key).is_err());
+            assert!(reader.get_raw(&root, key).is_err());
+            assert!(reader.contains_key(&root, key)?);
+        }
+        assert!(reader.get(&root, &mut keys.clone()).is_err());
+        assert!(matches!(reader.iter(&root).next(), Some(Err(_))));
+        assert!(reader.generate_proof(&root, keys[0]).is_err());
+
+        let proof = reader.generate_inclusion_proof(&root, keys[0])?;
+        HashTree::verify_inclusion_proof(&root, keys[0], &values[0], &proof)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_is_send_and_sync_whenever_its_database_is() {
+        use std::rc::Rc;
+
+        use starling::traits::{Decode, Encode};
+        use starling::tree_db::cache::CachedDatabase;
+        use starling::tree_db::concurrent::ConcurrentDatabase;
+        use starling::tree_db::HashTreeDB;
+
+        /// A value which is neither `Send` nor `Sync`, and is never held by the tree.
+        struct Local(Rc<Vec<u8>>);
+
+        impl Encode for Local {
+            fn encode(&self) -> Result<Vec<u8>, Exception> {
+                Ok(self.0.to_vec())
+            }
+        }
+
+        impl Decode for Local {
+            fn decode(buffer: &[u8]) -> Result<Self, Exception> {
+                Ok(Self(Rc::new(buffer.to_vec())))
+            }
+        }
+
+        type LocalTree<DatabaseType> = starling::merkle_bit::MerkleBIT<
+            DatabaseType,
+            starling::tree::tree_branch::TreeBranch<[u8; KEY_LEN]>,
+            starling::tree::tree_leaf::TreeLeaf<[u8; KEY_LEN]>,
+            starling::tree::tree_data::TreeData,
+            starling::tree::tree_node::TreeNode<[u8; KEY_LEN]>,
+            starling::tree_hasher::TreeHasher,
+            Local,
+            [u8; KEY_LEN],
+        >;
+
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<Tree>();
+        assert_send_sync::<LocalTree<HashTreeDB<[u8; KEY_LEN]>>>();
+        assert_send_sync::<LocalTree<ConcurrentDatabase<HashTreeDB<[u8; KEY_LEN]>, [u8; KEY_LEN]>>>(
+        );
+        assert_send_sync::<LocalTree<CachedDatabase<HashTreeDB<[u8; KEY_LEN]>, [u8; KEY_LEN]>>>();
+    }
+
+    #[test]
+    fn it_gets_values_in_the_order_of_the_keys() -> BinaryMerkleTreeResult<()> {
+        let seed = [0xCBu8; KEY_LEN];
+        let path = generate_path(seed);
+        let first = [0x10u8; KEY_LEN];
+        let second = [0x80u8; KEY_LEN];
+        let third = [0x40u8; KEY_LEN];
+        let missing = [0xFFu8; KEY_LEN];
+
+        let mut keys = vec![first, second, third];
+        let values = vec![vec![0x01u8], vec![0x02u8], vec![0x03u8]];
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+
+        // Unsorted keys, a missing key and duplicates all keep their positions
+        let lookup = vec![second, missing, first, second, third, first];
+        let items = bmt.get_ordered(&root, &lookup)?;
+        assert_eq!(
+            items,
+            vec![
+                Some(vec![0x02u8]),
+                None,
+                Some(vec![0x01u8]),
+                Some(vec![0x02u8]),
+                Some(vec![0x03u8]),
+                Some(vec![0x01u8]),
+            ]
+        );
+        assert_eq!(lookup, vec![second, missing, first, second, third, first]);
+
+        assert!(bmt.get_ordered(&root, &[])?.is_empty());
+        assert_eq!(bmt.get_ordered(&[0x00u8; KEY_LEN], &[first])?, vec![None]);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_only_the_keys_reaching_a_corrupt_node() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::{Branch, Data, Database, Encode, NodeVariant};
+        use starling::tree::tree_branch::TreeBranch;
+        use starling::tree::tree_data::TreeData;
+        use starling::tree::tree_node::TreeNode;
+        use starling::tree_hasher::TreeHasher;
+        use starling::utils::hash_scheme::HashScheme;
+
+        let seed = [0xCCu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut db = CountingDB::open(&path)?;
+        let mut bmt = CountedTree::from_db(db.clone(), 160)?;
+
+        let mut keys = vec![
+            [0x10u8; KEY_LEN],
+            [0x40u8; KEY_LEN],
+            [0x80u8; KEY_LEN],
+            [0xC0u8; KEY_LEN],
+        ];
+        let values = vec![vec![0x01u8], vec![0x02u8], vec![0x03u8], vec![0x04u8]];
+        let root = bmt.insert(None, &mut keys, &values)?;
+        let missing = [0x00u8; KEY_LEN];
+
+        // The data node of the second key is replaced by a branch
+        let scheme = HashScheme::new();
+        let data_location = scheme.data_location::<TreeHasher, _>(&keys[1], &values[1].encode()?);
+        db.insert(
+            data_location,
+            TreeNode::new(NodeVariant::Branch(TreeBranch::new())),
+        )?;
+
+        // The leaf of the third key is replaced by a data node
+        let leaf_location =
+            scheme.leaf_location_of_value::<TreeHasher, _>(&keys[2], &values[2].encode()?);
+        let mut data = TreeData::new();
+        data.set_value(&[0xAAu8]);
+        db.insert(leaf_location, TreeNode::new(NodeVariant::Data(data)))?;
+
+        // A plain get fails the whole batch
+        let mut lookup = vec![keys[0], keys[1], keys[2], keys[3], missing];
+        assert!(bmt.get(&root, &mut lookup).is_err());
+
+        let items = bmt.get_with_errors(&root, &mut lookup)?;
+        assert_eq!(items.len(), 5);
+        assert_eq!(items[&keys[0]].as_ref().unwrap(), &Some(vec![0x01u8]));
+        assert!(items[&keys[1]].is_err());
+        assert!(items[&keys[2]].is_err());
+        assert_eq!(items[&keys[3]].as_ref().unwrap(), &Some(vec![0x04u8]));
+        assert_eq!(items[&missing].as_ref().unwrap(), &None);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_counts_the_leaves_under_each_node_at_a_depth() -> BinaryMerkleTreeResult<()> {
+        let seed = [0xCEu8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+
+        // Most keys start with a zero bit, a few with a one bit
+        let (mut keys, values) = prepare_inserts(200, &mut rng);
+        for (i, key) in keys.iter_mut().enumerate() {
+            if i % 20 == 0 {
+                key[0] |= 0x80;
+            } else {
+                key[0] &= 0x7F;
+            }
+        }
+        keys.sort();
+
+        let mut bmt = Tree::open(&path, 160)?;
+        let root = bmt.insert(None, &mut keys, &values)?;
+
+        assert_eq!(
+            bmt.subtree_size_histogram(&root, 0)?,
+            vec![(root, keys.len() as u64)]
+        );
+
+        for depth in 1..5 {
+            let histogram = bmt.subtree_size_histogram(&root, depth)?;
+            let locations = histogram
+                .iter()
+                .map(|(location, _)| *location)
+                .collect::<Vec<_>>();
+            assert_eq!(locations, bmt.nodes_at_depth(&root, depth)?);
+            for (location, count) in &histogram {
+                let entries = bmt
+                    .iter(location)
+                    .collect::<BinaryMerkleTreeResult<Vec<_>>>()?;
+                assert_eq!(entries.len() as u64, *count);
+            }
+            let total = histogram.iter().map(|(_, count)| count).sum::<u64>();
+            assert_eq!(total, keys.len() as u64);
+        }
+
+        // The skew shows in the counts of the two halves
+        let halves = bmt.subtree_size_histogram(&root, 1)?;
+        assert_eq!(halves.len(), 2);
+        assert_eq!(halves[1].1, 10);
+        assert_eq!(halves[0].1, keys.len() as u64 - 10);
+
+        assert!(bmt.subtree_size_histogram(&root, 161).is_err());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_cancels_verifying_and_iterating_a_tree() -> BinaryMerkleTreeResult<()> {
+        let seed = [0xD0u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+
+        let cancel = AtomicBool::new(true);
+        match tree.verify_tree_cancellable(&root, &cancel) {
+            Err(e) => assert_eq!(e.kind(), &ErrorKind::Cancelled),
+            Ok(_) => panic!("Verification should have been cancelled"),
+        }
+        match tree.iter_cancellable(&root, &cancel).next() {
+            Some(Err(e)) => assert_eq!(e.kind(), &ErrorKind::Cancelled),
+            _ => panic!("Iteration should have been cancelled"),
+        }
+
+        // Cancelling part way through ends the iteration after a cancellation error
+        cancel.store(false, Ordering::Relaxed);
+        let mut entries = tree.iter_cancellable(&root, &cancel);
+        assert!(entries.next().transpose()?.is_some());
+        cancel.store(true, Ordering::Relaxed);
+        match entries.next() {
+            Some(Err(e)) => assert_eq!(e.kind(), &ErrorKind::Cancelled),
+            _ => panic!("Iteration should have been cancelled"),
+        }
+        assert!(entries.next().is_none());
+
+        cancel.store(false, Ordering::Relaxed);
+        tree.verify_tree(&root)?;
+        tree.verify_tree_cancellable(&root, &cancel)?;
+        let entries = tree
+            .iter_cancellable(&root, &cancel)
+            .collect::<BinaryMerkleTreeResult<Vec<_>>>()?;
+        assert_eq!(
+            entries,
+            tree.iter(&root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+        );
+        assert_eq!(entries.len(), keys.len());
+
+        // A root that is not in the tree fails verification
+        assert!(tree.verify_tree(&[0x01u8; KEY_LEN]).is_err());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_frees_a_data_node_shared_within_a_removed_root() -> BinaryMerkleTreeResult<()> {
+        let seed = [0xD1u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut keys = vec![[0x00u8; KEY_LEN], [0xFFu8; KEY_LEN]];
+        let values = vec![vec![0xABu8; 16], vec![0xABu8; 16]];
+
+        let mut tree = Tree::open(&path, 160)?;
+        tree.set_value_addressed_data(true);
+        let root = tree.insert(None, &mut keys, &values)?;
+        assert_eq!(tree.distinct_value_count(&root)?, 1);
+
+        // The data node is reached through both leaves, and must be freed by the second visit
+        tree.remove(&root)?;
+        assert!(tree.find_orphans(&[])?.is_empty());
+        assert_eq!(tree.get_one(&root, &keys[0])?, None);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_counts_references_of_branches_shared_between_trees() -> BinaryMerkleTreeResult<()> {
+        let seed = [0xD2u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+        for key in &mut keys[..32] {
+            key[0] &= 0x7F;
+        }
+        for key in &mut keys[32..] {
+            key[0] |= 0x80;
+        }
+        keys.sort();
+
+        let mut tree = Tree::open(&path, 160)?;
+
+        // The same keys build the same root, whose every node is then shared by both inserts
+        let first = tree.insert(None, &mut keys.clone(), &values)?;
+        let second = tree.insert(None, &mut keys.clone(), &values)?;
+        assert_eq!(first, second);
+        tree.remove(&first)?;
+        let items = tree.get(&second, &mut keys.clone())?;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(items[key], Some(value.clone()));
+        }
+        tree.remove(&second)?;
+        assert!(tree.find_orphans(&[])?.is_empty());
+
+        // The zero half of the full tree is the root of the tree over the keys of the zero half
+        for half_first in [true, false] {
+            let half = tree.insert(None, &mut keys[..32].to_vec(), &values[..32])?;
+            let full = tree.insert(None, &mut keys.clone(), &values)?;
+            let (removed, kept, kept_keys) = if half_first {
+                (half, full, &keys[..])
+            } else {
+                (full, half, &keys[..32])
+            };
+            tree.remove(&removed)?;
+            let items = tree.get(&kept, &mut kept_keys.to_vec())?;
+            for (key, value) in kept_keys.iter().zip(values.iter()) {
+                assert_eq!(items[key], Some(value.clone()));
+            }
+            tree.remove(&kept)?;
+            assert!(tree.find_orphans(&[])?.is_empty());
+        }
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_merges_roots_at_the_branch_level() -> BinaryMerkleTreeResult<()> {
+        let seed = [0xD3u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(64, &mut rng);
+        for key in &mut keys[..32] {
+            key[0] &= 0x7F;
+        }
+        for key in &mut keys[32..] {
+            key[0] |= 0x80;
+        }
+        keys.sort();
+
+        let mut tree = Tree::open(&path, 160)?;
+        let low_root = tree.insert(None, &mut keys[..32].to_vec(), &values[..32])?;
+        let high_root = tree.insert(None, &mut keys[32..].to_vec(), &values[32..])?;
+
+        // Roots split by the first bit are joined by a single new branch
+        let stored = tree.find_orphans(&[])?.len();
+        let halves_root = tree.merge_roots(&high_root, &low_root)?;
+        assert_eq!(tree.find_orphans(&[])?.len(), stored + 1);
+
+        // Interleaved key spaces are joined along every path where they interleave
+        let even_keys = keys.iter().copied().step_by(2).collect::<Vec<_>>();
+        let even_values = values.iter().cloned().step_by(2).collect::<Vec<_>>();
+        let odd_keys = keys.iter().copied().skip(1).step_by(2).collect::<Vec<_>>();
+        let odd_values = values
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .cloned()
+            .collect::<Vec<_>>();
+        let even_root = tree.insert(None, &mut even_keys.clone(), &even_values)?;
+        let odd_root = tree.insert(None, &mut odd_keys.clone(), &odd_values)?;
+        let interleaved_root = tree.merge_roots(&even_root, &odd_root)?;
+
+        let expected_root = tree.insert(None, &mut keys.clone(), &values)?;
+        assert_eq!(halves_root, expected_root);
+        assert_eq!(interleaved_root, expected_root);
+
+        // Every root holds its own references to the subtrees it shares
+        for root in [
+            low_root,
+            high_root,
+            even_root,
+            odd_root,
+            halves_root,
+            expected_root,
+        ] {
+            tree.remove(&root)?;
+            let items = tree.get(&interleaved_root, &mut keys.clone())?;
+            for (key, value) in keys.iter().zip(values.iter()) {
+                assert_eq!(items[key], Some(value.clone()));
+            }
+        }
+        tree.remove(&interleaved_root)?;
+        assert!(tree.find_orphans(&[])?.is_empty());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_recounts_a_snapshot_pin_left_by_a_dropped_tree() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+
+        let seed = [0xD5u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(16, &mut rng);
+
+        let db = CountingDB::open(&path)?;
+        let mut tree = CountedTree::from_db(db.clone(), 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+        let snapshot = tree.snapshot(&root)?;
+        drop(tree);
+        drop(snapshot);
+
+        // The pin was stored with the root, but only the dropped tree knew to release it
+        let mut tree = CountedTree::from_db(db.clone(), 160)?;
+        tree.remove(&root)?;
+        assert!(db.get_node(root)?.is_some());
+
+        tree.recount_references(&[root])?;
+        tree.remove(&root)?;
+        assert!(db.get_node(root)?.is_none());
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rebuilds_the_value_index_of_a_reopened_tree() -> BinaryMerkleTreeResult<()> {
+        use starling::traits::Database;
+
+        let seed = [0xD6u8; KEY_LEN];
+        let path = generate_path(seed);
+        let first = [0x01u8; KEY_LEN];
+        let second = [0x02u8; KEY_LEN];
+        let third = [0x03u8; KEY_LEN];
+        let a = vec![0xAAu8; 4];
+        let b = vec![0xBBu8; 4];
+
+        let db = CountingDB::open(&path)?;
+        let (root, shard_root) = {
+            let mut tree = CountedTree::from_db(db.clone(), 160)?;
+            tree.set_value_index(true)?;
+            let root = tree.insert(None, &mut [first, second], &[a.clone(), b.clone()])?;
+            // Leaves written by the bulk paths are indexed as well
+            let shard_root =
+                tree.merge_sorted_shards(vec![vec![(third, a.clone())].into_iter()])?;
+            assert_eq!(tree.keys_for_value(&shard_root, &a)?, vec![third]);
+            (root, shard_root)
+        };
+
+        // The index is not persisted, so the reopened tree rebuilds it from the stored leaves
+        let mut tree = CountedTree::from_db(db, 160)?;
+        assert!(tree.keys_for_value(&root, &a).is_err());
+        tree.set_value_index(true)?;
+        assert_eq!(tree.keys_for_value(&root, &a)?, vec![first]);
+        assert_eq!(tree.keys_for_value(&root, &b)?, vec![second]);
+        assert_eq!(tree.keys_for_value(&shard_root, &a)?, vec![third]);
+        let merged = tree.insert_one(Some(&root), &third, &a)?;
+        assert_eq!(tree.keys_for_value(&merged, &a)?, vec![first, third]);
+
+        tear_down(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_the_empty_root_as_a_tree_without_keys() -> BinaryMerkleTreeResult<()> {
+        use starling::tree_hasher::TreeHasher;
+
+        let seed = [0xD7u8; KEY_LEN];
+        let path = generate_path(seed);
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let (mut keys, values) = prepare_inserts(16, &mut rng);
+
+        let mut tree = Tree::open(&path, 160)?;
+        let root = tree.insert(None, &mut keys, &values)?;
+        let empty = Tree::empty_root();
+        let key = keys[0];
+        let cancel = AtomicBool::new(false);
+
+        assert!(tree.has_root(&empty)?);
+        assert_eq!(tree.get(&empty, &mut [key])?[&key], None);
+        assert_eq!(tree.get_ordered(&empty, &[key])?, vec![None]);
+        assert_eq!(tree.get_one(&empty, &key)?, None);
+        assert!(!tree.contains_key(&empty, &key)?);
+        assert_eq!(tree.get_raw(&empty, &key)?, None);
+        assert!(tree.get_value_reader(&empty, &key)?.is_none());
+        assert_eq!(tree.get_commitment(&empty, &key)?, None);
+        assert_eq!(tree.value_version(&empty, &key)?, None);
+        assert!(tree.get_ref(&empty, &mut [key])?[&key].is_none());
+        assert_eq!(tree.get_node_at(&empty)?, None);
+
+        assert_eq!(
+            tree.iter(&empty)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+                .len(),
+            0
+        );
+        assert_eq!(
+            tree.iter_cancellable(&empty, &cancel)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?
+                .len(),
+            0
+        );
+        assert_eq!(tree.iter_with_proofs(&empty).count(), 0);
+        assert_eq!(tree.iter_from(&empty, None, 8)?, (Vec::new(), None));
+        assert_eq!(tree.iter_from(&empty, Some(key), 8)?, (Vec::new(), None));
+        assert!(tree.entries_by_value(&empty, false)?.is_empty());
+        let mut dump = Vec::new();
+        tree.debug_dump(&empty, &mut dump)?;
+        assert!(dump.is_empty());
+        assert!(tree.to_json(&empty)?.contains("\"entries\":{}"));
+
+        assert_eq!(tree.structural_root(&empty)?, empty);
+        tree.verify_tree(&empty)?;
+        tree.verify_tree_cancellable(&empty, &cancel)?;
+        assert_eq!(tree.subtree_root(&empty, &[0x00u8], 1)?, None);
+        assert!(tree.nodes_at_depth(&empty, 0)?.is_empty());
+        assert!(tree.subtree_size_histogram(&empty, 1)?.is_empty());
+        assert_eq!(tree.distinct_value_count(&empty)?, 0);
+        assert_eq!(tree.depth_of(&empty)?, 0);
+        assert_eq!(tree.shared_node_count(&empty, &root)?, 0);
+        assert_eq!(tree.shared_node_count(&root, &empty)?, 0);
+        assert!(tree.find_orphans(&[root, empty])?.is_empty());
+
+        // Every key of a tree is new since the empty root, and none are new in the empty root
+        let mut sorted_keys = keys.clone();
+        sorted_keys.sort();
+        assert_eq!(
+            tree.changed_keys_since(&empty, &root)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?,
+            sorted_keys
+        );
+        assert_eq!(tree.changed_keys_since(&root, &empty).count(), 0);
+        assert_eq!(tree.changed_keys_since(&empty, &empty).count(), 0);
+        assert_eq!(
+            tree.keys_only_in(&root, &empty)
+                .collect::<BinaryMerkleTreeResult<Vec<_>>>()?,
+            sorted_keys
+        );
+        assert_eq!(tree.keys_only_in(&empty, &root).count(), 0);
+
+        let snapshot = tree.snapshot(&empty)?;
+        assert_eq!(tree.get_snapshot(&snapshot, &mut [key])?[&key], None);
+        let weak = tree.weak_snapshot(&empty)?;
+        let removed = tree.insert(None, &mut [keys[1]], &[values[1].clone()])?;
+        tree.remove(&removed)?;
+        assert_eq!(tree.get_snapshot(&weak, &mut [key])?[&key], None);
+
+        // An empty tree has no keys to match a predicate or to fall on either side of a boundary
+        let filtered = tree.filter(&empty, |_, _| true);
+        assert!(filtered.unwrap_err().to_string().contains("No keys match"));
+        let split = tree.split(&empty, &key);
+        assert!(split
+            .unwrap_err()
+            .to_string()
+            .contains("no keys on one side"));
+
+        // Rebuilding the empty tree gives the empty tree, and merging with it gives the other root
+        assert_eq!(tree.rekey(&empty, |k| *k)?, empty);
+        assert_eq!(tree.migrate_hasher::<TreeHasher>(&empty)?, empty);
+        assert_eq!(tree.merge_roots(&empty, &empty)?, empty);
+        assert_eq!(tree.merge_roots(&empty, &root)?, root);
+        assert_eq!(tree.merge_roots(&root, &empty)?, root);
+
+        // Each merge holds a reference to the root, and removing the empty root does nothing
+        tree.remove(&empty)?;
+        for _ in 0..3 {
+            assert_eq!(tree.get_one(&root, &key)?, Some(values[0].clone()));
+            tree.remove(&root)?;
+        }
+        assert!(!tree.has_root(&root)?);
+
+        tear_down(&path);
+        Ok(())
+    }
+
     fn generate_path(seed: [u8; KEY_LEN]) -> PathBuf {
         let mut rng: StdRng = SeedableRng::from_seed(seed);
         let suffix = rng.gen_range(1000, 100000);
@@ -2797,4 +8648,102 @@ pub mod integration_tests {
         }
         Ok(())
     }
+
+    /// Fixed datasets and the roots they hash to under the default hasher, catching any change to
+    /// the inputs of the hashing scheme described in the README.
+    #[cfg(not(any(
+        feature = "use_blake2b_rfc",
+        feature = "use_sha2",
+        feature = "use_sha3",
+        feature = "use_keccak",
+        feature = "use_seahash",
+        feature = "use_fx",
+        feature = "use_digest"
+    )))]
+    mod test_vectors {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::Hasher;
+
+        use starling::utils::json_export::from_hex;
+
+        use super::*;
+
+        /// The keys and values of the vector of eight keys.
+        fn eight_keys() -> (Vec<[u8; KEY_LEN]>, Vec<Vec<u8>>) {
+            let keys = (1..=8_u8).map(|i| [i; KEY_LEN]).collect::<Vec<_>>();
+            let values = (1..=8_u8)
+                .map(|i| vec![i; usize::from(i)])
+                .collect::<Vec<_>>();
+            (keys, values)
+        }
+
+        /// Parses an expected root.
+        fn root(hex: &str) -> [u8; KEY_LEN] {
+            let mut root = [0_u8; KEY_LEN];
+            root.copy_from_slice(&from_hex(hex).unwrap());
+            root
+        }
+
+        /// Hashes `parts` the way the default hasher fills a location.
+        fn location(parts: &[&[u8]]) -> [u8; KEY_LEN] {
+            let mut hasher = DefaultHasher::new();
+            for part in parts {
+                hasher.write(part);
+            }
+            let mut location = [0_u8; KEY_LEN];
+            location[..8].copy_from_slice(&hasher.finish().to_le_bytes());
+            location
+        }
+
+        #[test]
+        fn it_hashes_the_documented_scheme() -> BinaryMerkleTreeResult<()> {
+            let path = generate_path([0x59u8; KEY_LEN]);
+            let tree = Tree::open(&path, 160)?;
+            let keys = [[0x00u8; KEY_LEN], [0xFFu8; KEY_LEN]];
+            let values = [vec![0x01u8], vec![0x02u8]];
+
+            let mut leaves = Vec::new();
+            for (key, value) in keys.iter().zip(values.iter()) {
+                let data = location(&[b"d", key, value]);
+                leaves.push(location(&[b"l", key, &data]));
+            }
+            let expected = location(&[b"b", &leaves[0], &leaves[1]]);
+            assert_eq!(tree.root_for(&keys, &values)?, expected);
+
+            tear_down(&path);
+            Ok(())
+        }
+
+        #[test]
+        fn it_matches_the_test_vectors() -> BinaryMerkleTreeResult<()> {
+            let path = generate_path([0x5Au8; KEY_LEN]);
+            let mut tree = Tree::open(&path, 160)?;
+
+            let mut single = [[0x00u8; KEY_LEN]];
+            let single_values = [vec![0x01u8]];
+            let expected = root("2e6afb25a75b1250000000000000000000000000000000000000000000000000");
+            assert_eq!(tree.root_for(&single, &single_values)?, expected);
+            assert_eq!(tree.insert(None, &mut single, &single_values)?, expected);
+
+            let mut pair = [[0x00u8; KEY_LEN], [0xFFu8; KEY_LEN]];
+            let pair_values = [vec![0x01u8], vec![0x02u8]];
+            let expected = root("c5bfefbcf12dec37000000000000000000000000000000000000000000000000");
+            assert_eq!(tree.root_for(&pair, &pair_values)?, expected);
+            assert_eq!(tree.insert(None, &mut pair, &pair_values)?, expected);
+
+            let (mut keys, values) = eight_keys();
+            let expected = root("2d257c4096083bea000000000000000000000000000000000000000000000000");
+            assert_eq!(tree.root_for(&keys, &values)?, expected);
+            assert_eq!(tree.insert(None, &mut keys, &values)?, expected);
+
+            let mut combined = Tree::open(&path, 160)?;
+            combined.set_combined_leaves(true);
+            let expected = root("2b98b3de6e0f557d000000000000000000000000000000000000000000000000");
+            assert_eq!(combined.root_for(&keys, &values)?, expected);
+            assert_eq!(combined.insert(None, &mut keys, &values)?, expected);
+
+            tear_down(&path);
+            Ok(())
+        }
+    }
 }